@@ -3,34 +3,165 @@
 //! Для изменения существующих файлов используется только apply_patch.
 //! update_file оставлен для совместимости, но в режиме Agent не используется (deprecated).
 //! create_project — создание проекта из шаблона (BLOCK 14).
+//! find_definition, list_symbols — по индексу символов (tree-sitter), без чтения файлов целиком.
+//! С `ChangeSet` файловые инструменты работают через слой правок, диск не меняется
+//! (кроме create_project: шаблон создаётся сразу).
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
 
-use backend_core::{create_project_from_template, WorkspaceError};
+use backend_core::fs::ProjectWalker;
+use backend_core::{create_project_from_template, SessionSnapshot, WorkspaceError};
+use context_manager::SymbolIndex;
 
+use crate::staging::{rel_path, ChangeSet};
 use crate::types::{PatchError, ToolCall, ToolResult};
 
 /// Выполняет вызовы инструментов. Все пути относительно project_root.
 pub struct ToolExecutor {
     project_root: PathBuf,
+    /// Индекс символов: общий из IDE или открывается при первом обращении.
+    symbols: OnceLock<Arc<SymbolIndex>>,
+    /// Слой незаписанных правок сессии; None — запись сразу на диск.
+    changes: Option<Arc<ChangeSet>>,
+    /// Снимок исходных файлов перед записью на диск (откат сессии).
+    snapshot: Option<Arc<SessionSnapshot>>,
 }
 
 impl ToolExecutor {
     pub fn new(project_root: PathBuf) -> Self {
-        Self { project_root }
+        Self {
+            project_root,
+            symbols: OnceLock::new(),
+            changes: None,
+            snapshot: None,
+        }
+    }
+
+    /// Сохранять исходные файлы в снимок сессии перед записью на диск.
+    pub fn with_snapshot(mut self, snapshot: Arc<SessionSnapshot>) -> Self {
+        self.snapshot = Some(snapshot);
+        self
+    }
+
+    /// Писать изменения в слой вместо диска.
+    pub fn with_change_set(mut self, changes: Arc<ChangeSet>) -> Self {
+        self.changes = Some(changes);
+        self
+    }
+
+    /// Текущее содержимое файла с учётом слоя правок; None — файла нет.
+    pub fn file_content(&self, path: &str) -> Option<String> {
+        self.read(path).ok()
+    }
+
+    fn read(&self, path: &str) -> std::io::Result<String> {
+        match &self.changes {
+            Some(changes) => changes.read(path),
+            None => std::fs::read_to_string(self.resolve_path(path)),
+        }
+    }
+
+    fn write(&self, path: &str, content: &str) -> std::io::Result<()> {
+        if let Some(changes) = &self.changes {
+            return changes.write(path, content);
+        }
+        let rel = rel_path(path);
+        // Без снимка запись не выполняется: изменение нельзя было бы откатить.
+        if let Some(snapshot) = &self.snapshot {
+            snapshot.before_write(&rel).map_err(std::io::Error::other)?;
+        }
+        std::fs::write(self.resolve_path(path), content)?;
+        if let Some(snapshot) = &self.snapshot {
+            if let Err(e) = snapshot.after_write(&rel, content.as_bytes()) {
+                tracing::warn!(error = %e, "snapshot update failed");
+            }
+        }
+        Ok(())
+    }
+
+    /// Использовать уже построенный индекс символов проекта.
+    pub fn with_symbol_index(self, index: Arc<SymbolIndex>) -> Self {
+        let _ = self.symbols.set(index);
+        self
     }
 
     /// Выполняет один вызов инструмента.
     pub fn run(&self, call: &ToolCall) -> ToolResult {
-        match call.name.as_str() {
+        let result = match call.name.as_str() {
             "create_file" => self.create_file(call),
             "read_file" => self.read_file(call),
             "list_files" => self.list_files(call),
             "apply_patch" => self.apply_patch(call),
             "update_file" => self.update_file(call),
             "create_project" => self.create_project(call),
+            "find_definition" => self.find_definition(call),
+            "list_symbols" => self.list_symbols(call),
             _ => ToolResult::err(format!("Unknown tool: {}", call.name)),
+        };
+        // Изменённый файл переразбирается, чтобы следующие поиски видели правку.
+        // Правки в слое индекс не видит: он читает диск.
+        if result.success && self.changes.is_none() && matches!(call.name.as_str(), "create_file" | "apply_patch" | "update_file") {
+            let path = call.arguments.get("path").and_then(|v| v.as_str());
+            if let (Some(index), Some(path)) = (self.symbols.get(), path) {
+                let rel = PathBuf::from(path.trim_start_matches('/'));
+                if let Err(e) = index.update_files(&[rel]) {
+                    tracing::warn!(error = %e, "symbol index update failed");
+                }
+            }
         }
+        result
+    }
+
+    fn symbol_index(&self) -> &SymbolIndex {
+        self.symbols.get_or_init(|| {
+            let index = SymbolIndex::open(&self.project_root);
+            if let Err(e) = index.update() {
+                tracing::warn!(error = %e, "symbol index update failed");
+            }
+            Arc::new(index)
+        })
+    }
+
+    /// Где определён символ: `name` или `Type::method`.
+    fn find_definition(&self, call: &ToolCall) -> ToolResult {
+        let name = match call.arguments.get("name").and_then(|v| v.as_str()) {
+            Some(n) if !n.trim().is_empty() => n.trim(),
+            _ => return ToolResult::err("find_definition: missing 'name'"),
+        };
+        let found = self.symbol_index().find_definition(name);
+        if found.is_empty() {
+            return ToolResult::err(format!("find_definition: '{}' not found", name));
+        }
+        let lines: Vec<String> = found.iter().map(|s| s.describe()).collect();
+        ToolResult::ok(lines.join("\n"))
+    }
+
+    /// Символы файла с диапазонами строк.
+    fn list_symbols(&self, call: &ToolCall) -> ToolResult {
+        let path = match call.arguments.get("path").and_then(|v| v.as_str()) {
+            Some(p) => p,
+            None => return ToolResult::err("list_symbols: missing 'path'"),
+        };
+        let full = self.resolve_path(path);
+        if !full.starts_with(&self.project_root) {
+            return ToolResult::err("list_symbols: path outside project");
+        }
+        if !full.is_file() {
+            return ToolResult::err(format!("list_symbols: file not found: {}", path));
+        }
+        let index = self.symbol_index();
+        let rel = Path::new(path.trim_start_matches('/'));
+        // Файл мог измениться вне агента — переразбираем перед ответом.
+        if let Err(e) = index.update_files(&[rel.to_path_buf()]) {
+            return ToolResult::err(format!("list_symbols: {}", e));
+        }
+        let symbols = index.file_symbols(rel);
+        if symbols.is_empty() {
+            return ToolResult::ok(format!("No symbols in {} (unsupported language or empty)", path));
+        }
+        let lines: Vec<String> = symbols.iter().map(|s| s.describe()).collect();
+        ToolResult::ok(lines.join("\n"))
     }
 
     /// Создаёт проект из шаблона в project_root/name.
@@ -44,6 +175,11 @@ impl ToolExecutor {
         let name = call.arguments.get("name").and_then(|v| v.as_str());
         match create_project_from_template(template, &self.project_root, name) {
             Ok(path) => {
+                if let (Some(snapshot), Ok(rel)) = (&self.snapshot, path.strip_prefix(&self.project_root)) {
+                    if let Err(e) = snapshot.record_created_dir(rel) {
+                        tracing::warn!(error = %e, "snapshot of created project failed");
+                    }
+                }
                 let rel = path
                     .strip_prefix(&self.project_root)
                     .map(|p| p.to_string_lossy().into_owned())
@@ -79,12 +215,14 @@ impl ToolExecutor {
             None => return ToolResult::err("create_file: missing 'content'"),
         };
         let full = self.resolve_path(path);
-        if let Some(parent) = full.parent() {
-            if let Err(e) = std::fs::create_dir_all(parent) {
-                return ToolResult::err(format!("create_file: mkdir failed: {}", e));
+        if self.changes.is_none() {
+            if let Some(parent) = full.parent() {
+                if let Err(e) = std::fs::create_dir_all(parent) {
+                    return ToolResult::err(format!("create_file: mkdir failed: {}", e));
+                }
             }
         }
-        match std::fs::write(&full, content) {
+        match self.write(path, content) {
             Ok(()) => ToolResult::ok(format!("Created {}", path)),
             Err(e) => ToolResult::err(format!("create_file: {}", e)),
         }
@@ -99,7 +237,7 @@ impl ToolExecutor {
         if !full.starts_with(&self.project_root) {
             return ToolResult::err("read_file: path outside project");
         }
-        match std::fs::read_to_string(&full) {
+        match self.read(path) {
             Ok(s) => ToolResult::ok(s),
             Err(e) => ToolResult::err(format!("read_file: {}", e)),
         }
@@ -115,19 +253,45 @@ impl ToolExecutor {
         if !full.starts_with(&self.project_root) {
             return ToolResult::err("list_files: path outside project");
         }
-        if !full.is_dir() {
+        let rel = full.strip_prefix(&self.project_root).unwrap_or(&full);
+        let rel = rel_path(&rel.to_string_lossy());
+        let staged = self
+            .changes
+            .as_ref()
+            .map(|c| c.created_paths())
+            .unwrap_or_default();
+        // Каталог может существовать только в слое (новые файлы агента).
+        if !full.is_dir() && !staged.iter().any(|p| p.starts_with(&rel) && p != &rel) {
             return ToolResult::err("list_files: not a directory");
         }
-        let mut entries = Vec::new();
-        match std::fs::read_dir(&full) {
-            Ok(rd) => {
-                for e in rd.flatten() {
-                    let name = e.file_name().to_string_lossy().into_owned();
-                    let kind = if e.path().is_dir() { "dir" } else { "file" };
-                    entries.push(format!("{} ({})", name, kind));
-                }
+        // Игнорируемое (.gitignore, .kengaide/ignore) агенту не показываем.
+        let mut entries: Vec<String> = ProjectWalker::new(&self.project_root)
+            .max_depth(1)
+            .walk_dir(&rel)
+            .map(|e| {
+                let name = e
+                    .path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let kind = if e.is_dir { "dir" } else { "file" };
+                format!("{} ({})", name, kind)
+            })
+            .collect();
+        for path in staged {
+            let Ok(inner) = path.strip_prefix(&rel) else {
+                continue;
+            };
+            let mut parts = inner.components();
+            let Some(first) = parts.next() else {
+                continue;
+            };
+            let name = first.as_os_str().to_string_lossy();
+            let kind = if parts.next().is_some() { "dir" } else { "file" };
+            let entry = format!("{} ({})", name, kind);
+            if !entries.contains(&entry) {
+                entries.push(entry);
             }
-            Err(e) => return ToolResult::err(format!("list_files: {}", e)),
         }
         entries.sort();
         ToolResult::ok(entries.join("\n"))
@@ -153,7 +317,7 @@ impl ToolExecutor {
             return ToolResult::err("apply_patch: path outside project");
         }
 
-        let current = match std::fs::read_to_string(&full) {
+        let current = match self.read(path) {
             Ok(s) => s,
             Err(e) => {
                 if e.kind() == std::io::ErrorKind::NotFound {
@@ -195,7 +359,7 @@ impl ToolExecutor {
         }
 
         let new_content = current.replacen(before, after, 1);
-        match std::fs::write(&full, &new_content) {
+        match self.write(path, &new_content) {
             Ok(()) => ToolResult::ok(format!("Patched {}", path)),
             Err(e) => ToolResult::err(
                 PatchError::IoError {
@@ -221,7 +385,7 @@ impl ToolExecutor {
         if !full.starts_with(&self.project_root) {
             return ToolResult::err("update_file: path outside project");
         }
-        match std::fs::write(&full, content) {
+        match self.write(path, content) {
             Ok(()) => ToolResult::ok(format!("Updated {}", path)),
             Err(e) => ToolResult::err(format!("update_file: {}", e)),
         }
@@ -1,10 +1,18 @@
 //! Agent Tools — инструменты для IDE-агента.
 //!
-//! Минимум: create_file, read_file, list_files, update_file.
-//! Все пути относительно project_root.
+//! Минимум: create_file, read_file, list_files, update_file; навигация: find_definition, list_symbols.
+//! Все пути относительно project_root. Изменения можно копить в `ChangeSet` до принятия пользователем.
+//! `run_verification` проверяет правки сборкой/тестами проекта.
 
 mod executor;
+mod staging;
 mod types;
+mod verify;
 
 pub use executor::ToolExecutor;
+pub use staging::{ChangeSelection, ChangeSet, StagedChange, StagedHunk, StagingError};
 pub use types::{PatchError, ToolCall, ToolResult};
+pub use verify::{
+    load_verification_config, planned_checks, run_verification, CheckOutcome, CheckStatus, Diagnostic, ProjectKind,
+    VerificationConfig, VerificationReport, VerifyCommand,
+};
@@ -0,0 +1,574 @@
+//! Staged change set — виртуальный слой поверх проекта для сессии агента.
+//!
+//! Инструменты читают и пишут через слой: read_file видит незаписанные правки, на диск
+//! ничего не попадает до решения пользователя. В конце пользователь принимает изменения
+//! целиком, по файлам или по отдельным hunk'ам, либо отбрасывает их.
+//!
+//! Слой сессии сохраняется в `.kengaide/staged/<session_id>.json` после каждого изменения:
+//! непринятые правки переживают перезапуск IDE и подхватываются при продолжении сессии.
+
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+
+use backend_core::{SessionSnapshot, SnapshotError};
+use serde::{Deserialize, Serialize};
+
+/// Ошибки принятия изменений.
+#[derive(Debug, thiserror::Error)]
+pub enum StagingError {
+    #[error("No staged changes for {0}")]
+    NotStaged(String),
+    #[error("Hunk {hunk} not found in {path}")]
+    InvalidHunk { path: String, hunk: usize },
+    /// Файл изменился на диске после того, как агент его прочитал.
+    #[error("File changed on disk since it was staged: {0}")]
+    Conflict(String),
+    #[error("IO error for {0}: {1}")]
+    Io(String, std::io::Error),
+    #[error("Snapshot failed: {0}")]
+    Snapshot(#[from] SnapshotError),
+}
+
+/// Файл в слое: исходное содержимое (None — файла не было) и текущее.
+#[derive(Serialize, Deserialize)]
+struct StagedFile {
+    original: Option<String>,
+    content: String,
+}
+
+/// Изменения одной сессии агента поверх project_root.
+pub struct ChangeSet {
+    project_root: PathBuf,
+    files: Mutex<BTreeMap<PathBuf, StagedFile>>,
+    /// Снимок сессии: исходные файлы сохраняются перед записью принятого.
+    snapshot: OnceLock<Arc<SessionSnapshot>>,
+    /// Сессия, под которой слой сохраняется на диск; не задана — слой только в памяти.
+    session_id: OnceLock<String>,
+}
+
+/// Слой на диске.
+#[derive(Serialize, Deserialize)]
+struct PersistedChangeSet {
+    session_id: String,
+    files: BTreeMap<String, StagedFile>,
+}
+
+/// Директория сохранённых слоёв проекта.
+pub fn staged_dir(project_root: &Path) -> PathBuf {
+    project_root.join(".kengaide").join("staged")
+}
+
+/// Hunk изменения для выборочного принятия.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StagedHunk {
+    /// "@@ -a,b +c,d @@"
+    pub header: String,
+    /// Строки hunk'а с префиксами ' ', '-', '+'.
+    pub text: String,
+}
+
+/// Изменение файла: unified diff и hunk'и по порядку.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StagedChange {
+    pub path: String,
+    /// Файла не было на диске.
+    pub created: bool,
+    pub diff: String,
+    pub hunks: Vec<StagedHunk>,
+}
+
+/// Что принять: файл целиком (`hunks: None`) или hunk'и по индексам из `StagedChange::hunks`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeSelection {
+    pub path: String,
+    #[serde(default)]
+    pub hunks: Option<Vec<usize>>,
+}
+
+/// Нормализованный относительный путь: без ведущего '/' и компонент "."
+pub(crate) fn rel_path(path: &str) -> PathBuf {
+    Path::new(path.trim_start_matches('/'))
+        .components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect()
+}
+
+fn display(rel: &Path) -> String {
+    rel.to_string_lossy().replace('\\', "/")
+}
+
+impl ChangeSet {
+    pub fn new(project_root: PathBuf) -> Self {
+        Self {
+            project_root,
+            files: Mutex::new(BTreeMap::new()),
+            snapshot: OnceLock::new(),
+            session_id: OnceLock::new(),
+        }
+    }
+
+    /// Сохранённый слой сессии; None — его нет или он не читается.
+    pub fn load(project_root: &Path, session_id: &str) -> Option<Self> {
+        let path = staged_dir(project_root).join(format!("{}.json", session_id));
+        let raw = std::fs::read_to_string(&path).ok()?;
+        let persisted: PersistedChangeSet = match serde_json::from_str(&raw) {
+            Ok(p) => p,
+            Err(e) => {
+                tracing::warn!(path = %path.display(), error = %e, "staged changes skipped");
+                return None;
+            }
+        };
+        let set = Self::new(project_root.to_path_buf());
+        let _ = set.session_id.set(persisted.session_id);
+        *set.lock() = persisted
+            .files
+            .into_iter()
+            .map(|(path, f)| (rel_path(&path), f))
+            .collect();
+        Some(set)
+    }
+
+    /// Все сохранённые слои проекта с непринятыми изменениями.
+    pub fn load_all(project_root: &Path) -> Vec<Self> {
+        let Ok(rd) = std::fs::read_dir(staged_dir(project_root)) else {
+            return Vec::new();
+        };
+        rd.filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let name = e.file_name().to_string_lossy().into_owned();
+                let session_id = name.strip_suffix(".json")?;
+                Self::load(project_root, session_id)
+            })
+            .filter(|set| !set.is_empty())
+            .collect()
+    }
+
+    /// Сохранять слой на диск под session_id (сразу и после каждого изменения).
+    pub fn persist_as(&self, session_id: &str) {
+        if self.session_id.set(session_id.to_string()).is_ok() {
+            self.save(&self.lock());
+        }
+    }
+
+    /// Сессия, под которой слой сохраняется.
+    pub fn session_id(&self) -> Option<&str> {
+        self.session_id.get().map(String::as_str)
+    }
+
+    /// Пишет слой на диск; без изменений файл удаляется. Ошибка — только в лог:
+    /// слой в памяти остаётся рабочим.
+    fn save(&self, files: &BTreeMap<PathBuf, StagedFile>) {
+        let Some(session_id) = self.session_id.get() else {
+            return;
+        };
+        let dir = staged_dir(&self.project_root);
+        let path = dir.join(format!("{}.json", session_id));
+        if files.values().all(|f| f.original.as_deref() == Some(&f.content)) {
+            if path.exists() {
+                if let Err(e) = std::fs::remove_file(&path) {
+                    tracing::warn!(path = %path.display(), error = %e, "staged changes not removed");
+                }
+            }
+            return;
+        }
+        let persisted = PersistedChangeSet {
+            session_id: session_id.clone(),
+            files: files
+                .iter()
+                .map(|(rel, f)| {
+                    (
+                        display(rel),
+                        StagedFile {
+                            original: f.original.clone(),
+                            content: f.content.clone(),
+                        },
+                    )
+                })
+                .collect(),
+        };
+        let saved = serde_json::to_string(&persisted)
+            .map_err(std::io::Error::other)
+            .and_then(|json| {
+                std::fs::create_dir_all(&dir)?;
+                std::fs::write(&path, json)
+            });
+        if let Err(e) = saved {
+            tracing::warn!(path = %path.display(), error = %e, "staged changes not saved");
+        }
+    }
+
+    /// Корень проекта, поверх которого лежат правки.
+    pub fn root(&self) -> &Path {
+        &self.project_root
+    }
+
+    /// Подключает снимок сессии (session_id известен только после старта агента).
+    pub fn attach_snapshot(&self, snapshot: Arc<SessionSnapshot>) {
+        let _ = self.snapshot.set(snapshot);
+    }
+
+    /// Подключённый снимок сессии.
+    pub fn snapshot(&self) -> Option<Arc<SessionSnapshot>> {
+        self.snapshot.get().cloned()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<PathBuf, StagedFile>> {
+        self.files.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Содержимое файла с учётом незаписанных правок.
+    pub fn read(&self, path: &str) -> std::io::Result<String> {
+        let rel = rel_path(path);
+        if let Some(f) = self.lock().get(&rel) {
+            return Ok(f.content.clone());
+        }
+        std::fs::read_to_string(self.project_root.join(&rel))
+    }
+
+    /// Записывает в слой; исходное содержимое фиксируется при первой записи.
+    pub(crate) fn write(&self, path: &str, content: &str) -> std::io::Result<()> {
+        let rel = rel_path(path);
+        let mut files = self.lock();
+        if let Some(f) = files.get_mut(&rel) {
+            f.content = content.to_string();
+            self.save(&files);
+            return Ok(());
+        }
+        let full = self.project_root.join(&rel);
+        if full.is_dir() {
+            return Err(std::io::Error::other("path is a directory"));
+        }
+        let original = match std::fs::read_to_string(&full) {
+            Ok(s) => Some(s),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        files.insert(
+            rel,
+            StagedFile {
+                original,
+                content: content.to_string(),
+            },
+        );
+        self.save(&files);
+        Ok(())
+    }
+
+    /// Новые (ещё не созданные на диске) файлы — для list_files.
+    pub(crate) fn created_paths(&self) -> Vec<PathBuf> {
+        self.lock()
+            .iter()
+            .filter(|(_, f)| f.original.is_none())
+            .map(|(p, _)| p.clone())
+            .collect()
+    }
+
+    /// Изменённые файлы с текущим содержимым — для проверки в копии проекта.
+    pub(crate) fn staged_files(&self) -> Vec<(PathBuf, String)> {
+        self.lock()
+            .iter()
+            .filter(|(_, f)| f.original.as_deref() != Some(&f.content))
+            .map(|(p, f)| (p.clone(), f.content.clone()))
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().values().all(|f| f.original.as_deref() == Some(&f.content))
+    }
+
+    /// Сводный diff по всем изменённым файлам.
+    pub fn changes(&self) -> Vec<StagedChange> {
+        self.lock()
+            .iter()
+            .filter(|(_, f)| f.original.as_deref() != Some(&f.content))
+            .map(|(rel, f)| {
+                let path = display(rel);
+                let original = f.original.as_deref().unwrap_or("");
+                let patch = diffy::DiffOptions::new()
+                    .set_original_filename(if f.original.is_some() {
+                        format!("a/{}", path)
+                    } else {
+                        "/dev/null".to_string()
+                    })
+                    .set_modified_filename(format!("b/{}", path))
+                    .create_patch(original, &f.content);
+                StagedChange {
+                    created: f.original.is_none(),
+                    diff: patch.to_string(),
+                    hunks: patch.hunks().iter().map(format_hunk).collect(),
+                    path,
+                }
+            })
+            .collect()
+    }
+
+    /// Записывает выбранное на диск. Принятое убирается из слоя; непринятые hunk'и остаются.
+    /// Возвращает пути записанных файлов. Файл, изменённый на диске после чтения агентом, не трогается.
+    pub fn accept(&self, selection: &[ChangeSelection]) -> Result<Vec<String>, StagingError> {
+        let mut files = self.lock();
+        // Сначала проверяем всё, чтобы конфликт или неверный hunk не оставили принятие наполовину.
+        let mut planned = Vec::with_capacity(selection.len());
+        for sel in selection {
+            let rel = rel_path(&sel.path);
+            let Some(f) = files.get(&rel) else {
+                return Err(StagingError::NotStaged(sel.path.clone()));
+            };
+            let full = self.project_root.join(&rel);
+            let on_disk = match std::fs::read_to_string(&full) {
+                Ok(s) => Some(s),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => return Err(StagingError::Io(sel.path.clone(), e)),
+            };
+            if on_disk != f.original {
+                return Err(StagingError::Conflict(sel.path.clone()));
+            }
+            let new_content = match &sel.hunks {
+                None => f.content.clone(),
+                Some(indices) => {
+                    apply_hunks(f.original.as_deref().unwrap_or(""), &f.content, indices)
+                        .map_err(|hunk| StagingError::InvalidHunk {
+                            path: sel.path.clone(),
+                            hunk,
+                        })?
+                }
+            };
+            planned.push((rel, full, new_content));
+        }
+        let mut written = Vec::new();
+        for (rel, full, new_content) in planned {
+            let path = display(&rel);
+            if let Some(snapshot) = self.snapshot.get() {
+                snapshot.before_write(&rel)?;
+            }
+            if let Some(parent) = full.parent() {
+                std::fs::create_dir_all(parent).map_err(|e| StagingError::Io(path.clone(), e))?;
+            }
+            std::fs::write(&full, &new_content).map_err(|e| StagingError::Io(path.clone(), e))?;
+            if let Some(snapshot) = self.snapshot.get() {
+                if let Err(e) = snapshot.after_write(&rel, new_content.as_bytes()) {
+                    tracing::warn!(error = %e, "snapshot update failed");
+                }
+            }
+            written.push(path);
+            match files.get_mut(&rel) {
+                Some(f) if f.content != new_content => f.original = Some(new_content),
+                _ => {
+                    files.remove(&rel);
+                }
+            }
+        }
+        self.save(&files);
+        Ok(written)
+    }
+
+    /// Принимает все изменения.
+    pub fn accept_all(&self) -> Result<Vec<String>, StagingError> {
+        let selection: Vec<ChangeSelection> = self
+            .changes()
+            .into_iter()
+            .map(|c| ChangeSelection {
+                path: c.path,
+                hunks: None,
+            })
+            .collect();
+        self.accept(&selection)
+    }
+
+    /// Отбрасывает изменения файла (None — все).
+    pub fn discard(&self, path: Option<&str>) {
+        let mut files = self.lock();
+        match path {
+            Some(p) => {
+                files.remove(&rel_path(p));
+            }
+            None => files.clear(),
+        }
+        self.save(&files);
+    }
+}
+
+fn format_hunk(hunk: &diffy::Hunk<'_, str>) -> StagedHunk {
+    let mut text = String::new();
+    for line in hunk.lines() {
+        let (prefix, s) = match line {
+            diffy::Line::Context(s) => (' ', *s),
+            diffy::Line::Delete(s) => ('-', *s),
+            diffy::Line::Insert(s) => ('+', *s),
+        };
+        text.push(prefix);
+        text.push_str(s);
+        if !s.ends_with('\n') {
+            text.push('\n');
+        }
+    }
+    StagedHunk {
+        header: format!("@@ -{} +{} @@", hunk.old_range(), hunk.new_range()),
+        text,
+    }
+}
+
+/// Применяет к original только выбранные hunk'и diff(original, modified). Err — индекс вне диапазона.
+fn apply_hunks(original: &str, modified: &str, selected: &[usize]) -> Result<String, usize> {
+    let patch = diffy::create_patch(original, modified);
+    let hunks = patch.hunks();
+    if let Some(&bad) = selected.iter().find(|&&i| i >= hunks.len()) {
+        return Err(bad);
+    }
+    let lines: Vec<&str> = original.split_inclusive('\n').collect();
+    let mut out = String::with_capacity(modified.len());
+    let mut cursor = 0;
+    for (i, hunk) in hunks.iter().enumerate() {
+        let range = hunk.old_range();
+        // Пустой диапазон: start — строка, после которой вставка.
+        let start = if range.is_empty() { range.start() } else { range.start() - 1 };
+        for line in lines.get(cursor..start).unwrap_or_default() {
+            out.push_str(line);
+        }
+        let accept = selected.contains(&i);
+        for line in hunk.lines() {
+            match line {
+                diffy::Line::Context(s) => out.push_str(s),
+                diffy::Line::Delete(s) if !accept => out.push_str(s),
+                diffy::Line::Insert(s) if accept => out.push_str(s),
+                _ => {}
+            }
+        }
+        cursor = start + range.len();
+    }
+    for line in lines.get(cursor..).unwrap_or_default() {
+        out.push_str(line);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Временный проект с файлами; удаляется при drop.
+    struct TempProject(PathBuf);
+
+    impl TempProject {
+        fn new(name: &str, files: &[(&str, &str)]) -> Self {
+            let root = std::env::temp_dir()
+                .join(format!("kengaide-staging-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&root);
+            for (path, content) in files {
+                let full = root.join(path);
+                std::fs::create_dir_all(full.parent().unwrap()).unwrap();
+                std::fs::write(full, content).unwrap();
+            }
+            std::fs::create_dir_all(&root).unwrap();
+            Self(root)
+        }
+
+        fn read(&self, path: &str) -> String {
+            std::fs::read_to_string(self.0.join(path)).unwrap()
+        }
+    }
+
+    impl Drop for TempProject {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    const ORIGINAL: &str = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\n";
+    const MODIFIED: &str = "A\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nL\n";
+
+    #[test]
+    fn partial_accept_keeps_rest_staged() {
+        let project = TempProject::new("partial", &[("src/lib.rs", ORIGINAL)]);
+        let set = ChangeSet::new(project.0.clone());
+        set.write("src/lib.rs", MODIFIED).unwrap();
+        let changes = set.changes();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].hunks.len(), 2);
+
+        let written = set
+            .accept(&[ChangeSelection {
+                path: "src/lib.rs".into(),
+                hunks: Some(vec![0]),
+            }])
+            .unwrap();
+        assert_eq!(written, vec!["src/lib.rs".to_string()]);
+        assert_eq!(project.read("src/lib.rs"), ORIGINAL.replacen('a', "A", 1));
+
+        // Второй hunk остаётся в слое и считается от уже записанного содержимого.
+        let rest = set.changes();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].hunks.len(), 1);
+        assert_eq!(set.read("src/lib.rs").unwrap(), MODIFIED);
+        set.accept_all().unwrap();
+        assert_eq!(project.read("src/lib.rs"), MODIFIED);
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn conflict_when_file_changed_on_disk() {
+        let project = TempProject::new("conflict", &[("a.txt", "one\n"), ("b.txt", "two\n")]);
+        let set = ChangeSet::new(project.0.clone());
+        set.write("a.txt", "one!\n").unwrap();
+        set.write("b.txt", "two!\n").unwrap();
+        std::fs::write(project.0.join("b.txt"), "edited\n").unwrap();
+
+        let err = set.accept_all().unwrap_err();
+        assert!(matches!(err, StagingError::Conflict(p) if p == "b.txt"));
+        // Проверка идёт до записи: a.txt тоже не тронут.
+        assert_eq!(project.read("a.txt"), "one\n");
+        assert_eq!(set.changes().len(), 2);
+    }
+
+    #[test]
+    fn invalid_hunk_rejected() {
+        let project = TempProject::new("hunk", &[("a.txt", ORIGINAL)]);
+        let set = ChangeSet::new(project.0.clone());
+        set.write("a.txt", MODIFIED).unwrap();
+        let err = set
+            .accept(&[ChangeSelection {
+                path: "a.txt".into(),
+                hunks: Some(vec![5]),
+            }])
+            .unwrap_err();
+        assert!(matches!(err, StagingError::InvalidHunk { hunk: 5, .. }));
+        assert_eq!(project.read("a.txt"), ORIGINAL);
+    }
+
+    #[test]
+    fn created_file_written_on_accept() {
+        let project = TempProject::new("created", &[]);
+        let set = ChangeSet::new(project.0.clone());
+        set.write("src/new.rs", "fn main() {}\n").unwrap();
+        assert!(!project.0.join("src/new.rs").exists());
+        assert_eq!(set.created_paths(), vec![PathBuf::from("src/new.rs")]);
+        let changes = set.changes();
+        assert!(changes[0].created);
+        assert!(changes[0].diff.contains("/dev/null"));
+
+        set.accept_all().unwrap();
+        assert_eq!(project.read("src/new.rs"), "fn main() {}\n");
+        assert!(set.is_empty());
+        assert!(set.created_paths().is_empty());
+    }
+
+    #[test]
+    fn persisted_set_survives_reload() {
+        let project = TempProject::new("persist", &[("a.txt", "one\n")]);
+        let set = ChangeSet::new(project.0.clone());
+        set.persist_as("s1");
+        set.write("a.txt", "one!\n").unwrap();
+        set.write("new.txt", "new\n").unwrap();
+
+        let loaded = ChangeSet::load(&project.0, "s1").unwrap();
+        assert_eq!(loaded.session_id(), Some("s1"));
+        assert_eq!(loaded.read("a.txt").unwrap(), "one!\n");
+        assert_eq!(loaded.changes().len(), 2);
+        assert_eq!(ChangeSet::load_all(&project.0).len(), 1);
+
+        // Принятый слой с диска исчезает.
+        loaded.accept_all().unwrap();
+        assert!(ChangeSet::load(&project.0, "s1").is_none());
+        assert!(ChangeSet::load_all(&project.0).is_empty());
+    }
+}
@@ -0,0 +1,641 @@
+//! Проверка изменений агента: сборка/тесты проекта и разбор диагностик.
+//!
+//! Тип проекта определяется по маркерам шаблонов (Cargo.toml, pyproject.toml/requirements.txt,
+//! package.json) в корне или в директории первого уровня. Команды по умолчанию —
+//! `cargo check`, `pytest`, `npm test`; переопределяются в verification.json в
+//! project_root/.kengaide/ или ~/.kengaide/. Незаписанные правки (ChangeSet) проверяются в
+//! копии проекта, диск пользователя не меняется.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{mpsc, OnceLock};
+use std::time::{Duration, Instant};
+
+use backend_core::fs::ProjectWalker;
+
+use crate::staging::ChangeSet;
+
+/// Сколько диагностик одной проверки отдавать модели.
+const MAX_DIAGNOSTICS: usize = 20;
+/// Хвост вывода, если диагностики не разобраны.
+const OUTPUT_TAIL_CHARS: usize = 3000;
+/// Сколько ждать вывод после завершения команды: оставшиеся процессы могут держать pipe'ы.
+const OUTPUT_GRACE: Duration = Duration::from_secs(2);
+/// Сколько вывода одного потока держать в памяти: от болтливой команды остаётся хвост.
+const MAX_STREAM_BYTES: usize = 1024 * 1024;
+
+/// Тип проекта (совпадает с именем шаблона из TEMPLATES).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProjectKind {
+    Rust,
+    Python,
+    Node,
+}
+
+impl ProjectKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProjectKind::Rust => "rust",
+            ProjectKind::Python => "python",
+            ProjectKind::Node => "node",
+        }
+    }
+
+    fn detect(dir: &Path) -> Vec<ProjectKind> {
+        let mut kinds = Vec::new();
+        if dir.join("Cargo.toml").is_file() {
+            kinds.push(ProjectKind::Rust);
+        }
+        if ["pyproject.toml", "setup.py", "requirements.txt"]
+            .iter()
+            .any(|m| dir.join(m).is_file())
+        {
+            kinds.push(ProjectKind::Python);
+        }
+        if dir.join("package.json").is_file() {
+            kinds.push(ProjectKind::Node);
+        }
+        kinds
+    }
+
+    /// Команды по умолчанию; npm test — только если в package.json есть настоящий скрипт test.
+    fn default_commands(&self, dir: &Path) -> Vec<VerifyCommand> {
+        match self {
+            ProjectKind::Rust => vec![VerifyCommand::new("cargo check --message-format short")],
+            // 5 — тестов нет, это не ошибка.
+            ProjectKind::Python => vec![VerifyCommand {
+                command: "pytest -q".to_string(),
+                ok_exit_codes: vec![0, 5],
+            }],
+            ProjectKind::Node => {
+                let has_tests = std::fs::read_to_string(dir.join("package.json"))
+                    .ok()
+                    .and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok())
+                    .and_then(|v| v.pointer("/scripts/test").and_then(|t| t.as_str()).map(String::from))
+                    .is_some_and(|t| !t.contains("no test specified"));
+                if has_tests {
+                    vec![VerifyCommand::new("npm test")]
+                } else {
+                    Vec::new()
+                }
+            }
+        }
+    }
+}
+
+/// Команда проверки. Аргументы разделяются пробелами (без кавычек).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifyCommand {
+    pub command: String,
+    /// Коды выхода, означающие успех.
+    #[serde(default = "default_ok_exit_codes")]
+    pub ok_exit_codes: Vec<i32>,
+}
+
+fn default_ok_exit_codes() -> Vec<i32> {
+    vec![0]
+}
+
+impl VerifyCommand {
+    fn new(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            ok_exit_codes: default_ok_exit_codes(),
+        }
+    }
+}
+
+/// verification.json.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerificationConfig {
+    /// Сколько раз подряд отдавать модели ошибки проверки, прежде чем завершить с ними.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: usize,
+    /// Таймаут одной команды.
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Команды по типу проекта (rust, python, node) вместо команд по умолчанию; [] — не проверять.
+    #[serde(default)]
+    pub commands: HashMap<ProjectKind, Vec<VerifyCommand>>,
+}
+
+fn default_max_attempts() -> usize {
+    3
+}
+
+fn default_timeout_secs() -> u64 {
+    300
+}
+
+impl Default for VerificationConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            timeout_secs: default_timeout_secs(),
+            commands: HashMap::new(),
+        }
+    }
+}
+
+/// Загружает verification.json из project_root/.kengaide/ или ~/.kengaide/.
+pub fn load_verification_config(project_root: Option<&Path>) -> VerificationConfig {
+    let paths = [
+        project_root.map(|p| p.join(".kengaide").join("verification.json")),
+        dirs::home_dir().map(|h| h.join(".kengaide").join("verification.json")),
+    ];
+    for path in paths.into_iter().flatten() {
+        if let Ok(s) = std::fs::read_to_string(&path) {
+            match serde_json::from_str::<VerificationConfig>(&s) {
+                Ok(cfg) => return cfg,
+                Err(e) => tracing::warn!(path = %path.display(), error = %e, "verification config skipped"),
+            }
+        }
+    }
+    VerificationConfig::default()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Passed,
+    Failed,
+    /// Команда не найдена или не уложилась в таймаут — ошибкой кода не считается.
+    Unavailable,
+}
+
+/// Ошибка из вывода проверки.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnostic {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+/// Результат одной команды.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckOutcome {
+    pub kind: ProjectKind,
+    /// Директория проекта относительно корня ("" — корень).
+    pub dir: String,
+    pub command: String,
+    pub status: CheckStatus,
+    pub diagnostics: Vec<Diagnostic>,
+    /// Хвост вывода (stdout + stderr).
+    pub output: String,
+}
+
+/// Итог проверки.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VerificationReport {
+    pub checks: Vec<CheckOutcome>,
+}
+
+impl VerificationReport {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|c| c.status != CheckStatus::Failed)
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.checks
+            .iter()
+            .filter(|c| c.status == CheckStatus::Failed)
+            .map(|c| c.diagnostics.len().max(1))
+            .sum()
+    }
+
+    /// Ошибки для модели: диагностики, а если их не разобрать — хвост вывода.
+    pub fn failures(&self) -> String {
+        let mut s = String::new();
+        for check in self.checks.iter().filter(|c| c.status == CheckStatus::Failed) {
+            let dir = if check.dir.is_empty() { String::new() } else { format!(" (in {})", check.dir) };
+            s.push_str(&format!("$ {}{}\n", check.command, dir));
+            if check.diagnostics.is_empty() {
+                s.push_str(&check.output);
+                s.push('\n');
+            }
+            for d in check.diagnostics.iter().take(MAX_DIAGNOSTICS) {
+                match (&d.file, d.line) {
+                    (Some(file), Some(line)) => s.push_str(&format!("{}:{}: {}\n", file, line, d.message)),
+                    (Some(file), None) => s.push_str(&format!("{}: {}\n", file, d.message)),
+                    _ => s.push_str(&format!("{}\n", d.message)),
+                }
+            }
+            if check.diagnostics.len() > MAX_DIAGNOSTICS {
+                s.push_str(&format!("... and {} more\n", check.diagnostics.len() - MAX_DIAGNOSTICS));
+            }
+        }
+        s.trim_end().to_string()
+    }
+}
+
+/// Проекты для проверки: корень или, если в нём маркеров нет, директории первого уровня.
+fn project_dirs(root: &Path) -> Vec<(ProjectKind, String)> {
+    let at_root = ProjectKind::detect(root);
+    if !at_root.is_empty() {
+        return at_root.into_iter().map(|k| (k, String::new())).collect();
+    }
+    ProjectWalker::new(root)
+        .max_depth(1)
+        .walk()
+        .filter(|e| e.is_dir)
+        .flat_map(|e| {
+            let rel = e.path.to_string_lossy().replace('\\', "/");
+            ProjectKind::detect(&root.join(&e.path))
+                .into_iter()
+                .map(move |k| (k, rel.clone()))
+        })
+        .collect()
+}
+
+/// Команды, которые выполнит проверка (для UI до запуска).
+pub fn planned_checks(project_root: &Path, config: &VerificationConfig) -> Vec<String> {
+    project_dirs(project_root)
+        .into_iter()
+        .flat_map(|(kind, dir)| commands_for(kind, &project_root.join(&dir), config))
+        .map(|c| c.command)
+        .collect()
+}
+
+fn commands_for(kind: ProjectKind, dir: &Path, config: &VerificationConfig) -> Vec<VerifyCommand> {
+    config
+        .commands
+        .get(&kind)
+        .cloned()
+        .unwrap_or_else(|| kind.default_commands(dir))
+}
+
+/// Запускает проверки. С незаписанными правками — в `scratch`: копия проекта + правки слоя
+/// (Rust-сборка использует target/ проекта, node_modules подключается ссылкой).
+pub fn run_verification(
+    project_root: &Path,
+    changes: Option<&ChangeSet>,
+    config: &VerificationConfig,
+    scratch: &Path,
+) -> VerificationReport {
+    let staged = changes.map(|c| c.staged_files()).unwrap_or_default();
+    let work_root = if staged.is_empty() {
+        project_root.to_path_buf()
+    } else {
+        if let Err(e) = prepare_scratch(project_root, scratch, &staged) {
+            tracing::warn!(error = %e, "verification workspace failed");
+            return VerificationReport::default();
+        }
+        scratch.to_path_buf()
+    };
+    let mut report = VerificationReport::default();
+    for (kind, dir) in project_dirs(&work_root) {
+        let cwd = work_root.join(&dir);
+        for cmd in commands_for(kind, &cwd, config) {
+            let mut env = Vec::new();
+            if kind == ProjectKind::Rust && work_root != project_root {
+                env.push(("CARGO_TARGET_DIR", project_root.join(&dir).join("target")));
+            }
+            let (status, output) = run_command(&cmd, &cwd, &env, Duration::from_secs(config.timeout_secs));
+            let diagnostics = if status == CheckStatus::Failed {
+                parse_diagnostics(kind, &output)
+            } else {
+                Vec::new()
+            };
+            report.checks.push(CheckOutcome {
+                kind,
+                dir: dir.clone(),
+                command: cmd.command.clone(),
+                status,
+                diagnostics,
+                output: tail(&output, OUTPUT_TAIL_CHARS),
+            });
+        }
+    }
+    report
+}
+
+/// Синхронизирует копию проекта (только изменившиеся файлы) и накладывает правки слоя.
+fn prepare_scratch(project_root: &Path, scratch: &Path, staged: &[(PathBuf, String)]) -> std::io::Result<()> {
+    std::fs::create_dir_all(scratch)?;
+    for rel in ProjectWalker::new(project_root).allow_hidden(&[".cargo"]).files() {
+        let src = project_root.join(&rel);
+        let dst = scratch.join(&rel);
+        let (Ok(src_meta), dst_meta) = (std::fs::metadata(&src), std::fs::metadata(&dst)) else {
+            continue;
+        };
+        let fresh = dst_meta.is_ok_and(|d| {
+            d.len() == src_meta.len()
+                && matches!((src_meta.modified(), d.modified()), (Ok(s), Ok(d)) if s <= d)
+        });
+        if !fresh {
+            if let Some(parent) = dst.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::copy(&src, &dst)?;
+        }
+    }
+    for (rel, content) in staged {
+        let dst = scratch.join(rel);
+        if let Some(parent) = dst.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(dst, content)?;
+    }
+    link_dir(&project_root.join("node_modules"), &scratch.join("node_modules"));
+    Ok(())
+}
+
+/// Ссылка на тяжёлую директорию зависимостей вместо копии; ошибки не мешают проверке.
+fn link_dir(src: &Path, dst: &Path) {
+    if !src.is_dir() || dst.exists() {
+        return;
+    }
+    #[cfg(unix)]
+    let _ = std::os::unix::fs::symlink(src, dst);
+    #[cfg(windows)]
+    let _ = std::os::windows::fs::symlink_dir(src, dst);
+}
+
+/// Выполняет команду с таймаутом; вывод — stdout и stderr вместе.
+fn run_command(
+    cmd: &VerifyCommand,
+    cwd: &Path,
+    env: &[(&str, PathBuf)],
+    timeout: Duration,
+) -> (CheckStatus, String) {
+    let mut parts = cmd.command.split_whitespace();
+    let Some(program) = parts.next() else {
+        return (CheckStatus::Unavailable, "empty command".to_string());
+    };
+    // На Windows npm и подобные — .cmd-скрипты, их запускает cmd.
+    #[cfg(windows)]
+    let mut command = {
+        let mut c = Command::new("cmd");
+        c.arg("/C").arg(program);
+        c
+    };
+    #[cfg(not(windows))]
+    let mut command = Command::new(program);
+    command
+        .args(parts)
+        .current_dir(cwd)
+        .envs(env.iter().map(|(k, v)| (*k, v)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    // Своя группа процессов: по таймауту завершается всё дерево (cargo → rustc, npm → node).
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    #[cfg(windows)]
+    std::os::windows::process::CommandExt::creation_flags(&mut command, CREATE_NEW_PROCESS_GROUP);
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => return (CheckStatus::Unavailable, format!("{}: {}", program, e)),
+    };
+    let (tx, rx) = mpsc::channel();
+    let pipes = [
+        child.stdout.take().map(|s| Box::new(s) as Box<dyn Read + Send>),
+        child.stderr.take().map(|s| Box::new(s) as Box<dyn Read + Send>),
+    ];
+    let readers = pipes.iter().flatten().count();
+    for (i, mut pipe) in pipes.into_iter().flatten().enumerate() {
+        let tx = tx.clone();
+        std::thread::spawn(move || {
+            let _ = tx.send((i, read_tail(&mut pipe, MAX_STREAM_BYTES)));
+        });
+    }
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Some(status),
+            Ok(None) if started.elapsed() < timeout => std::thread::sleep(Duration::from_millis(100)),
+            _ => {
+                kill_tree(&mut child);
+                let _ = child.wait();
+                break None;
+            }
+        }
+    };
+    // Читатели не ждутся бесконечно: pipe может держать процесс вне группы.
+    let deadline = Instant::now() + OUTPUT_GRACE;
+    let mut outputs: Vec<(usize, String)> = Vec::with_capacity(readers);
+    while outputs.len() < readers {
+        match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(out) => outputs.push(out),
+            Err(_) => break,
+        }
+    }
+    outputs.sort_by_key(|(i, _)| *i);
+    let output: String = outputs
+        .into_iter()
+        .map(|(_, s)| s)
+        .filter(|s| !s.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    match status.and_then(|s| s.code()) {
+        None => (
+            CheckStatus::Unavailable,
+            format!("{}\n(timed out after {}s)", output, timeout.as_secs()),
+        ),
+        // Команда не найдена оболочкой.
+        Some(127) if cfg!(unix) => (CheckStatus::Unavailable, output),
+        Some(9009) if cfg!(windows) => (CheckStatus::Unavailable, output),
+        Some(code) if cmd.ok_exit_codes.contains(&code) => (CheckStatus::Passed, output),
+        Some(_) => (CheckStatus::Failed, output),
+    }
+}
+
+#[cfg(windows)]
+const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+
+/// Завершает команду вместе с дочерними процессами.
+fn kill_tree(child: &mut std::process::Child) {
+    // Группа создана при запуске: её id — pid лидера.
+    #[cfg(unix)]
+    let killed = Command::new("kill")
+        .args(["-KILL", "--", &format!("-{}", child.id())])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    #[cfg(windows)]
+    let killed = Command::new("taskkill")
+        .args(["/T", "/F", "/PID", &child.id().to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    #[cfg(any(unix, windows))]
+    if killed.is_ok_and(|s| s.success()) {
+        return;
+    }
+    let _ = child.kill();
+}
+
+/// Дочитывает поток до конца (иначе команда встанет на записи в pipe), оставляя последние
+/// `limit` байт.
+fn read_tail(pipe: &mut dyn Read, limit: usize) -> String {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 8192];
+    let mut dropped = 0usize;
+    loop {
+        match pipe.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        }
+        // Сдвиг раз в `limit` байт, а не на каждом чтении.
+        if buf.len() >= limit * 2 {
+            let excess = buf.len() - limit;
+            buf.drain(..excess);
+            dropped += excess;
+        }
+    }
+    if buf.len() > limit {
+        let excess = buf.len() - limit;
+        buf.drain(..excess);
+        dropped += excess;
+    }
+    if dropped == 0 {
+        return String::from_utf8_lossy(&buf).into_owned();
+    }
+    // Начало могло прийтись на середину символа.
+    let start = buf.iter().position(|b| (b & 0xC0) != 0x80).unwrap_or(buf.len());
+    format!(
+        "… ({} KB of earlier output dropped)\n{}",
+        (dropped + start) / 1024,
+        String::from_utf8_lossy(&buf[start..])
+    )
+}
+
+fn tail(s: &str, max: usize) -> String {
+    let s = s.trim_end();
+    match s.char_indices().rev().nth(max) {
+        Some((start, _)) => format!("…{}", &s[start..]),
+        None => s.to_string(),
+    }
+}
+
+fn cargo_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    // src/main.rs:3:5: error[E0425]: cannot find value `x` in this scope
+    RE.get_or_init(|| {
+        Regex::new(r"^(?P<file>[^\s:][^:]*):(?P<line>\d+):\d+: (?P<msg>error(?:\[\w+\])?: .+)$").expect("valid regex")
+    })
+}
+
+fn pytest_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    // FAILED tests/test_app.py::test_sum - assert 3 == 4
+    RE.get_or_init(|| {
+        Regex::new(r"^(?:FAILED|ERROR) (?P<file>[^\s:]+)(?:::(?P<test>\S+))?(?: - (?P<msg>.+))?$").expect("valid regex")
+    })
+}
+
+/// Ошибки из вывода: cargo (--message-format short) и pytest; для остального — пусто (берётся хвост вывода).
+fn parse_diagnostics(kind: ProjectKind, output: &str) -> Vec<Diagnostic> {
+    let lines = output.lines().map(str::trim_end);
+    match kind {
+        ProjectKind::Rust => lines
+            .filter_map(|l| cargo_regex().captures(l))
+            .map(|c| Diagnostic {
+                file: Some(c["file"].to_string()),
+                line: c["line"].parse().ok(),
+                message: c["msg"].to_string(),
+            })
+            .collect(),
+        ProjectKind::Python => lines
+            .filter_map(|l| pytest_regex().captures(l))
+            .map(|c| Diagnostic {
+                file: Some(c["file"].to_string()),
+                line: None,
+                message: match (c.name("test"), c.name("msg")) {
+                    (Some(t), Some(m)) => format!("{}: {}", t.as_str(), m.as_str()),
+                    (Some(t), None) => format!("{} failed", t.as_str()),
+                    (None, Some(m)) => m.as_str().to_string(),
+                    (None, None) => "collection error".to_string(),
+                },
+            })
+            .collect(),
+        ProjectKind::Node => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cargo_errors() {
+        let output = "\
+    Checking app v0.1.0 (/tmp/app)
+src/main.rs:3:5: error[E0425]: cannot find value `x` in this scope
+src/lib.rs:10:1: warning: unused import: `std::fs`
+src/lib.rs:12:9: error: expected one of `;` or `}`
+error: could not compile `app` (bin \"app\") due to 2 previous errors
+";
+        let diagnostics = parse_diagnostics(ProjectKind::Rust, output);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].file.as_deref(), Some("src/main.rs"));
+        assert_eq!(diagnostics[0].line, Some(3));
+        assert_eq!(diagnostics[0].message, "error[E0425]: cannot find value `x` in this scope");
+        assert_eq!(diagnostics[1].file.as_deref(), Some("src/lib.rs"));
+        assert_eq!(diagnostics[1].line, Some(12));
+        assert_eq!(diagnostics[1].message, "error: expected one of `;` or `}`");
+    }
+
+    #[test]
+    fn parses_pytest_failures() {
+        let output = "\
+tests/test_app.py F.                                                    [100%]
+FAILED tests/test_app.py::test_sum - assert 3 == 4
+FAILED tests/test_app.py::test_empty
+ERROR tests/test_broken.py - ImportError: no module named app
+ERROR tests/test_other.py
+=========================== 2 failed, 1 passed in 0.12s ============================
+";
+        let diagnostics = parse_diagnostics(ProjectKind::Python, output);
+        let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "test_sum: assert 3 == 4",
+                "test_empty failed",
+                "ImportError: no module named app",
+                "collection error",
+            ]
+        );
+        assert_eq!(diagnostics[0].file.as_deref(), Some("tests/test_app.py"));
+        assert_eq!(diagnostics[2].file.as_deref(), Some("tests/test_broken.py"));
+        assert!(diagnostics.iter().all(|d| d.line.is_none()));
+    }
+
+    #[test]
+    fn node_output_has_no_diagnostics() {
+        assert!(parse_diagnostics(ProjectKind::Node, "src/a.ts:1:1: error: x").is_empty());
+    }
+
+    #[test]
+    fn read_tail_keeps_last_bytes() {
+        let small = b"short output".to_vec();
+        assert_eq!(read_tail(&mut small.as_slice(), 64), "short output");
+
+        let mut big = vec![b'a'; 10_000];
+        big.extend_from_slice(b"the end");
+        let out = read_tail(&mut big.as_slice(), 100);
+        assert!(out.starts_with("… ("));
+        assert!(out.ends_with("the end"));
+        assert_eq!(out.lines().nth(1).unwrap().len(), 100);
+    }
+
+    #[test]
+    fn read_tail_skips_split_character() {
+        // Граница отсечения попадает внутрь двухбайтового «ж».
+        let mut data = "жжжж".as_bytes().to_vec();
+        data.extend_from_slice(b"ok");
+        let out = read_tail(&mut data.as_slice(), 5);
+        assert_eq!(out.lines().nth(1), Some("жok"));
+        assert!(!out.contains('\u{FFFD}'));
+    }
+}
@@ -0,0 +1,157 @@
+//! ApiEmbeddingProvider — OpenAI-совместимый `/v1/embeddings` (OpenAI, Mistral, локальные серверы).
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::traits::{EmbeddingProvider, ProviderError, ProviderType};
+
+/// Лимит OpenAI: до 2048 входов в запросе; берём меньше, чтобы не упираться в лимит токенов.
+const DEFAULT_MAX_BATCH: usize = 256;
+
+#[derive(Debug, Serialize)]
+struct EmbeddingsRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dimensions: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+pub struct ApiEmbeddingProvider {
+    id: String,
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+    /// Запрошенная размерность (text-embedding-3-* умеют укорачивать вектор).
+    requested_dimensions: Option<usize>,
+    /// Фактическая размерность: из конфига или из первого ответа (0 — неизвестна).
+    dimensions: AtomicUsize,
+    http: reqwest::Client,
+}
+
+impl ApiEmbeddingProvider {
+    pub fn new(
+        id: impl Into<String>,
+        base_url: impl Into<String>,
+        api_key: Option<String>,
+        model: impl Into<String>,
+        dimensions: Option<usize>,
+    ) -> Self {
+        Self {
+            id: id.into(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            api_key,
+            model: model.into(),
+            requested_dimensions: dimensions,
+            dimensions: AtomicUsize::new(dimensions.unwrap_or(0)),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// OpenAI text-embedding-3-small (1536).
+    pub fn openai(api_key: Option<String>) -> Self {
+        Self {
+            dimensions: AtomicUsize::new(1536),
+            ..Self::new(
+                "cloud-openai-embed",
+                "https://api.openai.com/v1",
+                api_key,
+                "text-embedding-3-small",
+                None,
+            )
+        }
+    }
+
+    /// Mistral mistral-embed (1024).
+    pub fn mistral(api_key: Option<String>) -> Self {
+        Self {
+            dimensions: AtomicUsize::new(1024),
+            ..Self::new(
+                "cloud-mistral-embed",
+                "https://api.mistral.ai/v1",
+                api_key,
+                "mistral-embed",
+                None,
+            )
+        }
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for ApiEmbeddingProvider {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn provider_type(&self) -> ProviderType {
+        ProviderType::Cloud
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        match self.dimensions.load(Ordering::Relaxed) {
+            0 => None,
+            n => Some(n),
+        }
+    }
+
+    fn max_batch_size(&self) -> usize {
+        DEFAULT_MAX_BATCH
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        if inputs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut request = self
+            .http
+            .post(format!("{}/embeddings", self.base_url))
+            .json(&EmbeddingsRequest {
+                model: &self.model,
+                input: inputs,
+                dimensions: self.requested_dimensions,
+            });
+        if let Some(ref key) = self.api_key {
+            request = request.bearer_auth(key);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| ProviderError::Unavailable(e.to_string()))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(ProviderError::Generation(format!(
+                "status {}: {}",
+                status, body
+            )));
+        }
+        let mut body: EmbeddingsResponse = response
+            .json()
+            .await
+            .map_err(|e| ProviderError::Generation(e.to_string()))?;
+        body.data.sort_by_key(|d| d.index);
+
+        if let Some(first) = body.data.first() {
+            self.dimensions
+                .store(first.embedding.len(), Ordering::Relaxed);
+        }
+        Ok(body.data.into_iter().map(|d| d.embedding).collect())
+    }
+
+    async fn is_available(&self) -> Result<bool, ProviderError> {
+        // Локальные OpenAI-совместимые серверы работают без ключа.
+        Ok(self.api_key.is_some() || !self.base_url.starts_with("https://api."))
+    }
+}
@@ -3,10 +3,12 @@
 //! Provider не знает контекст IDE — только получает готовый prompt и генерирует ответ.
 
 mod api_provider;
+mod embeddings;
 mod traits;
 
 pub use api_provider::ApiProvider;
+pub use embeddings::ApiEmbeddingProvider;
 pub use traits::{
-    AiChunk, AiChunkStream, AiMode, AiProvider, AiResponse, EditorContext, GenerateOptions,
-    GenerateRequest, ProviderCapabilities, ProviderError, ProviderType,
+    AiChunk, AiChunkStream, AiMode, AiProvider, AiResponse, EditorContext, EmbeddingProvider,
+    GenerateOptions, GenerateRequest, ProviderCapabilities, ProviderError, ProviderType,
 };
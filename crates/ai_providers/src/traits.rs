@@ -18,6 +18,8 @@ use std::pin::Pin;
 pub enum AiChunk {
     /// Генерация началась.
     Start,
+    /// Запрос ждёт в очереди провайдера (1 — следующий на выполнение).
+    Queued { position: usize },
     /// Очередной токен текста.
     Token { value: String },
     /// Генерация завершена успешно.
@@ -137,10 +139,58 @@ pub trait AiProvider: Send + Sync {
     }
 }
 
+// ---------------------------------------------------------------------------
+// Embeddings (семантический поиск, RAG)
+// ---------------------------------------------------------------------------
+
+/// Провайдер эмбеддингов: текст → вектор фиксированной размерности.
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// Уникальный id (local-embed, cloud-openai-embed, …).
+    fn id(&self) -> &str;
+
+    /// Локальный или облачный (облачным нельзя отдавать код без согласия пользователя).
+    fn provider_type(&self) -> ProviderType;
+
+    /// Размерность векторов. None — станет известна после первого запроса.
+    fn dimensions(&self) -> Option<usize>;
+
+    /// Максимум текстов в одном запросе к модели/API.
+    fn max_batch_size(&self) -> usize;
+
+    /// Эмбеддинги для пачки текстов (не больше max_batch_size), в том же порядке.
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError>;
+
+    /// Доступность (модель скачана, API ключ есть и т.д.).
+    async fn is_available(&self) -> Result<bool, ProviderError>;
+
+    /// Эмбеддинги для любого числа текстов: режет на пачки по max_batch_size.
+    async fn embed_all(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        let mut out = Vec::with_capacity(inputs.len());
+        for batch in inputs.chunks(self.max_batch_size().max(1)) {
+            let vectors = self.embed(batch).await?;
+            if vectors.len() != batch.len() {
+                return Err(ProviderError::Generation(format!(
+                    "expected {} embeddings, got {}",
+                    batch.len(),
+                    vectors.len()
+                )));
+            }
+            out.extend(vectors);
+        }
+        Ok(out)
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum ProviderError {
     #[error("Provider unavailable: {0}")]
     Unavailable(String),
     #[error("Generation failed: {0}")]
     Generation(String),
+    #[error("Prompt too long: {prompt_tokens} tokens, context window {context_tokens}")]
+    PromptTooLong {
+        prompt_tokens: usize,
+        context_tokens: usize,
+    },
 }
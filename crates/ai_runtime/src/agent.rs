@@ -2,21 +2,37 @@
 //!
 //! Только режим Agent подключает tools и этот цикл.
 
-use agent_tools::{ToolCall, ToolExecutor};
-use ai_providers::{AiChunk, AiMode, AiProvider, EditorContext, GenerateOptions, GenerateRequest};
+use agent_tools::{
+    run_verification, ChangeSet, CheckOutcome, StagedChange, ToolCall, ToolExecutor, ToolResult,
+    VerificationConfig, VerificationReport,
+};
+use ai_providers::{
+    AiChunk, AiMode, AiProvider, EditorContext, GenerateOptions, GenerateRequest, ProviderError,
+};
 use backend_core::{
-    append_audit_event, append_log, current_environment, finish_session_meta, save_session_meta,
-    AuditEvent, AuditSessionMeta,
+    append_audit_event, append_log, current_environment, finish_session_meta, load_transcript,
+    resume_session_meta, save_session_meta, save_transcript, AgentTranscript, AuditEvent,
+    AuditSessionMeta, SessionSnapshot, TranscriptMessage,
 };
+use context_manager::{SemanticIndex, SymbolIndex};
 use futures_util::StreamExt;
 use mcp_provider::{McpContextProvider, McpToolDescriptor, McpToolRegistry};
+use std::future::Future;
 use std::path::Path;
-use std::sync::Arc;
-use std::time::Instant;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
+use crate::approval::{proposed_diff, AgentApproval, ApprovalDecision, PendingApproval, READ_ONLY_TOOLS};
+use crate::compaction::{self, Compaction};
+use crate::delegation::{DelegateRequest, Delegation, DELEGATE_TOOL, DELEGATE_TOOL_PROMPT};
 use crate::error::AiRuntimeError;
+use crate::guardrails::{load_guardrails, ErrorStreak};
+use crate::orchestration::TaskRole;
+use crate::planning::{parse_plan, AgentPlan, AgentPlanning, PendingPlan, PlanDecision, PlanStep, PLANNING_PROMPT};
 use crate::provider_selector::ProviderSelector;
+use crate::redaction::{record_redaction, Placeholders, Redactor};
 
 fn emit_session_end(project_root: Option<&Path>, session_id: &str, status: &str) {
     append_audit_event(
@@ -29,16 +45,96 @@ fn emit_session_end(project_root: Option<&Path>, session_id: &str, status: &str)
     finish_session_meta(project_root, session_id, status);
 }
 
+/// Разговор с моделью: префикс (системный промпт + MCP-контекст) и сообщения сессии.
+/// Префикс строится заново при каждом запуске, сообщения сохраняются в `.kengaide/sessions/`.
+struct Conversation {
+    prefix: String,
+    transcript: AgentTranscript,
+    /// Под-агент не сохраняет разговор: продолжить можно только сессию родителя.
+    persist: bool,
+}
+
+impl Conversation {
+    fn push(&mut self, message: TranscriptMessage) {
+        self.transcript.messages.push(message);
+    }
+
+    /// Промпт для очередного хода: сообщения в текстовом формате агента + "Assistant: ".
+    fn prompt(&self) -> String {
+        let mut s = self.prefix.clone();
+        for m in &self.transcript.messages {
+            match m {
+                TranscriptMessage::User { content } => s.push_str(&format!("\n\nUser: {}", content)),
+                TranscriptMessage::Assistant { content } => {
+                    s.push_str(&format!("\n\nAssistant: {}", content))
+                }
+                // Вызов уже есть в ответе модели; отдельная запись — для истории.
+                TranscriptMessage::ToolCall { .. } => {}
+                TranscriptMessage::ToolResult { success, output } => s.push_str(&format!(
+                    "\n\nTool result: {}{}",
+                    if *success { "OK. " } else { "ERROR. " },
+                    output
+                )),
+                TranscriptMessage::System { content } => s.push_str(&format!("\n\n[System: {}]", content)),
+                TranscriptMessage::Summary { content } => {
+                    s.push_str(&format!("\n\n[Summary of earlier conversation:\n{}]", content))
+                }
+            }
+        }
+        s.push_str("\n\nAssistant: ");
+        s
+    }
+
+    /// Сжимает сообщения, если промпт занимает больше COMPACT_AT окна модели.
+    fn compact(&mut self, context_tokens: usize) -> Option<Compaction> {
+        let available = (context_tokens.saturating_sub(RESPONSE_MAX_TOKENS) * CHARS_PER_TOKEN)
+            .saturating_sub(self.prefix.len());
+        let used: usize = self.prompt().len() - self.prefix.len();
+        if used * 100 <= available * COMPACT_AT_PERCENT {
+            return None;
+        }
+        compaction::compact(&mut self.transcript.messages, available * COMPACT_TARGET_PERCENT / 100)
+    }
+
+    fn save(&self, project_root: &Path) {
+        if !self.persist {
+            return;
+        }
+        if let Err(e) = save_transcript(project_root, &self.transcript) {
+            tracing::warn!(error = %e, "agent session save failed");
+        }
+    }
+}
+
+/// Текст для разговора с моделью: без секретов, если провайдер облачный; счётчики — в аудит.
+/// UI и инструменты получают исходный текст.
+fn redact_for_prompt(
+    redactor: Option<&Redactor>,
+    project_root: Option<&Path>,
+    session_id: &str,
+    text: &str,
+) -> String {
+    let Some(redactor) = redactor else {
+        return text.to_string();
+    };
+    let (redacted, report) = redactor.redact(text);
+    record_redaction(project_root, "agent.log", session_id, &report);
+    redacted
+}
+
 /// Максимум символов MCP-контекста (~25% от типичного лимита контекста).
 const MCP_CONTEXT_MAX_CHARS: usize = 6000;
-/// Максимум вызовов инструментов на одно сообщение пользователя.
-const MAX_TOOL_CALLS_PER_MESSAGE: usize = 8;
-/// Максимум токенов за сессию (приблизительно: ~4 символа на токен).
-const MAX_TOKENS_PER_SESSION: usize = 32_000;
-/// Максимум времени работы агента (мс). 10 мин.
-const MAX_TIME_MS: u64 = 600_000;
-/// Сколько одинаковых ошибок подряд = зацикливание.
-const SAME_ERROR_THRESHOLD: usize = 3;
+/// Окно модели, если провайдер его не сообщает.
+const DEFAULT_CONTEXT_TOKENS: usize = 8192;
+/// Максимум токенов ответа модели за ход.
+const RESPONSE_MAX_TOKENS: usize = 4096;
+/// Оценка: ~4 символа на токен.
+const CHARS_PER_TOKEN: usize = 4;
+/// Максимум токенов ответа модели в фазе планирования.
+const PLAN_MAX_TOKENS: usize = 1024;
+/// Сжимать разговор, когда он занял столько процентов окна, — до COMPACT_TARGET_PERCENT.
+const COMPACT_AT_PERCENT: usize = 80;
+const COMPACT_TARGET_PERCENT: usize = 50;
 
 /// База системного промпта без списка инструментов (список строится динамически).
 const AGENT_SYSTEM_PROMPT_BASE: &str = r#"You are an IDE agent. Your goal is to MODIFY THE PROJECT using tools. NEVER output code as plain text — ALWAYS use tool_call.
@@ -49,6 +145,7 @@ Rules:
 3. For new projects: create_project first, then MUST implement with create_file/apply_patch. Never stop with just skeleton.
 4. For existing files: use ONLY apply_patch. Never overwrite a file wholly.
 5. After each tool result, send the next tool_call until done.
+6. You may put several tool_call blocks in one response (e.g. read three files at once); they run in order and all results come back together.
 
 "#;
 
@@ -59,8 +156,11 @@ pub fn build_agent_system_prompt(mcp_tools: &[McpToolDescriptor]) -> String {
     s.push_str("- create_project(template: string, name?: string) — create skeleton only. After this you MUST use create_file/apply_patch to add the actual implementation. Never stop with just hello world.\n");
     s.push_str("- list_files(path?: string) — list directory contents\n");
     s.push_str("- read_file(path: string) — read file content\n");
+    s.push_str("- find_definition(name: string) — where a function/struct/class/method is defined (file and lines); use \"Type::method\" for methods\n");
+    s.push_str("- list_symbols(path: string) — functions, types and methods of a file with line ranges\n");
     s.push_str("- create_file(path: string, content: string) — create new file (or overwrite only when creating from scratch)\n");
     s.push_str("- apply_patch(path: string, before: string, after: string) — apply contextual diff: exact \"before\" block is replaced by \"after\" (once). Use for all edits to existing files.\n");
+    s.push_str(DELEGATE_TOOL_PROMPT);
     for t in mcp_tools {
         let desc = t
             .description
@@ -91,6 +191,10 @@ or for MCP tools (use exact name mcp::server::tool):
 ```tool_call
 {"name": "mcp::server::tool", "arguments": {...}}
 ```
+or to hand a large, separable part of the task to a sub-agent:
+```tool_call
+{"name": "delegate", "arguments": {"task": "precise instruction", "paths": ["src/ui"]}}
+```
 
 If the task is unclear, ask ONE clarifying question.
 Otherwise, proceed immediately."#);
@@ -124,6 +228,8 @@ You have access to the following tools:
 - create_project(template: string, name?: string) — create new project from template (empty|rust|python|node). Use ONLY this for new projects. If path exists, returns success — use list_files/read_file to work with it.
 - list_files(path?: string) — list directory contents
 - read_file(path: string) — read file content
+- find_definition(name: string) — where a function/struct/class/method is defined (file and lines); use "Type::method" for methods
+- list_symbols(path: string) — functions, types and methods of a file with line ranges
 - create_file(path: string, content: string) — create new file (or overwrite only when creating from scratch)
 - apply_patch(path: string, before: string, after: string) — apply contextual diff: exact "before" block is replaced by "after" (once). Use for all edits to existing files.
 
@@ -139,6 +245,7 @@ or for edits:
 ```tool_call
 {"name": "apply_patch", "arguments": {"path": "relative/path", "before": "exact text to find", "after": "replacement"}}
 ```
+Several independent tool_call blocks may be sent in one response; results come back in the same order.
 
 If the task is unclear, ask ONE clarifying question.
 Otherwise, proceed immediately."#;
@@ -162,18 +269,276 @@ fn extract_json_object(s: &str) -> Option<&str> {
     None
 }
 
-/// Парсит из ответа модели один вызов инструмента (первый найденный).
-/// Формат: ```tool_call\n{ "name": "...", "arguments": {...} }\n``` или без закрывающего ```.
+/// Парсит из ответа модели первый вызов инструмента.
 pub fn parse_tool_call(response: &str) -> Option<ToolCall> {
-    let start = response.find(TOOL_CALL_MARKER)?;
-    let after_marker = response[start + TOOL_CALL_MARKER.len()..].trim_start();
-    let json_str = if let Some(end_pos) = after_marker.find(TOOL_CALL_END) {
-        after_marker[..end_pos].trim()
-    } else {
-        extract_json_object(after_marker)?.trim()
+    parse_tool_calls(response).into_iter().next()
+}
+
+/// Парсит из ответа модели все вызовы инструментов по порядку; некорректные блоки пропускаются.
+/// Формат: ```tool_call\n{ "name": "...", "arguments": {...} }\n``` (у последнего можно без закрывающего ```).
+pub fn parse_tool_calls(response: &str) -> Vec<ToolCall> {
+    parse_planned_calls(response).into_iter().map(|(call, _)| call).collect()
+}
+
+/// Вызовы вместе с номером шага плана (`"step": N` рядом с name).
+fn parse_planned_calls(response: &str) -> Vec<(ToolCall, Option<usize>)> {
+    let mut calls = Vec::new();
+    let mut rest = response;
+    while let Some(start) = rest.find(TOOL_CALL_MARKER) {
+        let after_marker = rest[start + TOOL_CALL_MARKER.len()..].trim_start();
+        let json_str = match after_marker.find(TOOL_CALL_END) {
+            Some(end_pos) => {
+                rest = &after_marker[end_pos + TOOL_CALL_END.len()..];
+                after_marker[..end_pos].trim()
+            }
+            // Без закрывающего ``` дальше блоков нет.
+            None => {
+                rest = "";
+                match extract_json_object(after_marker) {
+                    Some(json) => json.trim(),
+                    None => break,
+                }
+            }
+        };
+        if let Ok(call) = serde_json::from_str::<ToolCall>(json_str) {
+            let step = serde_json::from_str::<serde_json::Value>(json_str)
+                .ok()
+                .and_then(|v| v.get("step").and_then(|n| n.as_u64()))
+                .map(|n| n as usize);
+            calls.push((call, step));
+        }
+    }
+    calls
+}
+
+fn call_path(call: &ToolCall) -> Option<String> {
+    call.arguments.get("path").and_then(|v| v.as_str()).map(String::from)
+}
+
+/// Только читающий вызов: встроенный (READ_ONLY_TOOLS) или MCP с readOnlyHint.
+fn is_read_only(call: &ToolCall, mcp_tools: &[McpToolDescriptor]) -> bool {
+    READ_ONLY_TOOLS.contains(&call.name.as_str())
+        || mcp_tools
+            .iter()
+            .any(|t| t.read_only && t.namespaced_name() == call.name)
+}
+
+/// Результат одного вызова хода.
+struct CallOutcome {
+    call: ToolCall,
+    /// Шаг плана, над которым работал вызов.
+    step: Option<usize>,
+    success: bool,
+    output: String,
+    /// Выполнен с аргументами, исправленными пользователем.
+    edited: bool,
+    rejected: bool,
+}
+
+async fn call_mcp_tool(registry: Option<&McpToolRegistry>, call: &ToolCall) -> (bool, String) {
+    let Some(reg) = registry else {
+        return (false, "MCP not configured".to_string());
+    };
+    match reg.call_tool(&call.name, call.arguments.clone()).await {
+        Ok(val) => {
+            let out = if val.is_string() {
+                val.as_str().unwrap_or("").to_string()
+            } else if let Some(c) = val.get("content") {
+                c.as_str()
+                    .map(String::from)
+                    .unwrap_or_else(|| c.to_string())
+            } else {
+                val.to_string()
+            };
+            (true, out)
+        }
+        Err(e) => {
+            tracing::debug!(tool = %call.name, error = %e, "MCP tool call failed");
+            (false, format!("MCP error: {}", e))
+        }
+    }
+}
+
+/// Полный ответ модели на вспомогательный промпт (фаза планирования).
+async fn generate_text(
+    provider: &Arc<dyn AiProvider>,
+    prompt: String,
+    max_tokens: usize,
+) -> Result<String, AiRuntimeError> {
+    let request = GenerateRequest {
+        id: Uuid::new_v4().to_string(),
+        prompt,
+        context: Some(EditorContext::default()),
+        mode: AiMode::Agent,
+    };
+    let options = GenerateOptions {
+        temperature: Some(0.2),
+        max_tokens: Some(max_tokens),
+    };
+    let mut stream = provider.generate(request, options).await?;
+    let mut out = String::new();
+    while let Some(chunk) = stream.next().await {
+        match chunk {
+            AiChunk::Token { value } => out.push_str(&value),
+            AiChunk::End => break,
+            AiChunk::Error { error } => return Err(ProviderError::Generation(error).into()),
+            AiChunk::Start | AiChunk::Queued { .. } => {}
+        }
+    }
+    Ok(out.trim().to_string())
+}
+
+/// Вызов для выполнения: плейсхолдеры секретов в аргументах заменены исходными значениями.
+/// В разговор с моделью и в транскрипт идёт исходный вызов с плейсхолдерами.
+fn restore_call(placeholders: &Placeholders, call: &ToolCall) -> ToolCall {
+    ToolCall {
+        name: call.name.clone(),
+        arguments: placeholders.restore_json(&call.arguments),
+    }
+}
+
+/// Локальный инструмент в blocking-пуле: файловый ввод-вывод не занимает поток runtime.
+async fn run_local(executor: &Arc<ToolExecutor>, call: ToolCall) -> ToolResult {
+    let executor = Arc::clone(executor);
+    tokio::task::spawn_blocking(move || executor.run(&call))
+        .await
+        .unwrap_or_else(|_| ToolResult::err("Tool execution panicked"))
+}
+
+/// Выполняет читающие вызовы одновременно: локальные — в blocking-пуле, MCP — конкурентными
+/// запросами параллельно с ними. Результаты — в порядке вызовов.
+async fn run_reads(
+    executor: &Arc<ToolExecutor>,
+    registry: Option<&McpToolRegistry>,
+    calls: &[ToolCall],
+) -> Vec<(bool, String)> {
+    let (mcp, local): (Vec<&ToolCall>, Vec<&ToolCall>) = calls.iter().partition(|c| c.name.starts_with("mcp::"));
+    let mcp_results = futures_util::future::join_all(mcp.iter().map(|c| call_mcp_tool(registry, c)));
+    let local_results = futures_util::future::join_all(local.into_iter().map(|c| async move {
+        let result = run_local(executor, c.clone()).await;
+        (result.success, result.output)
+    }));
+    let (mcp_results, local_results) = tokio::join!(mcp_results, local_results);
+    let mut mcp_results = mcp_results.into_iter();
+    let mut local_results = local_results.into_iter();
+    calls
+        .iter()
+        .map(|c| {
+            let next = if c.name.starts_with("mcp::") {
+                mcp_results.next()
+            } else {
+                local_results.next()
+            };
+            next.unwrap_or((false, "Tool was not executed".to_string()))
+        })
+        .collect()
+}
+
+/// Рекурсивный вызов run_session для под-агента: у future должен быть известный тип.
+fn run_sub_session<'a>(
+    providers: &'a [Arc<dyn AiProvider>],
+    project_root: &'a Path,
+    task: &'a str,
+    emitter: AgentProgressEmitter,
+    provider_id: &'a str,
+    options: AgentSessionOptions,
+    delegation: &'a Delegation,
+) -> Pin<Box<dyn Future<Output = Result<String, AiRuntimeError>> + Send + 'a>> {
+    Box::pin(run_session(
+        providers,
+        project_root,
+        task,
+        emitter,
+        Some(provider_id),
+        options,
+        Some(delegation),
+    ))
+}
+
+/// Выполняет delegate: под-агент с прогрессом, вложенным в SubAgent; итог — результат вызова.
+async fn run_sub_agent(
+    providers: &[Arc<dyn AiProvider>],
+    project_root: &Path,
+    emitter: &AgentProgressEmitter,
+    provider_id: &str,
+    task: &str,
+    options: AgentSessionOptions,
+    delegation: &Delegation,
+) -> (bool, String) {
+    let sub_session_id = Uuid::new_v4().to_string();
+    append_audit_event(
+        Some(project_root),
+        &delegation.parent_session_id,
+        &AuditEvent::SubAgentStarted {
+            sub_session_id: sub_session_id.clone(),
+            task: task.to_string(),
+            tools: delegation.scope.tools.clone(),
+            paths: delegation.scope.paths.clone(),
+            max_turns: delegation.max_turns,
+        },
+    );
+    // Done под-агента — итог для родителя, если он остановился без ответа.
+    let stopped = Arc::new(Mutex::new(String::new()));
+    let nested: AgentProgressEmitter = {
+        let parent = Arc::clone(emitter);
+        let stopped = Arc::clone(&stopped);
+        let session_id = sub_session_id.clone();
+        Arc::new(move |progress| {
+            if let AgentProgress::Done { message } = &progress {
+                *stopped.lock().unwrap_or_else(|e| e.into_inner()) = message.clone();
+            }
+            parent(AgentProgress::SubAgent {
+                session_id: session_id.clone(),
+                progress: Box::new(progress),
+            });
+        })
+    };
+    let result = run_sub_session(
+        providers,
+        project_root,
+        task,
+        nested,
+        provider_id,
+        options,
+        delegation,
+    )
+    .await;
+    let (status, success, output) = match result {
+        Ok(summary) if !summary.trim().is_empty() => ("done", true, format!("Sub-agent finished. Summary:\n{}", summary)),
+        Ok(_) => {
+            let reason = stopped.lock().unwrap_or_else(|e| e.into_inner()).clone();
+            ("aborted", false, format!("Sub-agent stopped before finishing: {}", reason))
+        }
+        Err(e) => ("error", false, format!("Sub-agent failed: {}", e)),
     };
-    let call: ToolCall = serde_json::from_str(json_str).ok()?;
-    Some(call)
+    append_log(
+        Some(project_root),
+        "agent.log",
+        &format!("sub_agent {} status={}", sub_session_id, status),
+    );
+    append_audit_event(
+        Some(project_root),
+        &delegation.parent_session_id,
+        &AuditEvent::SubAgentFinished {
+            sub_session_id,
+            status: status.to_string(),
+        },
+    );
+    (success, output)
+}
+
+/// Проверки проекта в пуле блокирующих задач: сборка и тесты идут минутами.
+async fn verify(
+    project_root: &Path,
+    changes: Option<&Arc<ChangeSet>>,
+    config: &VerificationConfig,
+    scratch: &Path,
+) -> VerificationReport {
+    let (root, changes, config, scratch) =
+        (project_root.to_path_buf(), changes.cloned(), config.clone(), scratch.to_path_buf());
+    tokio::task::spawn_blocking(move || run_verification(&root, changes.as_deref(), &config, &scratch))
+        .await
+        .unwrap_or_default()
 }
 
 /// Прогресс агента для UI (session_started, model_selected, thinking, tool_call, tool_result, patch events, done).
@@ -196,12 +561,60 @@ pub enum AgentProgress {
     PatchApplied { path: String, before: String, after: String },
     /// Ошибка применения патча.
     PatchApplyError { path: String, message: String },
+    /// Изменяющий вызов ждёт решения пользователя (режим подтверждения).
+    ApprovalRequired {
+        approval_id: String,
+        name: String,
+        path: Option<String>,
+        /// Предлагаемое изменение: unified diff или аргументы вызова.
+        diff: String,
+        arguments: serde_json::Value,
+    },
+    /// Решение принято: approve, approve_all, reject, edit.
+    ApprovalResolved { approval_id: String, decision: String },
+    /// План задачи; при `review` агент ждёт решения пользователя (resolve_plan).
+    Plan {
+        plan_id: String,
+        steps: Vec<PlanStep>,
+        review: bool,
+    },
+    /// Статус шагов плана изменился.
+    PlanUpdated { steps: Vec<PlanStep> },
+    /// Старые ходы сжаты, чтобы разговор поместился в окно модели (оценка в токенах).
+    ContextCompacted { before_tokens: usize, after_tokens: usize },
+    /// Агент закончил правки, запущены проверки проекта.
+    VerificationStarted,
+    /// Результат проверок; при ошибках они возвращаются модели.
+    Verification { passed: bool, checks: Vec<CheckOutcome> },
+    /// Событие под-агента, запущенного через delegate (session_id — его собственный).
+    SubAgent { session_id: String, progress: Box<AgentProgress> },
+    /// Сводный diff незаписанных изменений сессии (перед Done); принятие — через ChangeSet.
+    ChangesStaged { changes: Vec<StagedChange> },
     Done { message: String },
 }
 
 /// Эмиттер прогресса агента (для Tauri events).
 pub type AgentProgressEmitter = Arc<dyn Fn(AgentProgress) + Send + Sync>;
 
+/// Необязательные режимы сессии агента.
+#[derive(Clone, Default)]
+pub struct AgentSessionOptions {
+    /// Подтверждение изменяющих вызовов пользователем.
+    pub approval: Option<AgentApproval>,
+    /// Слой правок: агент не пишет на диск, изменения принимаются пользователем в конце.
+    pub changes: Option<Arc<ChangeSet>>,
+    /// Продолжить сохранённую сессию (session_id) новым сообщением пользователя.
+    pub resume: Option<String>,
+    /// План перед выполнением с проверкой пользователем.
+    pub planning: Option<AgentPlanning>,
+    /// Проверка правок сборкой/тестами проекта перед завершением.
+    pub verification: Option<VerificationConfig>,
+    /// Семантический индекс проекта: правки агента на диске переиндексируются сразу.
+    pub semantic_index: Option<Arc<SemanticIndex>>,
+    /// Общий индекс символов IDE; без него агент открывает свой (второй писатель symbols.json).
+    pub symbol_index: Option<Arc<SymbolIndex>>,
+}
+
 /// Запускает цикл агента: generate → parse tool_call → execute → feed back, пока есть вызовы.
 /// По завершении эмитит Done { message }.
 /// Формирует блок MCP-контекста для вставки в промпт. Обрезает по MCP_CONTEXT_MAX_CHARS.
@@ -232,10 +645,44 @@ pub async fn run_agent_loop(
     project_root: &Path,
     user_message: &str,
     emitter: AgentProgressEmitter,
-    max_turns: usize,
     preferred_provider_id: Option<&str>,
+    options: AgentSessionOptions,
+) -> Result<String, AiRuntimeError> {
+    run_session(providers, project_root, user_message, emitter, preferred_provider_id, options, None).await
+}
+
+/// Цикл агента; с `delegation` — под-агент в области и бюджете родителя.
+async fn run_session(
+    providers: &[Arc<dyn AiProvider>],
+    project_root: &Path,
+    user_message: &str,
+    emitter: AgentProgressEmitter,
+    preferred_provider_id: Option<&str>,
+    options: AgentSessionOptions,
+    delegation: Option<&Delegation>,
 ) -> Result<String, AiRuntimeError> {
-    let session_id = Uuid::new_v4().to_string();
+    let AgentSessionOptions {
+        approval,
+        changes,
+        resume,
+        planning,
+        verification,
+        semantic_index,
+        symbol_index,
+    } = options;
+    let resumed = match resume {
+        Some(id) => Some(
+            load_transcript(project_root, &id).map_err(|e| AiRuntimeError::Session(e.to_string()))?,
+        ),
+        None => None,
+    };
+    let session_id = match delegation {
+        Some(d) => d.parent_session_id.clone(),
+        None => resumed
+            .as_ref()
+            .map(|t| t.session_id.clone())
+            .unwrap_or_else(|| Uuid::new_v4().to_string()),
+    };
     let project_root_opt = Some(project_root);
 
     let selection = ProviderSelector::select(
@@ -248,33 +695,59 @@ pub async fn run_agent_loop(
     .await?;
     let provider = selection.provider;
     let provider_id = provider.id().to_string();
+    let context_tokens = provider.capabilities().max_context_tokens.unwrap_or(DEFAULT_CONTEXT_TOKENS);
     let role_str = selection.role.as_str().to_string();
     let model_id = selection.model_id.clone();
+    let mut guardrails = load_guardrails(project_root_opt, selection.role)?;
+    if let Some(d) = delegation {
+        guardrails.max_turns = guardrails.max_turns.min(d.max_turns);
+        guardrails.max_time_ms = guardrails.max_time_ms.min(d.max_time_ms);
+        guardrails.max_tokens_per_session = guardrails.max_tokens_per_session.min(d.max_tokens);
+    }
+    // Под-агент пишет аудит в сессию родителя; начало и конец отмечает родитель.
+    let end_session = |status: &str| {
+        if delegation.is_none() {
+            emit_session_end(project_root_opt, &session_id, status);
+        }
+    };
 
-    append_audit_event(
-        project_root_opt,
-        &session_id,
-        &AuditEvent::SessionStart {
-            session_id: session_id.clone(),
-            mode: "agent".to_string(),
-            task: user_message.trim().to_string(),
-            policy: "default".to_string(),
-            environment: Some(current_environment()),
-        },
-    );
-    save_session_meta(
-        project_root_opt,
-        &AuditSessionMeta {
-            session_id: session_id.clone(),
-            started_at: chrono::Utc::now().to_rfc3339(),
-            ended_at: None,
-            mode: "agent".to_string(),
-            task: user_message.trim().to_string(),
-            status: "running".to_string(),
-            policy: "default".to_string(),
-            environment: Some(current_environment()),
-        },
-    );
+    if delegation.is_some() {
+        // Начало под-агента уже записано родителем (SubAgentStarted).
+    } else if resumed.is_some() {
+        append_audit_event(
+            project_root_opt,
+            &session_id,
+            &AuditEvent::SessionResumed {
+                task: user_message.trim().to_string(),
+            },
+        );
+        resume_session_meta(project_root_opt, &session_id);
+    } else {
+        append_audit_event(
+            project_root_opt,
+            &session_id,
+            &AuditEvent::SessionStart {
+                session_id: session_id.clone(),
+                mode: "agent".to_string(),
+                task: user_message.trim().to_string(),
+                policy: guardrails.policy_summary(),
+                environment: Some(current_environment()),
+            },
+        );
+        save_session_meta(
+            project_root_opt,
+            &AuditSessionMeta {
+                session_id: session_id.clone(),
+                started_at: chrono::Utc::now().to_rfc3339(),
+                ended_at: None,
+                mode: "agent".to_string(),
+                task: user_message.trim().to_string(),
+                status: "running".to_string(),
+                policy: guardrails.policy_summary(),
+                environment: Some(current_environment()),
+            },
+        );
+    }
     append_audit_event(
         project_root_opt,
         &session_id,
@@ -293,9 +766,11 @@ pub async fn run_agent_loop(
         },
     );
 
-    emitter(AgentProgress::SessionStarted {
-        session_id: session_id.clone(),
-    });
+    if delegation.is_none() {
+        emitter(AgentProgress::SessionStarted {
+            session_id: session_id.clone(),
+        });
+    }
     emitter(AgentProgress::ModelSelected {
         role: role_str,
         model_id: model_id.clone(),
@@ -309,7 +784,55 @@ pub async fn run_agent_loop(
             selection.model_id
         ),
     );
-    let executor = ToolExecutor::new(project_root.to_path_buf());
+    let mut executor = ToolExecutor::new(project_root.to_path_buf());
+    if let Some(index) = &symbol_index {
+        executor = executor.with_symbol_index(Arc::clone(index));
+    }
+    // Исходные файлы сохраняются в .kengaide/snapshots/<session_id>/ перед первой записью на диск.
+    // Под-агент пишет в снимок родителя: откат сессии покрывает и его правки.
+    // Восстановленный слой продолжаемой сессии уже несёт её снимок — второй экземпляр не создаётся.
+    let attached = changes.as_ref().and_then(|c| c.snapshot());
+    let snapshot = match delegation {
+        Some(d) => d.snapshot.clone(),
+        None if attached.is_some() => attached,
+        None => match SessionSnapshot::new(project_root, &session_id) {
+            Ok(snapshot) => Some(Arc::new(snapshot)),
+            Err(e) => {
+                tracing::warn!(error = %e, "session snapshot unavailable");
+                None
+            }
+        },
+    };
+    if let Some(snapshot) = &snapshot {
+        if let Some(changes) = &changes {
+            changes.attach_snapshot(Arc::clone(snapshot));
+        }
+        executor = executor.with_snapshot(Arc::clone(snapshot));
+    }
+    if let Some(changes) = &changes {
+        // Слой под-агента общий с родителем и сохраняется под его сессией.
+        if delegation.is_none() {
+            changes.persist_as(&session_id);
+        }
+        executor = executor.with_change_set(Arc::clone(changes));
+    }
+    // Общий с blocking-задачами параллельных чтений.
+    let executor = Arc::new(executor);
+    // Перед Done — сводка незаписанных изменений, чтобы UI показал diff на принятие.
+    let emit_done = |message: String| {
+        let staged = changes.as_ref().filter(|_| delegation.is_none()).map(|c| c.changes());
+        if let Some(staged) = staged.filter(|c| !c.is_empty()) {
+            append_audit_event(
+                project_root_opt,
+                &session_id,
+                &AuditEvent::ChangesStaged {
+                    files: staged.iter().map(|c| c.path.clone()).collect(),
+                },
+            );
+            emitter(AgentProgress::ChangesStaged { changes: staged });
+        }
+        emitter(AgentProgress::Done { message });
+    };
 
     let mcp_block = match McpContextProvider::from_config_file() {
         Ok(mcp) => {
@@ -322,76 +845,252 @@ pub async fn run_agent_loop(
         }
     };
 
-    let (mcp_tools, mcp_registry) = match McpToolRegistry::from_config_file() {
+    let (mut mcp_tools, mcp_registry) = match McpToolRegistry::from_config_file() {
         Ok(reg) if reg.has_servers() => {
             let tools = reg.list_all_tools().await;
             (tools, Some(reg))
         }
         _ => (Vec::new(), None),
     };
-    let system_prompt = build_agent_system_prompt(&mcp_tools);
+    if let Some(d) = delegation {
+        mcp_tools.retain(|t| d.scope.tools.contains(&t.namespaced_name()));
+    }
+    let mut system_prompt = build_agent_system_prompt(&mcp_tools);
+    if let Some(d) = delegation {
+        system_prompt.push_str(&d.scope.prompt());
+    }
 
-    let mut conversation = format!(
-        "{}{}\n\nUser: {}\n\nAssistant: ",
-        system_prompt,
-        mcp_block,
-        user_message.trim()
-    );
+    // Плейсхолдеры секретов общие для сессии, её планировщика и под-агентов.
+    let placeholders = delegation
+        .map(|d| Arc::clone(&d.placeholders))
+        .unwrap_or_default();
+    let redactor = Redactor::for_provider(project_root_opt, provider.provider_type())
+        .map(|r| r.with_placeholders(Arc::clone(&placeholders)));
+    let redact = |text: &str| redact_for_prompt(redactor.as_ref(), project_root_opt, &session_id, text);
+    let task = redact(user_message.trim());
+    let mut conversation = Conversation {
+        prefix: format!("{}{}", system_prompt, redact(&mcp_block)),
+        transcript: resumed.unwrap_or_else(|| AgentTranscript::new(&session_id, user_message.trim())),
+        persist: delegation.is_none(),
+    };
+    conversation.push(TranscriptMessage::User {
+        content: task.clone(),
+    });
+    conversation.save(project_root);
     let mut turn = 0;
     let mut tool_calls_in_run: usize = 0;
-    let mut last_tool_call: Option<(String, serde_json::Value)> = None;
+    // Все вызовы сессии (tool_calls_in_run сбрасывается) — для учёта под-агента у родителя.
+    let mut tool_calls_total: usize = 0;
+    let mut last_tool_calls: Vec<(String, serde_json::Value)> = Vec::new();
     let start_time = Instant::now();
+    // Ожидание решения пользователя не считается временем работы агента.
+    let mut approval_wait = Duration::ZERO;
+    let mut approve_all = false;
     let mut total_tokens_approx: usize = 0;
-    let mut last_errors: Vec<String> = Vec::with_capacity(SAME_ERROR_THRESHOLD);
+    let mut last_errors = ErrorStreak::new(guardrails.same_error_threshold);
+    // Были ли правки после последней проверки и сколько раз проверка не прошла.
+    let mut modified = false;
+    let mut verify_failures: usize = 0;
+    let verify_scratch = std::env::temp_dir().join(format!("kengaide-verify-{}", session_id));
 
     append_log(Some(project_root), "agent.log", &format!("agent_start user_msg_len={}", user_message.len()));
 
-    loop {
-        if turn >= max_turns {
-            append_log(Some(project_root), "agent.log", "guardrail: max_turns");
-            emit_session_end(project_root_opt, &session_id, "aborted");
-            emitter(AgentProgress::Done {
-                message: "Агент остановлен: достигнут лимит шагов. Попробуй переформулировать задачу.".to_string(),
+    // Статус шагов плана — в UI и аудит.
+    let report_plan = |plan: &AgentPlan, changed: Vec<PlanStep>| {
+        if changed.is_empty() {
+            return;
+        }
+        for step in &changed {
+            append_audit_event(
+                project_root_opt,
+                &session_id,
+                &AuditEvent::PlanStepUpdated {
+                    step: step.number,
+                    status: step.status.as_str().to_string(),
+                },
+            );
+        }
+        emitter(AgentProgress::PlanUpdated {
+            steps: plan.steps.clone(),
+        });
+    };
+
+    // Фаза планирования: шаги до первого вызова инструментов; пользователь может их исправить.
+    let mut plan: Option<AgentPlan> = None;
+    if let Some(planning) = planning.as_ref().filter(|p| p.policy.enabled) {
+        let (planner, planner_model) = if planning.policy.use_planning_model {
+            match ProviderSelector::select_for_role(
+                providers,
+                AiMode::Agent,
+                TaskRole::Planning,
+                Some(&provider_id),
+                project_root_opt,
+            )
+            .await
+            {
+                Ok(s) => (s.provider, s.model_id),
+                Err(_) => (Arc::clone(&provider), model_id.clone()),
+            }
+        } else {
+            (Arc::clone(&provider), model_id.clone())
+        };
+        emitter(AgentProgress::Thinking);
+        // Модель планирования может быть облачной при локальной модели задачи.
+        let planner_redactor = Redactor::for_provider(project_root_opt, planner.provider_type())
+            .map(|r| r.with_placeholders(Arc::clone(&placeholders)));
+        let plan_task = redact_for_prompt(planner_redactor.as_ref(), project_root_opt, &session_id, user_message.trim());
+        let prompt = format!("{}\n\nUser: {}\n\nAssistant: ", PLANNING_PROMPT, plan_task);
+        let steps = match generate_text(&planner, prompt, PLAN_MAX_TOKENS).await {
+            Ok(text) => {
+                total_tokens_approx += text.len() / CHARS_PER_TOKEN;
+                parse_plan(&text)
+            }
+            Err(e) => {
+                append_log(Some(project_root), "agent.log", &format!("planning failed: {}", e));
+                Vec::new()
+            }
+        };
+        let mut proposed = AgentPlan::new(steps);
+        if proposed.steps.is_empty() {
+            append_log(Some(project_root), "agent.log", "planning: no plan, executing directly");
+        } else {
+            append_audit_event(
+                project_root_opt,
+                &session_id,
+                &AuditEvent::PlanCreated {
+                    steps: proposed.descriptions(),
+                    model: planner_model,
+                },
+            );
+            let plan_id = Uuid::new_v4().to_string();
+            let review = planning.policy.review;
+            let decision = review.then(|| {
+                planning.gate.wait_plan(PendingPlan {
+                    plan_id: plan_id.clone(),
+                    steps: proposed.steps.clone(),
+                })
             });
+            emitter(AgentProgress::Plan {
+                plan_id: plan_id.clone(),
+                steps: proposed.steps.clone(),
+                review,
+            });
+            if let Some(decision) = decision {
+                let waiting = Instant::now();
+                // Без решения (UI не увидел план или закрыт) сессия не висит с загруженной моделью.
+                let decision = match planning.policy.timeout() {
+                    Some(timeout) => tokio::time::timeout(timeout, decision).await.ok(),
+                    None => Some(decision.await),
+                };
+                let decision = match decision {
+                    Some(decision) => decision.unwrap_or(PlanDecision::Cancel),
+                    None => {
+                        planning.gate.cancel_plan(&plan_id);
+                        append_log(Some(project_root), "agent.log", "planning: no decision in time");
+                        PlanDecision::Cancel
+                    }
+                };
+                approval_wait += waiting.elapsed();
+                let label = match &decision {
+                    PlanDecision::Approve => "approve",
+                    PlanDecision::Edit { .. } => "edit",
+                    PlanDecision::Cancel => "cancel",
+                };
+                let cancelled = matches!(decision, PlanDecision::Cancel);
+                if let PlanDecision::Edit { steps } = decision {
+                    proposed = AgentPlan::new(steps);
+                    emitter(AgentProgress::Plan {
+                        plan_id,
+                        steps: proposed.steps.clone(),
+                        review: false,
+                    });
+                }
+                append_audit_event(
+                    project_root_opt,
+                    &session_id,
+                    &AuditEvent::PlanReviewed {
+                        decision: label.to_string(),
+                        steps: proposed.descriptions(),
+                    },
+                );
+                if cancelled {
+                    append_log(Some(project_root), "agent.log", "planning: cancelled by user");
+                    end_session("cancelled");
+                    emit_done("План отклонён, задача не выполнялась.".to_string());
+                    return Ok(String::new());
+                }
+            }
+            if !proposed.steps.is_empty() {
+                // В префиксе, а не в сообщениях: сжатие разговора не должно терять план.
+                conversation.prefix.push_str("\n\n");
+                conversation.prefix.push_str(&redact(&proposed.prompt()));
+                plan = Some(proposed);
+            }
+        }
+    }
+
+    let result = 'session: loop {
+        if guardrails.turns_exhausted(turn) {
+            append_log(Some(project_root), "agent.log", "guardrail: max_turns");
+            end_session("aborted");
+            emit_done("Агент остановлен: достигнут лимит шагов. Попробуй переформулировать задачу.".to_string());
             break Ok(String::new());
         }
-        if start_time.elapsed().as_millis() as u64 > MAX_TIME_MS {
+        if guardrails.time_exceeded(start_time.elapsed().saturating_sub(approval_wait)) {
             append_log(Some(project_root), "agent.log", "guardrail: max_time");
-            emit_session_end(project_root_opt, &session_id, "aborted");
-            emitter(AgentProgress::Done {
-                message: "Агент остановлен: превышено время работы. Попробуй разбить задачу на части.".to_string(),
-            });
+            end_session("aborted");
+            emit_done("Агент остановлен: превышено время работы. Попробуй разбить задачу на части.".to_string());
             break Ok(String::new());
         }
-        if total_tokens_approx > MAX_TOKENS_PER_SESSION {
+        if guardrails.tokens_exceeded(total_tokens_approx) {
             append_log(Some(project_root), "agent.log", "guardrail: max_tokens");
-            emit_session_end(project_root_opt, &session_id, "aborted");
-            emitter(AgentProgress::Done {
-                message: "Агент остановлен: превышен лимит токенов. Попробуй переформулировать задачу.".to_string(),
-            });
+            end_session("aborted");
+            emit_done("Агент остановлен: превышен лимит токенов. Попробуй переформулировать задачу.".to_string());
             break Ok(String::new());
         }
         turn += 1;
         emitter(AgentProgress::Thinking);
+        if let Some(c) = conversation.compact(context_tokens) {
+            let (before_tokens, after_tokens) = (c.before_chars / CHARS_PER_TOKEN, c.after_chars / CHARS_PER_TOKEN);
+            append_log(
+                Some(project_root),
+                "agent.log",
+                &format!("context_compacted tokens={}->{}", before_tokens, after_tokens),
+            );
+            append_audit_event(
+                project_root_opt,
+                &session_id,
+                &AuditEvent::ContextCompacted {
+                    before_tokens,
+                    after_tokens,
+                },
+            );
+            emitter(AgentProgress::ContextCompacted {
+                before_tokens,
+                after_tokens,
+            });
+        }
+        conversation.save(project_root);
+        let prompt = conversation.prompt();
 
         append_audit_event(
             project_root_opt,
             &session_id,
             &AuditEvent::PromptSent {
-                tokens: Some(conversation.len() / 4),
+                tokens: Some(prompt.len() / CHARS_PER_TOKEN),
             },
         );
 
         let request_id = Uuid::new_v4().to_string();
         let gen_request = GenerateRequest {
             id: request_id.clone(),
-            prompt: conversation.clone(),
+            prompt,
             context: Some(EditorContext::default()),
             mode: AiMode::Agent,
         };
         let options = GenerateOptions {
             temperature: Some(0.3),
-            max_tokens: Some(4096),
+            max_tokens: Some(RESPONSE_MAX_TOKENS),
         };
 
         let mut stream = provider
@@ -405,7 +1104,7 @@ pub async fn run_agent_loop(
                 AiChunk::Token { value } => response.push_str(&value),
                 AiChunk::End => break,
                 AiChunk::Error { error } => {
-                    emit_session_end(project_root_opt, &session_id, "error");
+                    end_session("error");
                     append_audit_event(
                         project_root_opt,
                         &session_id,
@@ -413,192 +1112,422 @@ pub async fn run_agent_loop(
                             message: error.clone(),
                         },
                     );
-                    return Err(AiRuntimeError::Provider(ai_providers::ProviderError::Generation(
-                        error,
-                    )));
+                    break 'session Err(AiRuntimeError::Provider(ProviderError::Generation(error)));
                 }
-                AiChunk::Start => {}
+                AiChunk::Start | AiChunk::Queued { .. } => {}
             }
         }
 
         let response = response.trim().to_string();
-        total_tokens_approx += response.len() / 4;
+        total_tokens_approx += response.len() / CHARS_PER_TOKEN;
 
         if response.is_empty() {
             append_log(Some(project_root), "agent.log", "guardrail: empty_response");
-            emit_session_end(project_root_opt, &session_id, "error");
-            emitter(AgentProgress::Done {
-                message: "Агент остановлен: модель вернула пустой ответ. Попробуй переформулировать задачу или сменить провайдер.".to_string(),
-            });
+            end_session("error");
+            emit_done("Агент остановлен: модель вернула пустой ответ. Попробуй переформулировать задачу или сменить провайдер.".to_string());
             break Ok(String::new());
         }
 
-        if let Some(call) = parse_tool_call(&response) {
-            if tool_calls_in_run >= MAX_TOOL_CALLS_PER_MESSAGE {
-                emit_session_end(project_root_opt, &session_id, "aborted");
-                emitter(AgentProgress::Done {
-                    message: "Достигнут лимит вызовов инструментов (8). Остановка.".to_string(),
-                });
+        let (mut calls, steps): (Vec<ToolCall>, Vec<Option<usize>>) =
+            parse_planned_calls(&response).into_iter().unzip();
+        if !calls.is_empty() {
+            let budget = guardrails.tool_call_budget(tool_calls_in_run);
+            if budget == 0 {
+                append_log(Some(project_root), "agent.log", "guardrail: max_tool_calls");
+                end_session("aborted");
+                emit_done(format!(
+                    "Достигнут лимит вызовов инструментов ({}). Остановка.",
+                    guardrails.max_tool_calls_per_message
+                ));
                 break Ok(String::new());
             }
-            tool_calls_in_run += 1;
-
-            let path = call
-                .arguments
-                .get("path")
-                .and_then(|v| v.as_str())
-                .map(String::from);
-            append_log(
-                Some(project_root),
-                "agent.log",
-                &format!(
-                    "tool_call {} {}",
-                    call.name,
-                    path.as_deref().map(|p| format!("path={}", p)).unwrap_or_default()
-                ),
-            );
-            append_audit_event(
-                project_root_opt,
-                &session_id,
-                &AuditEvent::ToolCall {
-                    tool: call.name.clone(),
-                    path: path.clone(),
-                },
-            );
-            emitter(AgentProgress::ToolCall {
-                name: call.name.clone(),
-                path: path.clone(),
-            });
-
-            let is_repeat = last_tool_call
-                .as_ref()
-                .map(|(n, a)| n == &call.name && a == &call.arguments)
-                .unwrap_or(false);
-            last_tool_call = Some((call.name.clone(), call.arguments.clone()));
+            let skipped = calls.len().saturating_sub(budget);
+            if skipped > 0 {
+                append_log(
+                    Some(project_root),
+                    "agent.log",
+                    &format!("guardrail: max_tool_calls skipped={}", skipped),
+                );
+                calls.truncate(budget);
+            }
+            tool_calls_in_run += calls.len();
+            tool_calls_total += calls.len();
 
+            let batch: Vec<(String, serde_json::Value)> =
+                calls.iter().map(|c| (c.name.clone(), c.arguments.clone())).collect();
+            let is_repeat = batch == last_tool_calls;
+            last_tool_calls = batch;
             if is_repeat {
                 append_log(Some(project_root), "agent.log", "guardrail: repeated_tool_call");
-                emit_session_end(project_root_opt, &session_id, "aborted");
-                emitter(AgentProgress::Done {
-                    message: "Агент остановлен: повторяющиеся действия. Попробуй переформулировать задачу.".to_string(),
-                });
+                end_session("aborted");
+                emit_done("Агент остановлен: повторяющиеся действия. Попробуй переформулировать задачу.".to_string());
                 break Ok(String::new());
             }
 
-            let (success, output) = if call.name.starts_with("mcp::") {
-                match &mcp_registry {
-                    Some(reg) => {
-                        match reg.call_tool(&call.name, call.arguments.clone()).await {
-                            Ok(val) => {
-                                let out = if val.is_string() {
-                                    val.as_str().unwrap_or("").to_string()
-                                } else if let Some(c) = val.get("content") {
-                                    c.as_str()
-                                        .map(String::from)
-                                        .unwrap_or_else(|| c.to_string())
-                                } else {
-                                    val.to_string()
-                                };
-                                (true, out)
+            let announce = |call: &ToolCall| {
+                let path = call_path(call);
+                append_log(
+                    Some(project_root),
+                    "agent.log",
+                    &format!(
+                        "tool_call {} {}",
+                        call.name,
+                        path.as_deref().map(|p| format!("path={}", p)).unwrap_or_default()
+                    ),
+                );
+                append_audit_event(
+                    project_root_opt,
+                    &session_id,
+                    &AuditEvent::ToolCall {
+                        tool: call.name.clone(),
+                        path: path.clone(),
+                    },
+                );
+                emitter(AgentProgress::ToolCall {
+                    name: call.name.clone(),
+                    path,
+                });
+            };
+            // delegate сам ничего не меняет: подтверждаются вызовы под-агента.
+            let needs_approval = |name: &str, approve_all: bool| {
+                !approve_all
+                    && name != DELEGATE_TOOL
+                    && approval.as_ref().is_some_and(|a| a.policy.requires_approval(name))
+            };
+            let out_of_scope =
+                |call: &ToolCall| delegation.and_then(|d| d.scope.check(&call.name, call_path(call).as_deref()));
+
+            let mut outcomes: Vec<CallOutcome> = Vec::with_capacity(calls.len());
+            let mut next = 0;
+            while next < calls.len() {
+                // Подряд идущие читающие вызовы без подтверждения выполняются одновременно.
+                let reads = calls[next..]
+                    .iter()
+                    .take_while(|c| {
+                        is_read_only(c, &mcp_tools) && !needs_approval(&c.name, approve_all) && out_of_scope(c).is_none()
+                    })
+                    .count();
+                let group: Vec<ToolCall> = if reads > 1 {
+                    calls[next..next + reads].to_vec()
+                } else {
+                    Vec::new()
+                };
+                let executed: Vec<CallOutcome> = if !group.is_empty() {
+                    next += group.len();
+                    group.iter().for_each(announce);
+                    let restored: Vec<ToolCall> = group.iter().map(|c| restore_call(&placeholders, c)).collect();
+                    run_reads(&executor, mcp_registry.as_ref(), &restored)
+                        .await
+                        .into_iter()
+                        .zip(group)
+                        .zip(&steps[next - reads..next])
+                        .map(|(((success, output), call), step)| CallOutcome {
+                            call,
+                            step: *step,
+                            success,
+                            output,
+                            edited: false,
+                            rejected: false,
+                        })
+                        .collect()
+                } else {
+                    let mut call = calls[next].clone();
+                    let step = steps[next];
+                    next += 1;
+                    announce(&call);
+
+                    let denied = out_of_scope(&call);
+                    let mut rejection = None;
+                    let mut edited = false;
+                    if let Some(approval) = approval
+                        .as_ref()
+                        .filter(|_| denied.is_none() && needs_approval(&call.name, approve_all))
+                    {
+                        let request = PendingApproval {
+                            approval_id: Uuid::new_v4().to_string(),
+                            name: call.name.clone(),
+                            path: call_path(&call),
+                            diff: proposed_diff(&executor, &restore_call(&placeholders, &call)),
+                            arguments: call.arguments.clone(),
+                        };
+                        let approval_id = request.approval_id.clone();
+                        let decision = approval.gate.wait(request.clone());
+                        emitter(AgentProgress::ApprovalRequired {
+                            approval_id: request.approval_id,
+                            name: request.name,
+                            path: request.path,
+                            diff: request.diff,
+                            arguments: request.arguments,
+                        });
+                        let waiting = Instant::now();
+                        let decision = match approval.policy.timeout() {
+                            Some(timeout) => tokio::time::timeout(timeout, decision).await.ok(),
+                            None => Some(decision.await),
+                        };
+                        let decision = match decision {
+                            Some(Ok(decision)) => decision,
+                            // Отправитель пропал — решения не будет, изменение не применяется.
+                            Some(Err(_)) => ApprovalDecision::Reject {
+                                reason: Some("approval request dropped".to_string()),
+                            },
+                            None => {
+                                approval.gate.cancel(&approval_id);
+                                ApprovalDecision::Reject {
+                                    reason: Some("no decision from the user in time".to_string()),
+                                }
                             }
-                            Err(e) => {
-                                tracing::debug!(tool = %call.name, error = %e, "MCP tool call failed");
-                                (false, format!("MCP error: {}", e))
+                        };
+                        approval_wait += waiting.elapsed();
+                        let label = match &decision {
+                            ApprovalDecision::Approve => "approve",
+                            ApprovalDecision::ApproveAll => "approve_all",
+                            ApprovalDecision::Reject { .. } => "reject",
+                            ApprovalDecision::Edit { .. } => "edit",
+                        };
+                        append_log(
+                            Some(project_root),
+                            "agent.log",
+                            &format!("approval tool={} decision={}", call.name, label),
+                        );
+                        append_audit_event(
+                            project_root_opt,
+                            &session_id,
+                            &AuditEvent::ApprovalDecided {
+                                tool: call.name.clone(),
+                                decision: label.to_string(),
+                            },
+                        );
+                        emitter(AgentProgress::ApprovalResolved {
+                            approval_id,
+                            decision: label.to_string(),
+                        });
+                        match decision {
+                            ApprovalDecision::Approve => {}
+                            ApprovalDecision::ApproveAll => approve_all = true,
+                            ApprovalDecision::Edit { arguments } => {
+                                call.arguments = arguments;
+                                edited = true;
                             }
+                            ApprovalDecision::Reject { reason } => rejection = Some(reason),
                         }
                     }
-                    None => (false, "MCP not configured".to_string()),
-                }
-            } else {
-                if call.name == "apply_patch" {
-                    if let Some(ref p) = path {
-                        emitter(AgentProgress::PatchApplyStarted { path: p.clone() });
-                    }
-                }
-                let result = executor.run(&call);
-                if call.name == "apply_patch" {
-                    if let Some(ref p) = path {
-                        if result.success {
-                            append_audit_event(
-                                project_root_opt,
-                                &session_id,
-                                &AuditEvent::PatchApplied {
-                                    path: p.clone(),
-                                    hash: None,
-                                },
-                            );
-                            emitter(AgentProgress::PatchApplySuccess { path: p.clone() });
-                            if let (Some(before), Some(after)) = (
-                                call.arguments.get("before").and_then(|v| v.as_str()),
-                                call.arguments.get("after").and_then(|v| v.as_str()),
-                            ) {
-                                emitter(AgentProgress::PatchApplied {
-                                    path: p.clone(),
-                                    before: before.to_string(),
-                                    after: after.to_string(),
-                                });
+
+                    let (success, output) = if let Some(error) = denied {
+                        (false, error)
+                    } else if let Some(reason) = &rejection {
+                        let mut out = "Rejected by the user; the change was not applied.".to_string();
+                        if let Some(reason) = reason.as_deref().filter(|r| !r.trim().is_empty()) {
+                            out.push_str(&format!(" Reason: {}.", reason.trim()));
+                        }
+                        out.push_str(" Do not repeat this change; adjust the approach or ask the user.");
+                        (false, out)
+                    } else if call.name == DELEGATE_TOOL {
+                        match DelegateRequest::parse(&call.arguments) {
+                            Ok(request) => {
+                                let worked = start_time.elapsed().saturating_sub(approval_wait).as_millis() as u64;
+                                let sub = Delegation {
+                                    parent_session_id: session_id.clone(),
+                                    scope: request.scope(),
+                                    max_turns: request.max_turns(),
+                                    max_time_ms: guardrails.max_time_ms.saturating_sub(worked),
+                                    max_tokens: guardrails.max_tokens_per_session.saturating_sub(total_tokens_approx),
+                                    snapshot: snapshot.clone(),
+                                    placeholders: Arc::clone(&placeholders),
+                                    approval_wait: Mutex::new(Duration::ZERO),
+                                    tokens_used: Mutex::new(0),
+                                    tool_calls: Mutex::new(0),
+                                };
+                                let options = AgentSessionOptions {
+                                    approval: approval.clone(),
+                                    changes: changes.clone(),
+                                    semantic_index: semantic_index.clone(),
+                                    symbol_index: symbol_index.clone(),
+                                    ..Default::default()
+                                };
+                                let outcome =
+                                    run_sub_agent(providers, project_root, &emitter, &provider_id, &request.task, options, &sub)
+                                        .await;
+                                approval_wait += *sub.approval_wait.lock().unwrap_or_else(|e| e.into_inner());
+                                let sub_tokens = *sub.tokens_used.lock().unwrap_or_else(|e| e.into_inner());
+                                let sub_calls = *sub.tool_calls.lock().unwrap_or_else(|e| e.into_inner());
+                                total_tokens_approx += sub_tokens;
+                                tool_calls_total += sub_calls;
+                                outcome
                             }
-                        } else {
-                            emitter(AgentProgress::PatchApplyError {
-                                path: p.clone(),
-                                message: result.output.clone(),
-                            });
+                            Err(error) => (false, error),
                         }
+                    } else if call.name.starts_with("mcp::") {
+                        call_mcp_tool(mcp_registry.as_ref(), &restore_call(&placeholders, &call)).await
+                    } else {
+                        let path = call_path(&call);
+                        if call.name == "apply_patch" {
+                            if let Some(ref p) = path {
+                                emitter(AgentProgress::PatchApplyStarted { path: p.clone() });
+                            }
+                        }
+                        let result = run_local(&executor, restore_call(&placeholders, &call)).await;
+                        if call.name == "apply_patch" {
+                            if let Some(ref p) = path {
+                                if result.success && changes.is_some() {
+                                    // Правка в слое: на диске ничего нет, откатывать нечего.
+                                    emitter(AgentProgress::PatchApplySuccess { path: p.clone() });
+                                } else if result.success {
+                                    append_audit_event(
+                                        project_root_opt,
+                                        &session_id,
+                                        &AuditEvent::PatchApplied {
+                                            path: p.clone(),
+                                            hash: None,
+                                        },
+                                    );
+                                    emitter(AgentProgress::PatchApplySuccess { path: p.clone() });
+                                    if let (Some(before), Some(after)) = (
+                                        call.arguments.get("before").and_then(|v| v.as_str()),
+                                        call.arguments.get("after").and_then(|v| v.as_str()),
+                                    ) {
+                                        emitter(AgentProgress::PatchApplied {
+                                            path: p.clone(),
+                                            before: before.to_string(),
+                                            after: after.to_string(),
+                                        });
+                                    }
+                                } else {
+                                    emitter(AgentProgress::PatchApplyError {
+                                        path: p.clone(),
+                                        message: result.output.clone(),
+                                    });
+                                }
+                            }
+                        }
+                        (result.success, result.output)
+                    };
+                    vec![CallOutcome {
+                        call,
+                        step,
+                        success,
+                        output,
+                        edited,
+                        rejected: rejection.is_some(),
+                    }]
+                };
+
+                for outcome in executed {
+                    // Отказ пользователя — не ошибка инструмента, зацикливанием не считается.
+                    if outcome.rejected {
+                        last_errors.clear();
+                    } else if !outcome.success {
+                        if last_errors.push(outcome.output.clone()) {
+                            append_log(Some(project_root), "agent.log", "guardrail: repeated_errors");
+                            end_session("aborted");
+                            emit_done("Агент остановлен: повторяющиеся ошибки. Попробуй переформулировать задачу.".to_string());
+                            break 'session Ok(String::new());
+                        }
+                    } else {
+                        last_errors.clear();
                     }
-                }
-                (result.success, result.output)
-            };
 
-            if !success {
-                last_errors.push(output.clone());
-                if last_errors.len() > SAME_ERROR_THRESHOLD {
-                    last_errors.remove(0);
-                }
-                if last_errors.len() >= SAME_ERROR_THRESHOLD {
-                    let first = &last_errors[0];
-                    if last_errors.iter().all(|e| e == first) {
-                        append_log(Some(project_root), "agent.log", "guardrail: repeated_errors");
-                        emit_session_end(project_root_opt, &session_id, "aborted");
-                        emitter(AgentProgress::Done {
-                            message: "Агент остановлен: повторяющиеся ошибки. Попробуй переформулировать задачу.".to_string(),
-                        });
-                        break Ok(String::new());
+                    append_audit_event(
+                        project_root_opt,
+                        &session_id,
+                        &AuditEvent::ToolResult {
+                            success: outcome.success,
+                            output_len: Some(outcome.output.len()),
+                        },
+                    );
+                    emitter(AgentProgress::ToolResult {
+                        success: outcome.success,
+                        output: outcome.output.clone(),
+                    });
+                    // Правка проекта локальным инструментом — перед завершением нужна проверка.
+                    if outcome.success && !outcome.call.name.starts_with("mcp::") && !READ_ONLY_TOOLS.contains(&outcome.call.name.as_str()) {
+                        modified = true;
+                        // Запись сразу на диск — семантический индекс обновляется; правки в слое
+                        // попадут в индекс при принятии.
+                        if let (None, Some(index), Some(path)) =
+                            (&changes, &semantic_index, call_path(&outcome.call))
+                        {
+                            let index = Arc::clone(index);
+                            tokio::spawn(async move {
+                                let rel = std::path::PathBuf::from(path.trim_start_matches('/'));
+                                if let Err(e) = index.update_files(&[rel]).await {
+                                    tracing::warn!(error = %e, "semantic index update failed");
+                                }
+                            });
+                        }
                     }
+                    if let Some(plan) = plan.as_mut().filter(|_| !outcome.rejected) {
+                        let changed = plan.record_call(outcome.step, outcome.success);
+                        report_plan(plan, changed);
+                    }
+                    outcomes.push(outcome);
                 }
-            } else {
-                last_errors.clear();
             }
 
-            append_audit_event(
-                project_root_opt,
-                &session_id,
-                &AuditEvent::ToolResult {
-                    success,
-                    output_len: Some(output.len()),
-                },
-            );
-            emitter(AgentProgress::ToolResult {
-                success,
-                output: output.clone(),
+            conversation.push(TranscriptMessage::Assistant {
+                content: response.clone(),
             });
-            conversation.push_str(&response);
-            conversation.push_str("\n\nTool result: ");
-            conversation.push_str(if success { "OK. " } else { "ERROR. " });
-            conversation.push_str(&output);
-            if success {
-                if call.name == "create_project" {
-                    conversation.push_str("\n\n[System: Project skeleton created. The user asked: \"");
-                    conversation.push_str(user_message.trim());
-                    conversation.push_str("\". You MUST now implement using create_file or apply_patch. Do NOT stop.]");
-                } else if call.name == "list_files" {
-                    conversation.push_str("\n\n[System: You listed files. The user asked: \"");
-                    conversation.push_str(user_message.trim());
-                    conversation.push_str("\". You MUST now read_file the relevant files and then use create_file or apply_patch to implement. Do NOT stop with just listing.]");
-                } else if call.name == "read_file" {
-                    conversation.push_str("\n\n[System: You have the file content. Now use apply_patch to modify it according to the user's request, or create_file for new files. Do NOT stop.]");
+            for outcome in &outcomes {
+                conversation.push(TranscriptMessage::ToolCall {
+                    name: outcome.call.name.clone(),
+                    arguments: outcome.call.arguments.clone(),
+                });
+            }
+            // Результаты всех вызовов хода — одним сообщением, по порядку вызовов.
+            let result_text = |outcome: &CallOutcome| {
+                let mut text = String::new();
+                if outcome.edited {
+                    text.push_str("(executed with arguments edited by the user) ");
                 }
+                text.push_str(&redact(&outcome.output));
+                text
+            };
+            let mut output = if let [outcome] = outcomes.as_slice() {
+                result_text(outcome)
+            } else {
+                outcomes
+                    .iter()
+                    .enumerate()
+                    .map(|(i, o)| {
+                        format!(
+                            "[{}] {}{}: {}{}",
+                            i + 1,
+                            o.call.name,
+                            call_path(&o.call).map(|p| format!(" {}", p)).unwrap_or_default(),
+                            if o.success { "OK. " } else { "ERROR. " },
+                            result_text(o)
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n\n")
+            };
+            if skipped > 0 {
+                output.push_str(&format!(
+                    "\n\n{} more tool call(s) in this message were not executed: tool call limit reached.",
+                    skipped
+                ));
+            }
+            conversation.push(TranscriptMessage::ToolResult {
+                success: outcomes.iter().all(|o| o.success),
+                output,
+            });
+            let last = outcomes.last().map(|o| (o.call.name.as_str(), o.success));
+            let nudge = match last {
+                Some(("create_project", true)) => Some(format!(
+                    "Project skeleton created. The user asked: \"{}\". You MUST now implement using create_file or apply_patch. Do NOT stop.",
+                    task
+                )),
+                Some(("list_files", true)) => Some(format!(
+                    "You listed files. The user asked: \"{}\". You MUST now read_file the relevant files and then use create_file or apply_patch to implement. Do NOT stop with just listing.",
+                    task
+                )),
+                Some(("read_file", true)) => Some(
+                    "You have the file content. Now use apply_patch to modify it according to the user's request, or create_file for new files. Do NOT stop.".to_string(),
+                ),
+                _ => None,
+            };
+            if let Some(content) = nudge {
+                conversation.push(TranscriptMessage::System { content });
             }
-            conversation.push_str("\n\nAssistant: ");
         } else {
             let looks_like_completion = response.len() < 150
                 || response.to_lowercase().contains("done")
@@ -608,21 +1537,177 @@ pub async fn run_agent_loop(
                 || response.to_lowercase().contains("завершено");
 
             if !looks_like_completion && response.len() > 200 {
-                conversation.push_str(&response);
-                conversation.push_str(
-                    "\n\n[System: You must either call a tool or explicitly finish. Do not output long explanations without taking action.]\n\nAssistant: ",
-                );
+                conversation.push(TranscriptMessage::Assistant { content: response });
+                conversation.push(TranscriptMessage::System {
+                    content: "You must either call a tool or explicitly finish. Do not output long explanations without taking action.".to_string(),
+                });
                 tool_calls_in_run = 0;
                 continue;
             }
 
-            let final_message = response;
-            append_log(Some(project_root), "agent.log", &format!("done msg_len={}", final_message.len()));
-            emit_session_end(project_root_opt, &session_id, "done");
-            emitter(AgentProgress::Done {
-                message: final_message.clone(),
+            let mut final_message = response;
+            if let Some(config) = verification.as_ref().filter(|_| modified) {
+                modified = false;
+                emitter(AgentProgress::VerificationStarted);
+                let report = verify(project_root, changes.as_ref(), config, &verify_scratch).await;
+                if !report.checks.is_empty() {
+                    let passed = report.passed();
+                    append_log(
+                        Some(project_root),
+                        "agent.log",
+                        &format!("verification passed={} errors={}", passed, report.error_count()),
+                    );
+                    append_audit_event(
+                        project_root_opt,
+                        &session_id,
+                        &AuditEvent::VerificationRun {
+                            passed,
+                            commands: report.checks.iter().map(|c| c.command.clone()).collect(),
+                            errors: report.error_count(),
+                        },
+                    );
+                    emitter(AgentProgress::Verification {
+                        passed,
+                        checks: report.checks.clone(),
+                    });
+                    if !passed {
+                        verify_failures += 1;
+                        if verify_failures <= config.max_attempts {
+                            conversation.push(TranscriptMessage::Assistant { content: final_message });
+                            conversation.push(TranscriptMessage::System {
+                                content: format!(
+                                    "Verification failed after your changes. Fix these errors with apply_patch or create_file, then finish:\n{}",
+                                    redact(&report.failures())
+                                ),
+                            });
+                            tool_calls_in_run = 0;
+                            continue;
+                        }
+                        final_message.push_str(&format!(
+                            "\n\nПроверка не пройдена ({} ошибок), попытки исправления исчерпаны.",
+                            report.error_count()
+                        ));
+                    }
+                }
+            }
+
+            if let Some(plan) = plan.as_mut() {
+                let changed = plan.finish();
+                report_plan(plan, changed);
+            }
+            conversation.push(TranscriptMessage::Assistant {
+                content: final_message.clone(),
             });
+            append_log(Some(project_root), "agent.log", &format!("done msg_len={}", final_message.len()));
+            end_session("done");
+            emit_done(final_message.clone());
             break Ok(final_message);
         }
+    };
+    conversation.save(project_root);
+    if let Some(d) = delegation {
+        *d.approval_wait.lock().unwrap_or_else(|e| e.into_inner()) += approval_wait;
+        *d.tokens_used.lock().unwrap_or_else(|e| e.into_inner()) += total_tokens_approx;
+        *d.tool_calls.lock().unwrap_or_else(|e| e.into_inner()) += tool_calls_total;
+    }
+    if verification.is_some() && verify_scratch.exists() {
+        let _ = std::fs::remove_dir_all(&verify_scratch);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_calls_in_order() {
+        let response = "First read, then edit.\n\
+            ```tool_call\n{\"name\": \"read_file\", \"arguments\": {\"path\": \"a.rs\"}}\n```\n\
+            ```tool_call\n{\"name\": \"apply_patch\", \"step\": 2, \"arguments\": {\"path\": \"a.rs\", \"before\": \"x\", \"after\": \"y\"}}\n```\n\
+            ```tool_call\nnot json\n```\n\
+            ```tool_call\n{\"name\": \"read_file\", \"arguments\": {\"path\": \"b.rs\"}}";
+        let calls = parse_planned_calls(response);
+        let names: Vec<(&str, Option<&str>, Option<usize>)> = calls
+            .iter()
+            .map(|(c, step)| (c.name.as_str(), c.arguments["path"].as_str(), *step))
+            .collect();
+        // Битый блок пропущен, последний — без закрывающего ```.
+        assert_eq!(
+            names,
+            vec![
+                ("read_file", Some("a.rs"), None),
+                ("apply_patch", Some("a.rs"), Some(2)),
+                ("read_file", Some("b.rs"), None),
+            ]
+        );
+        assert_eq!(parse_tool_call(response).unwrap().arguments["path"], "a.rs");
+        assert!(parse_tool_calls("no tools here").is_empty());
+    }
+
+    #[test]
+    fn read_only_calls() {
+        let call = |name: &str| ToolCall {
+            name: name.to_string(),
+            arguments: serde_json::json!({}),
+        };
+        assert!(is_read_only(&call("read_file"), &[]));
+        assert!(is_read_only(&call("list_symbols"), &[]));
+        assert!(!is_read_only(&call("apply_patch"), &[]));
+        assert!(!is_read_only(&call("mcp::git::status"), &[]));
+    }
+
+    #[test]
+    fn compacts_only_past_threshold() {
+        let mut transcript = AgentTranscript::new("s1", "task");
+        for i in 0..6 {
+            transcript.messages.push(TranscriptMessage::Assistant {
+                content: format!("step {}", i),
+            });
+            transcript.messages.push(TranscriptMessage::ToolResult {
+                success: true,
+                output: "x".repeat(4000),
+            });
+        }
+        let mut conversation = Conversation {
+            prefix: "system".into(),
+            transcript,
+            persist: false,
+        };
+        let used = conversation.prompt().len() - conversation.prefix.len();
+        // Окно, в котором занято чуть меньше и чуть больше порога.
+        let window = |percent: usize| used * 100 / percent / CHARS_PER_TOKEN + RESPONSE_MAX_TOKENS + 10;
+        assert!(conversation.compact(window(COMPACT_AT_PERCENT - 5)).is_none());
+        let c = conversation.compact(window(COMPACT_AT_PERCENT + 5)).unwrap();
+        assert!(c.after_chars < c.before_chars);
+        let last = conversation.transcript.messages.last().unwrap();
+        assert!(matches!(last, TranscriptMessage::ToolResult { output, .. } if output.len() == 4000));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reads_keep_call_order() {
+        let root = std::env::temp_dir().join(format!("kengaide-reads-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("a.txt"), "A").unwrap();
+        std::fs::write(root.join("b.txt"), "B").unwrap();
+        let executor = Arc::new(ToolExecutor::new(root.clone()));
+        let call = |name: &str, path: &str| ToolCall {
+            name: name.to_string(),
+            arguments: serde_json::json!({ "path": path }),
+        };
+        // Локальные и MCP-вызовы вперемешку: результаты — в порядке вызовов.
+        let calls = vec![
+            call("read_file", "b.txt"),
+            call("mcp::docs::search", "x"),
+            call("read_file", "a.txt"),
+            call("read_file", "missing.txt"),
+        ];
+        let results = run_reads(&executor, None, &calls).await;
+        let _ = std::fs::remove_dir_all(&root);
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0], (true, "B".to_string()));
+        assert_eq!(results[1], (false, "MCP not configured".to_string()));
+        assert_eq!(results[2], (true, "A".to_string()));
+        assert!(!results[3].0);
     }
 }
@@ -0,0 +1,212 @@
+//! Подтверждение изменений агента пользователем (human-in-the-loop).
+//!
+//! В режиме подтверждения цикл агента останавливается на изменяющих вызовах, эмитит
+//! `AgentProgress::ApprovalRequired` с предлагаемым diff и ждёт решения из UI.
+
+use agent_tools::{ToolCall, ToolExecutor};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+use crate::planning::{PendingPlan, PlanDecision};
+
+/// Инструменты только для чтения: по умолчанию выполняются без подтверждения.
+pub const READ_ONLY_TOOLS: &[&str] = &["read_file", "list_files", "find_definition", "list_symbols"];
+
+/// Сколько ждать решения пользователя по умолчанию.
+const DEFAULT_APPROVAL_TIMEOUT_SECS: u64 = 600;
+
+fn default_auto_approve() -> Vec<String> {
+    READ_ONLY_TOOLS.iter().map(|t| t.to_string()).collect()
+}
+
+fn default_timeout_secs() -> u64 {
+    DEFAULT_APPROVAL_TIMEOUT_SECS
+}
+
+/// Политика подтверждения вызовов инструментов агента.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApprovalPolicy {
+    /// Останавливаться на вызовах вне `auto_approve` и ждать решения пользователя.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Инструменты без подтверждения; `mcp::server::*` — все инструменты MCP-сервера.
+    #[serde(default = "default_auto_approve")]
+    pub auto_approve: Vec<String>,
+    /// Сколько ждать решения; без решения вызов отклоняется. 0 — ждать без ограничения.
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for ApprovalPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            auto_approve: default_auto_approve(),
+            timeout_secs: DEFAULT_APPROVAL_TIMEOUT_SECS,
+        }
+    }
+}
+
+impl ApprovalPolicy {
+    pub fn timeout(&self) -> Option<Duration> {
+        (self.timeout_secs > 0).then(|| Duration::from_secs(self.timeout_secs))
+    }
+
+    pub fn requires_approval(&self, tool: &str) -> bool {
+        self.enabled
+            && !self.auto_approve.iter().any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => tool.starts_with(prefix),
+                None => pattern == tool,
+            })
+    }
+}
+
+/// Решение пользователя по предложенному вызову.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "decision", rename_all = "snake_case")]
+pub enum ApprovalDecision {
+    Approve,
+    /// Подтвердить этот вызов и все следующие в сессии.
+    ApproveAll,
+    Reject {
+        #[serde(default)]
+        reason: Option<String>,
+    },
+    /// Выполнить с исправленными аргументами (например, другим `after` в apply_patch).
+    Edit { arguments: serde_json::Value },
+}
+
+/// Вызов, ждущий подтверждения (то же, что в событии ApprovalRequired).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingApproval {
+    pub approval_id: String,
+    pub name: String,
+    pub path: Option<String>,
+    pub diff: String,
+    pub arguments: serde_json::Value,
+}
+
+type PendingMap = HashMap<String, (PendingApproval, oneshot::Sender<ApprovalDecision>)>;
+type PendingPlans = HashMap<String, (PendingPlan, oneshot::Sender<PlanDecision>)>;
+
+/// Ожидающие решения запросы подтверждения и планы на проверке (общие для всех сессий агента).
+#[derive(Default)]
+pub struct ApprovalGate {
+    pending: Mutex<PendingMap>,
+    plans: Mutex<PendingPlans>,
+}
+
+impl ApprovalGate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Регистрирует запрос; решение придёт через `resolve`.
+    pub(crate) fn wait(&self, request: PendingApproval) -> oneshot::Receiver<ApprovalDecision> {
+        let (tx, rx) = oneshot::channel();
+        self.lock().insert(request.approval_id.clone(), (request, tx));
+        rx
+    }
+
+    /// Снимает запрос без решения (истёк таймаут ожидания).
+    pub(crate) fn cancel(&self, approval_id: &str) {
+        self.lock().remove(approval_id);
+    }
+
+    /// Передаёт решение ожидающему агенту. false — запроса нет (уже решён или агент завершён).
+    pub fn resolve(&self, approval_id: &str, decision: ApprovalDecision) -> bool {
+        match self.lock().remove(approval_id) {
+            Some((_, tx)) => tx.send(decision).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Запросы, ждущие решения (например, для UI, открытого после события ApprovalRequired).
+    pub fn pending(&self) -> Vec<PendingApproval> {
+        self.lock()
+            .values()
+            .filter(|(_, tx)| !tx.is_closed())
+            .map(|(request, _)| request.clone())
+            .collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, PendingMap> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Регистрирует план на проверку; решение придёт через `resolve_plan`.
+    pub(crate) fn wait_plan(&self, plan: PendingPlan) -> oneshot::Receiver<PlanDecision> {
+        let (tx, rx) = oneshot::channel();
+        self.lock_plans().insert(plan.plan_id.clone(), (plan, tx));
+        rx
+    }
+
+    /// Снимает план с проверки без решения (истёк таймаут ожидания).
+    pub(crate) fn cancel_plan(&self, plan_id: &str) {
+        self.lock_plans().remove(plan_id);
+    }
+
+    /// Передаёт решение по плану. false — план не ждёт решения.
+    pub fn resolve_plan(&self, plan_id: &str, decision: PlanDecision) -> bool {
+        match self.lock_plans().remove(plan_id) {
+            Some((_, tx)) => tx.send(decision).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Планы, ждущие проверки пользователем.
+    pub fn pending_plans(&self) -> Vec<PendingPlan> {
+        self.lock_plans()
+            .values()
+            .filter(|(_, tx)| !tx.is_closed())
+            .map(|(plan, _)| plan.clone())
+            .collect()
+    }
+
+    fn lock_plans(&self) -> std::sync::MutexGuard<'_, PendingPlans> {
+        self.plans.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Режим подтверждения для одного запуска агента.
+#[derive(Clone)]
+pub struct AgentApproval {
+    pub gate: Arc<ApprovalGate>,
+    pub policy: ApprovalPolicy,
+}
+
+/// Предлагаемое изменение в виде unified diff (для файловых инструментов) или аргументов вызова.
+/// Текущее содержимое берётся через executor — с учётом незаписанных правок сессии.
+pub(crate) fn proposed_diff(executor: &ToolExecutor, call: &ToolCall) -> String {
+    let arg = |name: &str| call.arguments.get(name).and_then(|v| v.as_str());
+    let path = arg("path").map(|p| p.trim_start_matches('/'));
+    let current = path.and_then(|p| executor.file_content(p));
+    let file_diff = |old: &str, new: &str, path: &str| {
+        diffy::DiffOptions::new()
+            .set_original_filename(format!("a/{}", path))
+            .set_modified_filename(format!("b/{}", path))
+            .create_patch(old, new)
+            .to_string()
+    };
+    match (call.name.as_str(), path) {
+        ("apply_patch", Some(path)) => {
+            let (before, after) = (arg("before").unwrap_or(""), arg("after").unwrap_or(""));
+            match current {
+                // Патч в контексте файла, если блок находится однозначно.
+                Some(old) if !before.is_empty() && old.matches(before).count() == 1 => {
+                    file_diff(&old, &old.replacen(before, after, 1), path)
+                }
+                _ => file_diff(before, after, path),
+            }
+        }
+        ("create_file" | "update_file", Some(path)) => file_diff(
+            current.as_deref().unwrap_or(""),
+            arg("content").unwrap_or(""),
+            path,
+        ),
+        _ => serde_json::to_string_pretty(&call.arguments).unwrap_or_default(),
+    }
+}
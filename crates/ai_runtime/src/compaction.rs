@@ -0,0 +1,329 @@
+//! Сжатие разговора агента, когда он приближается к окну модели.
+//!
+//! Системный промпт и последние ходы остаются дословно. Сначала большие результаты
+//! инструментов и содержимое файлов в старых вызовах заменяются короткими сводками;
+//! если этого мало — старые сообщения сворачиваются в одно резюме. Если окно переполняют
+//! сами последние ходы, их самые большие результаты инструментов обрезаются в середине.
+
+use backend_core::TranscriptMessage;
+
+/// Сколько последних ответов модели (с их результатами) не трогать.
+const KEEP_RECENT_TURNS: usize = 2;
+/// Результаты и вызовы короче этого не сжимаются.
+const DIGEST_MIN_CHARS: usize = 600;
+/// Длина цитаты сообщения пользователя или ответа в резюме.
+const SUMMARY_QUOTE_CHARS: usize = 300;
+
+/// Итог сжатия (в символах промпта).
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Compaction {
+    pub before_chars: usize,
+    pub after_chars: usize,
+}
+
+/// Начало последних KEEP_RECENT_TURNS ходов: индекс соответствующего ответа модели.
+fn recent_start(messages: &[TranscriptMessage]) -> usize {
+    messages
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, m)| matches!(m, TranscriptMessage::Assistant { .. }))
+        .nth(KEEP_RECENT_TURNS - 1)
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+fn message_chars(m: &TranscriptMessage) -> usize {
+    match m {
+        TranscriptMessage::User { content }
+        | TranscriptMessage::Assistant { content }
+        | TranscriptMessage::System { content }
+        | TranscriptMessage::Summary { content } => content.len(),
+        TranscriptMessage::ToolResult { output, .. } => output.len(),
+        TranscriptMessage::ToolCall { .. } => 0,
+    }
+}
+
+/// Символов в сообщениях (форматирование ролей — копейки, не учитывается).
+fn total_chars(messages: &[TranscriptMessage]) -> usize {
+    messages.iter().map(message_chars).sum()
+}
+
+fn quote(s: &str, max: usize) -> String {
+    let s = s.trim();
+    match s.char_indices().nth(max) {
+        Some((end, _)) => format!("{}…", &s[..end]),
+        None => s.to_string(),
+    }
+}
+
+/// "apply_patch src/main.rs" — имя вызова и путь, если есть.
+fn describe_call(name: &str, arguments: &serde_json::Value) -> String {
+    match arguments.get("path").and_then(|v| v.as_str()) {
+        Some(path) => format!("{} {}", name, path),
+        None => name.to_string(),
+    }
+}
+
+/// Заменяет большие результаты и вызовы до `end` сводками. Возвращает, сколько заменено.
+fn digest_old(messages: &mut [TranscriptMessage], end: usize) -> usize {
+    let mut replaced = 0;
+    // Вызовы хода: их результаты приходят одним сообщением.
+    let mut pending: Vec<String> = Vec::new();
+    for m in messages.iter_mut().take(end) {
+        match m {
+            TranscriptMessage::ToolCall { name, arguments } => {
+                pending.push(describe_call(name, arguments));
+            }
+            TranscriptMessage::ToolResult { success, output } => {
+                let calls = std::mem::take(&mut pending);
+                if output.len() > DIGEST_MIN_CHARS {
+                    *output = format!(
+                        "[compacted: {} {} — {} lines, {} chars; call the tool again if you need it]",
+                        if calls.is_empty() { "tool".to_string() } else { calls.join(", ") },
+                        if *success { "succeeded" } else { "failed" },
+                        output.lines().count(),
+                        output.len()
+                    );
+                    replaced += 1;
+                }
+            }
+            TranscriptMessage::Assistant { content } if content.len() > DIGEST_MIN_CHARS => {
+                // Вызовы с содержимым файлов: аргументы уже сохранены в записях ToolCall.
+                let calls = crate::agent::parse_tool_calls(content);
+                if !calls.is_empty() {
+                    let described: Vec<String> = calls
+                        .iter()
+                        .map(|c| describe_call(&c.name, &c.arguments))
+                        .collect();
+                    *content = format!(
+                        "[compacted tool_call: {}, {} chars]",
+                        described.join(", "),
+                        content.len()
+                    );
+                    replaced += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    replaced
+}
+
+/// Середина текста заменяется пометкой, остаются `keep` символов (начало и конец).
+fn cut_middle(s: &str, keep: usize) -> String {
+    let chars = s.chars().count();
+    if chars <= keep {
+        return s.to_string();
+    }
+    let head: String = s.chars().take(keep / 2).collect();
+    let tail: String = s.chars().skip(chars - (keep - keep / 2)).collect();
+    format!("{}\n[… {} chars truncated …]\n{}", head, chars - keep, tail)
+}
+
+/// Обрезает самые большие результаты инструментов, пока сообщения не уложатся в
+/// `target_chars` (каждый — не короче DIGEST_MIN_CHARS). Возвращает, сколько обрезано.
+fn truncate_outputs(messages: &mut [TranscriptMessage], target_chars: usize) -> usize {
+    let mut by_size: Vec<(usize, usize)> = messages
+        .iter()
+        .enumerate()
+        .filter_map(|(i, m)| match m {
+            TranscriptMessage::ToolResult { output, .. } if output.len() > DIGEST_MIN_CHARS => {
+                Some((i, output.len()))
+            }
+            _ => None,
+        })
+        .collect();
+    by_size.sort_by_key(|&(_, len)| std::cmp::Reverse(len));
+    let mut truncated = 0;
+    for (i, _) in by_size {
+        let excess = total_chars(messages).saturating_sub(target_chars);
+        if excess == 0 {
+            break;
+        }
+        if let TranscriptMessage::ToolResult { output, .. } = &mut messages[i] {
+            let keep = output.chars().count().saturating_sub(excess).max(DIGEST_MIN_CHARS);
+            let cut = cut_middle(output, keep);
+            if cut.len() < output.len() {
+                *output = cut;
+                truncated += 1;
+            }
+        }
+    }
+    truncated
+}
+
+/// Резюме сообщений: задачи пользователя, действия и их итог, ответы модели.
+fn summarize(messages: &[TranscriptMessage]) -> String {
+    let mut lines = Vec::new();
+    let mut pending: Vec<String> = Vec::new();
+    for m in messages {
+        match m {
+            TranscriptMessage::User { content } => lines.push(format!(
+                "- User asked: {}",
+                quote(content, SUMMARY_QUOTE_CHARS)
+            )),
+            TranscriptMessage::Summary { content } => lines.push(content.trim().to_string()),
+            TranscriptMessage::ToolCall { name, arguments } => {
+                pending.push(describe_call(name, arguments));
+            }
+            TranscriptMessage::ToolResult { success, .. } => {
+                let calls = std::mem::take(&mut pending);
+                let status = match (calls.len(), *success) {
+                    (0, _) => continue,
+                    (1, true) => "ok",
+                    (1, false) => "failed",
+                    (_, true) => "all ok",
+                    (_, false) => "some failed",
+                };
+                lines.push(format!("- {} ({})", calls.join(", "), status));
+            }
+            TranscriptMessage::Assistant { content } => {
+                if crate::agent::parse_tool_call(content).is_none() {
+                    lines.push(format!(
+                        "- Assistant: {}",
+                        quote(content, SUMMARY_QUOTE_CHARS)
+                    ));
+                }
+            }
+            TranscriptMessage::System { .. } => {}
+        }
+    }
+    lines.join("\n")
+}
+
+/// Сжимает сообщения до `target_chars` (без учёта префикса). None — сжимать нечего.
+pub(crate) fn compact(
+    messages: &mut Vec<TranscriptMessage>,
+    target_chars: usize,
+) -> Option<Compaction> {
+    let before_chars = total_chars(messages);
+    let end = recent_start(messages);
+    let mut changed = false;
+    if end > 0 {
+        changed = digest_old(messages, end) > 0;
+        if total_chars(messages) > target_chars {
+            let older: Vec<TranscriptMessage> = messages.drain(..end).collect();
+            messages.insert(
+                0,
+                TranscriptMessage::Summary {
+                    content: summarize(&older),
+                },
+            );
+            changed = true;
+        }
+    }
+    // Последние ходы сами не помещаются в окно.
+    if total_chars(messages) > target_chars {
+        changed |= truncate_outputs(messages, target_chars) > 0;
+    }
+    let after_chars = total_chars(messages);
+    (changed && after_chars < before_chars).then_some(Compaction {
+        before_chars,
+        after_chars,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(s: &str) -> TranscriptMessage {
+        TranscriptMessage::User { content: s.into() }
+    }
+
+    fn assistant(s: &str) -> TranscriptMessage {
+        TranscriptMessage::Assistant { content: s.into() }
+    }
+
+    fn read_call(path: &str) -> TranscriptMessage {
+        TranscriptMessage::ToolCall {
+            name: "read_file".into(),
+            arguments: serde_json::json!({ "path": path }),
+        }
+    }
+
+    fn result(output: String) -> TranscriptMessage {
+        TranscriptMessage::ToolResult {
+            success: true,
+            output,
+        }
+    }
+
+    /// Ход модели: ответ с вызовом read_file и его результат.
+    fn turn(path: &str, output_chars: usize) -> Vec<TranscriptMessage> {
+        vec![
+            assistant(&format!(
+                "```tool_call\n{{\"name\": \"read_file\", \"arguments\": {{\"path\": \"{}\"}}}}\n```",
+                path
+            )),
+            read_call(path),
+            result("x".repeat(output_chars)),
+        ]
+    }
+
+    fn conversation(turns: &[(&str, usize)]) -> Vec<TranscriptMessage> {
+        let mut messages = vec![user("refactor the parser")];
+        for (path, chars) in turns {
+            messages.extend(turn(path, *chars));
+        }
+        messages
+    }
+
+    #[test]
+    fn nothing_to_compact_when_recent_turns_fit() {
+        let mut messages = conversation(&[("a.rs", 100), ("b.rs", 100)]);
+        assert!(compact(&mut messages, 10_000).is_none());
+        assert_eq!(messages.len(), 7);
+    }
+
+    #[test]
+    fn old_results_are_digested_first() {
+        let mut messages = conversation(&[("a.rs", 5000), ("b.rs", 100), ("c.rs", 100)]);
+        let c = compact(&mut messages, 10_000).unwrap();
+        assert!(c.after_chars < c.before_chars);
+        // Большой старый результат заменён сводкой, резюме не понадобилось.
+        assert!(matches!(&messages[3], TranscriptMessage::ToolResult { output, .. }
+            if output.starts_with("[compacted: read_file a.rs succeeded")));
+        assert!(matches!(&messages[0], TranscriptMessage::User { .. }));
+    }
+
+    #[test]
+    fn older_messages_fold_into_summary() {
+        let mut messages = conversation(&[("a.rs", 300), ("b.rs", 300), ("c.rs", 300), ("d.rs", 300)]);
+        compact(&mut messages, 700).unwrap();
+        // Резюме первым, затем два последних хода дословно.
+        assert_eq!(messages.len(), 7);
+        let TranscriptMessage::Summary { content } = &messages[0] else {
+            panic!("expected summary, got {:?}", messages[0]);
+        };
+        assert!(content.contains("- User asked: refactor the parser"));
+        assert!(content.contains("- read_file a.rs (ok)"));
+        assert!(content.contains("- read_file b.rs (ok)"));
+        assert!(!content.contains("c.rs"));
+        assert!(matches!(&messages[2], TranscriptMessage::ToolCall { arguments, .. } if arguments["path"] == "c.rs"));
+        assert!(matches!(&messages[6], TranscriptMessage::ToolResult { output, .. } if output.len() == 300));
+    }
+
+    #[test]
+    fn oversized_recent_turn_is_truncated() {
+        let mut messages = conversation(&[("huge.log", 50_000)]);
+        let c = compact(&mut messages, 5000).unwrap();
+        assert!(c.after_chars <= 5000 + 100, "{:?}", c);
+        let TranscriptMessage::ToolResult { output, .. } = &messages[3] else {
+            panic!("expected tool result");
+        };
+        assert!(output.contains("chars truncated"));
+        // Первое сообщение пользователя не трогается.
+        assert!(matches!(&messages[0], TranscriptMessage::User { content } if content == "refactor the parser"));
+    }
+
+    #[test]
+    fn cut_keeps_head_and_tail() {
+        let s = format!("{}{}{}", "a".repeat(10), "é".repeat(100), "z".repeat(10));
+        let cut = cut_middle(&s, 20);
+        assert!(cut.starts_with("aaaaaaaaaa\n[… 100 chars truncated …]\n"));
+        assert!(cut.ends_with("zzzzzzzzzz"));
+        assert_eq!(cut_middle("short", 20), "short");
+    }
+}
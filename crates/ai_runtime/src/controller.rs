@@ -3,8 +3,12 @@
 //! UI не ждёт полного ответа; получает чанки по событиям. Отмена через cancel(request_id).
 
 use ai_providers::{AiChunk, AiMode, AiProvider, EditorContext, GenerateOptions, GenerateRequest};
-use backend_core::{append_log, command_router::AiRequest};
-use context_manager::{Context, ContextBuilder, ContextLimits};
+use backend_core::{
+    append_log, command_router::AiRequest, selection_lines, ContextRef, ReferenceResolver,
+};
+use context_manager::{
+    Context, ContextBuilder, ContextLimits, OmittedItem, SemanticIndex, SymbolIndex,
+};
 use futures_util::StreamExt;
 use std::collections::HashMap;
 use std::path::Path;
@@ -16,6 +20,7 @@ use crate::error::AiRuntimeError;
 use crate::orchestration::TaskRole;
 use crate::prompt_builder::PromptBuilder;
 use crate::provider_selector::ProviderSelector;
+use crate::redaction::{record_redaction, Redactor};
 use crate::runtime::get_project_tree;
 
 /// Результат run_stream: request_id + метаданные для UI.
@@ -24,8 +29,13 @@ pub struct RunStreamResult {
     pub request_id: String,
     pub role: TaskRole,
     pub model_id: String,
+    /// Что не поместилось в окно модели (обрезано или отброшено).
+    pub context_omitted: Vec<OmittedItem>,
 }
 
+/// Сколько релевантных фрагментов из семантического индекса добавлять в контекст.
+const SEMANTIC_TOP_K: usize = 5;
+
 /// Эмиттер чанков: (request_id, chunk) → UI (например через Tauri events).
 pub type ChunkEmitter = Arc<dyn Fn(&str, &AiChunk) + Send + Sync>;
 
@@ -34,6 +44,10 @@ pub struct AiController {
     runtime: Arc<RwLock<crate::runtime::AiRuntime>>,
     /// request_id → провайдер, который обрабатывает этот запрос (для cancel).
     active_requests: Arc<RwLock<HashMap<String, Arc<dyn AiProvider>>>>,
+    /// Индекс открытого проекта; None — поиск по смыслу выключен.
+    semantic_index: RwLock<Option<Arc<SemanticIndex>>>,
+    /// Индекс символов открытого проекта: определения из выделения попадают в контекст.
+    symbol_index: RwLock<Option<Arc<SymbolIndex>>>,
 }
 
 impl AiController {
@@ -41,9 +55,29 @@ impl AiController {
         Self {
             runtime,
             active_requests: Arc::new(RwLock::new(HashMap::new())),
+            semantic_index: RwLock::new(None),
+            symbol_index: RwLock::new(None),
         }
     }
 
+    /// Подключает (или отключает) семантический индекс проекта.
+    pub async fn set_semantic_index(&self, index: Option<Arc<SemanticIndex>>) {
+        *self.semantic_index.write().await = index;
+    }
+
+    pub async fn semantic_index(&self) -> Option<Arc<SemanticIndex>> {
+        self.semantic_index.read().await.clone()
+    }
+
+    /// Подключает (или отключает) индекс символов проекта.
+    pub async fn set_symbol_index(&self, index: Option<Arc<SymbolIndex>>) {
+        *self.symbol_index.write().await = index;
+    }
+
+    pub async fn symbol_index(&self) -> Option<Arc<SymbolIndex>> {
+        self.symbol_index.read().await.clone()
+    }
+
     /// Запускает streaming-генерацию: строит контекст и промпт, выбирает провайдера,
     /// вызывает provider.generate(), эмитит каждый чанк через emitter.
     /// Возвращает request_id сразу после старта; поток чанков идёт асинхронно.
@@ -57,19 +91,8 @@ impl AiController {
         emitter: ChunkEmitter,
     ) -> Result<RunStreamResult, AiRuntimeError> {
         let (mode, user_input) = Self::extract_mode_and_input(&request);
-        let context_limits = {
-            let guard = self.runtime.read().await;
-            guard.context_limits().clone()
-        };
-        let context = self.build_context(
-            project_root,
-            current_file.as_ref(),
-            selection,
-            &context_limits,
-        )?;
-        let prompt = PromptBuilder::build(mode, &context, &user_input)?;
 
-        let (provider, role, model_id) = {
+        let (provider, role, model_id, context_limits) = {
             let guard = self.runtime.read().await;
             let sel = ProviderSelector::select(
                 guard.providers(),
@@ -87,17 +110,54 @@ impl AiController {
                 sel.policy_source
             );
             append_log(project_root, "runtime.log", &log_line);
-            (sel.provider, sel.role, sel.model_id)
+            // Контекст пакуется под окно выбранной модели.
+            let limits = guard
+                .context_limits()
+                .clone()
+                .with_max_tokens(sel.provider.capabilities().max_context_tokens);
+            (sel.provider, sel.role, sel.model_id, limits)
         };
+        let references = request.references().unwrap_or_default();
+        let context = self
+            .build_context(
+                project_root,
+                current_file.as_ref(),
+                selection,
+                &user_input,
+                &references,
+                &context_limits,
+            )
+            .await;
+        if !context.omitted.is_empty() {
+            let items: Vec<String> = context
+                .omitted
+                .iter()
+                .map(|o| format!("{}:{}/{}", o.name, o.kept_chars, o.original_chars))
+                .collect();
+            append_log(
+                project_root,
+                "runtime.log",
+                &format!(
+                    "context packed budget={} omitted=[{}]",
+                    context_limits.max_chars,
+                    items.join(", ")
+                ),
+            );
+        }
+        let prompt = PromptBuilder::build(mode, &context, &user_input)?;
         let request_id = Uuid::new_v4().to_string();
 
         let editor_ctx = Self::editor_context_from_request(&request, current_file.as_ref(), selection);
-        let gen_request = GenerateRequest {
+        let mut gen_request = GenerateRequest {
             id: request_id.clone(),
             prompt,
             context: Some(editor_ctx),
             mode,
         };
+        if let Some(redactor) = Redactor::for_provider(project_root, provider.provider_type()) {
+            let report = redactor.redact_request(&mut gen_request);
+            record_redaction(project_root, "runtime.log", &request_id, &report);
+        }
 
         let stream = provider
             .generate(gen_request, options)
@@ -128,6 +188,7 @@ impl AiController {
             request_id,
             role,
             model_id,
+            context_omitted: context.omitted,
         })
     }
 
@@ -144,14 +205,14 @@ impl AiController {
 
     fn extract_mode_and_input(request: &AiRequest) -> (AiMode, String) {
         match request {
-            AiRequest::Chat { message } => (AiMode::Chat, message.clone()),
+            AiRequest::Chat { message, .. } => (AiMode::Chat, message.clone()),
             AiRequest::Explain { selection: sel, .. } => {
                 let input = sel.clone().unwrap_or_else(|| "Explain this code".to_string());
                 (AiMode::Explain, input)
             }
             AiRequest::Refactor { instruction, .. } => (AiMode::Refactor, instruction.clone()),
             AiRequest::Generate { prompt, .. } => (AiMode::Generate, prompt.clone()),
-            AiRequest::Agent { message } => (AiMode::Agent, message.clone()),
+            AiRequest::Agent { message, .. } => (AiMode::Agent, message.clone()),
         }
     }
 
@@ -175,13 +236,15 @@ impl AiController {
         }
     }
 
-    fn build_context(
+    async fn build_context(
         &self,
         project_root: Option<&Path>,
         current_file: Option<&(std::path::PathBuf, String)>,
         selection: Option<&str>,
+        user_input: &str,
+        references: &[ContextRef],
         context_limits: &ContextLimits,
-    ) -> Result<Context, AiRuntimeError> {
+    ) -> Context {
         let mut builder = ContextBuilder::new(context_limits.clone());
         if let Some((path, content)) = current_file {
             builder = builder.current_file(path.clone(), content.clone());
@@ -192,6 +255,71 @@ impl AiController {
         if let Some(root) = project_root {
             builder = builder.project_tree(get_project_tree(root));
         }
-        builder.build().map_err(Into::into)
+        let symbol_index = self
+            .symbol_index()
+            .await
+            .filter(|index| project_root.is_none_or(|root| root == index.root()));
+        if let (Some(s), Some(index)) = (selection, symbol_index.as_deref()) {
+            builder = builder.symbol_definitions(index, s);
+        }
+        if let (Some(root), false) = (project_root, references.is_empty()) {
+            let mut resolver = ReferenceResolver::new(root);
+            if let Some(index) = symbol_index.as_deref() {
+                resolver = resolver.with_symbols(index);
+            }
+            if let Some((path, content)) = current_file {
+                let lines = selection.and_then(|s| selection_lines(content, s));
+                resolver = resolver.with_current_file(path, lines);
+            }
+            builder = builder.references(&resolver, references);
+        }
+        builder = self
+            .add_semantic_hits(builder, project_root, current_file, selection, user_input)
+            .await;
+        builder.build()
+    }
+
+    /// Добавляет top-k фрагментов индекса с их близостью как релевантностью.
+    /// Ошибка поиска не мешает запросу — контекст просто без них.
+    async fn add_semantic_hits(
+        &self,
+        mut builder: ContextBuilder,
+        project_root: Option<&Path>,
+        current_file: Option<&(std::path::PathBuf, String)>,
+        selection: Option<&str>,
+        user_input: &str,
+    ) -> ContextBuilder {
+        let Some(index) = self.semantic_index().await else {
+            return builder;
+        };
+        if project_root.is_some_and(|root| root != index.root()) {
+            return builder;
+        }
+        let query = match selection {
+            Some(s) if !s.trim().is_empty() => format!("{}\n{}", user_input, s),
+            _ => user_input.to_string(),
+        };
+        let hits = match index.search(&query, SEMANTIC_TOP_K).await {
+            Ok(hits) => hits,
+            Err(e) => {
+                tracing::warn!(error = %e, "semantic search failed");
+                return builder;
+            }
+        };
+        let current = current_file.map(|(p, _)| p);
+        for hit in hits {
+            // Текущий файл и так целиком в контексте.
+            if current.is_some_and(|p| p.ends_with(&hit.path)) {
+                continue;
+            }
+            let label = format!(
+                "{} (lines {}-{})",
+                hit.path.display(),
+                hit.start_line,
+                hit.end_line
+            );
+            builder = builder.add_ranked_file(label.into(), hit.content, hit.score);
+        }
+        builder
     }
 }
@@ -0,0 +1,216 @@
+//! Под-агенты: главный агент поручает часть задачи отдельному циклу с узкой инструкцией,
+//! списком инструментов, областью путей и бюджетом, и получает назад итог.
+//!
+//! Под-агент пишет аудит в сессию родителя (между SubAgentStarted и SubAgentFinished),
+//! его прогресс вкладывается в `AgentProgress::SubAgent`. Под-агент не может делегировать дальше.
+
+use backend_core::SessionSnapshot;
+use serde::Deserialize;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::approval::READ_ONLY_TOOLS;
+use crate::redaction::Placeholders;
+
+/// Имя инструмента делегирования.
+pub(crate) const DELEGATE_TOOL: &str = "delegate";
+
+/// Ходов под-агента по умолчанию и максимум.
+const SUB_AGENT_DEFAULT_TURNS: usize = 15;
+const SUB_AGENT_MAX_TURNS: usize = 30;
+
+/// Инструменты под-агента, если родитель не задал список.
+const SUB_AGENT_DEFAULT_TOOLS: &[&str] = &[
+    "list_files",
+    "read_file",
+    "find_definition",
+    "list_symbols",
+    "create_file",
+    "apply_patch",
+];
+
+/// Описание инструмента для системного промпта главного агента.
+pub(crate) const DELEGATE_TOOL_PROMPT: &str = "- delegate(task: string, tools?: string[], paths?: string[], max_turns?: number) — hand a self-contained part of a large task to a sub-agent. It works only with the listed tools (default: read and edit tools) and only under the listed paths (default: whole project), then returns a summary of what it did. Give it a precise task; check its summary before continuing.\n";
+
+/// Аргументы вызова delegate.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct DelegateRequest {
+    pub task: String,
+    #[serde(default)]
+    pub tools: Option<Vec<String>>,
+    #[serde(default)]
+    pub paths: Vec<String>,
+    #[serde(default)]
+    pub max_turns: Option<usize>,
+}
+
+impl DelegateRequest {
+    pub fn parse(arguments: &serde_json::Value) -> Result<Self, String> {
+        let request: Self = serde_json::from_value(arguments.clone())
+            .map_err(|e| format!("Invalid delegate arguments: {}", e))?;
+        if request.task.trim().is_empty() {
+            return Err("delegate requires a non-empty \"task\"".to_string());
+        }
+        Ok(request)
+    }
+
+    pub fn max_turns(&self) -> usize {
+        self.max_turns
+            .unwrap_or(SUB_AGENT_DEFAULT_TURNS)
+            .clamp(1, SUB_AGENT_MAX_TURNS)
+    }
+
+    pub fn scope(&self) -> SubAgentScope {
+        let tools = match &self.tools {
+            Some(tools) => tools
+                .iter()
+                .filter(|t| t.as_str() != DELEGATE_TOOL)
+                .cloned()
+                .collect(),
+            None => SUB_AGENT_DEFAULT_TOOLS.iter().map(|t| t.to_string()).collect(),
+        };
+        let paths = self
+            .paths
+            .iter()
+            .filter_map(|p| normalize(p))
+            .filter(|p| !p.is_empty())
+            .collect();
+        SubAgentScope { tools, paths }
+    }
+}
+
+/// Что разрешено под-агенту: инструменты и пути (пусто — весь проект).
+#[derive(Debug, Clone)]
+pub(crate) struct SubAgentScope {
+    pub tools: Vec<String>,
+    pub paths: Vec<String>,
+}
+
+impl SubAgentScope {
+    /// Ошибка для модели, если вызов вне области; None — можно выполнять.
+    pub fn check(&self, tool: &str, path: Option<&str>) -> Option<String> {
+        if !self.tools.iter().any(|t| t == tool) {
+            return Some(format!(
+                "Tool {} is not allowed for this sub-agent. Allowed tools: {}.",
+                tool,
+                self.tools.join(", ")
+            ));
+        }
+        if self.paths.is_empty() {
+            return None;
+        }
+        let inside = match path {
+            Some(path) => normalize(path).is_some_and(|p| {
+                self.paths
+                    .iter()
+                    .any(|scope| p == *scope || p.starts_with(&format!("{}/", scope)))
+            }),
+            // Без пути — только чтение (например, list_files корня).
+            None => READ_ONLY_TOOLS.contains(&tool),
+        };
+        (!inside).then(|| {
+            format!(
+                "Path {} is outside this sub-agent's scope. Allowed paths: {}.",
+                path.unwrap_or("(none)"),
+                self.paths.join(", ")
+            )
+        })
+    }
+
+    /// Ограничения для системного промпта под-агента.
+    pub fn prompt(&self) -> String {
+        let mut s = String::from(
+            "\n\nYou are a sub-agent working on one part of a larger task for another agent.\n",
+        );
+        s.push_str(&format!("Use ONLY these tools: {}.\n", self.tools.join(", ")));
+        if !self.paths.is_empty() {
+            s.push_str(&format!(
+                "Read and change ONLY files under: {}.\n",
+                self.paths.join(", ")
+            ));
+        }
+        s.push_str("When finished, reply without tool calls with a short summary: which files you changed and what remains to be done, if anything.");
+        s
+    }
+}
+
+/// Путь относительно корня в виде a/b; None — выход за пределы проекта.
+/// Ведущий '/' — от корня проекта, как в ToolExecutor.
+fn normalize(path: &str) -> Option<String> {
+    let path = path.replace('\\', "/");
+    let mut parts = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => return None,
+            p => parts.push(p),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+/// Запуск под-агента: область, бюджет из остатка родителя и общие с родителем снимок и аудит.
+pub(crate) struct Delegation {
+    /// Сессия родителя: в неё пишется аудит под-агента.
+    pub parent_session_id: String,
+    pub scope: SubAgentScope,
+    pub max_turns: usize,
+    pub max_time_ms: u64,
+    pub max_tokens: usize,
+    pub snapshot: Option<Arc<SessionSnapshot>>,
+    /// Плейсхолдеры секретов родителя: задача и вызовы под-агента могут их содержать.
+    pub placeholders: Arc<Placeholders>,
+    /// Сколько под-агент ждал решений пользователя — родитель не считает это своим временем.
+    pub approval_wait: Mutex<Duration>,
+    /// Израсходованное под-агентом (токены, вызовы инструментов) — родитель засчитывает себе.
+    pub tokens_used: Mutex<usize>,
+    pub tool_calls: Mutex<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scope(paths: &[&str]) -> SubAgentScope {
+        DelegateRequest {
+            task: "t".into(),
+            tools: None,
+            paths: paths.iter().map(|p| p.to_string()).collect(),
+            max_turns: None,
+        }
+        .scope()
+    }
+
+    #[test]
+    fn normalize_paths() {
+        assert_eq!(normalize("src/./lib.rs").as_deref(), Some("src/lib.rs"));
+        assert_eq!(normalize("/src/lib.rs").as_deref(), Some("src/lib.rs"));
+        assert_eq!(normalize("src\\main.rs").as_deref(), Some("src/main.rs"));
+        assert_eq!(normalize("src/../../etc/passwd"), None);
+        assert_eq!(normalize(".."), None);
+    }
+
+    #[test]
+    fn scope_checks_paths() {
+        let scope = scope(&["src", "/tests/"]);
+        assert_eq!(scope.paths, vec!["src", "tests"]);
+        assert!(scope.check("read_file", Some("src/lib.rs")).is_none());
+        assert!(scope.check("read_file", Some("/src/lib.rs")).is_none());
+        assert!(scope.check("apply_patch", Some("src")).is_none());
+        assert!(scope.check("read_file", Some("tests/a.rs")).is_none());
+        // Соседняя директория с тем же префиксом — вне области.
+        assert!(scope.check("read_file", Some("src2/lib.rs")).is_some());
+        assert!(scope.check("read_file", Some("src/../Cargo.toml")).is_some());
+        // Без пути — только чтение.
+        assert!(scope.check("list_files", None).is_none());
+        assert!(scope.check("create_file", None).is_some());
+    }
+
+    #[test]
+    fn scope_checks_tools() {
+        let all = scope(&[]);
+        assert!(all.check("apply_patch", Some("anything/x.rs")).is_none());
+        assert!(all.check(DELEGATE_TOOL, None).is_some());
+        assert!(all.check("run_command", None).is_some());
+    }
+}
@@ -5,6 +5,8 @@
 use ai_providers::ProviderError;
 use thiserror::Error;
 
+use crate::guardrails::GuardrailError;
+
 /// Ошибки AI Runtime.
 #[derive(Error, Debug)]
 pub enum AiRuntimeError {
@@ -14,12 +16,15 @@ pub enum AiRuntimeError {
     #[error("provider error: {0}")]
     Provider(#[from] ProviderError),
 
-    #[error("context error: {0}")]
-    Context(#[from] context_manager::ContextError),
-
     #[error("prompt build failed: {0}")]
     PromptBuild(String),
 
     #[error("response post-processing failed: {0}")]
     PostProcess(String),
+
+    #[error("agent session error: {0}")]
+    Session(String),
+
+    #[error("guardrails: {0}")]
+    Guardrails(#[from] GuardrailError),
 }
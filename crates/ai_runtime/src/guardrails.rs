@@ -0,0 +1,360 @@
+//! Ограничения цикла агента: шаги, вызовы инструментов, токены, время, повтор ошибок.
+//!
+//! Конфиг: guardrails.json в ~/.kengaide/ и project_root/.kengaide/ —
+//! `{"defaults": {...}, "roles": {"coding": {...}}}`. Поля задаются по отдельности: проект
+//! переопределяет пользователя, `roles.<role>` — `defaults` своего файла. Некорректный
+//! конфиг — ошибка запуска агента, а не тихий откат к умолчаниям.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::orchestration::TaskRole;
+
+const CONFIG_FILE: &str = "guardrails.json";
+
+#[derive(Debug, thiserror::Error)]
+pub enum GuardrailError {
+    #[error("Invalid guardrails config {path}: {source}")]
+    Parse {
+        path: String,
+        source: serde_json::Error,
+    },
+    #[error("Invalid guardrails config {path}: {message}")]
+    Invalid { path: String, message: String },
+}
+
+/// Значения ограничений для одной сессии агента.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentGuardrails {
+    /// Ходов модели за запуск.
+    pub max_turns: usize,
+    /// Вызовов инструментов на одно сообщение пользователя.
+    pub max_tool_calls_per_message: usize,
+    /// Сгенерированных токенов за запуск (~4 символа на токен).
+    pub max_tokens_per_session: usize,
+    /// Время работы без ожидания решений пользователя.
+    pub max_time_ms: u64,
+    /// Столько одинаковых ошибок подряд — зацикливание.
+    pub same_error_threshold: usize,
+}
+
+impl Default for AgentGuardrails {
+    fn default() -> Self {
+        Self {
+            max_turns: 50,
+            max_tool_calls_per_message: 8,
+            max_tokens_per_session: 32_000,
+            max_time_ms: 600_000,
+            same_error_threshold: 3,
+        }
+    }
+}
+
+impl AgentGuardrails {
+    /// Для AuditEvent::SessionStart.policy.
+    pub fn policy_summary(&self) -> String {
+        format!(
+            "max_turns={} max_tool_calls={} max_tokens={} max_time_ms={} same_error_threshold={}",
+            self.max_turns,
+            self.max_tool_calls_per_message,
+            self.max_tokens_per_session,
+            self.max_time_ms,
+            self.same_error_threshold
+        )
+    }
+
+    /// Сделано `turns` ходов — лимит исчерпан (ровно `max_turns` ходов разрешено).
+    pub fn turns_exhausted(&self, turns: usize) -> bool {
+        turns >= self.max_turns
+    }
+
+    /// Время работы больше лимита (ровно `max_time_ms` — ещё можно).
+    pub fn time_exceeded(&self, worked: Duration) -> bool {
+        worked.as_millis() > u128::from(self.max_time_ms)
+    }
+
+    /// Токенов больше лимита (ровно `max_tokens_per_session` — ещё можно).
+    pub fn tokens_exceeded(&self, tokens: usize) -> bool {
+        tokens > self.max_tokens_per_session
+    }
+
+    /// Сколько вызовов инструментов ещё можно сделать в этом сообщении; 0 — лимит исчерпан.
+    pub fn tool_call_budget(&self, used: usize) -> usize {
+        self.max_tool_calls_per_message.saturating_sub(used)
+    }
+
+    fn apply(&mut self, o: &GuardrailOverrides) {
+        if let Some(v) = o.max_turns {
+            self.max_turns = v;
+        }
+        if let Some(v) = o.max_tool_calls_per_message {
+            self.max_tool_calls_per_message = v;
+        }
+        if let Some(v) = o.max_tokens_per_session {
+            self.max_tokens_per_session = v;
+        }
+        if let Some(v) = o.max_time_ms {
+            self.max_time_ms = v;
+        }
+        if let Some(v) = o.same_error_threshold {
+            self.same_error_threshold = v;
+        }
+    }
+}
+
+/// Последние ошибки инструментов: `same_error_threshold` одинаковых подряд — зацикливание.
+pub(crate) struct ErrorStreak {
+    threshold: usize,
+    last: Vec<String>,
+}
+
+impl ErrorStreak {
+    pub(crate) fn new(threshold: usize) -> Self {
+        Self {
+            threshold,
+            last: Vec::with_capacity(threshold),
+        }
+    }
+
+    /// Запоминает ошибку; true — последние `threshold` ошибок одинаковые.
+    pub(crate) fn push(&mut self, error: String) -> bool {
+        self.last.push(error);
+        if self.last.len() > self.threshold {
+            self.last.remove(0);
+        }
+        self.last.len() >= self.threshold && self.last.iter().all(|e| *e == self.last[0])
+    }
+
+    /// Успех или отказ пользователя прерывает серию.
+    pub(crate) fn clear(&mut self) {
+        self.last.clear();
+    }
+}
+
+/// Заданные в файле значения; отсутствующие берутся с уровня ниже.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GuardrailOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_turns: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tool_calls_per_message: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens_per_session: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_time_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub same_error_threshold: Option<usize>,
+}
+
+impl GuardrailOverrides {
+    fn validate(&self) -> Result<(), String> {
+        let fields = [
+            ("max_turns", self.max_turns.map(|v| v as u64)),
+            (
+                "max_tool_calls_per_message",
+                self.max_tool_calls_per_message.map(|v| v as u64),
+            ),
+            (
+                "max_tokens_per_session",
+                self.max_tokens_per_session.map(|v| v as u64),
+            ),
+            ("max_time_ms", self.max_time_ms),
+            (
+                "same_error_threshold",
+                self.same_error_threshold.map(|v| v as u64),
+            ),
+        ];
+        for (name, value) in fields {
+            if value == Some(0) {
+                return Err(format!("{} must be greater than 0", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// guardrails.json.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GuardrailsConfig {
+    #[serde(default)]
+    pub defaults: GuardrailOverrides,
+    /// Переопределения по роли задачи (chat, coding, planning, analysis, documentation).
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub roles: HashMap<TaskRole, GuardrailOverrides>,
+}
+
+impl GuardrailsConfig {
+    fn validate(&self) -> Result<(), String> {
+        self.defaults.validate()?;
+        for (role, overrides) in &self.roles {
+            overrides
+                .validate()
+                .map_err(|e| format!("roles.{}: {}", role.as_str(), e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Читает и проверяет один файл; None — файла нет.
+fn read_config(path: &Path) -> Result<Option<GuardrailsConfig>, GuardrailError> {
+    let Ok(s) = std::fs::read_to_string(path) else {
+        return Ok(None);
+    };
+    let cfg: GuardrailsConfig =
+        serde_json::from_str(&s).map_err(|source| GuardrailError::Parse {
+            path: path.display().to_string(),
+            source,
+        })?;
+    cfg.validate().map_err(|message| GuardrailError::Invalid {
+        path: path.display().to_string(),
+        message,
+    })?;
+    Ok(Some(cfg))
+}
+
+/// Ограничения для роли: встроенные ← ~/.kengaide ← project/.kengaide, в каждом файле общие ← roles.<role>.
+pub fn load_guardrails(
+    project_root: Option<&Path>,
+    role: TaskRole,
+) -> Result<AgentGuardrails, GuardrailError> {
+    let paths: [Option<PathBuf>; 2] = [
+        dirs::home_dir().map(|h| h.join(".kengaide").join(CONFIG_FILE)),
+        project_root.map(|p| p.join(".kengaide").join(CONFIG_FILE)),
+    ];
+    let mut guardrails = AgentGuardrails::default();
+    for path in paths.into_iter().flatten() {
+        if let Some(cfg) = read_config(&path)? {
+            guardrails.apply(&cfg.defaults);
+            if let Some(overrides) = cfg.roles.get(&role) {
+                guardrails.apply(overrides);
+            }
+        }
+    }
+    Ok(guardrails)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> AgentGuardrails {
+        AgentGuardrails {
+            max_turns: 3,
+            max_tool_calls_per_message: 2,
+            max_tokens_per_session: 100,
+            max_time_ms: 1_000,
+            same_error_threshold: 2,
+        }
+    }
+
+    #[test]
+    fn default_limits() {
+        let d = AgentGuardrails::default();
+        assert_eq!(d.max_turns, 50);
+        assert_eq!(d.max_tool_calls_per_message, 8);
+        assert_eq!(d.max_tokens_per_session, 32_000);
+        assert_eq!(d.max_time_ms, 600_000);
+        assert_eq!(d.same_error_threshold, 3);
+    }
+
+    #[test]
+    fn turns_stop_at_limit() {
+        let g = limits();
+        assert!(!g.turns_exhausted(2));
+        assert!(g.turns_exhausted(3));
+        assert!(g.turns_exhausted(4));
+    }
+
+    #[test]
+    fn time_stops_past_limit() {
+        let g = limits();
+        assert!(!g.time_exceeded(Duration::from_millis(999)));
+        assert!(!g.time_exceeded(Duration::from_millis(1_000)));
+        assert!(g.time_exceeded(Duration::from_millis(1_001)));
+    }
+
+    #[test]
+    fn tokens_stop_past_limit() {
+        let g = limits();
+        assert!(!g.tokens_exceeded(99));
+        assert!(!g.tokens_exceeded(100));
+        assert!(g.tokens_exceeded(101));
+    }
+
+    #[test]
+    fn tool_call_budget_runs_out() {
+        let g = limits();
+        assert_eq!(g.tool_call_budget(0), 2);
+        assert_eq!(g.tool_call_budget(1), 1);
+        assert_eq!(g.tool_call_budget(2), 0);
+        assert_eq!(g.tool_call_budget(5), 0);
+    }
+
+    #[test]
+    fn repeated_errors_trip_at_threshold() {
+        let mut streak = ErrorStreak::new(3);
+        assert!(!streak.push("e".to_string()));
+        assert!(!streak.push("e".to_string()));
+        assert!(streak.push("e".to_string()));
+
+        // Разные ошибки подряд — не зацикливание; считаются только последние `threshold`.
+        let mut streak = ErrorStreak::new(3);
+        assert!(!streak.push("a".to_string()));
+        assert!(!streak.push("b".to_string()));
+        assert!(!streak.push("b".to_string()));
+        assert!(streak.push("b".to_string()));
+
+        let mut streak = ErrorStreak::new(2);
+        assert!(!streak.push("e".to_string()));
+        streak.clear();
+        assert!(!streak.push("e".to_string()));
+        assert!(streak.push("e".to_string()));
+    }
+
+    #[test]
+    fn overrides_apply_per_field() {
+        let cfg: GuardrailsConfig = serde_json::from_str(
+            r#"{"defaults": {"max_turns": 10, "max_time_ms": 5000},
+                "roles": {"coding": {"max_turns": 20}}}"#,
+        )
+        .unwrap();
+        cfg.validate().unwrap();
+        let mut g = AgentGuardrails::default();
+        g.apply(&cfg.defaults);
+        g.apply(&cfg.roles[&TaskRole::Coding]);
+        assert_eq!(g.max_turns, 20);
+        assert_eq!(g.max_time_ms, 5000);
+        assert_eq!(g.max_tool_calls_per_message, 8);
+    }
+
+    #[test]
+    fn rejects_zero_and_unknown_fields() {
+        let zero: GuardrailsConfig =
+            serde_json::from_str(r#"{"roles": {"coding": {"max_tokens_per_session": 0}}}"#)
+                .unwrap();
+        assert_eq!(
+            zero.validate().unwrap_err(),
+            "roles.coding: max_tokens_per_session must be greater than 0"
+        );
+        assert!(serde_json::from_str::<GuardrailsConfig>(r#"{"defaults": {"max_steps": 5}}"#)
+            .is_err());
+    }
+
+    #[test]
+    fn read_config_reports_path() {
+        let path = std::env::temp_dir().join(format!(
+            "kengaide-guardrails-{}.json",
+            std::process::id()
+        ));
+        assert!(read_config(&path).unwrap().is_none());
+        std::fs::write(&path, r#"{"defaults": {"max_turns": 0}}"#).unwrap();
+        let err = read_config(&path).unwrap_err();
+        let _ = std::fs::remove_file(&path);
+        assert!(matches!(err, GuardrailError::Invalid { .. }));
+        assert!(err.to_string().contains("max_turns must be greater than 0"));
+    }
+}
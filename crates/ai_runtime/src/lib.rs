@@ -3,20 +3,39 @@
 //! Pipeline: PromptBuilder → ProviderSelector → generate (streaming) → emit chunks.
 
 mod agent;
+mod approval;
+mod compaction;
 mod controller;
+mod delegation;
 mod error;
+mod guardrails;
 mod orchestration;
+mod planning;
 mod prompt_builder;
 mod provider_selector;
+mod redaction;
 mod runtime;
 mod streaming;
 
 pub use orchestration::{TaskRole, load_model_roles, ensure_model_roles_config};
 pub use agent::{
-    build_agent_system_prompt, run_agent_loop, AgentProgress, AgentProgressEmitter,
+    build_agent_system_prompt, run_agent_loop, AgentProgress, AgentProgressEmitter, AgentSessionOptions,
     AGENT_SYSTEM_PROMPT,
 };
+pub use approval::{
+    AgentApproval, ApprovalDecision, ApprovalGate, ApprovalPolicy, PendingApproval, READ_ONLY_TOOLS,
+};
+pub use agent_tools::{
+    load_verification_config, ChangeSelection, ChangeSet, CheckOutcome, CheckStatus, Diagnostic, StagedChange,
+    StagingError, VerificationConfig, VerificationReport,
+};
 pub use ai_providers::AiResponse;
 pub use controller::{AiController, ChunkEmitter};
 pub use error::AiRuntimeError;
+pub use guardrails::{load_guardrails, AgentGuardrails, GuardrailError, GuardrailOverrides, GuardrailsConfig};
+pub use planning::{AgentPlanning, PendingPlan, PlanDecision, PlanStep, PlanStepStatus, PlanningPolicy};
+pub use redaction::{
+    load_redaction_config, Placeholders, RedactionConfig, RedactionPattern, RedactionReport,
+    Redactor,
+};
 pub use runtime::AiRuntime;
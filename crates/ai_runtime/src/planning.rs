@@ -0,0 +1,291 @@
+//! Фаза планирования агента: список шагов до выполнения и их статус по ходу работы.
+//!
+//! План строит модель роли Planning (или модель сессии), пользователь может его исправить
+//! до начала выполнения. Вызовы инструментов с `"step": N` продвигают шаги плана.
+
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::approval::ApprovalGate;
+
+/// Максимум шагов плана; лишние отбрасываются.
+const MAX_PLAN_STEPS: usize = 12;
+/// Сколько ждать проверки плана по умолчанию.
+const DEFAULT_PLAN_TIMEOUT_SECS: u64 = 600;
+
+fn default_timeout_secs() -> u64 {
+    DEFAULT_PLAN_TIMEOUT_SECS
+}
+
+/// Промпт фазы планирования; задача пользователя добавляется в конец.
+pub(crate) const PLANNING_PROMPT: &str = r#"You are planning work for an IDE agent that edits the project with tools (list_files, read_file, find_definition, create_file, apply_patch, create_project).
+Break the user's task into 2-8 short, concrete steps in execution order. Do not call tools and do not write code.
+
+Output ONLY this block:
+```plan
+{"steps": ["first step", "second step"]}
+```"#;
+
+/// Настройки фазы планирования.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanningPolicy {
+    /// Строить план перед выполнением.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Ждать, пока пользователь подтвердит или исправит план.
+    #[serde(default)]
+    pub review: bool,
+    /// План строит модель роли Planning (model_roles.json), а не модель задачи.
+    #[serde(default)]
+    pub use_planning_model: bool,
+    /// Сколько ждать проверки; без решения задача отменяется. 0 — ждать без ограничения.
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for PlanningPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            review: false,
+            use_planning_model: false,
+            timeout_secs: DEFAULT_PLAN_TIMEOUT_SECS,
+        }
+    }
+}
+
+impl PlanningPolicy {
+    pub fn timeout(&self) -> Option<Duration> {
+        (self.timeout_secs > 0).then(|| Duration::from_secs(self.timeout_secs))
+    }
+}
+
+/// Фаза планирования для одного запуска агента.
+#[derive(Clone)]
+pub struct AgentPlanning {
+    pub gate: Arc<ApprovalGate>,
+    pub policy: PlanningPolicy,
+}
+
+/// Решение пользователя по предложенному плану.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "decision", rename_all = "snake_case")]
+pub enum PlanDecision {
+    Approve,
+    /// Выполнять исправленный план.
+    Edit { steps: Vec<String> },
+    /// Не выполнять задачу.
+    Cancel,
+}
+
+/// План, ждущий проверки (то же, что в событии Plan с review).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingPlan {
+    pub plan_id: String,
+    pub steps: Vec<PlanStep>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanStepStatus {
+    Pending,
+    InProgress,
+    Done,
+    Failed,
+}
+
+impl PlanStepStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlanStepStatus::Pending => "pending",
+            PlanStepStatus::InProgress => "in_progress",
+            PlanStepStatus::Done => "done",
+            PlanStepStatus::Failed => "failed",
+        }
+    }
+}
+
+/// Шаг плана; номер — с 1, как его видит модель.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanStep {
+    pub number: usize,
+    pub description: String,
+    pub status: PlanStepStatus,
+}
+
+/// План сессии и статус шагов.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AgentPlan {
+    pub steps: Vec<PlanStep>,
+}
+
+impl AgentPlan {
+    pub fn new(steps: Vec<String>) -> Self {
+        Self {
+            steps: steps
+                .into_iter()
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .take(MAX_PLAN_STEPS)
+                .enumerate()
+                .map(|(i, description)| PlanStep {
+                    number: i + 1,
+                    description,
+                    status: PlanStepStatus::Pending,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn descriptions(&self) -> Vec<String> {
+        self.steps.iter().map(|s| s.description.clone()).collect()
+    }
+
+    /// План для промпта агента.
+    pub fn prompt(&self) -> String {
+        let mut s = String::from(
+            "Plan for the user's latest request. Execute it in order. Add \"step\": N next to \"name\" in each tool_call to say which step it works on.\n",
+        );
+        for step in &self.steps {
+            s.push_str(&format!("{}. {}\n", step.number, step.description));
+        }
+        s.trim_end().to_string()
+    }
+
+    /// Отмечает результат вызова для шага (None — текущий шаг). Предыдущие шаги считаются
+    /// выполненными. Возвращает изменённые шаги.
+    pub fn record_call(&mut self, step: Option<usize>, success: bool) -> Vec<PlanStep> {
+        let current = step
+            .filter(|n| (1..=self.steps.len()).contains(n))
+            .or_else(|| {
+                self.steps
+                    .iter()
+                    .find(|s| s.status != PlanStepStatus::Done)
+                    .map(|s| s.number)
+            });
+        let Some(current) = current else {
+            return Vec::new();
+        };
+        let mut changed = Vec::new();
+        for s in &mut self.steps {
+            let status = if s.number < current && s.status != PlanStepStatus::Pending {
+                PlanStepStatus::Done
+            } else if s.number == current {
+                if success {
+                    PlanStepStatus::InProgress
+                } else {
+                    PlanStepStatus::Failed
+                }
+            } else {
+                continue;
+            };
+            if s.status != status {
+                s.status = status;
+                changed.push(s.clone());
+            }
+        }
+        changed
+    }
+
+    /// Агент закончил: начатые шаги выполнены, неначатые остаются pending.
+    pub fn finish(&mut self) -> Vec<PlanStep> {
+        let mut changed = Vec::new();
+        for s in &mut self.steps {
+            if s.status == PlanStepStatus::InProgress {
+                s.status = PlanStepStatus::Done;
+                changed.push(s.clone());
+            }
+        }
+        changed
+    }
+}
+
+/// Шаги из ответа модели: блок ```plan с JSON или нумерованный список.
+pub(crate) fn parse_plan(response: &str) -> Vec<String> {
+    #[derive(Deserialize)]
+    struct PlanBlock {
+        steps: Vec<String>,
+    }
+    let json = response
+        .find("```plan")
+        .map(|start| &response[start + "```plan".len()..])
+        .map(|rest| rest.split("```").next().unwrap_or(rest))
+        .or_else(|| response.find('{').map(|start| &response[start..]));
+    if let Some(plan) = json.and_then(|j| serde_json::from_str::<PlanBlock>(j.trim()).ok()) {
+        return plan.steps;
+    }
+    response
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            let rest = line.trim_start_matches(|c: char| c.is_ascii_digit());
+            (rest.len() < line.len())
+                .then(|| rest.strip_prefix(['.', ')']))
+                .flatten()
+                .map(|s| s.trim().to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statuses(plan: &AgentPlan) -> Vec<PlanStepStatus> {
+        plan.steps.iter().map(|s| s.status).collect()
+    }
+
+    #[test]
+    fn parses_plan_block_and_numbered_list() {
+        let block = "Sure.\n```plan\n{\"steps\": [\"read config\", \"add field\"]}\n```";
+        assert_eq!(parse_plan(block), vec!["read config", "add field"]);
+        let list = "Plan:\n1. read config\n2) add field\n- note\n10. run tests";
+        assert_eq!(parse_plan(list), vec!["read config", "add field", "run tests"]);
+    }
+
+    #[test]
+    fn malformed_or_empty_plan() {
+        // Битый JSON — разбирается как список; списка нет — шагов нет.
+        assert!(parse_plan("```plan\n{\"steps\": [\"a\",\n```").is_empty());
+        assert!(parse_plan("I will just do it.").is_empty());
+        assert!(parse_plan("```plan\n{\"steps\": []}\n```").is_empty());
+        // Пустые шаги отбрасываются, лишние обрезаются.
+        let plan = AgentPlan::new(vec!["  ".into(), "one".into(), String::new()]);
+        assert_eq!(plan.descriptions(), vec!["one"]);
+        assert_eq!(plan.steps[0].number, 1);
+        let many = AgentPlan::new((0..20).map(|i| i.to_string()).collect());
+        assert_eq!(many.steps.len(), MAX_PLAN_STEPS);
+    }
+
+    #[test]
+    fn record_call_advances_steps() {
+        use PlanStepStatus::*;
+        let mut plan = AgentPlan::new(vec!["a".into(), "b".into(), "c".into()]);
+        // Без номера — текущий (первый невыполненный) шаг.
+        assert_eq!(plan.record_call(None, true).len(), 1);
+        assert_eq!(statuses(&plan), vec![InProgress, Pending, Pending]);
+        // Переход к шагу 2 завершает начатый шаг 1.
+        plan.record_call(Some(2), true);
+        assert_eq!(statuses(&plan), vec![Done, InProgress, Pending]);
+        plan.record_call(Some(3), false);
+        assert_eq!(statuses(&plan), vec![Done, Done, Failed]);
+        // Номер вне плана — текущий шаг; повтор без изменений ничего не возвращает.
+        plan.record_call(Some(7), true);
+        assert_eq!(statuses(&plan), vec![Done, Done, InProgress]);
+        assert!(plan.record_call(Some(3), true).is_empty());
+        assert_eq!(plan.finish().len(), 1);
+        assert_eq!(statuses(&plan), vec![Done, Done, Done]);
+    }
+
+    #[test]
+    fn unstarted_steps_are_not_marked_done() {
+        use PlanStepStatus::*;
+        let mut plan = AgentPlan::new(vec!["a".into(), "b".into(), "c".into()]);
+        plan.record_call(Some(3), true);
+        assert_eq!(statuses(&plan), vec![Pending, Pending, InProgress]);
+        plan.finish();
+        assert_eq!(statuses(&plan), vec![Pending, Pending, Done]);
+        assert!(AgentPlan::default().record_call(None, true).is_empty());
+    }
+}
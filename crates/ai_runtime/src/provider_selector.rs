@@ -32,6 +32,17 @@ impl ProviderSelector {
         project_root: Option<&Path>,
     ) -> Result<ProviderSelection, AiRuntimeError> {
         let role = TaskClassifier::classify(mode, user_message);
+        Self::select_for_role(providers, mode, role, preferred_id, project_root).await
+    }
+
+    /// Выбирает провайдера для заданной роли (например, Planning для фазы планирования).
+    pub async fn select_for_role(
+        providers: &[Arc<dyn AiProvider>],
+        mode: AiMode,
+        role: TaskRole,
+        preferred_id: Option<&str>,
+        project_root: Option<&Path>,
+    ) -> Result<ProviderSelection, AiRuntimeError> {
         let model_roles = load_model_roles(project_root);
         let target_model_id = RoleResolver::resolve(role, &model_roles);
 
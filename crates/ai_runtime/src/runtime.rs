@@ -2,6 +2,8 @@
 
 use ai_providers::{AiChunk, AiMode, AiProvider, EditorContext, GenerateOptions, GenerateRequest};
 use backend_core::command_router::AiRequest;
+use backend_core::fs::ProjectWalker;
+use backend_core::references::{selection_lines, ContextRef, ReferenceResolver};
 use context_manager::{Context, ContextBuilder, ContextLimits};
 use futures_util::StreamExt;
 use model_manager::ModelManager;
@@ -14,12 +16,13 @@ use ai_providers::AiResponse;
 use crate::error::AiRuntimeError;
 use crate::prompt_builder::PromptBuilder;
 use crate::provider_selector::ProviderSelector;
+use crate::redaction::{record_redaction, Redactor};
 
 /// AI Runtime. Управляет провайдерами, контекстом, вызывает generate.
 pub struct AiRuntime {
     providers: Vec<Arc<dyn AiProvider>>,
     preferred_provider_id: Option<String>,
-    #[allow(dead_code)]
+    /// Каталог установленных моделей.
     model_manager: Arc<ModelManager>,
     context_limits: ContextLimits,
     #[allow(dead_code)]
@@ -28,13 +31,7 @@ pub struct AiRuntime {
 
 /// Публичный доступ к дереву проекта (для контроллера).
 pub fn get_project_tree(root: &Path) -> Vec<std::path::PathBuf> {
-    walkdir::WalkDir::new(root)
-        .max_depth(5)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file())
-        .filter_map(|e| e.path().strip_prefix(root).ok().map(|p| p.to_path_buf()))
-        .collect()
+    ProjectWalker::new(root).max_depth(5).files()
 }
 
 impl AiRuntime {
@@ -67,6 +64,11 @@ impl AiRuntime {
         &self.providers
     }
 
+    /// Каталог моделей (список, удаление, проверка установленных моделей).
+    pub fn model_catalog(&self) -> Arc<ModelManager> {
+        Arc::clone(&self.model_manager)
+    }
+
     pub fn context_limits(&self) -> &ContextLimits {
         &self.context_limits
     }
@@ -81,10 +83,7 @@ impl AiRuntime {
     ) -> Result<AiResponse, AiRuntimeError> {
         let (mode, user_input) = Self::extract_mode_and_input(&request);
         let editor_ctx = editor_context_from_request(&request, current_file.as_ref(), selection);
-        let context = self.build_context(project_root, current_file, selection)?;
-        let prompt = PromptBuilder::build(mode, &context, &user_input)?;
-
-        let selection = ProviderSelector::select(
+        let provider_selection = ProviderSelector::select(
             &self.providers,
             mode,
             &user_input,
@@ -92,14 +91,25 @@ impl AiRuntime {
             project_root,
         )
         .await?;
-        let provider = selection.provider;
+        let provider = provider_selection.provider;
+        let limits = self
+            .context_limits
+            .clone()
+            .with_max_tokens(provider.capabilities().max_context_tokens);
+        let references = request.references().unwrap_or_default();
+        let context = Self::build_context(limits, project_root, current_file, selection, &references);
+        let prompt = PromptBuilder::build(mode, &context, &user_input)?;
         let request_id = Uuid::new_v4().to_string();
-        let gen_request = GenerateRequest {
+        let mut gen_request = GenerateRequest {
             id: request_id,
             prompt,
             context: Some(editor_ctx),
             mode,
         };
+        if let Some(redactor) = Redactor::for_provider(project_root, provider.provider_type()) {
+            let report = redactor.redact_request(&mut gen_request);
+            record_redaction(project_root, "runtime.log", &gen_request.id, &report);
+        }
         let options = GenerateOptions {
             temperature: None,
             max_tokens: None,
@@ -119,7 +129,7 @@ impl AiRuntime {
                     break;
                 }
                 AiChunk::Error { error } => return Err(AiRuntimeError::Provider(ai_providers::ProviderError::Generation(error))),
-                AiChunk::Start => {}
+                AiChunk::Start | AiChunk::Queued { .. } => {}
             }
         }
         if !done && content.is_empty() {
@@ -135,7 +145,7 @@ impl AiRuntime {
 
     fn extract_mode_and_input(request: &AiRequest) -> (AiMode, String) {
         match request {
-            AiRequest::Chat { message } => (AiMode::Chat, message.clone()),
+            AiRequest::Chat { message, .. } => (AiMode::Chat, message.clone()),
             AiRequest::Explain {
                 selection: sel, ..
             } => {
@@ -146,30 +156,37 @@ impl AiRuntime {
             }
             AiRequest::Refactor { instruction, .. } => (AiMode::Refactor, instruction.clone()),
             AiRequest::Generate { prompt, .. } => (AiMode::Generate, prompt.clone()),
-            AiRequest::Agent { message } => (AiMode::Agent, message.clone()),
+            AiRequest::Agent { message, .. } => (AiMode::Agent, message.clone()),
         }
     }
 
     fn build_context(
-        &self,
+        limits: ContextLimits,
         project_root: Option<&Path>,
         current_file: Option<(std::path::PathBuf, String)>,
         selection: Option<&str>,
-    ) -> Result<Context, AiRuntimeError> {
-        let mut builder = ContextBuilder::new(self.context_limits.clone());
+        references: &[ContextRef],
+    ) -> Context {
+        let mut builder = ContextBuilder::new(limits);
 
+        if let Some(root) = project_root {
+            let tree = get_project_tree(root);
+            builder = builder.project_tree(tree);
+            let mut resolver = ReferenceResolver::new(root);
+            if let Some((path, content)) = &current_file {
+                let lines = selection.and_then(|s| selection_lines(content, s));
+                resolver = resolver.with_current_file(path, lines);
+            }
+            builder = builder.references(&resolver, references);
+        }
         if let Some((path, content)) = current_file {
             builder = builder.current_file(path, content);
         }
         if let Some(s) = selection {
             builder = builder.selection(s.to_string());
         }
-        if let Some(root) = project_root {
-            let tree = get_project_tree(root);
-            builder = builder.project_tree(tree);
-        }
 
-        builder.build().map_err(Into::into)
+        builder.build()
     }
 }
 
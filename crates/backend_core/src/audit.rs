@@ -36,6 +36,10 @@ pub enum AuditEvent {
         #[serde(skip_serializing_if = "Option::is_none")]
         environment: Option<AuditEnvironment>,
     },
+    /// Сессия продолжена новым сообщением пользователя.
+    SessionResumed {
+        task: String,
+    },
     TaskClassified {
         role: String,
         reason: String,
@@ -67,6 +71,63 @@ pub enum AuditEvent {
         #[serde(skip_serializing_if = "Option::is_none")]
         hash: Option<String>,
     },
+    /// Изменения сессии ждут принятия пользователем (staged change set).
+    ChangesStaged {
+        files: Vec<String>,
+    },
+    /// Изменения сессии откачены по снимку.
+    SnapshotRestored {
+        files: Vec<String>,
+        conflicts: Vec<String>,
+    },
+    /// Решение пользователя по изменяющему вызову (approve / approve_all / reject / edit).
+    ApprovalDecided {
+        tool: String,
+        decision: String,
+    },
+    /// План задачи от модели (фаза планирования).
+    PlanCreated {
+        steps: Vec<String>,
+        model: String,
+    },
+    /// Решение пользователя по плану (approve / edit / cancel) и итоговые шаги.
+    PlanReviewed {
+        decision: String,
+        steps: Vec<String>,
+    },
+    /// Статус шага плана (pending / in_progress / done / failed).
+    PlanStepUpdated {
+        step: usize,
+        status: String,
+    },
+    /// Разговор сжат перед приближением к окну модели (оценка в токенах).
+    ContextCompacted {
+        before_tokens: usize,
+        after_tokens: usize,
+    },
+    /// Запущен под-агент (delegate); его события идут следом до SubAgentFinished.
+    SubAgentStarted {
+        sub_session_id: String,
+        task: String,
+        tools: Vec<String>,
+        paths: Vec<String>,
+        max_turns: usize,
+    },
+    /// Под-агент завершился: done, aborted или error.
+    SubAgentFinished {
+        sub_session_id: String,
+        status: String,
+    },
+    /// Проверка правок сборкой/тестами проекта (errors — число найденных ошибок).
+    VerificationRun {
+        passed: bool,
+        commands: Vec<String>,
+        errors: usize,
+    },
+    /// Секреты заменены плейсхолдерами перед отправкой провайдеру (вид → количество).
+    SecretsRedacted {
+        counts: std::collections::BTreeMap<String, usize>,
+    },
     Error {
         message: String,
     },
@@ -149,6 +210,24 @@ pub fn finish_session_meta(
     }
 }
 
+/// Помечает сессию снова выполняющейся (продолжение сохранённого разговора).
+pub fn resume_session_meta(project_root: Option<&Path>, session_id: &str) {
+    let dir = match ensure_audit_dir(project_root) {
+        Ok(d) => d,
+        Err(_) => return,
+    };
+    let path = dir.join(format!("{}_meta.json", session_id));
+    if let Ok(s) = std::fs::read_to_string(&path) {
+        if let Ok(mut meta) = serde_json::from_str::<AuditSessionMeta>(&s) {
+            meta.ended_at = None;
+            meta.status = "running".to_string();
+            if let Ok(json) = serde_json::to_string_pretty(&meta) {
+                let _ = std::fs::write(&path, json);
+            }
+        }
+    }
+}
+
 /// Читает события сессии для Replay.
 pub fn read_session_events(
     project_root: Option<&Path>,
@@ -5,16 +5,77 @@
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::references::{parse_mentions, ContextRef, ReferenceResolver};
+
 /// Типы запросов от UI к AI Runtime.
+///
+/// `references` — явные ссылки на контекст; к ним добавляются `@kind:target` из текста запроса.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum AiRequest {
-    Chat { message: String },
-    Explain { path: String, selection: Option<String> },
-    Refactor { path: String, selection: String, instruction: String },
-    Generate { path: String, prompt: String },
+    Chat {
+        message: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        references: Vec<ContextRef>,
+    },
+    Explain {
+        path: String,
+        selection: Option<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        references: Vec<ContextRef>,
+    },
+    Refactor {
+        path: String,
+        selection: String,
+        instruction: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        references: Vec<ContextRef>,
+    },
+    Generate {
+        path: String,
+        prompt: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        references: Vec<ContextRef>,
+    },
     /// Агент: план + вызов инструментов (create_file, read_file, list_files, update_file).
-    Agent { message: String },
+    Agent {
+        message: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        references: Vec<ContextRef>,
+    },
+}
+
+impl AiRequest {
+    /// Явные ссылки и упоминания из текста, без повторов.
+    /// Ошибка — нераспознанные упоминания.
+    pub fn references(&self) -> Result<Vec<ContextRef>, Vec<String>> {
+        let (explicit, text) = match self {
+            AiRequest::Chat { message, references } | AiRequest::Agent { message, references } => {
+                (references, message.as_str())
+            }
+            AiRequest::Explain { references, .. } => (references, ""),
+            AiRequest::Refactor {
+                instruction,
+                references,
+                ..
+            } => (references, instruction.as_str()),
+            AiRequest::Generate { prompt, references, .. } => (references, prompt.as_str()),
+        };
+        let mut refs = explicit.clone();
+        let mut errors = Vec::new();
+        for mention in parse_mentions(text) {
+            match mention {
+                Ok(r) if !refs.contains(&r) => refs.push(r),
+                Ok(_) => {}
+                Err(e) => errors.push(e),
+            }
+        }
+        if errors.is_empty() {
+            Ok(refs)
+        } else {
+            Err(errors)
+        }
+    }
 }
 
 /// Ответ AI (сырой текст, UI форматирует сам).
@@ -42,9 +103,14 @@ impl CommandRouter {
     }
 
     /// Валидирует запрос перед передачей в AI Runtime.
-    pub fn validate(&self, request: &AiRequest) -> Result<(), RouterError> {
+    /// Ссылки на контекст проверяются через `resolver`; без проекта ссылки недопустимы.
+    pub fn validate(
+        &self,
+        request: &AiRequest,
+        resolver: Option<&ReferenceResolver<'_>>,
+    ) -> Result<(), RouterError> {
         match request {
-            AiRequest::Chat { message } => {
+            AiRequest::Chat { message, .. } => {
                 if message.trim().is_empty() {
                     return Err(RouterError::Validation("Message cannot be empty".into()));
                 }
@@ -59,17 +125,35 @@ impl CommandRouter {
                     return Err(RouterError::Validation("Path and selection required".into()));
                 }
             }
-            AiRequest::Generate { path, prompt } => {
+            AiRequest::Generate { path, prompt, .. } => {
                 if path.trim().is_empty() || prompt.trim().is_empty() {
                     return Err(RouterError::Validation("Path and prompt required".into()));
                 }
             }
-            AiRequest::Agent { message } => {
+            AiRequest::Agent { message, .. } => {
                 if message.trim().is_empty() {
                     return Err(RouterError::Validation("Agent message cannot be empty".into()));
                 }
             }
         }
+        let refs = request.references().map_err(|errors| {
+            RouterError::Validation(format!("Unresolved references: {}", errors.join("; ")))
+        })?;
+        if refs.is_empty() {
+            return Ok(());
+        }
+        let resolver = resolver.ok_or(RouterError::NoProject)?;
+        let unresolved: Vec<String> = refs
+            .iter()
+            .filter_map(|r| resolver.check(r).err())
+            .map(|e| e.to_string())
+            .collect();
+        if !unresolved.is_empty() {
+            return Err(RouterError::Validation(format!(
+                "Unresolved references: {}",
+                unresolved.join("; ")
+            )));
+        }
         Ok(())
     }
 }
@@ -1,8 +1,30 @@
 //! Файловая система: чтение, запись, обход дерева.
+//!
+//! Обход проекта учитывает .gitignore, .ignore и `.kengaide/ignore`; один обходчик на всех:
+//! дерево в UI, дерево для AI-контекста, list_files агента, индекс.
 
-use std::path::PathBuf;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::WalkBuilder;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use thiserror::Error;
-use walkdir::WalkDir;
+
+/// Игнор-файл проекта (синтаксис .gitignore, пути от корня проекта).
+pub const PROJECT_IGNORE_FILE: &str = ".kengaide/ignore";
+/// Пропускаются всегда, независимо от игнор-файлов.
+const ALWAYS_SKIP: &[&str] = &[".git"];
+/// Встроенный слой игнора: зависимости, сборка, виртуальные окружения. Ниже .gitignore
+/// и `.kengaide/ignore` — их `!build/` возвращает директорию в обход.
+const DEFAULT_IGNORE: &[&str] = &[
+    "node_modules/",
+    "target/",
+    "dist/",
+    "build/",
+    ".venv/",
+    "venv/",
+    "__pycache__/",
+    ".next/",
+];
 
 #[derive(Error, Debug)]
 pub enum FsError {
@@ -34,15 +56,123 @@ impl FsService {
         std::fs::write(path, content).map_err(Into::into)
     }
 
-    /// Возвращает дерево файлов проекта (относительные пути).
-    pub fn project_tree(&self, root: &std::path::Path, max_depth: usize) -> Vec<PathBuf> {
-        WalkDir::new(root)
-            .max_depth(max_depth)
-            .into_iter()
+    /// Возвращает дерево файлов проекта (относительные пути), без игнорируемых.
+    pub fn project_tree(&self, root: &Path, max_depth: usize) -> Vec<PathBuf> {
+        ProjectWalker::new(root).max_depth(max_depth).files()
+    }
+}
+
+/// Запись обхода проекта.
+#[derive(Debug, Clone)]
+pub struct WalkEntry {
+    /// Путь относительно корня проекта.
+    pub path: PathBuf,
+    pub is_dir: bool,
+    /// Глубина от директории обхода (1 — её прямые дети).
+    pub depth: usize,
+}
+
+/// Обход проекта с учётом .gitignore, .ignore, `.kengaide/ignore` и встроенного слоя
+/// (`DEFAULT_IGNORE`). Скрытые записи (имя с точки) пропускаются, кроме разрешённых
+/// через `allow_hidden`.
+#[derive(Clone)]
+pub struct ProjectWalker {
+    root: PathBuf,
+    max_depth: Option<usize>,
+    allowed_hidden: Vec<String>,
+    project_ignore: Arc<Gitignore>,
+}
+
+impl ProjectWalker {
+    pub fn new(root: &Path) -> Self {
+        let mut builder = GitignoreBuilder::new(root);
+        for pattern in DEFAULT_IGNORE {
+            let _ = builder.add_line(None, pattern);
+        }
+        // Корневой .gitignore повторяется поверх встроенного слоя ради исключений (`!build/`);
+        // остальные его правила и так применяет обходчик.
+        for ignore_path in [root.join(".gitignore"), root.join(PROJECT_IGNORE_FILE)] {
+            if ignore_path.is_file() {
+                if let Some(e) = builder.add(&ignore_path) {
+                    tracing::warn!(path = %ignore_path.display(), error = %e, "project ignore file");
+                }
+            }
+        }
+        let project_ignore = builder.build().unwrap_or_else(|e| {
+            tracing::warn!(error = %e, "project ignore file skipped");
+            Gitignore::empty()
+        });
+        Self {
+            root: root.to_path_buf(),
+            max_depth: None,
+            allowed_hidden: Vec::new(),
+            project_ignore: Arc::new(project_ignore),
+        }
+    }
+
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Скрытые записи с этими именами не пропускаются (например, `.vscode` в дереве UI).
+    pub fn allow_hidden(mut self, names: &[&str]) -> Self {
+        self.allowed_hidden = names.iter().map(|n| n.to_string()).collect();
+        self
+    }
+
+    /// Обход всего проекта, в порядке путей.
+    pub fn walk(&self) -> impl Iterator<Item = WalkEntry> {
+        self.walk_dir(Path::new(""))
+    }
+
+    /// Обход поддиректории (путь от корня проекта); глубина считается от неё.
+    /// Игнор-файлы родительских директорий учитываются.
+    pub fn walk_dir(&self, rel_dir: &Path) -> impl Iterator<Item = WalkEntry> {
+        let root = self.root.clone();
+        let project_ignore = Arc::clone(&self.project_ignore);
+        let allowed_hidden = self.allowed_hidden.clone();
+
+        let mut builder = WalkBuilder::new(self.root.join(rel_dir));
+        builder
+            .max_depth(self.max_depth)
+            // Свой фильтр скрытых — с исключениями.
+            .hidden(false)
+            // .gitignore действует и в проекте без git (свежий шаблон).
+            .require_git(false)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .filter_entry(move |e| {
+                if e.depth() == 0 {
+                    return true;
+                }
+                let name = e.file_name().to_string_lossy();
+                if ALWAYS_SKIP.contains(&name.as_ref()) {
+                    return false;
+                }
+                if name.starts_with('.') && !allowed_hidden.iter().any(|a| a == name.as_ref()) {
+                    return false;
+                }
+                let is_dir = e.file_type().map(|t| t.is_dir()).unwrap_or(false);
+                !project_ignore.matched(e.path(), is_dir).is_ignore()
+            });
+
+        builder
+            .build()
             .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file())
-            .map(|e| e.path().strip_prefix(root).unwrap_or(e.path()).to_path_buf())
-            .collect()
+            .filter(|e| e.depth() > 0)
+            .filter_map(move |e| {
+                let path = e.path().strip_prefix(&root).ok()?.to_path_buf();
+                Some(WalkEntry {
+                    path,
+                    is_dir: e.file_type().map(|t| t.is_dir()).unwrap_or(false),
+                    depth: e.depth(),
+                })
+            })
+    }
+
+    /// Файлы проекта (относительные пути).
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.walk().filter(|e| !e.is_dir).map(|e| e.path).collect()
     }
 }
 
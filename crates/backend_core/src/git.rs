@@ -1,8 +1,14 @@
-//! Git: статус, diff, базовая интеграция.
+//! Git: статус, diff, история файла, blame.
 
+use serde::{Deserialize, Serialize};
 use std::path::Path;
 use thiserror::Error;
 
+/// Длина короткого id коммита.
+const SHORT_ID_LEN: usize = 8;
+/// Сколько коммитов просматривать в поисках истории файла.
+const MAX_LOG_WALK: usize = 2000;
+
 #[derive(Error, Debug)]
 pub enum GitError {
     #[error("Git error: {0}")]
@@ -37,6 +43,144 @@ impl GitService {
         }
         Ok(None)
     }
+
+    /// Незакоммиченные изменения (индекс и рабочая копия против HEAD) в формате unified diff.
+    pub fn diff(&self, repo_path: &Path) -> Result<String, GitError> {
+        self.diff_against_head(repo_path, None)
+    }
+
+    /// Изменения одного файла (путь от корня репозитория) против HEAD.
+    pub fn file_diff(&self, repo_path: &Path, file_path: &Path) -> Result<String, GitError> {
+        self.diff_against_head(repo_path, Some(file_path))
+    }
+
+    /// Последние коммиты, менявшие файл, от новых к старым.
+    pub fn file_log(
+        &self,
+        repo_path: &Path,
+        file_path: &Path,
+        limit: usize,
+    ) -> Result<Vec<CommitSummary>, GitError> {
+        let repo = git2::Repository::open(repo_path).map_err(|_| GitError::NotRepo)?;
+        let mut walk = repo.revwalk()?;
+        if walk.push_head().is_err() {
+            // Коммитов ещё нет.
+            return Ok(Vec::new());
+        }
+        walk.set_sorting(git2::Sort::TIME)?;
+        let mut out = Vec::new();
+        for oid in walk.take(MAX_LOG_WALK) {
+            let commit = repo.find_commit(oid?)?;
+            let entry_id = |tree: &git2::Tree| tree.get_path(file_path).ok().map(|e| e.id());
+            let current = entry_id(&commit.tree()?);
+            let parent = match commit.parent(0) {
+                Ok(p) => entry_id(&p.tree()?),
+                Err(_) => None,
+            };
+            if current.is_some() && current != parent {
+                out.push(CommitSummary::from_commit(&commit));
+                if out.len() >= limit {
+                    break;
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// Авторство строк рабочей копии файла `start..=end` (с 1): по строке на запись.
+    pub fn blame(
+        &self,
+        repo_path: &Path,
+        file_path: &Path,
+        start: usize,
+        end: usize,
+    ) -> Result<Vec<BlameLine>, GitError> {
+        let repo = git2::Repository::open(repo_path).map_err(|_| GitError::NotRepo)?;
+        let committed = repo.blame_file(file_path, None)?;
+        // Номера строк — по рабочей копии, с учётом незакоммиченных правок.
+        let blame = match std::fs::read(repo_path.join(file_path)) {
+            Ok(buffer) => committed.blame_buffer(&buffer)?,
+            Err(_) => committed,
+        };
+        let mut out = Vec::new();
+        for hunk in blame.iter() {
+            let first = hunk.final_start_line();
+            let commit = if hunk.final_commit_id().is_zero() {
+                None
+            } else {
+                repo.find_commit(hunk.final_commit_id())
+                    .ok()
+                    .map(|c| CommitSummary::from_commit(&c))
+            };
+            for line in first..first + hunk.lines_in_hunk() {
+                if (start..=end).contains(&line) {
+                    out.push(BlameLine {
+                        line,
+                        commit: commit.clone(),
+                    });
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    fn diff_against_head(&self, repo_path: &Path, file_path: Option<&Path>) -> Result<String, GitError> {
+        let repo = git2::Repository::open(repo_path).map_err(|_| GitError::NotRepo)?;
+        // В свежем репозитории HEAD ещё нет — сравнение с пустым деревом.
+        let head = repo.head().ok().and_then(|h| h.peel_to_tree().ok());
+        let mut opts = git2::DiffOptions::new();
+        opts.include_untracked(true).show_untracked_content(true);
+        if let Some(path) = file_path {
+            opts.pathspec(path).disable_pathspec_match(true);
+        }
+        let diff = repo.diff_tree_to_workdir_with_index(head.as_ref(), Some(&mut opts))?;
+        let mut out = String::new();
+        diff.print(git2::DiffFormat::Patch, |_, _, line| {
+            if matches!(line.origin(), '+' | '-' | ' ') {
+                out.push(line.origin());
+            }
+            out.push_str(&String::from_utf8_lossy(line.content()));
+            true
+        })?;
+        Ok(out)
+    }
+}
+
+/// Коммит для контекста: короткий id, автор, дата, первая строка сообщения.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommitSummary {
+    pub id: String,
+    pub author: String,
+    /// Unix time, секунды.
+    pub time: i64,
+    pub summary: String,
+}
+
+impl CommitSummary {
+    fn from_commit(commit: &git2::Commit) -> Self {
+        let id = commit.id().to_string();
+        Self {
+            id: id[..SHORT_ID_LEN.min(id.len())].to_string(),
+            author: commit.author().name().unwrap_or("unknown").to_string(),
+            time: commit.time().seconds(),
+            summary: commit.summary().unwrap_or("").to_string(),
+        }
+    }
+
+    /// "abc12345 2024-05-01 Author: summary".
+    pub fn describe(&self) -> String {
+        let date = chrono::DateTime::from_timestamp(self.time, 0)
+            .map(|d| d.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        format!("{} {} {}: {}", self.id, date, self.author, self.summary)
+    }
+}
+
+/// Строка blame; `commit` None — изменение ещё не закоммичено.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlameLine {
+    pub line: usize,
+    pub commit: Option<CommitSummary>,
 }
 
 impl Default for GitService {
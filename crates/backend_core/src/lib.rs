@@ -9,19 +9,34 @@ pub mod git;
 pub mod logs;
 pub mod patch;
 pub mod project;
+pub mod references;
+pub mod snapshot;
+pub mod transcript;
 pub mod workspace;
 
 pub use command_router::CommandRouter;
 pub use fs::FsService;
-pub use git::GitService;
+pub use git::{BlameLine, CommitSummary, GitService};
 pub use project::ProjectService;
+pub use references::{
+    parse_mentions, selection_lines, ContextRef, GitSource, ReferenceError, ReferenceResolver,
+    ResolvedReference, SymbolLookup,
+};
 pub use audit::{
     append_audit_event, audit_dir, current_environment, ensure_audit_dir, finish_session_meta,
-    list_sessions, read_session_events, save_session_meta, AuditEnvironment, AuditEvent,
-    AuditSessionMeta,
+    list_sessions, read_session_events, resume_session_meta, save_session_meta, AuditEnvironment,
+    AuditEvent, AuditSessionMeta,
 };
 pub use logs::{append_log, ensure_logs_dir, logs_dir};
 pub use patch::rollback_patch;
+pub use snapshot::{
+    list_snapshots, restore_snapshot, RestoreReport, SessionSnapshot, SnapshotEntry, SnapshotError,
+    SnapshotManifest,
+};
+pub use transcript::{
+    list_transcripts, load_transcript, save_transcript, AgentTranscript, TranscriptError,
+    TranscriptMessage, TranscriptSummary,
+};
 pub use workspace::{
     create_project_from_template, ensure_workspace_dir, ProjectConfig, WorkspaceError, TEMPLATES,
 };
\ No newline at end of file
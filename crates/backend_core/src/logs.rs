@@ -1,6 +1,7 @@
 //! Логирование в .kengaide/logs/ для отладки.
 //!
-//! Файлы: agent.log, mcp.log, runtime.log
+//! Файлы: agent.log, mcp.log, runtime.log. В глобальный runtime.log приложение также
+//! направляет события `tracing` из крейтов.
 
 use std::path::Path;
 
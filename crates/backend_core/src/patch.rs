@@ -1,4 +1,5 @@
 //! Rollback патчей: замена after → before (обратное apply_patch).
+//! Ненадёжен, если текст менялся позже; точный откат сессии — `snapshot::restore_snapshot`.
 
 use std::path::Path;
 
@@ -0,0 +1,687 @@
+//! Явные ссылки на контекст в запросах: `@file:path`, `@folder:path`, `@symbol:name`,
+//! `@git:diff|file|log|blame`, `@audit:session`.
+//!
+//! Разрешаются на стороне backend: пути только внутри проекта, содержимое читает сервер.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use thiserror::Error;
+
+use crate::audit::{audit_dir, read_session_events};
+use crate::fs::ProjectWalker;
+use crate::git::{BlameLine, GitError, GitService};
+
+/// Сколько файлов папки попадает в контекст по `@folder:`.
+const MAX_FOLDER_FILES: usize = 20;
+/// Файлы крупнее не читаются (обычно сгенерированные или бинарные).
+const MAX_REFERENCE_FILE_BYTES: u64 = 256 * 1024;
+/// Сколько коммитов истории файла показывать по `@git:log`.
+const MAX_LOG_COMMITS: usize = 10;
+
+/// Ссылка на контекст. В JSON и в тексте — строка вида `@kind:target` (`@` можно опустить).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ContextRef {
+    File(String),
+    Folder(String),
+    Symbol(String),
+    Git(GitSource),
+    AuditSession(String),
+}
+
+/// Источник git-контекста. Без пути — текущий файл запроса (для blame — его выделение).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum GitSource {
+    /// `@git:diff` — все незакоммиченные изменения.
+    Diff,
+    /// `@git:file[:path]` — изменения файла против HEAD.
+    FileDiff(Option<String>),
+    /// `@git:log[:path]` — последние коммиты, менявшие файл.
+    Log(Option<String>),
+    /// `@git:blame[:path:start-end]` — авторство строк.
+    Blame(Option<(String, usize, usize)>),
+}
+
+impl FromStr for GitSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, arg) = match s.split_once(':') {
+            Some((kind, arg)) => (kind, Some(arg.to_string())),
+            None => (s, None),
+        };
+        match (kind, arg) {
+            ("diff", None) => Ok(Self::Diff),
+            ("file", arg) => Ok(Self::FileDiff(arg)),
+            ("log", arg) => Ok(Self::Log(arg)),
+            ("blame", None) => Ok(Self::Blame(None)),
+            ("blame", Some(arg)) => {
+                let lines = arg.rsplit_once(':').and_then(|(path, range)| {
+                    let (a, b) = range.split_once('-')?;
+                    let (a, b) = (a.parse().ok()?, b.parse().ok()?);
+                    (a >= 1 && a <= b).then(|| (path.to_string(), a, b))
+                });
+                lines
+                    .map(|l| Self::Blame(Some(l)))
+                    .ok_or_else(|| "blame expects path:start-end".to_string())
+            }
+            _ => Err("expected diff, file, log or blame".to_string()),
+        }
+    }
+}
+
+impl fmt::Display for GitSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let with_path = |f: &mut fmt::Formatter<'_>, kind: &str, path: &Option<String>| match path {
+            Some(p) => write!(f, "{}:{}", kind, p),
+            None => write!(f, "{}", kind),
+        };
+        match self {
+            Self::Diff => write!(f, "diff"),
+            Self::FileDiff(path) => with_path(f, "file", path),
+            Self::Log(path) => with_path(f, "log", path),
+            Self::Blame(Some((path, a, b))) => write!(f, "blame:{}:{}-{}", path, a, b),
+            Self::Blame(None) => write!(f, "blame"),
+        }
+    }
+}
+
+impl FromStr for ContextRef {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let raw = s.trim();
+        let body = raw.strip_prefix('@').unwrap_or(raw);
+        let (kind, target) = body
+            .split_once(':')
+            .ok_or_else(|| format!("{}: expected @kind:target", raw))?;
+        let target = target.trim();
+        if target.is_empty() {
+            return Err(format!("{}: empty target", raw));
+        }
+        match kind {
+            "file" => Ok(Self::File(target.to_string())),
+            "folder" => Ok(Self::Folder(target.to_string())),
+            "symbol" => Ok(Self::Symbol(target.to_string())),
+            "git" => target
+                .parse()
+                .map(Self::Git)
+                .map_err(|e| format!("{}: {}", raw, e)),
+            "audit" => Ok(Self::AuditSession(target.to_string())),
+            _ => Err(format!("{}: unknown reference kind", raw)),
+        }
+    }
+}
+
+impl TryFrom<String> for ContextRef {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<ContextRef> for String {
+    fn from(r: ContextRef) -> Self {
+        r.to_string()
+    }
+}
+
+impl fmt::Display for ContextRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(p) => write!(f, "@file:{}", p),
+            Self::Folder(p) => write!(f, "@folder:{}", p),
+            Self::Symbol(n) => write!(f, "@symbol:{}", n),
+            Self::Git(source) => write!(f, "@git:{}", source),
+            Self::AuditSession(id) => write!(f, "@audit:{}", id),
+        }
+    }
+}
+
+/// Упоминания `@kind:target` в тексте запроса. Токены с `@`, но без известного вида
+/// (`@param`, e-mail), не считаются ссылками; ошибка — известный вид с неверной целью.
+pub fn parse_mentions(text: &str) -> Vec<Result<ContextRef, String>> {
+    const KINDS: &[&str] = &["@file:", "@folder:", "@symbol:", "@git:", "@audit:"];
+    text.split_whitespace()
+        .map(|token| token.trim_end_matches([',', '.', ';', ')', '`', '"', '\'']))
+        .filter(|token| KINDS.iter().any(|k| token.starts_with(k)))
+        .map(str::parse)
+        .collect()
+}
+
+#[derive(Error, Debug)]
+pub enum ReferenceError {
+    #[error("{0}: path must be relative to the project without '..'")]
+    InvalidPath(String),
+    #[error("{0}: outside the project")]
+    OutsideProject(String),
+    #[error("{0}: not found")]
+    NotFound(String),
+    #[error("{0}: symbol index is not available")]
+    NoSymbolIndex(String),
+    #[error("{0}: no file is open")]
+    NoCurrentFile(String),
+    #[error("{0}: no lines are selected")]
+    NoSelection(String),
+    #[error("{0}: {1}")]
+    Git(String, String),
+    #[error("{0}: {1}")]
+    Io(String, std::io::Error),
+}
+
+/// Разрешённая ссылка: метка для контекста и содержимое.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResolvedReference {
+    pub label: String,
+    pub content: String,
+}
+
+/// Определения символов для `@symbol:` (реализует индекс символов context_manager).
+pub trait SymbolLookup {
+    /// Тексты определений символа; пусто — символ не найден.
+    fn definitions(&self, name: &str) -> Vec<ResolvedReference>;
+
+    /// Есть ли определение, без чтения текстов (для проверки ссылки).
+    fn has_definition(&self, name: &str) -> bool {
+        !self.definitions(name).is_empty()
+    }
+}
+
+/// Строки выделения в файле (с 1, включительно); None — выделение не найдено.
+pub fn selection_lines(content: &str, selection: &str) -> Option<(usize, usize)> {
+    if selection.trim().is_empty() {
+        return None;
+    }
+    let pos = content.find(selection)?;
+    let start = content[..pos].matches('\n').count() + 1;
+    Some((start, start + selection.trim_end_matches('\n').lines().count().max(1) - 1))
+}
+
+/// Разрешает ссылки относительно корня проекта.
+pub struct ReferenceResolver<'a> {
+    root: &'a Path,
+    symbols: Option<&'a dyn SymbolLookup>,
+    /// Текущий файл запроса (от корня) и выделенные строки — для `@git:` без пути.
+    current_file: Option<(String, Option<(usize, usize)>)>,
+}
+
+impl<'a> ReferenceResolver<'a> {
+    pub fn new(root: &'a Path) -> Self {
+        Self {
+            root,
+            symbols: None,
+            current_file: None,
+        }
+    }
+
+    pub fn with_symbols(mut self, symbols: &'a dyn SymbolLookup) -> Self {
+        self.symbols = Some(symbols);
+        self
+    }
+
+    /// Текущий файл запроса (абсолютный или от корня) и выделенные в нём строки.
+    pub fn with_current_file(mut self, path: &Path, lines: Option<(usize, usize)>) -> Self {
+        let rel = path.strip_prefix(self.root).unwrap_or(path);
+        if rel.is_relative() {
+            self.current_file = Some((rel.to_string_lossy().replace('\\', "/"), lines));
+        }
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        self.root
+    }
+
+    /// Проверяет, что ссылка разрешима, не читая содержимое файлов.
+    pub fn check(&self, reference: &ContextRef) -> Result<(), ReferenceError> {
+        let name = reference.to_string();
+        match reference {
+            ContextRef::File(path) => {
+                if !self.project_path(&name, path)?.is_file() {
+                    return Err(ReferenceError::NotFound(name));
+                }
+            }
+            ContextRef::Folder(path) => {
+                if !self.project_path(&name, path)?.is_dir() {
+                    return Err(ReferenceError::NotFound(name));
+                }
+            }
+            ContextRef::Symbol(symbol) => {
+                let lookup = self
+                    .symbols
+                    .ok_or_else(|| ReferenceError::NoSymbolIndex(name.clone()))?;
+                if !lookup.has_definition(symbol) {
+                    return Err(ReferenceError::NotFound(name));
+                }
+            }
+            ContextRef::Git(source) => self.check_git(&name, source)?,
+            ContextRef::AuditSession(id) => {
+                if !self.audit_session_path(&name, id)?.is_file() {
+                    return Err(ReferenceError::NotFound(name));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Загружает содержимое ссылки; папка даёт несколько файлов.
+    pub fn resolve(&self, reference: &ContextRef) -> Result<Vec<ResolvedReference>, ReferenceError> {
+        let name = reference.to_string();
+        match reference {
+            ContextRef::File(path) => {
+                let full = self.project_path(&name, path)?;
+                if !full.is_file() {
+                    return Err(ReferenceError::NotFound(name));
+                }
+                let content = read_capped(&full).map_err(|e| ReferenceError::Io(name, e))?;
+                Ok(vec![ResolvedReference {
+                    label: path.trim_end_matches('/').to_string(),
+                    content,
+                }])
+            }
+            ContextRef::Folder(path) => {
+                let full = self.project_path(&name, path)?;
+                if !full.is_dir() {
+                    return Err(ReferenceError::NotFound(name));
+                }
+                let rel = full.strip_prefix(self.canonical_root(&name)?).unwrap_or(Path::new(""));
+                let files = ProjectWalker::new(self.root)
+                    .walk_dir(rel)
+                    .filter(|e| !e.is_dir)
+                    .filter(|e| {
+                        std::fs::metadata(self.root.join(&e.path))
+                            .is_ok_and(|m| m.len() <= MAX_REFERENCE_FILE_BYTES)
+                    })
+                    // Нечитаемые как текст (бинарные) пропускаются.
+                    .filter_map(|e| {
+                        let content = std::fs::read_to_string(self.root.join(&e.path)).ok()?;
+                        Some(ResolvedReference {
+                            label: e.path.display().to_string(),
+                            content,
+                        })
+                    })
+                    .take(MAX_FOLDER_FILES)
+                    .collect();
+                Ok(files)
+            }
+            ContextRef::Symbol(symbol) => {
+                let lookup = self
+                    .symbols
+                    .ok_or_else(|| ReferenceError::NoSymbolIndex(name.clone()))?;
+                let definitions = lookup.definitions(symbol);
+                if definitions.is_empty() {
+                    return Err(ReferenceError::NotFound(name));
+                }
+                Ok(definitions)
+            }
+            ContextRef::Git(source) => Ok(vec![self.resolve_git(&name, source)?]),
+            ContextRef::AuditSession(id) => {
+                self.audit_session_path(&name, id)?;
+                let events = read_session_events(Some(self.root), id).map_err(|e| match e.kind() {
+                    std::io::ErrorKind::NotFound => ReferenceError::NotFound(name.clone()),
+                    _ => ReferenceError::Io(name.clone(), e),
+                })?;
+                let content = events
+                    .iter()
+                    .filter_map(|ev| serde_json::to_string(ev).ok())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                Ok(vec![ResolvedReference {
+                    label: format!("audit session {}", id),
+                    content,
+                }])
+            }
+        }
+    }
+
+    /// Репозиторий и файл git-ссылки существуют; diff и blame не строятся.
+    fn check_git(&self, name: &str, source: &GitSource) -> Result<(), ReferenceError> {
+        if !GitService::new().is_repo(self.root) {
+            return Err(ReferenceError::Git(name.to_string(), GitError::NotRepo.to_string()));
+        }
+        match source {
+            GitSource::Diff => {}
+            GitSource::FileDiff(path) | GitSource::Log(path) => {
+                self.git_file(name, path.as_deref())?;
+            }
+            GitSource::Blame(Some((path, _, _))) => {
+                self.git_file(name, Some(path))?;
+            }
+            GitSource::Blame(None) => {
+                let (_, lines) = self
+                    .current_file
+                    .as_ref()
+                    .ok_or_else(|| ReferenceError::NoCurrentFile(name.to_string()))?;
+                lines.ok_or_else(|| ReferenceError::NoSelection(name.to_string()))?;
+                self.git_file(name, None)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_git(&self, name: &str, source: &GitSource) -> Result<ResolvedReference, ReferenceError> {
+        let git = GitService::new();
+        let git_err = |e: crate::git::GitError| ReferenceError::Git(name.to_string(), e.to_string());
+        let (label, content) = match source {
+            GitSource::Diff => ("git diff".to_string(), git.diff(self.root).map_err(git_err)?),
+            GitSource::FileDiff(path) => {
+                let rel = self.git_file(name, path.as_deref())?;
+                let diff = git.file_diff(self.root, Path::new(&rel)).map_err(git_err)?;
+                (format!("git diff {}", rel), diff)
+            }
+            GitSource::Log(path) => {
+                let rel = self.git_file(name, path.as_deref())?;
+                let log = git
+                    .file_log(self.root, Path::new(&rel), MAX_LOG_COMMITS)
+                    .map_err(git_err)?;
+                let lines: Vec<String> = log.iter().map(|c| c.describe()).collect();
+                (format!("git log {}", rel), lines.join("\n"))
+            }
+            GitSource::Blame(range) => {
+                let (rel, start, end) = match range {
+                    Some((path, start, end)) => (self.git_file(name, Some(path))?, *start, *end),
+                    None => {
+                        let (path, lines) = self
+                            .current_file
+                            .as_ref()
+                            .ok_or_else(|| ReferenceError::NoCurrentFile(name.to_string()))?;
+                        let (start, end) = lines.ok_or_else(|| ReferenceError::NoSelection(name.to_string()))?;
+                        (self.git_file(name, Some(path))?, start, end)
+                    }
+                };
+                let blame = git.blame(self.root, Path::new(&rel), start, end).map_err(git_err)?;
+                let source = std::fs::read_to_string(self.root.join(&rel))
+                    .map_err(|e| ReferenceError::Io(name.to_string(), e))?;
+                (
+                    format!("git blame {} (lines {}-{})", rel, start, end),
+                    format_blame(&blame, &source),
+                )
+            }
+        };
+        let content = if content.trim().is_empty() {
+            "(nothing)".to_string()
+        } else {
+            content
+        };
+        Ok(ResolvedReference { label, content })
+    }
+
+    /// Файл для git-ссылки: указанный или текущий; путь от корня.
+    fn git_file(&self, name: &str, path: Option<&str>) -> Result<String, ReferenceError> {
+        let path = match path {
+            Some(p) => p,
+            None => self
+                .current_file
+                .as_ref()
+                .map(|(p, _)| p.as_str())
+                .ok_or_else(|| ReferenceError::NoCurrentFile(name.to_string()))?,
+        };
+        let full = self.project_path(name, path)?;
+        if !full.is_file() {
+            return Err(ReferenceError::NotFound(name.to_string()));
+        }
+        let rel = full.strip_prefix(self.canonical_root(name)?).unwrap_or(&full);
+        Ok(rel.to_string_lossy().replace('\\', "/"))
+    }
+
+    /// Путь внутри проекта: относительный, без `..`, после разрешения симлинков — под корнем.
+    fn project_path(&self, name: &str, path: &str) -> Result<PathBuf, ReferenceError> {
+        let rel = Path::new(path);
+        if rel.is_absolute() || rel.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+            return Err(ReferenceError::InvalidPath(name.to_string()));
+        }
+        let full = self
+            .root
+            .join(rel)
+            .canonicalize()
+            .map_err(|_| ReferenceError::NotFound(name.to_string()))?;
+        if !full.starts_with(self.canonical_root(name)?) {
+            return Err(ReferenceError::OutsideProject(name.to_string()));
+        }
+        Ok(full)
+    }
+
+    fn canonical_root(&self, name: &str) -> Result<PathBuf, ReferenceError> {
+        self.root
+            .canonicalize()
+            .map_err(|e| ReferenceError::Io(name.to_string(), e))
+    }
+
+    /// session_id — только имя файла в директории аудита проекта.
+    fn audit_session_path(&self, name: &str, id: &str) -> Result<PathBuf, ReferenceError> {
+        if id.contains(['/', '\\']) || id.starts_with('.') {
+            return Err(ReferenceError::InvalidPath(name.to_string()));
+        }
+        Ok(audit_dir(Some(self.root)).join(format!("{}.jsonl", id)))
+    }
+}
+
+/// Текст файла; крупнее MAX_REFERENCE_FILE_BYTES — начало с пометкой об обрезке.
+/// Не-UTF-8 (бинарный) файл — ошибка InvalidData, как у read_to_string.
+fn read_capped(path: &Path) -> std::io::Result<String> {
+    use std::io::Read;
+    let file = std::fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut bytes = Vec::new();
+    file.take(MAX_REFERENCE_FILE_BYTES).read_to_end(&mut bytes)?;
+    let truncated = size > MAX_REFERENCE_FILE_BYTES;
+    if let Err(e) = std::str::from_utf8(&bytes) {
+        // Обрезка могла разрезать последний символ — это не бинарный файл.
+        if !truncated || e.error_len().is_some() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e));
+        }
+        bytes.truncate(e.valid_up_to());
+    }
+    let mut content = String::from_utf8(bytes)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    if truncated {
+        content.push_str(&format!(
+            "\n… (truncated: file is {} KB, showing the first {} KB)",
+            size / 1024,
+            MAX_REFERENCE_FILE_BYTES / 1024
+        ));
+    }
+    Ok(content)
+}
+
+/// Строки с авторством: заголовок на каждую группу строк одного коммита.
+fn format_blame(blame: &[BlameLine], source: &str) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut out = String::new();
+    let mut last: Option<Option<&str>> = None;
+    for b in blame {
+        let id = b.commit.as_ref().map(|c| c.id.as_str());
+        if last != Some(id) {
+            let header = b
+                .commit
+                .as_ref()
+                .map(|c| c.describe())
+                .unwrap_or_else(|| "not committed yet".to_string());
+            out.push_str(&format!("# {}\n", header));
+            last = Some(id);
+        }
+        let text = lines.get(b.line - 1).copied().unwrap_or("");
+        out.push_str(&format!("{:>5} | {}\n", b.line, text));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Временный проект; удаляется при drop.
+    struct TempProject(PathBuf);
+
+    impl TempProject {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir()
+                .join(format!("kengaide-references-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&root);
+            std::fs::create_dir_all(root.join("src")).unwrap();
+            std::fs::write(root.join("src/lib.rs"), "pub fn f() {}\n").unwrap();
+            Self(root)
+        }
+    }
+
+    impl Drop for TempProject {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn parses_mentions() {
+        let mentions = parse_mentions(
+            "Fix @file:src/lib.rs, see @symbol:Parser::parse. Mail me@example.com, @param x, @git:nope",
+        );
+        assert_eq!(mentions.len(), 3);
+        assert_eq!(mentions[0], Ok(ContextRef::File("src/lib.rs".into())));
+        assert_eq!(mentions[1], Ok(ContextRef::Symbol("Parser::parse".into())));
+        assert!(mentions[2].is_err());
+        assert!(parse_mentions("@file: empty").iter().all(|m| m.is_err()));
+        let reference: ContextRef = "folder:src".parse().unwrap();
+        assert_eq!(reference.to_string(), "@folder:src");
+    }
+
+    #[test]
+    fn rejects_paths_outside_project() {
+        let project = TempProject::new("paths");
+        let resolver = ReferenceResolver::new(&project.0);
+        let check = |path: &str| resolver.check(&ContextRef::File(path.into()));
+        assert!(check("src/lib.rs").is_ok());
+        assert!(check("./src/lib.rs").is_ok());
+        assert!(matches!(check("../etc/passwd"), Err(ReferenceError::InvalidPath(_))));
+        assert!(matches!(check("src/../../x"), Err(ReferenceError::InvalidPath(_))));
+        let absolute = project.0.join("src/lib.rs").display().to_string();
+        assert!(matches!(check(&absolute), Err(ReferenceError::InvalidPath(_))));
+        assert!(matches!(check("src/missing.rs"), Err(ReferenceError::NotFound(_))));
+        assert!(matches!(
+            resolver.check(&ContextRef::AuditSession("../x".into())),
+            Err(ReferenceError::InvalidPath(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinks_outside_project() {
+        let project = TempProject::new("symlink");
+        let outside = TempProject::new("symlink-outside");
+        std::os::unix::fs::symlink(outside.0.join("src/lib.rs"), project.0.join("leak.rs")).unwrap();
+        std::os::unix::fs::symlink(project.0.join("src/lib.rs"), project.0.join("inside.rs")).unwrap();
+        let resolver = ReferenceResolver::new(&project.0);
+        assert!(matches!(
+            resolver.resolve(&ContextRef::File("leak.rs".into())),
+            Err(ReferenceError::OutsideProject(_))
+        ));
+        assert!(resolver.check(&ContextRef::File("inside.rs".into())).is_ok());
+    }
+
+    #[test]
+    fn large_files_are_truncated() {
+        let project = TempProject::new("large");
+        let big = "é".repeat(MAX_REFERENCE_FILE_BYTES as usize);
+        std::fs::write(project.0.join("big.log"), &big).unwrap();
+        std::fs::write(project.0.join("data.bin"), [0xffu8, 0xfe, 0, 1]).unwrap();
+        let resolver = ReferenceResolver::new(&project.0);
+        let resolved = resolver.resolve(&ContextRef::File("big.log".into())).unwrap();
+        let content = &resolved[0].content;
+        assert!(content.len() < MAX_REFERENCE_FILE_BYTES as usize + 100);
+        assert!(content.ends_with("showing the first 256 KB)"));
+        assert!(content.starts_with("éé"));
+        assert!(matches!(
+            resolver.resolve(&ContextRef::File("data.bin".into())),
+            Err(ReferenceError::Io(..))
+        ));
+        let small = resolver.resolve(&ContextRef::File("src/lib.rs".into())).unwrap();
+        assert_eq!(small[0].content, "pub fn f() {}\n");
+    }
+
+    #[test]
+    fn parses_git_sources() {
+        let git = |s: &str| s.parse::<GitSource>();
+        assert_eq!(git("diff"), Ok(GitSource::Diff));
+        assert_eq!(git("file"), Ok(GitSource::FileDiff(None)));
+        assert_eq!(git("file:src/lib.rs"), Ok(GitSource::FileDiff(Some("src/lib.rs".into()))));
+        assert_eq!(git("log:a.rs"), Ok(GitSource::Log(Some("a.rs".into()))));
+        assert_eq!(git("blame"), Ok(GitSource::Blame(None)));
+        assert_eq!(git("blame:src/a:b.rs:3-7"), Ok(GitSource::Blame(Some(("src/a:b.rs".into(), 3, 7)))));
+        assert!(git("diff:src").is_err());
+        assert!(git("blame:a.rs").is_err());
+        assert!(git("blame:a.rs:0-3").is_err());
+        assert!(git("blame:a.rs:5-2").is_err());
+        assert!(git("status").is_err());
+        for s in ["diff", "file:a.rs", "log", "blame:a.rs:1-1"] {
+            assert_eq!(git(s).unwrap().to_string(), s);
+        }
+        assert_eq!(
+            "@git:log:src/lib.rs".parse::<ContextRef>(),
+            Ok(ContextRef::Git(GitSource::Log(Some("src/lib.rs".into()))))
+        );
+    }
+
+    #[test]
+    fn selection_line_ranges() {
+        let content = "one\ntwo\nthree\nfour\n";
+        assert_eq!(selection_lines(content, "one"), Some((1, 1)));
+        assert_eq!(selection_lines(content, "two\nthree"), Some((2, 3)));
+        // Завершающий перевод строки не добавляет строку.
+        assert_eq!(selection_lines(content, "two\nthree\n"), Some((2, 3)));
+        assert_eq!(selection_lines(content, "ur\n"), Some((4, 4)));
+        assert_eq!(selection_lines(content, "five"), None);
+        assert_eq!(selection_lines(content, "  \n"), None);
+    }
+
+    #[test]
+    fn formats_blame_groups() {
+        let commit = |id: &str| {
+            Some(crate::git::CommitSummary {
+                id: id.into(),
+                author: "Ann".into(),
+                time: 0,
+                summary: format!("commit {}", id),
+            })
+        };
+        let blame = vec![
+            BlameLine { line: 2, commit: commit("aaa") },
+            BlameLine { line: 3, commit: commit("aaa") },
+            BlameLine { line: 4, commit: None },
+            BlameLine { line: 9, commit: commit("bbb") },
+        ];
+        let out = format_blame(&blame, "l1\nl2\nl3\nl4\n");
+        assert_eq!(
+            out,
+            "# aaa 1970-01-01 Ann: commit aaa\n    2 | l2\n    3 | l3\n\
+             # not committed yet\n    4 | l4\n\
+             # bbb 1970-01-01 Ann: commit bbb\n    9 | \n"
+        );
+    }
+
+    #[test]
+    fn git_check_without_resolving() {
+        let project = TempProject::new("git-check");
+        let resolver = ReferenceResolver::new(&project.0);
+        let git = |s: &str| resolver.check(&ContextRef::Git(s.parse().unwrap()));
+        assert!(matches!(git("diff"), Err(ReferenceError::Git(..))));
+        std::fs::create_dir(project.0.join(".git")).unwrap();
+        assert!(git("diff").is_ok());
+        assert!(git("file:src/lib.rs").is_ok());
+        assert!(matches!(git("log:missing.rs"), Err(ReferenceError::NotFound(_))));
+        assert!(matches!(git("blame"), Err(ReferenceError::NoCurrentFile(_))));
+        let with_file = ReferenceResolver::new(&project.0).with_current_file(Path::new("src/lib.rs"), None);
+        assert!(matches!(
+            with_file.check(&ContextRef::Git(GitSource::Blame(None))),
+            Err(ReferenceError::NoSelection(_))
+        ));
+        let selected = ReferenceResolver::new(&project.0)
+            .with_current_file(&project.0.join("src/lib.rs"), Some((1, 1)));
+        assert!(selected.check(&ContextRef::Git(GitSource::Blame(None))).is_ok());
+        assert!(matches!(
+            resolver.check(&ContextRef::Symbol("f".into())),
+            Err(ReferenceError::NoSymbolIndex(_))
+        ));
+    }
+}
@@ -0,0 +1,409 @@
+//! Снимки файлов перед изменениями сессии агента и точный откат всей сессии.
+//!
+//! Перед первой записью в файл его содержимое и хеш сохраняются в
+//! `.kengaide/snapshots/<session_id>/` (files/<path> + manifest.json). Откат возвращает
+//! исходное содержимое и удаляет созданные файлы; файлы, изменённые после агента, — конфликт.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotError {
+    #[error("Invalid session id: {0}")]
+    InvalidSessionId(String),
+    #[error("Snapshot not found: {0}")]
+    NotFound(String),
+    #[error("Path outside project: {0}")]
+    OutsideProject(String),
+    #[error("IO error for {0}: {1}")]
+    Io(String, std::io::Error),
+    #[error("Invalid snapshot manifest: {0}")]
+    Manifest(#[from] serde_json::Error),
+}
+
+/// Файл в снимке.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    /// Относительный путь с '/'.
+    pub path: String,
+    /// Файл существовал до сессии (иначе откат его удаляет).
+    pub existed: bool,
+    /// SHA-256 исходного содержимого.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// SHA-256 после последней записи агентом — для обнаружения правок после сессии.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub written_hash: Option<String>,
+}
+
+/// manifest.json снимка.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub session_id: String,
+    pub created_at: String,
+    #[serde(default)]
+    pub files: Vec<SnapshotEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restored_at: Option<String>,
+}
+
+/// Результат отката.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RestoreReport {
+    pub restored: Vec<String>,
+    /// Изменены после сессии и не тронуты (откат с force перезаписывает).
+    pub conflicts: Vec<String>,
+}
+
+/// Директория снимков проекта.
+pub fn snapshots_dir(project_root: &Path) -> PathBuf {
+    project_root.join(".kengaide").join("snapshots")
+}
+
+fn snapshot_dir(project_root: &Path, session_id: &str) -> Result<PathBuf, SnapshotError> {
+    if session_id.is_empty()
+        || session_id.starts_with('.')
+        || session_id.contains(['/', '\\'])
+    {
+        return Err(SnapshotError::InvalidSessionId(session_id.to_string()));
+    }
+    Ok(snapshots_dir(project_root).join(session_id))
+}
+
+/// Относительный путь без "..", абсолютных и "." компонент.
+fn normalize(path: &Path) -> Result<PathBuf, SnapshotError> {
+    let mut out = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::Normal(p) => out.push(p),
+            Component::CurDir => {}
+            _ => return Err(SnapshotError::OutsideProject(path.display().to_string())),
+        }
+    }
+    Ok(out)
+}
+
+fn display(rel: &Path) -> String {
+    rel.to_string_lossy().replace('\\', "/")
+}
+
+fn hash(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Хеш файла на диске; None — файла нет.
+fn disk_hash(path: &Path) -> Result<Option<String>, SnapshotError> {
+    match std::fs::read(path) {
+        Ok(data) => Ok(Some(hash(&data))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(SnapshotError::Io(path.display().to_string(), e)),
+    }
+}
+
+/// Снимок одной сессии; файлы добавляются лениво, при первой записи.
+pub struct SessionSnapshot {
+    project_root: PathBuf,
+    dir: PathBuf,
+    manifest: Mutex<SnapshotManifest>,
+}
+
+impl SessionSnapshot {
+    /// Снимок сессии; для продолжаемой сессии дополняется существующий (исходные версии сохраняются).
+    pub fn new(project_root: &Path, session_id: &str) -> Result<Self, SnapshotError> {
+        let dir = snapshot_dir(project_root, session_id)?;
+        let manifest = match read_manifest(&dir) {
+            Ok(manifest) if manifest.restored_at.is_none() => manifest,
+            _ => SnapshotManifest {
+                session_id: session_id.to_string(),
+                created_at: chrono::Utc::now().to_rfc3339(),
+                files: Vec::new(),
+                restored_at: None,
+            },
+        };
+        Ok(Self {
+            project_root: project_root.to_path_buf(),
+            dir,
+            manifest: Mutex::new(manifest),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SnapshotManifest> {
+        self.manifest.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn save(&self, manifest: &SnapshotManifest) -> Result<(), SnapshotError> {
+        let path = self.dir.join(MANIFEST_FILE);
+        std::fs::create_dir_all(&self.dir).map_err(|e| SnapshotError::Io(self.dir.display().to_string(), e))?;
+        std::fs::write(&path, serde_json::to_string_pretty(manifest)?)
+            .map_err(|e| SnapshotError::Io(path.display().to_string(), e))
+    }
+
+    /// Сохраняет исходный файл, если он ещё не в снимке. Вызывать до записи.
+    pub fn before_write(&self, path: &Path) -> Result<(), SnapshotError> {
+        let rel = normalize(path)?;
+        let key = display(&rel);
+        let mut manifest = self.lock();
+        if manifest.files.iter().any(|f| f.path == key) {
+            return Ok(());
+        }
+        let full = self.project_root.join(&rel);
+        let entry = match std::fs::read(&full) {
+            Ok(data) => {
+                let blob = self.dir.join("files").join(&rel);
+                if let Some(parent) = blob.parent() {
+                    std::fs::create_dir_all(parent).map_err(|e| SnapshotError::Io(key.clone(), e))?;
+                }
+                std::fs::write(&blob, &data).map_err(|e| SnapshotError::Io(key.clone(), e))?;
+                SnapshotEntry {
+                    path: key,
+                    existed: true,
+                    hash: Some(hash(&data)),
+                    written_hash: None,
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => SnapshotEntry {
+                path: key,
+                existed: false,
+                hash: None,
+                written_hash: None,
+            },
+            Err(e) => return Err(SnapshotError::Io(key, e)),
+        };
+        manifest.files.push(entry);
+        self.save(&manifest)
+    }
+
+    /// Запоминает хеш записанного агентом содержимого.
+    pub fn after_write(&self, path: &Path, content: &[u8]) -> Result<(), SnapshotError> {
+        let key = display(&normalize(path)?);
+        let mut manifest = self.lock();
+        if let Some(entry) = manifest.files.iter_mut().find(|f| f.path == key) {
+            entry.written_hash = Some(hash(content));
+        }
+        self.save(&manifest)
+    }
+
+    /// Регистрирует файлы каталога, созданного целиком (create_project), как новые.
+    pub fn record_created_dir(&self, dir: &Path) -> Result<(), SnapshotError> {
+        let rel = normalize(dir)?;
+        let mut stack = vec![self.project_root.join(&rel)];
+        let mut files = Vec::new();
+        while let Some(dir) = stack.pop() {
+            let entries = std::fs::read_dir(&dir).map_err(|e| SnapshotError::Io(dir.display().to_string(), e))?;
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    stack.push(path);
+                } else if let Ok(inner) = path.strip_prefix(&self.project_root) {
+                    files.push(inner.to_path_buf());
+                }
+            }
+        }
+        let mut manifest = self.lock();
+        for file in files {
+            let key = display(&file);
+            if manifest.files.iter().any(|f| f.path == key) {
+                continue;
+            }
+            let written_hash = disk_hash(&self.project_root.join(&file))?;
+            manifest.files.push(SnapshotEntry {
+                path: key,
+                existed: false,
+                hash: None,
+                written_hash,
+            });
+        }
+        self.save(&manifest)
+    }
+}
+
+fn read_manifest(dir: &Path) -> Result<SnapshotManifest, SnapshotError> {
+    let path = dir.join(MANIFEST_FILE);
+    let s = std::fs::read_to_string(&path).map_err(|e| SnapshotError::Io(path.display().to_string(), e))?;
+    Ok(serde_json::from_str(&s)?)
+}
+
+/// Снимки проекта, новые первыми.
+pub fn list_snapshots(project_root: &Path) -> Vec<SnapshotManifest> {
+    let Ok(entries) = std::fs::read_dir(snapshots_dir(project_root)) else {
+        return Vec::new();
+    };
+    let mut out: Vec<SnapshotManifest> = entries
+        .flatten()
+        .filter_map(|e| read_manifest(&e.path()).ok())
+        .collect();
+    out.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    out
+}
+
+/// Откатывает все изменения сессии к снимку. Файлы, изменённые после сессии,
+/// попадают в conflicts и не трогаются, если не задан force.
+pub fn restore_snapshot(
+    project_root: &Path,
+    session_id: &str,
+    force: bool,
+) -> Result<RestoreReport, SnapshotError> {
+    let dir = snapshot_dir(project_root, session_id)?;
+    if !dir.join(MANIFEST_FILE).is_file() {
+        return Err(SnapshotError::NotFound(session_id.to_string()));
+    }
+    let mut manifest = read_manifest(&dir)?;
+    let mut report = RestoreReport::default();
+    for entry in &manifest.files {
+        let rel = normalize(Path::new(&entry.path))?;
+        let full = project_root.join(&rel);
+        let current = disk_hash(&full)?;
+        if current == entry.hash {
+            continue;
+        }
+        if current != entry.written_hash && !force {
+            report.conflicts.push(entry.path.clone());
+            continue;
+        }
+        if entry.existed {
+            let blob = dir.join("files").join(&rel);
+            let data = std::fs::read(&blob).map_err(|e| SnapshotError::Io(entry.path.clone(), e))?;
+            if let Some(parent) = full.parent() {
+                std::fs::create_dir_all(parent).map_err(|e| SnapshotError::Io(entry.path.clone(), e))?;
+            }
+            std::fs::write(&full, data).map_err(|e| SnapshotError::Io(entry.path.clone(), e))?;
+        } else {
+            std::fs::remove_file(&full).map_err(|e| SnapshotError::Io(entry.path.clone(), e))?;
+            // Пустые каталоги, созданные агентом, тоже убираем.
+            let mut parent = full.parent();
+            while let Some(p) = parent.filter(|p| *p != project_root) {
+                if std::fs::remove_dir(p).is_err() {
+                    break;
+                }
+                parent = p.parent();
+            }
+        }
+        report.restored.push(entry.path.clone());
+    }
+    if report.conflicts.is_empty() {
+        manifest.restored_at = Some(chrono::Utc::now().to_rfc3339());
+        std::fs::write(dir.join(MANIFEST_FILE), serde_json::to_string_pretty(&manifest)?)
+            .map_err(|e| SnapshotError::Io(session_id.to_string(), e))?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Временный проект; удаляется при drop.
+    struct TempProject(PathBuf);
+
+    impl TempProject {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir()
+                .join(format!("kengaide-snapshot-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&root);
+            std::fs::create_dir_all(&root).unwrap();
+            Self(root)
+        }
+
+        fn write(&self, path: &str, content: &str) {
+            let full = self.0.join(path);
+            std::fs::create_dir_all(full.parent().unwrap()).unwrap();
+            std::fs::write(full, content).unwrap();
+        }
+
+        fn read(&self, path: &str) -> Option<String> {
+            std::fs::read_to_string(self.0.join(path)).ok()
+        }
+    }
+
+    impl Drop for TempProject {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Запись агентом через снимок.
+    fn agent_write(snapshot: &SessionSnapshot, project: &TempProject, path: &str, content: &str) {
+        snapshot.before_write(Path::new(path)).unwrap();
+        project.write(path, content);
+        snapshot.after_write(Path::new(path), content.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn restores_modified_file() {
+        let project = TempProject::new("modified");
+        project.write("src/lib.rs", "original\n");
+        let snapshot = SessionSnapshot::new(&project.0, "s1").unwrap();
+        agent_write(&snapshot, &project, "src/lib.rs", "first\n");
+        // Повторная запись не перезаписывает исходную версию в снимке.
+        agent_write(&snapshot, &project, "src/lib.rs", "second\n");
+
+        let report = restore_snapshot(&project.0, "s1", false).unwrap();
+        assert_eq!(report.restored, vec!["src/lib.rs"]);
+        assert!(report.conflicts.is_empty());
+        assert_eq!(project.read("src/lib.rs").as_deref(), Some("original\n"));
+        let manifest = &list_snapshots(&project.0)[0];
+        assert!(manifest.restored_at.is_some());
+    }
+
+    #[test]
+    fn removes_created_files_and_dirs() {
+        let project = TempProject::new("created");
+        let snapshot = SessionSnapshot::new(&project.0, "s1").unwrap();
+        agent_write(&snapshot, &project, "src/deep/new.rs", "fn f() {}\n");
+        project.write("app/src/main.rs", "fn main() {}\n");
+        project.write("app/Cargo.toml", "[package]\n");
+        snapshot.record_created_dir(Path::new("app")).unwrap();
+
+        let mut report = restore_snapshot(&project.0, "s1", false).unwrap();
+        report.restored.sort();
+        assert_eq!(report.restored, vec!["app/Cargo.toml", "app/src/main.rs", "src/deep/new.rs"]);
+        assert!(!project.0.join("src").exists());
+        assert!(!project.0.join("app").exists());
+        assert!(project.0.exists());
+    }
+
+    #[test]
+    fn user_edits_are_conflicts_unless_forced() {
+        let project = TempProject::new("conflict");
+        project.write("a.txt", "original\n");
+        project.write("b.txt", "b\n");
+        let snapshot = SessionSnapshot::new(&project.0, "s1").unwrap();
+        agent_write(&snapshot, &project, "a.txt", "agent\n");
+        agent_write(&snapshot, &project, "b.txt", "agent b\n");
+        project.write("a.txt", "user\n");
+
+        let report = restore_snapshot(&project.0, "s1", false).unwrap();
+        assert_eq!(report.conflicts, vec!["a.txt"]);
+        assert_eq!(report.restored, vec!["b.txt"]);
+        assert_eq!(project.read("a.txt").as_deref(), Some("user\n"));
+        assert_eq!(project.read("b.txt").as_deref(), Some("b\n"));
+        // С конфликтами снимок не помечается восстановленным.
+        assert!(list_snapshots(&project.0)[0].restored_at.is_none());
+
+        let report = restore_snapshot(&project.0, "s1", true).unwrap();
+        assert_eq!(report.restored, vec!["a.txt"]);
+        assert_eq!(project.read("a.txt").as_deref(), Some("original\n"));
+    }
+
+    #[test]
+    fn rejects_bad_ids_and_paths() {
+        let project = TempProject::new("invalid");
+        assert!(matches!(
+            SessionSnapshot::new(&project.0, "../x"),
+            Err(SnapshotError::InvalidSessionId(_))
+        ));
+        assert!(matches!(
+            restore_snapshot(&project.0, "missing", false),
+            Err(SnapshotError::NotFound(_))
+        ));
+        let snapshot = SessionSnapshot::new(&project.0, "s1").unwrap();
+        assert!(matches!(
+            snapshot.before_write(Path::new("../outside.txt")),
+            Err(SnapshotError::OutsideProject(_))
+        ));
+    }
+}
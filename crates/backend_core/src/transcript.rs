@@ -0,0 +1,135 @@
+//! Сохранённые разговоры агента для продолжения сессии.
+//!
+//! `.kengaide/sessions/<session_id>.json`: сообщения пользователя, ответы модели (с вызовами
+//! инструментов), результаты инструментов и системные подсказки — в том виде, в каком они
+//! ушли модели (после редактирования секретов).
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, thiserror::Error)]
+pub enum TranscriptError {
+    #[error("Invalid session id: {0}")]
+    InvalidSessionId(String),
+    #[error("Session not found: {0}")]
+    NotFound(String),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid session file: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Сообщение разговора.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "role", rename_all = "snake_case")]
+pub enum TranscriptMessage {
+    User { content: String },
+    /// Ответ модели целиком (вместе с блоком tool_call).
+    Assistant { content: String },
+    ToolCall {
+        name: String,
+        arguments: serde_json::Value,
+    },
+    ToolResult { success: bool, output: String },
+    /// Подсказка цикла агента модели ("[System: ...]").
+    System { content: String },
+    /// Резюме старых сообщений, заменившее их при сжатии разговора.
+    Summary { content: String },
+}
+
+/// Разговор сессии агента.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentTranscript {
+    pub session_id: String,
+    pub created_at: String,
+    pub updated_at: String,
+    /// Первая задача пользователя.
+    pub task: String,
+    #[serde(default)]
+    pub messages: Vec<TranscriptMessage>,
+}
+
+/// Краткие сведения о сессии для списка.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptSummary {
+    pub session_id: String,
+    pub created_at: String,
+    pub updated_at: String,
+    pub task: String,
+    /// Сколько раз пользователь писал в сессию.
+    pub turns: usize,
+}
+
+impl AgentTranscript {
+    pub fn new(session_id: &str, task: &str) -> Self {
+        let now = chrono::Utc::now().to_rfc3339();
+        Self {
+            session_id: session_id.to_string(),
+            created_at: now.clone(),
+            updated_at: now,
+            task: task.to_string(),
+            messages: Vec::new(),
+        }
+    }
+
+    pub fn summary(&self) -> TranscriptSummary {
+        TranscriptSummary {
+            session_id: self.session_id.clone(),
+            created_at: self.created_at.clone(),
+            updated_at: self.updated_at.clone(),
+            task: self.task.clone(),
+            turns: self
+                .messages
+                .iter()
+                .filter(|m| matches!(m, TranscriptMessage::User { .. }))
+                .count(),
+        }
+    }
+}
+
+/// Директория разговоров проекта.
+pub fn transcripts_dir(project_root: &Path) -> PathBuf {
+    project_root.join(".kengaide").join("sessions")
+}
+
+fn transcript_path(project_root: &Path, session_id: &str) -> Result<PathBuf, TranscriptError> {
+    if session_id.is_empty() || session_id.starts_with('.') || session_id.contains(['/', '\\']) {
+        return Err(TranscriptError::InvalidSessionId(session_id.to_string()));
+    }
+    Ok(transcripts_dir(project_root).join(format!("{}.json", session_id)))
+}
+
+/// Сохраняет разговор (перезаписывает файл сессии).
+pub fn save_transcript(project_root: &Path, transcript: &AgentTranscript) -> Result<(), TranscriptError> {
+    let path = transcript_path(project_root, &transcript.session_id)?;
+    std::fs::create_dir_all(transcripts_dir(project_root))?;
+    let mut transcript = transcript.clone();
+    transcript.updated_at = chrono::Utc::now().to_rfc3339();
+    std::fs::write(path, serde_json::to_string_pretty(&transcript)?)?;
+    Ok(())
+}
+
+pub fn load_transcript(project_root: &Path, session_id: &str) -> Result<AgentTranscript, TranscriptError> {
+    let path = transcript_path(project_root, session_id)?;
+    let s = std::fs::read_to_string(&path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => TranscriptError::NotFound(session_id.to_string()),
+        _ => TranscriptError::Io(e),
+    })?;
+    Ok(serde_json::from_str(&s)?)
+}
+
+/// Сессии, которые можно продолжить, — последние изменённые первыми.
+pub fn list_transcripts(project_root: &Path) -> Vec<TranscriptSummary> {
+    let Ok(entries) = std::fs::read_dir(transcripts_dir(project_root)) else {
+        return Vec::new();
+    };
+    let mut list: Vec<TranscriptSummary> = entries
+        .flatten()
+        .filter(|e| e.path().extension().is_some_and(|x| x == "json"))
+        .filter_map(|e| std::fs::read_to_string(e.path()).ok())
+        .filter_map(|s| serde_json::from_str::<AgentTranscript>(&s).ok())
+        .map(|t| t.summary())
+        .collect();
+    list.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+    list
+}
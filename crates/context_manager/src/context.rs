@@ -1,14 +1,26 @@
 //! Контекст запроса: файлы, выделение, дерево.
 
 use serde::{Deserialize, Serialize};
+use backend_core::references::{ContextRef, ReferenceResolver};
 use std::path::PathBuf;
-use thiserror::Error;
 
-#[derive(Error, Debug)]
-pub enum ContextError {
-    #[error("Context limit exceeded: {0}")]
-    LimitExceeded(String),
-}
+use crate::symbols::SymbolIndex;
+
+/// Грубая оценка: символов на токен.
+const CHARS_PER_TOKEN: usize = 4;
+/// Доля окна модели под контекст; остальное — системный промпт, запрос и ответ.
+const CONTEXT_WINDOW_PERCENT: usize = 50;
+/// Меньше этого обрезанный фрагмент бесполезен — элемент отбрасывается целиком.
+const MIN_PARTIAL_CHARS: usize = 400;
+/// Доля оставшегося бюджета под окно текущего файла, если есть дополнительные файлы.
+const CURRENT_FILE_WINDOW_PERCENT: usize = 75;
+const TRUNCATED_MARKER: &str = "\n[... truncated]";
+/// Определения символов из выделения важнее найденного по смыслу (близость ≤ 1).
+const DEFINITION_SCORE: f32 = 2.0;
+/// Сколько определений из выделения добавлять.
+const MAX_DEFINITIONS: usize = 8;
+/// Явные ссылки пользователя (@file:, @symbol:, ...) важнее всего остального.
+const REFERENCE_SCORE: f32 = 3.0;
 
 /// Лимиты контекста (в символах, грубая оценка токенов ~4 chars/token).
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,6 +38,17 @@ impl Default for ContextLimits {
     }
 }
 
+impl ContextLimits {
+    /// Сужает лимит до окна модели (max_context_tokens провайдера).
+    pub fn with_max_tokens(mut self, max_context_tokens: Option<usize>) -> Self {
+        if let Some(tokens) = max_context_tokens {
+            let chars = tokens * CHARS_PER_TOKEN * CONTEXT_WINDOW_PERCENT / 100;
+            self.max_chars = self.max_chars.min(chars);
+        }
+        self
+    }
+}
+
 /// Собранный контекст для AI.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Context {
@@ -37,6 +60,9 @@ pub struct Context {
     pub project_tree: Vec<PathBuf>,
     /// Дополнительные файлы по запросу.
     pub extra_files: Vec<FileContext>,
+    /// Что не поместилось в лимит: обрезано или отброшено.
+    #[serde(default)]
+    pub omitted: Vec<OmittedItem>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,13 +71,39 @@ pub struct FileContext {
     pub content: String,
 }
 
+/// Элемент контекста, урезанный при упаковке в лимит.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OmittedItem {
+    /// Путь файла или "selection".
+    pub name: String,
+    pub original_chars: usize,
+    /// 0 — отброшен целиком.
+    pub kept_chars: usize,
+}
+
+impl OmittedItem {
+    pub fn is_dropped(&self) -> bool {
+        self.kept_chars == 0
+    }
+}
+
+/// Дополнительный файл с оценкой релевантности (больше — важнее).
+struct RankedFile {
+    file: FileContext,
+    score: f32,
+}
+
 /// Построитель контекста с учётом лимитов.
+///
+/// Упаковка по приоритету: выделение, окно текущего файла вокруг выделения,
+/// дополнительные файлы по убыванию релевантности. Не поместившееся обрезается или
+/// отбрасывается и попадает в `Context::omitted`.
 pub struct ContextBuilder {
     limits: ContextLimits,
     current_file: Option<FileContext>,
     selection: Option<String>,
     project_tree: Vec<PathBuf>,
-    extra_files: Vec<FileContext>,
+    extra_files: Vec<RankedFile>,
 }
 
 impl ContextBuilder {
@@ -80,35 +132,223 @@ impl ContextBuilder {
         self
     }
 
-    pub fn add_file(mut self, path: PathBuf, content: String) -> Self {
-        if self.extra_files.len() < self.limits.max_files {
-            self.extra_files.push(FileContext { path, content });
+    /// Дополнительный файл без оценки: уступает ранжированным.
+    pub fn add_file(self, path: PathBuf, content: String) -> Self {
+        self.add_ranked_file(path, content, 0.0)
+    }
+
+    /// Дополнительный файл с релевантностью (например, близость из семантического индекса).
+    pub fn add_ranked_file(mut self, path: PathBuf, content: String, score: f32) -> Self {
+        self.extra_files.push(RankedFile {
+            file: FileContext { path, content },
+            score,
+        });
+        self
+    }
+
+    /// Добавляет определения символов, упомянутых в тексте (обычно в выделении),
+    /// кроме определённых в текущем файле.
+    pub fn symbol_definitions(mut self, index: &SymbolIndex, text: &str) -> Self {
+        let current = self.current_file.as_ref().map(|f| f.path.clone());
+        let symbols = index
+            .referenced_in(text)
+            .into_iter()
+            .filter(|s| !current.as_ref().is_some_and(|p| p.ends_with(&s.path)))
+            .take(MAX_DEFINITIONS);
+        for symbol in symbols {
+            if let Some(content) = index.read_definition(&symbol) {
+                self = self.add_ranked_file(symbol.context_label().into(), content, DEFINITION_SCORE);
+            }
         }
         self
     }
 
-    /// Собирает контекст и проверяет лимиты.
-    pub fn build(self) -> Result<Context, ContextError> {
-        let total_chars: usize = self
-            .current_file
-            .as_ref()
-            .map(|f| f.content.len())
-            .unwrap_or(0)
-            + self.selection.as_ref().map(|s| s.len()).unwrap_or(0)
-            + self.extra_files.iter().map(|f| f.content.len()).sum::<usize>();
-
-        if total_chars > self.limits.max_chars {
-            return Err(ContextError::LimitExceeded(format!(
-                "{} > {}",
-                total_chars, self.limits.max_chars
-            )));
+    /// Добавляет содержимое явных ссылок запроса; они пакуются первыми среди дополнительных.
+    /// Неразрешимые пропускаются (их отсекает `CommandRouter::validate`).
+    pub fn references(mut self, resolver: &ReferenceResolver<'_>, refs: &[ContextRef]) -> Self {
+        for reference in refs {
+            match resolver.resolve(reference) {
+                Ok(resolved) => {
+                    for r in resolved {
+                        self = self.add_ranked_file(r.label.into(), r.content, REFERENCE_SCORE);
+                    }
+                }
+                Err(e) => tracing::warn!(error = %e, "context reference skipped"),
+            }
         }
+        self
+    }
+
+    /// Упаковывает контекст в лимит символов.
+    pub fn build(self) -> Context {
+        let mut remaining = self.limits.max_chars;
+        let mut omitted = Vec::new();
 
-        Ok(Context {
-            current_file: self.current_file,
-            selection: self.selection,
+        let selection = self.selection.and_then(|s| {
+            let kept = truncate_chars(&s, remaining);
+            if kept.len() < s.len() {
+                omitted.push(OmittedItem {
+                    name: "selection".to_string(),
+                    original_chars: s.len(),
+                    kept_chars: kept.len(),
+                });
+            }
+            remaining -= kept.len();
+            (!kept.is_empty()).then(|| kept.to_string())
+        });
+
+        let current_file = self.current_file.and_then(|f| {
+            if f.content.len() <= remaining {
+                remaining -= f.content.len();
+                return Some(f);
+            }
+            let budget = if self.extra_files.is_empty() {
+                remaining
+            } else {
+                remaining * CURRENT_FILE_WINDOW_PERCENT / 100
+            };
+            let window = (budget >= MIN_PARTIAL_CHARS)
+                .then(|| window_around(&f.content, selection.as_deref(), budget))
+                .flatten();
+            omitted.push(OmittedItem {
+                name: f.path.display().to_string(),
+                original_chars: f.content.len(),
+                kept_chars: window.as_ref().map(|(text, _, _)| text.len()).unwrap_or(0),
+            });
+            let (content, start, end) = window?;
+            remaining -= content.len();
+            Some(FileContext {
+                path: PathBuf::from(format!("{} (lines {}-{})", f.path.display(), start, end)),
+                content,
+            })
+        });
+
+        let mut ranked = self.extra_files;
+        // Стабильная сортировка: при равной оценке сохраняется порядок добавления.
+        ranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+        let mut extra_files = Vec::new();
+        for RankedFile { file, .. } in ranked {
+            let name = file.path.display().to_string();
+            let original_chars = file.content.len();
+            if extra_files.len() >= self.limits.max_files {
+                omitted.push(OmittedItem {
+                    name,
+                    original_chars,
+                    kept_chars: 0,
+                });
+                continue;
+            }
+            if original_chars <= remaining {
+                remaining -= original_chars;
+                extra_files.push(file);
+                continue;
+            }
+            if remaining < MIN_PARTIAL_CHARS {
+                omitted.push(OmittedItem {
+                    name,
+                    original_chars,
+                    kept_chars: 0,
+                });
+                continue;
+            }
+            let content = truncate_lines(&file.content, remaining);
+            remaining -= content.len();
+            omitted.push(OmittedItem {
+                name,
+                original_chars,
+                kept_chars: content.len(),
+            });
+            extra_files.push(FileContext {
+                path: file.path,
+                content,
+            });
+        }
+
+        if !omitted.is_empty() {
+            tracing::debug!(
+                budget = self.limits.max_chars,
+                omitted = omitted.len(),
+                "context packed with omissions"
+            );
+        }
+
+        Context {
+            current_file,
+            selection,
             project_tree: self.project_tree,
-            extra_files: self.extra_files,
+            extra_files,
+            omitted,
+        }
+    }
+}
+
+/// Начало строки не длиннее `max` байт (по границе символа).
+fn truncate_chars(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut cut = max;
+    while !s.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    &s[..cut]
+}
+
+/// Целые строки с начала, с пометкой об обрезке; всего не больше `max` байт.
+fn truncate_lines(content: &str, max: usize) -> String {
+    let budget = max.saturating_sub(TRUNCATED_MARKER.len());
+    let mut out = String::new();
+    for line in content.lines() {
+        if out.len() + line.len() + 1 > budget {
+            break;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    if out.is_empty() {
+        out.push_str(truncate_chars(content, budget));
+    }
+    out.push_str(TRUNCATED_MARKER);
+    out
+}
+
+/// Окно строк файла вокруг выделения (без выделения — с начала файла), не больше `budget` байт.
+/// Возвращает текст и номера строк (с 1, включительно).
+fn window_around(content: &str, anchor: Option<&str>, budget: usize) -> Option<(String, usize, usize)> {
+    let lines: Vec<&str> = content.lines().collect();
+    let (anchor_start, anchor_end) = anchor
+        .filter(|a| !a.trim().is_empty())
+        .and_then(|a| content.find(a).map(|pos| (pos, a)))
+        .map(|(pos, a)| {
+            let start = content[..pos].matches('\n').count();
+            (start, start + a.lines().count().max(1))
         })
+        .unwrap_or((0, 0));
+    let cost = |i: usize| lines[i].len() + 1;
+
+    // Сначала строки выделения, потом поочерёдно ниже и выше.
+    let (mut start, mut end, mut used) = (anchor_start, anchor_start, 0);
+    while end < anchor_end.min(lines.len()) && used + cost(end) <= budget {
+        used += cost(end);
+        end += 1;
+    }
+    if end >= anchor_end {
+        loop {
+            let mut grew = false;
+            if end < lines.len() && used + cost(end) <= budget {
+                used += cost(end);
+                end += 1;
+                grew = true;
+            }
+            if start > 0 && used + cost(start - 1) <= budget {
+                start -= 1;
+                used += cost(start);
+                grew = true;
+            }
+            if !grew {
+                break;
+            }
+        }
     }
+    (end > start).then(|| (lines[start..end].join("\n"), start + 1, end))
 }
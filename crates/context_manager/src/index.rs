@@ -0,0 +1,695 @@
+//! Семантический индекс проекта: исходники режутся на чанки по строкам, чанки превращаются
+//! в эмбеддинги и хранятся в `.kengaide/index/` (manifest.json + vectors.<поколение>.f32).
+//!
+//! Обновление инкрементальное: переиндексируются только файлы с изменённым mtime/размером.
+//! Новое состояние собирается в копии и подменяет старое целиком; на диске точка фиксации —
+//! rename манифеста, ссылающегося на свой файл векторов.
+
+use ai_providers::EmbeddingProvider;
+use backend_core::fs::ProjectWalker;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+use thiserror::Error;
+use tokio::sync::{Mutex, RwLock};
+
+const INDEX_DIR: &str = ".kengaide/index";
+const MANIFEST_FILE: &str = "manifest.json";
+/// Файл векторов индекса без поколения (до атомарной записи).
+const VECTORS_FILE: &str = "vectors.f32";
+/// Строк в чанке и перекрытие соседних чанков.
+const CHUNK_LINES: usize = 40;
+const CHUNK_OVERLAP: usize = 8;
+/// Чанк длиннее обрезается (минифицированный код, длинные строки).
+const MAX_CHUNK_CHARS: usize = 2_000;
+/// Файлы крупнее не индексируются (сгенерированный код, дампы).
+const MAX_FILE_BYTES: u64 = 256 * 1024;
+/// Сколько чанков копить перед запросом эмбеддингов.
+const EMBED_BATCH_CHUNKS: usize = 256;
+
+const INDEXED_EXTENSIONS: &[&str] = &[
+    "rs", "py", "ts", "tsx", "js", "jsx", "go", "java", "kt", "c", "h", "cpp", "hpp", "cs", "rb",
+    "php", "swift", "scala", "sh", "sql", "md", "toml", "yaml", "yml",
+];
+
+#[derive(Error, Debug)]
+pub enum IndexError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Embedding failed: {0}")]
+    Embedding(String),
+    #[error("Index corrupted: {0}")]
+    Corrupt(String),
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Manifest {
+    /// Индекс привязан к модели эмбеддингов: другая модель — другое пространство векторов.
+    provider_id: String,
+    dimensions: usize,
+    /// Номер записи: векторы лежат в `vectors.<generation>.f32` (0 — `vectors.f32`).
+    #[serde(default)]
+    generation: u64,
+    /// Относительный путь (через '/') → файл.
+    files: BTreeMap<String, FileEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FileEntry {
+    modified: u64,
+    size: u64,
+    chunks: Vec<ChunkEntry>,
+}
+
+/// Строки чанка, с 1, включительно.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct ChunkEntry {
+    start_line: usize,
+    end_line: usize,
+}
+
+#[derive(Clone, Default)]
+struct IndexState {
+    manifest: Manifest,
+    /// Векторы чанков по файлам (в порядке manifest.files[..].chunks).
+    vectors: HashMap<String, Vec<Vec<f32>>>,
+}
+
+/// Итог обновления индекса.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IndexStats {
+    pub files_indexed: usize,
+    pub files_removed: usize,
+    pub chunks_embedded: usize,
+    pub total_chunks: usize,
+}
+
+/// Найденный фрагмент кода.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChunkHit {
+    /// Путь относительно корня проекта.
+    pub path: PathBuf,
+    pub start_line: usize,
+    pub end_line: usize,
+    /// Косинусная близость к запросу.
+    pub score: f32,
+    pub content: String,
+}
+
+/// Файл на диске, кандидат на индексацию.
+struct ScannedFile {
+    rel: String,
+    modified: u64,
+    size: u64,
+}
+
+/// Индекс одного проекта.
+pub struct SemanticIndex {
+    root: PathBuf,
+    dir: PathBuf,
+    embedder: Arc<dyn EmbeddingProvider>,
+    state: RwLock<IndexState>,
+    /// Одно обновление за раз (полное и по событиям файлов).
+    update_lock: Mutex<()>,
+}
+
+impl SemanticIndex {
+    /// Открывает индекс проекта; сохранённый индекс другой модели игнорируется.
+    pub fn open(root: &Path, embedder: Arc<dyn EmbeddingProvider>) -> Self {
+        let dir = root.join(INDEX_DIR);
+        let state = match load_state(&dir) {
+            Ok(Some(state)) if state.manifest.provider_id == embedder.id() => state,
+            Ok(_) => IndexState::default(),
+            Err(e) => {
+                tracing::warn!(dir = %dir.display(), error = %e, "semantic index reset");
+                IndexState::default()
+            }
+        };
+        Self {
+            root: root.to_path_buf(),
+            dir,
+            embedder,
+            state: RwLock::new(state),
+            update_lock: Mutex::new(()),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Полное инкрементальное обновление: новые и изменённые файлы, удалённые — из индекса.
+    pub async fn update(&self) -> Result<IndexStats, IndexError> {
+        let _guard = self.update_lock.lock().await;
+        let root = self.root.clone();
+        let scanned = tokio::task::spawn_blocking(move || scan_project(&root))
+            .await
+            .map_err(|e| IndexError::Io(std::io::Error::other(e)))?;
+
+        let (changed, removed) = {
+            let state = self.state.read().await;
+            let on_disk: HashSet<&str> = scanned.iter().map(|f| f.rel.as_str()).collect();
+            let removed: Vec<String> = state
+                .manifest
+                .files
+                .keys()
+                .filter(|k| !on_disk.contains(k.as_str()))
+                .cloned()
+                .collect();
+            let changed: Vec<ScannedFile> = scanned
+                .into_iter()
+                .filter(|f| match state.manifest.files.get(&f.rel) {
+                    Some(e) => e.modified != f.modified || e.size != f.size,
+                    None => true,
+                })
+                .collect();
+            (changed, removed)
+        };
+        self.reindex(changed, removed).await
+    }
+
+    /// Обновление по событиям файлов (сохранение, правка агентом). Пути — относительно корня.
+    pub async fn update_files(&self, paths: &[PathBuf]) -> Result<IndexStats, IndexError> {
+        let _guard = self.update_lock.lock().await;
+        let mut changed = Vec::new();
+        let mut removed = Vec::new();
+        for rel in paths {
+            let key = rel_key(rel);
+            match scan_file(&self.root, rel) {
+                Some(file) => changed.push(file),
+                None => removed.push(key),
+            }
+        }
+        self.reindex(changed, removed).await
+    }
+
+    /// Изменения копятся в копии состояния: поиск видит прежний индекс, пока новый не готов.
+    async fn reindex(
+        &self,
+        changed: Vec<ScannedFile>,
+        removed: Vec<String>,
+    ) -> Result<IndexStats, IndexError> {
+        let mut stats = IndexStats {
+            files_removed: removed.len(),
+            ..IndexStats::default()
+        };
+        let mut next = self.state.read().await.clone();
+        for key in &removed {
+            next.manifest.files.remove(key);
+            next.vectors.remove(key);
+        }
+
+        // Чанки копятся по нескольким файлам, эмбеддинги — пачками.
+        let mut queue: VecDeque<ScannedFile> = changed.into();
+        let mut pending: Vec<(ScannedFile, Vec<ChunkEntry>, Vec<String>)> = Vec::new();
+        let mut pending_chunks = 0;
+        let mut rebuilt = false;
+        loop {
+            if let Some(file) = queue.pop_front() {
+                let full = self.root.join(&file.rel);
+                let content = match tokio::fs::read_to_string(&full).await {
+                    Ok(c) => c,
+                    // Не UTF-8 или исчез — просто не индексируем.
+                    Err(_) => continue,
+                };
+                let chunks = chunk_lines(&content);
+                let texts: Vec<String> = chunks
+                    .iter()
+                    .map(|(_, text)| format!("{}\n{}", file.rel, text))
+                    .collect();
+                pending_chunks += texts.len();
+                pending.push((file, chunks.into_iter().map(|(c, _)| c).collect(), texts));
+                if pending_chunks < EMBED_BATCH_CHUNKS {
+                    continue;
+                }
+            } else if pending.is_empty() {
+                break;
+            }
+
+            let batch = std::mem::take(&mut pending);
+            pending_chunks = 0;
+            if let Some(embedded) = self.embed_batch(&mut next, &batch).await? {
+                stats.chunks_embedded += embedded;
+                stats.files_indexed += batch.len();
+                continue;
+            }
+            // Модель сменила размерность — старые векторы несравнимы: весь проект заново в
+            // пустое состояние, прежний индекс остаётся в работе до конца перестроения.
+            if rebuilt {
+                return Err(IndexError::Embedding(
+                    "embedding dimensions changed during rebuild".to_string(),
+                ));
+            }
+            rebuilt = true;
+            next = IndexState::default();
+            stats.chunks_embedded = 0;
+            stats.files_indexed = 0;
+            let root = self.root.clone();
+            queue = tokio::task::spawn_blocking(move || scan_project(&root))
+                .await
+                .map_err(|e| IndexError::Io(std::io::Error::other(e)))?
+                .into();
+        }
+
+        stats.total_chunks = next.manifest.files.values().map(|f| f.chunks.len()).sum();
+        if stats.files_indexed > 0 || stats.files_removed > 0 {
+            let dir = self.dir.clone();
+            let (next, saved) = tokio::task::spawn_blocking(move || {
+                let saved = save_state(&dir, &mut next);
+                (next, saved)
+            })
+            .await
+            .map_err(|e| IndexError::Io(std::io::Error::other(e)))?;
+            *self.state.write().await = next;
+            saved?;
+        }
+        Ok(stats)
+    }
+
+    /// Эмбеддинги пачки в `state`. None — размерность не совпала с индексом, ничего не записано.
+    async fn embed_batch(
+        &self,
+        state: &mut IndexState,
+        pending: &[(ScannedFile, Vec<ChunkEntry>, Vec<String>)],
+    ) -> Result<Option<usize>, IndexError> {
+        let texts: Vec<String> = pending.iter().flat_map(|(_, _, t)| t.iter().cloned()).collect();
+        let vectors = self
+            .embedder
+            .embed_all(&texts)
+            .await
+            .map_err(|e| IndexError::Embedding(e.to_string()))?;
+        let dims = vectors.first().map(|v| v.len()).unwrap_or(0);
+
+        if state.manifest.dimensions != 0 && dims != 0 && state.manifest.dimensions != dims {
+            tracing::warn!(
+                old = state.manifest.dimensions,
+                new = dims,
+                "embedding dimensions changed, semantic index rebuild"
+            );
+            return Ok(None);
+        }
+        state.manifest.provider_id = self.embedder.id().to_string();
+        if dims != 0 {
+            state.manifest.dimensions = dims;
+        }
+
+        let mut iter = vectors.into_iter();
+        for (file, chunks, _) in pending {
+            let file_vectors: Vec<Vec<f32>> = iter.by_ref().take(chunks.len()).collect();
+            state.manifest.files.insert(
+                file.rel.clone(),
+                FileEntry {
+                    modified: file.modified,
+                    size: file.size,
+                    chunks: chunks.clone(),
+                },
+            );
+            state.vectors.insert(file.rel.clone(), file_vectors);
+        }
+        Ok(Some(texts.len()))
+    }
+
+    /// Top-k чанков, ближайших к запросу.
+    pub async fn search(&self, query: &str, k: usize) -> Result<Vec<ChunkHit>, IndexError> {
+        if query.trim().is_empty() || k == 0 {
+            return Ok(Vec::new());
+        }
+        if self.state.read().await.manifest.files.is_empty() {
+            return Ok(Vec::new());
+        }
+        let query_vec = self
+            .embedder
+            .embed(&[query.to_string()])
+            .await
+            .map_err(|e| IndexError::Embedding(e.to_string()))?
+            .into_iter()
+            .next()
+            .unwrap_or_default();
+        let query_vec = query_vec.as_slice();
+
+        let mut scored: Vec<(f32, String, ChunkEntry)> = {
+            let state = self.state.read().await;
+            state
+                .manifest
+                .files
+                .iter()
+                .flat_map(|(rel, entry)| {
+                    let vectors = state.vectors.get(rel);
+                    entry.chunks.iter().enumerate().filter_map(move |(i, chunk)| {
+                        let v = vectors?.get(i)?;
+                        Some((cosine(query_vec, v), rel.clone(), *chunk))
+                    })
+                })
+                .collect()
+        };
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.truncate(k);
+
+        let mut hits = Vec::with_capacity(scored.len());
+        for (score, rel, chunk) in scored {
+            let Ok(content) = tokio::fs::read_to_string(self.root.join(&rel)).await else {
+                continue;
+            };
+            let text: Vec<&str> = content
+                .lines()
+                .skip(chunk.start_line - 1)
+                .take(chunk.end_line + 1 - chunk.start_line)
+                .collect();
+            hits.push(ChunkHit {
+                path: PathBuf::from(rel),
+                start_line: chunk.start_line,
+                end_line: chunk.end_line,
+                score,
+                content: text.join("\n"),
+            });
+        }
+        Ok(hits)
+    }
+}
+
+/// Режет файл на перекрывающиеся окна строк. Пустые окна пропускаются.
+fn chunk_lines(content: &str) -> Vec<(ChunkEntry, String)> {
+    let lines: Vec<&str> = content.lines().collect();
+    let step = CHUNK_LINES - CHUNK_OVERLAP;
+    let mut out = Vec::new();
+    let mut start = 0;
+    while start < lines.len() {
+        let end = (start + CHUNK_LINES).min(lines.len());
+        let mut text = lines[start..end].join("\n");
+        if !text.trim().is_empty() {
+            if text.len() > MAX_CHUNK_CHARS {
+                let mut cut = MAX_CHUNK_CHARS;
+                while !text.is_char_boundary(cut) {
+                    cut -= 1;
+                }
+                text.truncate(cut);
+            }
+            out.push((
+                ChunkEntry {
+                    start_line: start + 1,
+                    end_line: end,
+                },
+                text,
+            ));
+        }
+        if end == lines.len() {
+            break;
+        }
+        start += step;
+    }
+    out
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return f32::MIN;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let na = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let nb = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if na == 0.0 || nb == 0.0 {
+        0.0
+    } else {
+        dot / (na * nb)
+    }
+}
+
+fn rel_key(rel: &Path) -> String {
+    rel.to_string_lossy().replace('\\', "/")
+}
+
+fn is_indexable(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| INDEXED_EXTENSIONS.contains(&e))
+        .unwrap_or(false)
+}
+
+fn scan_file(root: &Path, rel: &Path) -> Option<ScannedFile> {
+    if !is_indexable(rel) {
+        return None;
+    }
+    let meta = std::fs::metadata(root.join(rel)).ok()?;
+    if !meta.is_file() || meta.len() > MAX_FILE_BYTES {
+        return None;
+    }
+    let modified = meta
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    Some(ScannedFile {
+        rel: rel_key(rel),
+        modified,
+        size: meta.len(),
+    })
+}
+
+fn scan_project(root: &Path) -> Vec<ScannedFile> {
+    ProjectWalker::new(root)
+        .files()
+        .iter()
+        .filter_map(|rel| scan_file(root, rel))
+        .collect()
+}
+
+fn load_state(dir: &Path) -> Result<Option<IndexState>, IndexError> {
+    let manifest_path = dir.join(MANIFEST_FILE);
+    if !manifest_path.exists() {
+        return Ok(None);
+    }
+    let manifest: Manifest = serde_json::from_str(&std::fs::read_to_string(&manifest_path)?)
+        .map_err(|e| IndexError::Corrupt(e.to_string()))?;
+    let raw = std::fs::read(dir.join(vectors_file(manifest.generation)))?;
+    let floats: Vec<f32> = raw
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+
+    let total: usize = manifest.files.values().map(|f| f.chunks.len()).sum();
+    if floats.len() != total * manifest.dimensions {
+        return Err(IndexError::Corrupt(format!(
+            "{} floats for {} chunks x {}",
+            floats.len(),
+            total,
+            manifest.dimensions
+        )));
+    }
+
+    let mut vectors = HashMap::new();
+    let mut rows = floats.chunks_exact(manifest.dimensions.max(1));
+    for (rel, entry) in &manifest.files {
+        let file_vectors: Vec<Vec<f32>> = rows
+            .by_ref()
+            .take(entry.chunks.len())
+            .map(|r| r.to_vec())
+            .collect();
+        vectors.insert(rel.clone(), file_vectors);
+    }
+    Ok(Some(IndexState { manifest, vectors }))
+}
+
+fn vectors_file(generation: u64) -> String {
+    match generation {
+        0 => VECTORS_FILE.to_string(),
+        n => format!("vectors.{}.f32", n),
+    }
+}
+
+/// Векторы пишутся подряд в порядке manifest.files (BTreeMap) и их чанков, в файл нового
+/// поколения; затем манифест подменяется через rename. Обрыв до rename оставляет прежний
+/// индекс целым, после — старые файлы векторов просто лишние и удаляются.
+fn save_state(dir: &Path, state: &mut IndexState) -> Result<(), IndexError> {
+    std::fs::create_dir_all(dir)?;
+    let mut raw = Vec::new();
+    for rel in state.manifest.files.keys() {
+        for v in state.vectors.get(rel).into_iter().flatten() {
+            for x in v {
+                raw.extend_from_slice(&x.to_le_bytes());
+            }
+        }
+    }
+    let mut manifest = state.manifest.clone();
+    manifest.generation += 1;
+    let vectors = vectors_file(manifest.generation);
+    std::fs::write(dir.join(&vectors), raw)?;
+    let json = serde_json::to_string(&manifest).map_err(|e| IndexError::Corrupt(e.to_string()))?;
+    let tmp = dir.join(format!("{}.tmp", MANIFEST_FILE));
+    std::fs::write(&tmp, json)?;
+    std::fs::rename(&tmp, dir.join(MANIFEST_FILE))?;
+    state.manifest.generation = manifest.generation;
+
+    for entry in std::fs::read_dir(dir)?.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with("vectors.") && name.ends_with(".f32") && name != vectors {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ai_providers::{ProviderError, ProviderType};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct TempProject(PathBuf);
+
+    impl TempProject {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!(
+                "kengaide-index-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&root);
+            std::fs::create_dir_all(&root).unwrap();
+            Self(root)
+        }
+
+        fn write(&self, rel: &str, text: &str) {
+            let path = self.0.join(rel);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        }
+
+        fn index_files(&self) -> Vec<String> {
+            let mut names: Vec<String> = std::fs::read_dir(self.0.join(INDEX_DIR))
+                .unwrap()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.path().is_file())
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .collect();
+            names.sort();
+            names
+        }
+    }
+
+    impl Drop for TempProject {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Эмбеддинги заданной размерности; размерность меняется на лету, как при смене модели.
+    struct FakeEmbedder {
+        dims: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl EmbeddingProvider for FakeEmbedder {
+        fn id(&self) -> &str {
+            "fake-embed"
+        }
+
+        fn provider_type(&self) -> ProviderType {
+            ProviderType::Local
+        }
+
+        fn dimensions(&self) -> Option<usize> {
+            Some(self.dims.load(Ordering::SeqCst))
+        }
+
+        fn max_batch_size(&self) -> usize {
+            16
+        }
+
+        async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+            let dims = self.dims.load(Ordering::SeqCst);
+            Ok(inputs.iter().map(|t| vec![t.len() as f32; dims]).collect())
+        }
+
+        async fn is_available(&self) -> Result<bool, ProviderError> {
+            Ok(true)
+        }
+    }
+
+    fn open(project: &TempProject, dims: usize) -> (SemanticIndex, Arc<FakeEmbedder>) {
+        let embedder = Arc::new(FakeEmbedder {
+            dims: AtomicUsize::new(dims),
+        });
+        (SemanticIndex::open(&project.0, embedder.clone()), embedder)
+    }
+
+    #[tokio::test]
+    async fn saves_by_generation() {
+        let project = TempProject::new("generation");
+        project.write("src/a.rs", "fn a() {}\n");
+        project.write("src/b.rs", "fn b() {}\n");
+        let (index, _) = open(&project, 4);
+        index.update().await.unwrap();
+        assert_eq!(project.index_files(), ["manifest.json", "vectors.1.f32"]);
+
+        project.write("src/a.rs", "fn a() { changed(); }\n");
+        index.update_files(&[PathBuf::from("src/a.rs")]).await.unwrap();
+        // Прежнее поколение удалено, временный манифест не остался.
+        assert_eq!(project.index_files(), ["manifest.json", "vectors.2.f32"]);
+
+        let (reopened, _) = open(&project, 4);
+        let state = reopened.state.read().await;
+        assert_eq!(state.manifest.generation, 2);
+        assert_eq!(state.manifest.files.len(), 2);
+        assert_eq!(state.vectors["src/a.rs"][0].len(), 4);
+    }
+
+    #[tokio::test]
+    async fn stray_vectors_do_not_replace_saved_index() {
+        let project = TempProject::new("stray");
+        project.write("src/a.rs", "fn a() {}\n");
+        let (index, _) = open(&project, 4);
+        index.update().await.unwrap();
+        // Обрыв записи до rename манифеста: файл следующего поколения есть, манифест прежний.
+        std::fs::write(project.0.join(INDEX_DIR).join("vectors.2.f32"), [0u8; 3]).unwrap();
+
+        let (reopened, _) = open(&project, 4);
+        let state = reopened.state.read().await;
+        assert_eq!(state.manifest.generation, 1);
+        assert_eq!(state.vectors["src/a.rs"].len(), 1);
+    }
+
+    #[tokio::test]
+    async fn loads_index_without_generation() {
+        let project = TempProject::new("legacy");
+        let dir = project.0.join(INDEX_DIR);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join(MANIFEST_FILE),
+            r#"{"provider_id":"fake-embed","dimensions":2,
+                "files":{"a.rs":{"modified":1,"size":2,"chunks":[{"start_line":1,"end_line":1}]}}}"#,
+        )
+        .unwrap();
+        let raw: Vec<u8> = [1.0f32, 2.0].iter().flat_map(|x| x.to_le_bytes()).collect();
+        std::fs::write(dir.join(VECTORS_FILE), raw).unwrap();
+
+        let (index, _) = open(&project, 2);
+        assert_eq!(index.state.read().await.vectors["a.rs"], [vec![1.0, 2.0]]);
+    }
+
+    #[tokio::test]
+    async fn dimension_change_rebuilds_whole_project() {
+        let project = TempProject::new("dims");
+        project.write("src/a.rs", "fn a() {}\n");
+        project.write("src/b.rs", "fn b() {}\n");
+        project.write("src/c.rs", "fn c() {}\n");
+        let (index, embedder) = open(&project, 4);
+        index.update().await.unwrap();
+
+        // Правка одного файла с новой моделью перестраивает весь индекс, а не оставляет в нём
+        // только этот файл.
+        embedder.dims.store(8, Ordering::SeqCst);
+        project.write("src/a.rs", "fn a() { changed(); }\n");
+        let stats = index.update_files(&[PathBuf::from("src/a.rs")]).await.unwrap();
+        assert_eq!(stats.files_indexed, 3);
+        assert_eq!(stats.total_chunks, 3);
+
+        let (reopened, _) = open(&project, 8);
+        let state = reopened.state.read().await;
+        assert_eq!(state.manifest.dimensions, 8);
+        assert_eq!(state.manifest.files.len(), 3);
+        assert!(state.vectors.values().flatten().all(|v| v.len() == 8));
+    }
+}
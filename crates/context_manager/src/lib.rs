@@ -1,7 +1,12 @@
 //! Context Manager — сбор контекста для AI.
 //!
-//! Ответственность: текущий файл, выделение, дерево проекта, лимиты, грубая токенизация.
+//! Ответственность: текущий файл, выделение, дерево проекта, лимиты, грубая токенизация,
+//! семантический индекс проекта, индекс символов (tree-sitter).
 
 mod context;
+mod index;
+mod symbols;
 
-pub use context::{Context, ContextBuilder, ContextError, ContextLimits};
+pub use context::{Context, ContextBuilder, ContextLimits, FileContext, OmittedItem};
+pub use index::{ChunkHit, IndexError, IndexStats, SemanticIndex};
+pub use symbols::{Symbol, SymbolError, SymbolIndex, SymbolKind, SymbolStats};
@@ -0,0 +1,674 @@
+//! Индекс символов проекта (функции, структуры/классы, impl, методы) на tree-sitter.
+//!
+//! Языки — те, под которые есть шаблоны: Rust, Python, TypeScript/JavaScript.
+//! Хранится в `.kengaide/index/symbols/`; обновление инкрементальное по mtime/размеру.
+
+use backend_core::fs::ProjectWalker;
+use backend_core::references::{ResolvedReference, SymbolLookup};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::UNIX_EPOCH;
+use thiserror::Error;
+use tree_sitter::{Node, Parser};
+
+const SYMBOLS_DIR: &str = ".kengaide/index/symbols";
+const SYMBOLS_FILE: &str = "symbols.json";
+/// Файлы крупнее не разбираются (сгенерированный код).
+const MAX_FILE_BYTES: u64 = 512 * 1024;
+/// Имя с большим числом определений (new, default, ...) в контекст не тянем — неоднозначно.
+const MAX_DEFINITIONS_PER_NAME: usize = 3;
+/// Короткие идентификаторы из выделения не ищем.
+const MIN_REFERENCE_LEN: usize = 3;
+
+#[derive(Error, Debug)]
+pub enum SymbolError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Parse failed: {0}")]
+    Parse(String),
+    #[error("Index corrupted: {0}")]
+    Corrupt(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolKind {
+    Function,
+    Method,
+    Struct,
+    Enum,
+    Trait,
+    Impl,
+    Class,
+    Interface,
+    TypeAlias,
+    Module,
+}
+
+impl SymbolKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SymbolKind::Function => "function",
+            SymbolKind::Method => "method",
+            SymbolKind::Struct => "struct",
+            SymbolKind::Enum => "enum",
+            SymbolKind::Trait => "trait",
+            SymbolKind::Impl => "impl",
+            SymbolKind::Class => "class",
+            SymbolKind::Interface => "interface",
+            SymbolKind::TypeAlias => "type",
+            SymbolKind::Module => "module",
+        }
+    }
+}
+
+/// Определение символа.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Путь относительно корня проекта (через '/').
+    pub path: String,
+    /// Строки определения, с 1, включительно.
+    pub start_line: usize,
+    pub end_line: usize,
+    /// Тип/класс, которому принадлежит метод.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+}
+
+impl Symbol {
+    /// "kind name (path:start-end)" — для инструментов агента.
+    pub fn describe(&self) -> String {
+        let name = match self.container {
+            Some(ref c) => format!("{}::{}", c, self.name),
+            None => self.name.clone(),
+        };
+        format!(
+            "{} {} ({}:{}-{})",
+            self.kind.as_str(),
+            name,
+            self.path,
+            self.start_line,
+            self.end_line
+        )
+    }
+
+    /// "path (lines start-end, kind name)" — метка фрагмента в контексте запроса.
+    pub fn context_label(&self) -> String {
+        format!(
+            "{} (lines {}-{}, {} {})",
+            self.path,
+            self.start_line,
+            self.end_line,
+            self.kind.as_str(),
+            self.name
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lang {
+    Rust,
+    Python,
+    TypeScript,
+    Tsx,
+}
+
+impl Lang {
+    fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "rs" => Some(Lang::Rust),
+            "py" => Some(Lang::Python),
+            "ts" | "mts" | "cts" | "js" | "mjs" | "cjs" => Some(Lang::TypeScript),
+            "tsx" | "jsx" => Some(Lang::Tsx),
+            _ => None,
+        }
+    }
+
+    fn grammar(self) -> tree_sitter::Language {
+        match self {
+            Lang::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Lang::Python => tree_sitter_python::LANGUAGE.into(),
+            Lang::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            Lang::Tsx => tree_sitter_typescript::LANGUAGE_TSX.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct SymbolFile {
+    files: BTreeMap<String, FileSymbols>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FileSymbols {
+    modified: u64,
+    size: u64,
+    symbols: Vec<Symbol>,
+}
+
+/// Итог обновления индекса символов.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SymbolStats {
+    pub files_parsed: usize,
+    pub files_removed: usize,
+    pub total_symbols: usize,
+}
+
+/// Индекс символов одного проекта. Разбор синхронный — из async вызывать через spawn_blocking.
+pub struct SymbolIndex {
+    root: PathBuf,
+    dir: PathBuf,
+    state: RwLock<SymbolFile>,
+}
+
+impl SymbolIndex {
+    /// Открывает сохранённый индекс (повреждённый — начинается заново).
+    pub fn open(root: &Path) -> Self {
+        let dir = root.join(SYMBOLS_DIR);
+        let state = match load(&dir) {
+            Ok(state) => state,
+            Err(e) => {
+                tracing::warn!(dir = %dir.display(), error = %e, "symbol index reset");
+                SymbolFile::default()
+            }
+        };
+        Self {
+            root: root.to_path_buf(),
+            dir,
+            state: RwLock::new(state),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Полное инкрементальное обновление: разбираются новые и изменённые файлы.
+    pub fn update(&self) -> Result<SymbolStats, SymbolError> {
+        let on_disk: Vec<(String, u64, u64)> = ProjectWalker::new(&self.root)
+            .files()
+            .iter()
+            .filter_map(|rel| stat_source(&self.root, rel))
+            .collect();
+        let keys: HashSet<&str> = on_disk.iter().map(|(k, _, _)| k.as_str()).collect();
+        let (changed, removed) = {
+            let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+            let removed: Vec<String> = state
+                .files
+                .keys()
+                .filter(|k| !keys.contains(k.as_str()))
+                .cloned()
+                .collect();
+            let changed: Vec<(String, u64, u64)> = on_disk
+                .iter()
+                .filter(|(k, modified, size)| match state.files.get(k) {
+                    Some(f) => f.modified != *modified || f.size != *size,
+                    None => true,
+                })
+                .cloned()
+                .collect();
+            (changed, removed)
+        };
+        self.reindex(changed, removed)
+    }
+
+    /// Обновление отдельных файлов (пути от корня): после сохранения или правки агентом.
+    pub fn update_files(&self, paths: &[PathBuf]) -> Result<SymbolStats, SymbolError> {
+        let mut changed = Vec::new();
+        let mut removed = Vec::new();
+        for rel in paths {
+            match stat_source(&self.root, rel) {
+                Some(entry) => changed.push(entry),
+                None => removed.push(rel_key(rel)),
+            }
+        }
+        self.reindex(changed, removed)
+    }
+
+    fn reindex(
+        &self,
+        changed: Vec<(String, u64, u64)>,
+        removed: Vec<String>,
+    ) -> Result<SymbolStats, SymbolError> {
+        let mut parsed = Vec::new();
+        for (key, modified, size) in changed {
+            let Some(lang) = Lang::from_path(Path::new(&key)) else {
+                continue;
+            };
+            let Ok(source) = std::fs::read_to_string(self.root.join(&key)) else {
+                continue;
+            };
+            match parse_symbols(lang, &key, &source) {
+                Ok(symbols) => parsed.push((
+                    key,
+                    FileSymbols {
+                        modified,
+                        size,
+                        symbols,
+                    },
+                )),
+                Err(e) => tracing::debug!(path = %key, error = %e, "symbols skipped"),
+            }
+        }
+
+        let mut state = self.state.write().unwrap_or_else(|e| e.into_inner());
+        let mut stats = SymbolStats::default();
+        for key in &removed {
+            if state.files.remove(key).is_some() {
+                stats.files_removed += 1;
+            }
+        }
+        stats.files_parsed = parsed.len();
+        for (key, file) in parsed {
+            state.files.insert(key, file);
+        }
+        stats.total_symbols = state.files.values().map(|f| f.symbols.len()).sum();
+        if stats.files_parsed > 0 || stats.files_removed > 0 {
+            save(&self.dir, &state)?;
+        }
+        Ok(stats)
+    }
+
+    /// Определения по имени (точное совпадение; `Type::method` — с учётом контейнера).
+    pub fn find_definition(&self, name: &str) -> Vec<Symbol> {
+        let (container, name) = match name.rsplit_once("::").or_else(|| name.rsplit_once('.')) {
+            Some((c, n)) => (Some(c), n),
+            None => (None, name),
+        };
+        let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+        state
+            .files
+            .values()
+            .flat_map(|f| f.symbols.iter())
+            .filter(|s| s.name == name)
+            .filter(|s| container.is_none() || s.container.as_deref() == container)
+            .cloned()
+            .collect()
+    }
+
+    /// Символы файла (путь от корня), в порядке следования.
+    pub fn file_symbols(&self, path: &Path) -> Vec<Symbol> {
+        let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+        state
+            .files
+            .get(&rel_key(path))
+            .map(|f| f.symbols.clone())
+            .unwrap_or_default()
+    }
+
+    /// Определения символов, упомянутых в тексте (например, в выделении).
+    /// Имена с множеством определений пропускаются.
+    pub fn referenced_in(&self, text: &str) -> Vec<Symbol> {
+        let mut seen = HashSet::new();
+        let mut out = Vec::new();
+        for ident in text
+            .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .filter(|w| w.len() >= MIN_REFERENCE_LEN)
+            .filter(|w| !w.starts_with(|c: char| c.is_ascii_digit()))
+        {
+            if !seen.insert(ident) {
+                continue;
+            }
+            let defs = self.find_definition(ident);
+            if !defs.is_empty() && defs.len() <= MAX_DEFINITIONS_PER_NAME {
+                out.extend(defs);
+            }
+        }
+        out
+    }
+
+    /// Текст определения с диска.
+    pub fn read_definition(&self, symbol: &Symbol) -> Option<String> {
+        let content = std::fs::read_to_string(self.root.join(&symbol.path)).ok()?;
+        let lines: Vec<&str> = content
+            .lines()
+            .skip(symbol.start_line.saturating_sub(1))
+            .take(symbol.end_line + 1 - symbol.start_line)
+            .collect();
+        Some(lines.join("\n"))
+    }
+}
+
+/// Для `@symbol:name` в запросах.
+impl SymbolLookup for SymbolIndex {
+    fn definitions(&self, name: &str) -> Vec<ResolvedReference> {
+        self.find_definition(name)
+            .into_iter()
+            .filter_map(|symbol| {
+                let content = self.read_definition(&symbol)?;
+                Some(ResolvedReference {
+                    label: symbol.context_label(),
+                    content,
+                })
+            })
+            .collect()
+    }
+
+    fn has_definition(&self, name: &str) -> bool {
+        !self.find_definition(name).is_empty()
+    }
+}
+
+/// Разбирает файл и собирает определения.
+fn parse_symbols(lang: Lang, path: &str, source: &str) -> Result<Vec<Symbol>, SymbolError> {
+    let mut parser = Parser::new();
+    parser
+        .set_language(&lang.grammar())
+        .map_err(|e| SymbolError::Parse(e.to_string()))?;
+    let tree = parser
+        .parse(source, None)
+        .ok_or_else(|| SymbolError::Parse(format!("{}: no tree", path)))?;
+    let mut out = Vec::new();
+    collect(tree.root_node(), source.as_bytes(), lang, path, None, &mut out);
+    Ok(out)
+}
+
+/// Обходит дерево; `container` — имя ближайшего impl/class/trait для методов.
+fn collect(
+    node: Node,
+    src: &[u8],
+    lang: Lang,
+    path: &str,
+    container: Option<&str>,
+    out: &mut Vec<Symbol>,
+) {
+    let found = definition(node, src, lang);
+    let mut child_container = container.map(str::to_string);
+    if let Some((kind, name)) = found {
+        let is_function = kind == SymbolKind::Function;
+        let (kind, owner) = match container {
+            Some(c) if is_function => (SymbolKind::Method, Some(c.to_string())),
+            _ => (kind, None),
+        };
+        match kind {
+            SymbolKind::Impl | SymbolKind::Class | SymbolKind::Trait | SymbolKind::Interface => {
+                child_container = Some(name.clone());
+            }
+            // Вложенные функции — не методы.
+            SymbolKind::Function | SymbolKind::Method => child_container = None,
+            _ => {}
+        }
+        out.push(Symbol {
+            name,
+            kind,
+            path: path.to_string(),
+            start_line: node.start_position().row + 1,
+            end_line: node.end_position().row + 1,
+            container: owner,
+        });
+    }
+    let mut cursor = node.walk();
+    let children: Vec<Node> = node.named_children(&mut cursor).collect();
+    for child in children {
+        collect(child, src, lang, path, child_container.as_deref(), out);
+    }
+}
+
+/// Определение в узле: вид и имя.
+fn definition(node: Node, src: &[u8], lang: Lang) -> Option<(SymbolKind, String)> {
+    let field = |name: &str| {
+        node.child_by_field_name(name)
+            .and_then(|n| n.utf8_text(src).ok())
+            .map(str::to_string)
+    };
+    let kind = match (lang, node.kind()) {
+        (Lang::Rust, "function_item" | "function_signature_item") => SymbolKind::Function,
+        (Lang::Rust, "struct_item" | "union_item") => SymbolKind::Struct,
+        (Lang::Rust, "enum_item") => SymbolKind::Enum,
+        (Lang::Rust, "trait_item") => SymbolKind::Trait,
+        (Lang::Rust, "type_item") => SymbolKind::TypeAlias,
+        (Lang::Rust, "mod_item") => SymbolKind::Module,
+        // impl Trait for Type — символ называется по типу.
+        (Lang::Rust, "impl_item") => return field("type").map(|t| (SymbolKind::Impl, t)),
+        (Lang::Python, "function_definition") => SymbolKind::Function,
+        (Lang::Python, "class_definition") => SymbolKind::Class,
+        (
+            Lang::TypeScript | Lang::Tsx,
+            "function_declaration" | "generator_function_declaration" | "method_definition"
+            | "method_signature" | "abstract_method_signature",
+        ) => SymbolKind::Function,
+        (Lang::TypeScript | Lang::Tsx, "class_declaration" | "abstract_class_declaration") => {
+            SymbolKind::Class
+        }
+        (Lang::TypeScript | Lang::Tsx, "interface_declaration") => SymbolKind::Interface,
+        (Lang::TypeScript | Lang::Tsx, "type_alias_declaration") => SymbolKind::TypeAlias,
+        (Lang::TypeScript | Lang::Tsx, "enum_declaration") => SymbolKind::Enum,
+        (Lang::TypeScript | Lang::Tsx, "internal_module") => SymbolKind::Module,
+        // const handler = () => {...}
+        (Lang::TypeScript | Lang::Tsx, "variable_declarator") => {
+            let value = node.child_by_field_name("value")?;
+            if !matches!(value.kind(), "arrow_function" | "function_expression" | "function") {
+                return None;
+            }
+            SymbolKind::Function
+        }
+        _ => return None,
+    };
+    field("name").map(|name| (kind, name))
+}
+
+fn rel_key(rel: &Path) -> String {
+    rel.to_string_lossy().replace('\\', "/")
+}
+
+/// (ключ, mtime, размер) исходника поддерживаемого языка.
+fn stat_source(root: &Path, rel: &Path) -> Option<(String, u64, u64)> {
+    Lang::from_path(rel)?;
+    let meta = std::fs::metadata(root.join(rel)).ok()?;
+    if !meta.is_file() || meta.len() > MAX_FILE_BYTES {
+        return None;
+    }
+    let modified = meta
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    Some((rel_key(rel), modified, meta.len()))
+}
+
+fn load(dir: &Path) -> Result<SymbolFile, SymbolError> {
+    let path = dir.join(SYMBOLS_FILE);
+    if !path.exists() {
+        return Ok(SymbolFile::default());
+    }
+    serde_json::from_str(&std::fs::read_to_string(&path)?)
+        .map_err(|e| SymbolError::Corrupt(e.to_string()))
+}
+
+/// Запись через временный файл: обрыв посреди записи не портит сохранённый индекс.
+fn save(dir: &Path, state: &SymbolFile) -> Result<(), SymbolError> {
+    std::fs::create_dir_all(dir)?;
+    let json = serde_json::to_string(state).map_err(|e| SymbolError::Corrupt(e.to_string()))?;
+    let tmp = dir.join(format!("{}.tmp", SYMBOLS_FILE));
+    std::fs::write(&tmp, json)?;
+    std::fs::rename(&tmp, dir.join(SYMBOLS_FILE))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TempProject(PathBuf);
+
+    impl TempProject {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!(
+                "kengaide-symbols-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&root);
+            std::fs::create_dir_all(&root).unwrap();
+            Self(root)
+        }
+
+        fn write(&self, rel: &str, text: &str) {
+            let path = self.0.join(rel);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        }
+    }
+
+    impl Drop for TempProject {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    const RUST_SOURCE: &str = "\
+pub struct Parser {
+    pos: usize,
+}
+
+impl Parser {
+    pub fn parse(&self) -> usize {
+        fn helper() -> usize {
+            1
+        }
+        helper()
+    }
+}
+
+pub trait Visit {
+    fn visit(&self);
+}
+
+pub enum Token {
+    Word,
+}
+
+pub fn run() {}
+";
+
+    const TS_SOURCE: &str = "\
+export class Store {
+  load(id: string): void {}
+}
+
+export interface Options {
+  verbose: boolean;
+}
+
+export type Id = string;
+
+export const handler = (x: number) => x + 1;
+
+const limit = 10;
+";
+
+    fn find<'a>(symbols: &'a [Symbol], name: &str) -> &'a Symbol {
+        symbols
+            .iter()
+            .find(|s| s.name == name)
+            .unwrap_or_else(|| panic!("no symbol {}", name))
+    }
+
+    #[test]
+    fn extracts_rust_definitions() {
+        let symbols = parse_symbols(Lang::Rust, "src/lib.rs", RUST_SOURCE).unwrap();
+
+        let parser = find(&symbols, "Parser");
+        assert_eq!(parser.kind, SymbolKind::Struct);
+        assert_eq!((parser.start_line, parser.end_line), (1, 3));
+
+        let parse = find(&symbols, "parse");
+        assert_eq!(parse.kind, SymbolKind::Method);
+        assert_eq!(parse.container.as_deref(), Some("Parser"));
+        assert_eq!((parse.start_line, parse.end_line), (6, 11));
+
+        // Вложенная функция не становится методом.
+        let helper = find(&symbols, "helper");
+        assert_eq!(helper.kind, SymbolKind::Function);
+        assert_eq!(helper.container, None);
+
+        let visit = find(&symbols, "visit");
+        assert_eq!(visit.kind, SymbolKind::Method);
+        assert_eq!(visit.container.as_deref(), Some("Visit"));
+        assert_eq!(find(&symbols, "Visit").kind, SymbolKind::Trait);
+        assert_eq!(find(&symbols, "Token").kind, SymbolKind::Enum);
+        assert_eq!(find(&symbols, "run").kind, SymbolKind::Function);
+        assert!(symbols
+            .iter()
+            .any(|s| s.kind == SymbolKind::Impl && s.name == "Parser"));
+    }
+
+    #[test]
+    fn extracts_typescript_definitions() {
+        let symbols = parse_symbols(Lang::TypeScript, "src/store.ts", TS_SOURCE).unwrap();
+
+        assert_eq!(find(&symbols, "Store").kind, SymbolKind::Class);
+        let load = find(&symbols, "load");
+        assert_eq!(load.kind, SymbolKind::Method);
+        assert_eq!(load.container.as_deref(), Some("Store"));
+        assert_eq!(find(&symbols, "Options").kind, SymbolKind::Interface);
+        assert_eq!(find(&symbols, "Id").kind, SymbolKind::TypeAlias);
+        let handler = find(&symbols, "handler");
+        assert_eq!(handler.kind, SymbolKind::Function);
+        assert_eq!(handler.start_line, 11);
+        // Обычная константа — не определение.
+        assert!(symbols.iter().all(|s| s.name != "limit"));
+    }
+
+    #[test]
+    fn updates_incrementally() {
+        let project = TempProject::new("update");
+        project.write("src/lib.rs", RUST_SOURCE);
+        project.write("src/store.ts", TS_SOURCE);
+        project.write("README.md", "# not a source\n");
+
+        let index = SymbolIndex::open(&project.0);
+        let stats = index.update().unwrap();
+        assert_eq!((stats.files_parsed, stats.files_removed), (2, 0));
+        assert_eq!(index.find_definition("Parser::parse").len(), 1);
+        assert_eq!(index.find_definition("Store.load").len(), 1);
+        assert!(index.find_definition("Other::parse").is_empty());
+
+        // Без изменений ничего не разбирается заново.
+        let stats = index.update().unwrap();
+        assert_eq!((stats.files_parsed, stats.files_removed), (0, 0));
+
+        // Размер меняется — правка видна даже в пределах той же секунды mtime.
+        project.write("src/lib.rs", "pub fn renamed() {}\n");
+        std::fs::remove_file(project.0.join("src/store.ts")).unwrap();
+        let stats = index.update().unwrap();
+        assert_eq!((stats.files_parsed, stats.files_removed), (1, 1));
+        assert!(index.find_definition("Parser").is_empty());
+        assert!(index.find_definition("Store").is_empty());
+        assert_eq!(index.find_definition("renamed").len(), 1);
+        assert_eq!(stats.total_symbols, 1);
+
+        let dir = project.0.join(SYMBOLS_DIR);
+        assert!(dir.join(SYMBOLS_FILE).exists());
+        assert!(!dir.join(format!("{}.tmp", SYMBOLS_FILE)).exists());
+
+        // Сохранённый индекс переживает переоткрытие.
+        let reopened = SymbolIndex::open(&project.0);
+        assert_eq!(reopened.find_definition("renamed").len(), 1);
+        assert!(reopened.find_definition("Store").is_empty());
+    }
+
+    #[test]
+    fn updates_listed_files() {
+        let project = TempProject::new("files");
+        project.write("src/lib.rs", RUST_SOURCE);
+        let index = SymbolIndex::open(&project.0);
+        index.update().unwrap();
+
+        project.write("src/extra.rs", "pub struct Extra;\n");
+        let stats = index.update_files(&[PathBuf::from("src/extra.rs")]).unwrap();
+        assert_eq!((stats.files_parsed, stats.files_removed), (1, 0));
+        assert_eq!(index.file_symbols(Path::new("src/extra.rs")).len(), 1);
+
+        std::fs::remove_file(project.0.join("src/lib.rs")).unwrap();
+        let stats = index.update_files(&[PathBuf::from("src/lib.rs")]).unwrap();
+        assert_eq!((stats.files_parsed, stats.files_removed), (0, 1));
+        assert!(index.file_symbols(Path::new("src/lib.rs")).is_empty());
+        assert_eq!(index.find_definition("Extra").len(), 1);
+    }
+}
@@ -8,6 +8,7 @@ use crate::error::GigaChatError;
 use crate::models::GigaChatModel;
 
 const CHAT_URL: &str = "https://gigachat.devices.sberbank.ru/api/v1/chat/completions";
+const EMBEDDINGS_URL: &str = "https://gigachat.devices.sberbank.ru/api/v1/embeddings";
 
 #[derive(Debug, Serialize)]
 struct ChatRequest {
@@ -42,6 +43,23 @@ struct Usage {
     total_tokens: Option<u32>,
 }
 
+#[derive(Debug, Serialize)]
+struct EmbeddingsRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
 pub struct GigaChatClient {
     auth: AuthManager,
     http_client: reqwest::Client,
@@ -133,6 +151,40 @@ impl GigaChatClient {
         Err(GigaChatError::Api("Max retries exceeded".to_string()))
     }
 
+    /// Эмбеддинги пачки текстов (в порядке входа).
+    pub async fn embeddings(
+        &self,
+        model: &str,
+        inputs: &[String],
+    ) -> Result<Vec<Vec<f32>>, GigaChatError> {
+        let token = self.auth.get_token().await?;
+        let response = self
+            .http_client
+            .post(EMBEDDINGS_URL)
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&EmbeddingsRequest {
+                model,
+                input: inputs,
+            })
+            .send()
+            .await
+            .map_err(|e| GigaChatError::Http(e.to_string()))?;
+
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| GigaChatError::Http(e.to_string()))?;
+        if !status.is_success() {
+            return Err(GigaChatError::Api(format!("status {}: {}", status, body)));
+        }
+
+        let mut parsed: EmbeddingsResponse =
+            serde_json::from_str(&body).map_err(|e| GigaChatError::Api(e.to_string()))?;
+        parsed.data.sort_by_key(|d| d.index);
+        Ok(parsed.data.into_iter().map(|d| d.embedding).collect())
+    }
+
     pub async fn healthcheck(&self) -> Result<bool, GigaChatError> {
         let token = self.auth.get_token().await?;
         Ok(!token.is_empty())
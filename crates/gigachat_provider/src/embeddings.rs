@@ -0,0 +1,58 @@
+//! GigaChat Embeddings — impl EmbeddingProvider (модель "Embeddings", 1024 измерения).
+
+use std::sync::Arc;
+
+use ai_providers::{EmbeddingProvider, ProviderError, ProviderType};
+use async_trait::async_trait;
+
+use crate::client::GigaChatClient;
+
+const EMBEDDINGS_MODEL: &str = "Embeddings";
+const EMBEDDINGS_DIMENSIONS: usize = 1024;
+const MAX_BATCH: usize = 32;
+
+pub struct GigaChatEmbeddingProvider {
+    client: Arc<GigaChatClient>,
+}
+
+impl GigaChatEmbeddingProvider {
+    pub(crate) fn new(client: Arc<GigaChatClient>) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for GigaChatEmbeddingProvider {
+    fn id(&self) -> &str {
+        "cloud-gigachat-embed"
+    }
+
+    fn provider_type(&self) -> ProviderType {
+        ProviderType::Cloud
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        Some(EMBEDDINGS_DIMENSIONS)
+    }
+
+    fn max_batch_size(&self) -> usize {
+        MAX_BATCH
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        if inputs.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.client
+            .embeddings(EMBEDDINGS_MODEL, inputs)
+            .await
+            .map_err(|e| ProviderError::Generation(e.to_string()))
+    }
+
+    async fn is_available(&self) -> Result<bool, ProviderError> {
+        self.client
+            .healthcheck()
+            .await
+            .map_err(|e| ProviderError::Unavailable(e.to_string()))
+    }
+}
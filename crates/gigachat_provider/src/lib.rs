@@ -2,9 +2,11 @@
 
 mod auth;
 mod client;
+mod embeddings;
 mod error;
 mod models;
 mod provider;
 
+pub use embeddings::GigaChatEmbeddingProvider;
 pub use error::GigaChatError;
 pub use provider::GigaChatProvider;
@@ -10,6 +10,7 @@ use async_trait::async_trait;
 
 use crate::auth::AuthManager;
 use crate::client::GigaChatClient;
+use crate::embeddings::GigaChatEmbeddingProvider;
 use crate::error::GigaChatError;
 use crate::models::GigaChatModel;
 
@@ -36,6 +37,11 @@ impl GigaChatProvider {
             model_name: GigaChatModel::GigaChatUltra.as_str().to_string(),
         })
     }
+
+    /// Провайдер эмбеддингов на том же клиенте (общий OAuth-токен).
+    pub fn embedding_provider(&self) -> GigaChatEmbeddingProvider {
+        GigaChatEmbeddingProvider::new(Arc::clone(&self.client))
+    }
 }
 
 #[async_trait]
@@ -2,6 +2,8 @@
 
 use std::path::PathBuf;
 
+use crate::error::LocalProviderError;
+
 /// Вариант модели: 10B для десктопа (~10 ГБ) или 702B для high-end (~170+ ГБ).
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum ModelVariant {
@@ -26,6 +28,14 @@ pub struct HuggingFaceModelConfig {
 }
 
 impl ModelVariant {
+    /// Все варианты моделей.
+    pub const ALL: [ModelVariant; 4] = [
+        ModelVariant::GigaChat,
+        ModelVariant::DeepSeekCoder,
+        ModelVariant::SmolLM2,
+        ModelVariant::Full,
+    ];
+
     pub fn hf_config(&self) -> HuggingFaceModelConfig {
         match self {
             ModelVariant::GigaChat => HuggingFaceModelConfig {
@@ -73,6 +83,11 @@ impl ModelVariant {
         }
     }
 
+    /// Вариант по model_id (обратное к `model_id`).
+    pub fn from_model_id(model_id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|v| v.model_id() == model_id)
+    }
+
     pub fn provider_id(&self) -> &'static str {
         match self {
             ModelVariant::GigaChat => "local-gigachat",
@@ -85,6 +100,51 @@ impl ModelVariant {
 
 pub const DEFAULT_CONTEXT_SIZE: usize = 8192;
 pub const DEFAULT_MAX_TOKENS: usize = 2048;
+/// Размер batch для decode (префилл режется на чанки этого размера).
+pub const DEFAULT_BATCH_SIZE: usize = 512;
+/// Минимальный разумный контекст; меньше — ошибка конфигурации.
+pub const MIN_CONTEXT_SIZE: usize = 512;
+/// Сколько токенов черновая модель предлагает за шаг speculative decoding.
+pub const DEFAULT_DRAFT_TOKENS: usize = 5;
+
+/// Черновая модель для speculative decoding: предлагает токены, целевая модель проверяет их одним batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DraftConfig {
+    pub variant: ModelVariant,
+    /// Токенов черновика за шаг.
+    pub n_draft: usize,
+    /// Параметры загрузки черновой модели — её собственные, как у её провайдера,
+    /// чтобы движок в общем пуле не зависел от того, кто загрузил его первым.
+    pub context_size: usize,
+    pub n_threads: Option<usize>,
+    pub n_batch: usize,
+    pub use_mmap: bool,
+    pub use_mlock: bool,
+    pub max_parallel: usize,
+}
+
+impl DraftConfig {
+    /// Черновик с параметрами загрузки из конфигурации самой черновой модели.
+    pub fn from_config(draft: &LocalConfig, n_draft: usize) -> Self {
+        Self {
+            variant: draft.model_variant,
+            n_draft,
+            context_size: draft.context_size,
+            n_threads: draft.n_threads,
+            n_batch: draft.n_batch,
+            use_mmap: draft.use_mmap,
+            use_mlock: draft.use_mlock,
+            max_parallel: draft.max_parallel,
+        }
+    }
+
+    /// Черновик с параметрами загрузки по умолчанию.
+    pub fn new(variant: ModelVariant, n_draft: usize) -> Self {
+        let mut config = LocalConfig::default_config();
+        config.model_variant = variant;
+        Self::from_config(&config, n_draft)
+    }
+}
 
 /// Базовый URL для скачивания с HuggingFace.
 pub fn hf_resolve_url(repo_id: &str, path: &str) -> String {
@@ -105,9 +165,23 @@ pub fn hf_api_tree_url(repo_id: &str, path: &str) -> String {
 #[allow(clippy::module_name_repetitions)]
 pub struct LocalConfig {
     pub models_dir: PathBuf,
+    /// Окно контекста модели в токенах (промпт + ответ).
     pub context_size: usize,
+    /// Максимум токенов ответа.
     pub max_tokens: usize,
+    /// Потоки CPU; None — все логические ядра.
     pub n_threads: Option<usize>,
+    /// Размер batch для decode.
+    pub n_batch: usize,
+    /// Загружать веса через mmap (быстрый старт, страницы подгружаются по требованию).
+    pub use_mmap: bool,
+    /// Закрепить веса в RAM (mlock), чтобы ОС не выгружала их в swap.
+    pub use_mlock: bool,
+    /// Сколько запросов к модели выполняется одновременно; остальные ждут в очереди.
+    /// Потоки CPU делятся поровну между параллельными запросами.
+    pub max_parallel: usize,
+    /// Черновая модель для speculative decoding; None — обычная генерация.
+    pub draft: Option<DraftConfig>,
     pub model_variant: ModelVariant,
 }
 
@@ -142,7 +216,69 @@ impl LocalConfig {
             context_size: DEFAULT_CONTEXT_SIZE,
             max_tokens: DEFAULT_MAX_TOKENS,
             n_threads: None,
+            n_batch: DEFAULT_BATCH_SIZE,
+            use_mmap: true,
+            use_mlock: false,
+            max_parallel: 1,
+            draft: None,
             model_variant: ModelVariant::GigaChat,
         }
     }
+
+    /// Конфигурация загрузки черновой модели; None — черновик не настроен.
+    pub fn draft_config(&self) -> Option<LocalConfig> {
+        let draft = self.draft?;
+        Some(LocalConfig {
+            models_dir: self.models_dir.clone(),
+            context_size: draft.context_size,
+            max_tokens: DEFAULT_MAX_TOKENS.min(draft.context_size.saturating_sub(1)),
+            n_threads: draft.n_threads,
+            n_batch: draft.n_batch,
+            use_mmap: draft.use_mmap,
+            use_mlock: draft.use_mlock,
+            max_parallel: draft.max_parallel,
+            draft: None,
+            model_variant: draft.variant,
+        })
+    }
+
+    /// Проверяет согласованность параметров inference.
+    pub fn validate(&self) -> Result<(), LocalProviderError> {
+        if self.context_size < MIN_CONTEXT_SIZE {
+            return Err(LocalProviderError::InvalidConfig(format!(
+                "context_size {} < {}",
+                self.context_size, MIN_CONTEXT_SIZE
+            )));
+        }
+        if self.max_tokens == 0 || self.max_tokens >= self.context_size {
+            return Err(LocalProviderError::InvalidConfig(format!(
+                "max_tokens must be in 1..{}",
+                self.context_size
+            )));
+        }
+        if self.n_batch == 0 {
+            return Err(LocalProviderError::InvalidConfig("n_batch must be > 0".into()));
+        }
+        if self.max_parallel == 0 {
+            return Err(LocalProviderError::InvalidConfig("max_parallel must be > 0".into()));
+        }
+        if let Some(draft) = self.draft {
+            if draft.variant == self.model_variant {
+                return Err(LocalProviderError::InvalidConfig(
+                    "draft model must differ from target model".into(),
+                ));
+            }
+            // Проверка черновика идёт одним batch: последний принятый токен + n_draft.
+            if draft.n_draft == 0 || draft.n_draft >= self.n_batch {
+                return Err(LocalProviderError::InvalidConfig(format!(
+                    "n_draft must be in 1..{}",
+                    self.n_batch
+                )));
+            }
+        }
+        if self.n_threads == Some(0) {
+            return Err(LocalProviderError::InvalidConfig("n_threads must be > 0".into()));
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,171 @@
+//! LocalEmbeddingProvider — эмбеддинги через llama.cpp (GGUF embedding-модель, например
+//! nomic-embed-text или bge). Модель грузится при первом запросе.
+
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use ai_providers::{EmbeddingProvider, ProviderError, ProviderType};
+use async_trait::async_trait;
+use llama_cpp_2::context::params::LlamaContextParams;
+use llama_cpp_2::llama_backend::LlamaBackend;
+use llama_cpp_2::llama_batch::LlamaBatch;
+use llama_cpp_2::model::params::LlamaModelParams;
+use llama_cpp_2::model::{AddBos, LlamaModel};
+use tokio::sync::OnceCell;
+
+use crate::error::LocalProviderError;
+use crate::hardware_detect::cpu_cores;
+use crate::inference::shared_backend;
+
+/// Окно embedding-моделей (BERT-подобные — 512 токенов); длинный текст обрезается.
+const DEFAULT_EMBEDDING_CONTEXT: u32 = 512;
+const MAX_BATCH: usize = 64;
+
+struct EmbeddingEngine {
+    backend: Arc<LlamaBackend>,
+    model: LlamaModel,
+    n_ctx: u32,
+    n_threads: i32,
+}
+
+impl EmbeddingEngine {
+    fn load(path: &std::path::Path, n_ctx: u32) -> Result<Self, LocalProviderError> {
+        let backend = shared_backend()?;
+        let model = LlamaModel::load_from_file(&backend, path, &LlamaModelParams::default())
+            .map_err(|e| LocalProviderError::ModelLoadFailed(e.to_string()))?;
+        Ok(Self {
+            backend,
+            model,
+            n_ctx,
+            n_threads: cpu_cores().unwrap_or(4) as i32,
+        })
+    }
+
+    fn dimensions(&self) -> usize {
+        self.model.n_embd() as usize
+    }
+
+    /// Эмбеддинг каждого текста (пулинг — по настройке модели), нормированный по L2.
+    fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, LocalProviderError> {
+        let n_ctx = NonZeroU32::new(self.n_ctx)
+            .ok_or_else(|| LocalProviderError::InferenceFailed("Invalid context size".into()))?;
+        // Не-каузальные модели обрабатывают вход одним ubatch.
+        let ctx_params = LlamaContextParams::default()
+            .with_n_ctx(Some(n_ctx))
+            .with_n_batch(self.n_ctx)
+            .with_n_ubatch(self.n_ctx)
+            .with_n_threads(self.n_threads)
+            .with_n_threads_batch(self.n_threads)
+            .with_embeddings(true);
+        let mut ctx = self
+            .model
+            .new_context(&self.backend, ctx_params)
+            .map_err(|e| LocalProviderError::InferenceFailed(e.to_string()))?;
+        let mut batch = LlamaBatch::new(self.n_ctx as usize, 1);
+
+        let mut out = Vec::with_capacity(inputs.len());
+        for text in inputs {
+            let mut tokens = self
+                .model
+                .str_to_token(text, AddBos::Always)
+                .map_err(|e| LocalProviderError::InferenceFailed(e.to_string()))?;
+            tokens.truncate(self.n_ctx as usize);
+
+            ctx.clear_kv_cache();
+            batch.clear();
+            batch
+                .add_sequence(&tokens, 0, false)
+                .map_err(|e| LocalProviderError::InferenceFailed(e.to_string()))?;
+            ctx.decode(&mut batch)
+                .map_err(|e| LocalProviderError::InferenceFailed(e.to_string()))?;
+            let embedding = ctx
+                .embeddings_seq_ith(0)
+                .map_err(|e| LocalProviderError::InferenceFailed(e.to_string()))?;
+            out.push(normalize(embedding));
+        }
+        Ok(out)
+    }
+}
+
+fn normalize(v: &[f32]) -> Vec<f32> {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        v.iter().map(|x| x / norm).collect()
+    } else {
+        v.to_vec()
+    }
+}
+
+pub struct LocalEmbeddingProvider {
+    model_path: PathBuf,
+    n_ctx: u32,
+    engine: OnceCell<Arc<EmbeddingEngine>>,
+}
+
+impl LocalEmbeddingProvider {
+    /// Провайдер на GGUF embedding-модели по пути `model_path`.
+    pub fn new(model_path: PathBuf) -> Self {
+        Self {
+            model_path,
+            n_ctx: DEFAULT_EMBEDDING_CONTEXT,
+            engine: OnceCell::new(),
+        }
+    }
+
+    /// Окно контекста модели в токенах (по умолчанию 512).
+    pub fn with_context_size(mut self, n_ctx: u32) -> Self {
+        self.n_ctx = n_ctx;
+        self
+    }
+
+    async fn engine(&self) -> Result<Arc<EmbeddingEngine>, ProviderError> {
+        self.engine
+            .get_or_try_init(|| async {
+                let path = self.model_path.clone();
+                let n_ctx = self.n_ctx;
+                tokio::task::spawn_blocking(move || EmbeddingEngine::load(&path, n_ctx))
+                    .await
+                    .map_err(|e| ProviderError::Unavailable(e.to_string()))?
+                    .map(Arc::new)
+                    .map_err(|e| ProviderError::Unavailable(e.to_string()))
+            })
+            .await
+            .cloned()
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for LocalEmbeddingProvider {
+    fn id(&self) -> &str {
+        "local-embed"
+    }
+
+    fn provider_type(&self) -> ProviderType {
+        ProviderType::Local
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.engine.get().map(|e| e.dimensions())
+    }
+
+    fn max_batch_size(&self) -> usize {
+        MAX_BATCH
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        if inputs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let engine = self.engine().await?;
+        let inputs = inputs.to_vec();
+        tokio::task::spawn_blocking(move || engine.embed(&inputs))
+            .await
+            .map_err(|e| ProviderError::Generation(e.to_string()))?
+            .map_err(|e| ProviderError::Generation(e.to_string()))
+    }
+
+    async fn is_available(&self) -> Result<bool, ProviderError> {
+        Ok(self.model_path.exists())
+    }
+}
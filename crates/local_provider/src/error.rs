@@ -19,6 +19,24 @@ pub enum LocalProviderError {
     #[error("model load failed: {0}")]
     ModelLoadFailed(String),
 
+    #[error("insufficient memory: need {required} bytes, available {available} bytes")]
+    InsufficientMemory { required: u64, available: u64 },
+
+    #[error("insufficient disk space: need {required} bytes, available {available} bytes")]
+    InsufficientDisk { required: u64, available: u64 },
+
+    #[error("prompt too long: {prompt_tokens} tokens, context window {context_size}")]
+    PromptTooLong {
+        prompt_tokens: usize,
+        context_size: usize,
+    },
+
+    #[error("invalid config: {0}")]
+    InvalidConfig(String),
+
+    #[error("model is busy: {0}")]
+    ModelBusy(String),
+
     #[error("hardware detection failed: {0}")]
     HardwareDetectionFailed(String),
 
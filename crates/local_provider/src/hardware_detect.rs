@@ -1,8 +1,14 @@
-//! Определение CPU, RAM, GPU.
+//! Определение CPU, RAM, GPU, свободного диска; preflight и рекомендация моделей.
 
+use std::path::Path;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
+use serde::Serialize;
+
+use crate::config::{LocalConfig, ModelVariant};
 use crate::error::LocalProviderError;
+use crate::model_manager::ModelManager;
+use crate::pool::LOAD_OVERHEAD_BYTES;
 
 static DETECTED_THREADS: AtomicUsize = AtomicUsize::new(0);
 
@@ -29,3 +35,215 @@ pub fn ram_bytes() -> Result<u64, LocalProviderError> {
 pub fn ram_gb() -> Result<f64, LocalProviderError> {
     Ok(ram_bytes()? as f64 / (1024.0 * 1024.0 * 1024.0))
 }
+
+/// Всего RAM в байтах.
+pub fn total_ram_bytes() -> u64 {
+    let mut sys = sysinfo::System::new();
+    sys.refresh_memory();
+    sys.total_memory()
+}
+
+/// Свободное место на диске, где лежит `path` (или ближайший существующий родитель).
+/// None — диск не найден.
+pub fn free_disk_bytes(path: &Path) -> Option<u64> {
+    let existing = path.ancestors().find(|p| p.exists())?;
+    let existing = existing.canonicalize().unwrap_or_else(|_| existing.to_path_buf());
+    let disks = sysinfo::Disks::new_with_refreshed_list();
+    disks
+        .list()
+        .iter()
+        .filter(|d| existing.starts_with(d.mount_point()))
+        .max_by_key(|d| d.mount_point().as_os_str().len())
+        .map(|d| d.available_space())
+}
+
+/// Векторные расширения CPU, от которых зависит скорость llama.cpp.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct CpuFeatures {
+    pub avx2: bool,
+    pub avx512f: bool,
+    pub fma: bool,
+    pub neon: bool,
+}
+
+pub fn cpu_features() -> CpuFeatures {
+    #[cfg(target_arch = "x86_64")]
+    {
+        CpuFeatures {
+            avx2: std::arch::is_x86_feature_detected!("avx2"),
+            avx512f: std::arch::is_x86_feature_detected!("avx512f"),
+            fma: std::arch::is_x86_feature_detected!("fma"),
+            neon: false,
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        CpuFeatures {
+            neon: std::arch::is_aarch64_feature_detected!("neon"),
+            ..CpuFeatures::default()
+        }
+    }
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        CpuFeatures::default()
+    }
+}
+
+/// Насколько модель подходит машине.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Suitability {
+    /// Лучший вариант для этой машины.
+    Recommended,
+    /// Запустится, но с оговорками (мало свободной RAM, медленный CPU).
+    Possible,
+    /// Не запустится: не хватает RAM или места на диске.
+    Unsupported,
+}
+
+/// Оценка одного варианта модели.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelRecommendation {
+    pub model_id: String,
+    pub display_name: String,
+    pub size_bytes: u64,
+    pub installed: bool,
+    pub suitability: Suitability,
+    pub reasons: Vec<String>,
+}
+
+/// Результат preflight: железо + ранжированные модели (лучшая первой).
+#[derive(Debug, Clone, Serialize)]
+pub struct HardwareReport {
+    pub total_ram_bytes: u64,
+    pub available_ram_bytes: u64,
+    /// Свободно на диске с директорией моделей; None — не удалось определить.
+    pub free_disk_bytes: Option<u64>,
+    pub cpu_cores: usize,
+    pub cpu_features: CpuFeatures,
+    pub recommendations: Vec<ModelRecommendation>,
+}
+
+/// Снимок железа, по которому ранжируются модели.
+#[derive(Debug, Clone, Copy)]
+pub struct HardwareSnapshot {
+    pub total_ram_bytes: u64,
+    pub available_ram_bytes: u64,
+    pub free_disk_bytes: Option<u64>,
+    pub cpu_features: CpuFeatures,
+}
+
+/// Preflight для директории моделей: железо и рекомендация вариантов.
+pub fn preflight(models_dir: &Path) -> Result<HardwareReport, LocalProviderError> {
+    let snapshot = HardwareSnapshot {
+        total_ram_bytes: total_ram_bytes(),
+        available_ram_bytes: ram_bytes()?,
+        free_disk_bytes: free_disk_bytes(models_dir),
+        cpu_features: cpu_features(),
+    };
+    let mut config = LocalConfig::default_config();
+    config.models_dir = models_dir.to_path_buf();
+    let variants = ALL_VARIANTS.map(|variant| {
+        config.model_variant = variant;
+        let installed = ModelManager::new(config.clone()).find_gguf_path().is_some();
+        (variant, installed)
+    });
+    Ok(HardwareReport {
+        total_ram_bytes: snapshot.total_ram_bytes,
+        available_ram_bytes: snapshot.available_ram_bytes,
+        free_disk_bytes: snapshot.free_disk_bytes,
+        cpu_cores: cpu_cores()?,
+        cpu_features: snapshot.cpu_features,
+        recommendations: recommend(&snapshot, &variants),
+    })
+}
+
+const ALL_VARIANTS: [ModelVariant; 4] = [
+    ModelVariant::Full,
+    ModelVariant::GigaChat,
+    ModelVariant::DeepSeekCoder,
+    ModelVariant::SmolLM2,
+];
+
+const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+/// Ранжирует варианты `(variant, installed)`: сначала подходящие, среди них — более крупные
+/// (качество выше). Лучший запускаемый получает `Recommended`.
+pub fn recommend(
+    hw: &HardwareSnapshot,
+    variants: &[(ModelVariant, bool)],
+) -> Vec<ModelRecommendation> {
+    let mut out: Vec<ModelRecommendation> = variants
+        .iter()
+        .map(|&(variant, installed)| {
+            let size_bytes = (variant.hf_config().size_gb * GB) as u64;
+            let required_ram = size_bytes + LOAD_OVERHEAD_BYTES;
+            let mut reasons = Vec::new();
+            let mut suitability = Suitability::Possible;
+
+            if hw.total_ram_bytes < required_ram {
+                suitability = Suitability::Unsupported;
+                reasons.push(format!(
+                    "нужно {:.1} ГБ RAM, установлено {:.1} ГБ",
+                    required_ram as f64 / GB,
+                    hw.total_ram_bytes as f64 / GB
+                ));
+            } else if hw.available_ram_bytes < required_ram {
+                reasons.push(format!(
+                    "свободно {:.1} ГБ RAM из нужных {:.1} ГБ — придётся закрыть другие программы",
+                    hw.available_ram_bytes as f64 / GB,
+                    required_ram as f64 / GB
+                ));
+            }
+
+            if installed {
+                reasons.push("уже скачана".to_string());
+            } else if let Some(free) = hw.free_disk_bytes {
+                if free < size_bytes {
+                    suitability = Suitability::Unsupported;
+                    reasons.push(format!(
+                        "нужно {:.1} ГБ на диске, свободно {:.1} ГБ",
+                        size_bytes as f64 / GB,
+                        free as f64 / GB
+                    ));
+                }
+            }
+
+            let fast_cpu = hw.cpu_features.avx2 || hw.cpu_features.neon;
+            if !fast_cpu && size_bytes as f64 > 2.0 * GB {
+                reasons.push("CPU без AVX2 — генерация будет медленной".to_string());
+            } else if hw.cpu_features.avx512f {
+                reasons.push("AVX-512 ускоряет генерацию".to_string());
+            }
+
+            ModelRecommendation {
+                model_id: variant.model_id().to_string(),
+                display_name: variant.display_name().to_string(),
+                size_bytes,
+                installed,
+                suitability,
+                reasons,
+            }
+        })
+        .collect();
+
+    out.sort_by(|a, b| {
+        a.suitability
+            .cmp(&b.suitability)
+            .then(b.size_bytes.cmp(&a.size_bytes))
+    });
+    // Лучший — крупнейший запускаемый без оговорок по RAM; если таких нет — первый запускаемый.
+    let best = out
+        .iter()
+        .position(|r| {
+            r.suitability != Suitability::Unsupported
+                && hw.available_ram_bytes >= r.size_bytes + LOAD_OVERHEAD_BYTES
+        })
+        .or_else(|| out.iter().position(|r| r.suitability != Suitability::Unsupported));
+    if let Some(i) = best {
+        out[i].suitability = Suitability::Recommended;
+        let r = out.remove(i);
+        out.insert(0, r);
+    }
+    out
+}
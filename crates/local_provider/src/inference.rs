@@ -2,59 +2,116 @@
 
 use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
 use llama_cpp_2::context::params::LlamaContextParams;
+use llama_cpp_2::context::LlamaContext;
 use llama_cpp_2::llama_backend::LlamaBackend;
 use llama_cpp_2::llama_batch::LlamaBatch;
 use llama_cpp_2::model::params::LlamaModelParams;
 use llama_cpp_2::model::{AddBos, LlamaModel};
 use llama_cpp_2::sampling::LlamaSampler;
+use llama_cpp_2::token::LlamaToken;
 use std::num::NonZeroU32;
 
-use crate::config::DEFAULT_CONTEXT_SIZE;
+use crate::config::LocalConfig;
 use crate::error::LocalProviderError;
 use crate::hardware_detect::cpu_cores;
 
-/// Максимум токенов в одном batch для decode (ограничение llama.cpp; при большем префилле — "Insufficient Space").
-const PREFILL_BATCH_SIZE: i32 = 512;
+/// llama.cpp backend инициализируется один раз на процесс (повторный init — ошибка),
+/// поэтому все движки делят один экземпляр.
+static BACKEND: Mutex<Option<Arc<LlamaBackend>>> = Mutex::new(None);
+
+pub(crate) fn shared_backend() -> Result<Arc<LlamaBackend>, LocalProviderError> {
+    let mut guard = BACKEND
+        .lock()
+        .map_err(|e| LocalProviderError::ModelLoadFailed(e.to_string()))?;
+    if let Some(ref backend) = *guard {
+        return Ok(Arc::clone(backend));
+    }
+    let backend = Arc::new(
+        LlamaBackend::init().map_err(|e| LocalProviderError::ModelLoadFailed(e.to_string()))?,
+    );
+    *guard = Some(Arc::clone(&backend));
+    Ok(backend)
+}
 
 pub struct InferenceEngine {
     backend: Arc<LlamaBackend>,
     model: Arc<LlamaModel>,
+    /// Окно контекста модели (из LocalConfig).
+    context_size: usize,
+    /// Потоки на один запрос (общее число делится между max_parallel запросами).
+    n_threads: usize,
+    /// Максимум токенов в одном batch для decode (при большем префилле — "Insufficient Space").
+    n_batch: usize,
 }
 
 impl InferenceEngine {
-    pub fn load(path: &Path, _n_threads: Option<usize>) -> Result<Self, LocalProviderError> {
-        let backend = LlamaBackend::init()
-            .map_err(|e| LocalProviderError::ModelLoadFailed(e.to_string()))?;
+    pub fn load(path: &Path, config: &LocalConfig) -> Result<Self, LocalProviderError> {
+        config.validate()?;
+        let backend = shared_backend()?;
 
-        let model_params = LlamaModelParams::default();
+        let model_params = LlamaModelParams::default()
+            .with_use_mmap(config.use_mmap)
+            .with_use_mlock(config.use_mlock);
         let model = LlamaModel::load_from_file(&backend, path, &model_params)
             .map_err(|e| LocalProviderError::ModelLoadFailed(e.to_string()))?;
 
+        let total_threads = match config.n_threads {
+            Some(n) => n,
+            None => cpu_cores().unwrap_or(4),
+        };
+        let n_threads = (total_threads / config.max_parallel).max(1);
+
         Ok(Self {
-            backend: Arc::new(backend),
+            backend,
             model: Arc::new(model),
+            context_size: config.context_size,
+            n_threads,
+            n_batch: config.n_batch,
         })
     }
 
-    pub fn generate(
+    pub fn context_size(&self) -> usize {
+        self.context_size
+    }
+
+    /// Токенизирует промпт и проверяет, что он помещается в окно контекста.
+    pub fn tokenize_prompt(&self, prompt: &str) -> Result<Vec<LlamaToken>, LocalProviderError> {
+        let tokens = self
+            .model
+            .str_to_token(prompt, AddBos::Always)
+            .map_err(|e| LocalProviderError::InferenceFailed(e.to_string()))?;
+        if tokens.len() >= self.context_size {
+            return Err(LocalProviderError::PromptTooLong {
+                prompt_tokens: tokens.len(),
+                context_size: self.context_size,
+            });
+        }
+        Ok(tokens)
+    }
+
+    /// Создаёт контекст под промпт + ответ (не больше окна модели) и выполняет префилл.
+    /// Возвращает контекст, batch с последним токеном промпта и реальный лимит ответа.
+    fn prefill(
         &self,
-        prompt: &str,
+        tokens: &[LlamaToken],
         max_tokens: usize,
-    ) -> Result<(String, u32, u64), LocalProviderError> {
-        let n_threads = cpu_cores().unwrap_or(4);
-        let ctx_size = DEFAULT_CONTEXT_SIZE.min(max_tokens + prompt.len() / 4 + 256);
+    ) -> Result<(LlamaContext<'_>, LlamaBatch, usize), LocalProviderError> {
+        let n_tokens = tokens.len();
+        let max_tokens = max_tokens.min(self.context_size.saturating_sub(n_tokens));
+        let ctx_size = self.context_size.min(n_tokens + max_tokens);
 
         let n_ctx = NonZeroU32::new(ctx_size as u32)
-            .or(NonZeroU32::new(2048))
             .ok_or_else(|| LocalProviderError::InferenceFailed("Invalid context size".into()))?;
+        let n_batch = self.n_batch.min(ctx_size);
 
-        let n_threads_i32 = n_threads as i32;
+        let n_threads_i32 = self.n_threads as i32;
         let ctx_params = LlamaContextParams::default()
             .with_n_ctx(Some(n_ctx))
+            .with_n_batch(n_batch as u32)
             .with_n_threads(n_threads_i32)
             .with_n_threads_batch(n_threads_i32);
 
@@ -63,18 +120,12 @@ impl InferenceEngine {
             .new_context(&self.backend, ctx_params)
             .map_err(|e| LocalProviderError::InferenceFailed(e.to_string()))?;
 
-        let tokens_list = self
-            .model
-            .str_to_token(prompt, AddBos::Always)
-            .map_err(|e| LocalProviderError::InferenceFailed(e.to_string()))?;
-
-        let mut batch = LlamaBatch::new(PREFILL_BATCH_SIZE as usize, 1);
-        let n_tokens = tokens_list.len();
+        let mut batch = LlamaBatch::new(n_batch, 1);
         let last_pos = (n_tokens as i32).saturating_sub(1);
 
-        // Prefill по чанкам, чтобы не превышать лимит batch (иначе "Insufficient Space of 512").
+        // Prefill по чанкам, чтобы не превышать лимит batch (иначе "Insufficient Space").
         let mut pos = 0i32;
-        for chunk in tokens_list.chunks(PREFILL_BATCH_SIZE as usize) {
+        for chunk in tokens.chunks(n_batch) {
             batch.clear();
             for (j, &token) in chunk.iter().enumerate() {
                 let p = pos + j as i32;
@@ -88,17 +139,66 @@ impl InferenceEngine {
             pos += chunk.len() as i32;
         }
 
+        Ok((ctx, batch, max_tokens))
+    }
+
+    fn piece(&self, token: LlamaToken) -> String {
+        self.model
+            .token_to_str(token, llama_cpp_2::model::Special::Tokenize)
+            .unwrap_or_else(|_| String::new())
+    }
+
+    /// Токенизация продолжения текста (без BOS).
+    fn tokenize_continuation(&self, text: &str) -> Result<Vec<LlamaToken>, LocalProviderError> {
+        self.model
+            .str_to_token(text, AddBos::Never)
+            .map_err(|e| LocalProviderError::InferenceFailed(e.to_string()))
+    }
+
+    pub fn generate(
+        &self,
+        prompt: &str,
+        max_tokens: usize,
+    ) -> Result<(String, u32, u64), LocalProviderError> {
+        let start = Instant::now();
+        let mut output = String::new();
+        let mut tokens_generated: u32 = 0;
+        let never_cancel = AtomicBool::new(false);
+        let tokens = self.tokenize_prompt(prompt)?;
+        self.generate_stream(&tokens, max_tokens, &never_cancel, |piece| {
+            output.push_str(piece);
+            tokens_generated += 1;
+        })?;
+        let latency_ms = start.elapsed().as_millis() as u64;
+        Ok((output, tokens_generated, latency_ms))
+    }
+
+    /// Стриминговая генерация по токенам промпта (см. `tokenize_prompt`): для каждого токена
+    /// вызывается `on_token`; при `cancel_requested.load(Ordering::Relaxed) == true` цикл прерывается.
+    pub fn generate_stream<F>(
+        &self,
+        tokens: &[LlamaToken],
+        max_tokens: usize,
+        cancel_requested: &AtomicBool,
+        mut on_token: F,
+    ) -> Result<(), LocalProviderError>
+    where
+        F: FnMut(&str),
+    {
+        let (mut ctx, mut batch, max_tokens) = self.prefill(tokens, max_tokens)?;
+
         let mut sampler = LlamaSampler::chain_simple([
             LlamaSampler::dist(1234),
             LlamaSampler::greedy(),
         ]);
 
-        let start = Instant::now();
-        let mut output = String::new();
-        let mut n_cur = n_tokens as i32;
-        let mut tokens_generated: u32 = 0;
+        let mut n_cur = tokens.len() as i32;
 
         for _ in 0..max_tokens {
+            if cancel_requested.load(Ordering::Relaxed) {
+                break;
+            }
+
             let token = sampler.sample(&ctx, batch.n_tokens() - 1);
             sampler.accept(token);
 
@@ -106,11 +206,8 @@ impl InferenceEngine {
                 break;
             }
 
-            let piece = self
-                .model
-                .token_to_str(token, llama_cpp_2::model::Special::Tokenize)
-                .unwrap_or_else(|_| String::new());
-            output.push_str(&piece);
+            let piece = self.piece(token);
+            on_token(&piece);
 
             batch.clear();
             batch
@@ -118,107 +215,187 @@ impl InferenceEngine {
                 .map_err(|e| LocalProviderError::InferenceFailed(e.to_string()))?;
 
             n_cur += 1;
-            tokens_generated += 1;
 
             ctx.decode(&mut batch)
                 .map_err(|e| LocalProviderError::InferenceFailed(e.to_string()))?;
         }
 
-        let latency_ms = start.elapsed().as_millis() as u64;
-        Ok((output, tokens_generated, latency_ms))
+        Ok(())
     }
 
-    /// Стриминговая генерация: для каждого токена вызывается `on_token`;
-    /// при `cancel_requested.load(Ordering::Relaxed) == true` цикл прерывается.
-    pub fn generate_stream<F>(
+    /// Speculative decoding: `draft` предлагает до `n_draft` токенов, целевая модель проверяет их
+    /// одним batch и принимает совпавший с её greedy-выбором префикс плюс свой следующий токен.
+    /// Вывод совпадает с обычной greedy-генерацией целевой модели.
+    ///
+    /// Черновик передаётся текстом и перетокенизируется целевой моделью, поэтому словари
+    /// моделей могут различаться (например, SmolLM2 как черновик для DeepSeek-Coder).
+    pub fn generate_speculative<F>(
         &self,
+        draft: &InferenceEngine,
         prompt: &str,
+        tokens: &[LlamaToken],
         max_tokens: usize,
+        n_draft: usize,
         cancel_requested: &AtomicBool,
         mut on_token: F,
-    ) -> Result<(), LocalProviderError>
+    ) -> Result<SpeculativeStats, LocalProviderError>
     where
         F: FnMut(&str),
     {
-        let n_threads = cpu_cores().unwrap_or(4);
-        let ctx_size = DEFAULT_CONTEXT_SIZE.min(max_tokens + prompt.len() / 4 + 256);
-
-        let n_ctx = NonZeroU32::new(ctx_size as u32)
-            .or(NonZeroU32::new(2048))
-            .ok_or_else(|| LocalProviderError::InferenceFailed("Invalid context size".into()))?;
-
-        let n_threads_i32 = n_threads as i32;
-        let ctx_params = LlamaContextParams::default()
-            .with_n_ctx(Some(n_ctx))
-            .with_n_threads(n_threads_i32)
-            .with_n_threads_batch(n_threads_i32);
+        let mut stats = SpeculativeStats::default();
+
+        // Промпт не влезает в окно черновой модели — обычная генерация.
+        let draft_tokens = match draft.tokenize_prompt(prompt) {
+            Ok(t) => t,
+            Err(_) => {
+                self.generate_stream(tokens, max_tokens, cancel_requested, |piece| {
+                    stats.generated += 1;
+                    on_token(piece);
+                })?;
+                return Ok(stats);
+            }
+        };
+
+        let (mut ctx, mut batch, max_tokens) = self.prefill(tokens, max_tokens)?;
+        let (mut draft_ctx, mut draft_batch, _) =
+            draft.prefill(&draft_tokens, draft.context_size)?;
+        let draft_ctx_size = draft.context_size;
+        let mut sampler = LlamaSampler::greedy();
+
+        // Первый токен ответа — из логитов префилла; в KV целевой модели его ещё нет.
+        let mut next = sampler.sample(&ctx, batch.n_tokens() - 1);
+        let mut target_pos = tokens.len();
+        let mut draft_pos = draft_tokens.len();
+        // Принятый текст, который черновая модель ещё не видела.
+        let mut draft_pending = String::new();
+
+        while stats.generated < max_tokens {
+            if cancel_requested.load(Ordering::Relaxed) || self.model.is_eog_token(next) {
+                break;
+            }
+            let next_piece = self.piece(next);
+            on_token(&next_piece);
+            stats.generated += 1;
+            draft_pending.push_str(&next_piece);
 
-        let mut ctx = self
-            .model
-            .new_context(&self.backend, ctx_params)
-            .map_err(|e| LocalProviderError::InferenceFailed(e.to_string()))?;
+            let budget = (max_tokens - stats.generated).min(n_draft);
+            if budget == 0 {
+                break;
+            }
 
-        let tokens_list = self
-            .model
-            .str_to_token(prompt, AddBos::Always)
-            .map_err(|e| LocalProviderError::InferenceFailed(e.to_string()))?;
+            // 1. Черновик: догоняем принятый текст и предлагаем продолжение.
+            let pending = draft.tokenize_continuation(&draft_pending)?;
+            draft_pending.clear();
+            let mut proposal = String::new();
+            if !pending.is_empty() && draft_pos + pending.len() + budget < draft_ctx_size {
+                decode_at(&mut draft_ctx, &mut draft_batch, &pending, draft_pos, draft.n_batch)?;
+                draft_pos += pending.len();
+                for j in 0..budget {
+                    let token = sampler.sample(&draft_ctx, draft_batch.n_tokens() - 1);
+                    if draft.model.is_eog_token(token) {
+                        break;
+                    }
+                    proposal.push_str(&draft.piece(token));
+                    decode_at(&mut draft_ctx, &mut draft_batch, &[token], draft_pos + j, 1)?;
+                }
+                // Спекулятивный хвост черновика отбрасываем: принятое вернётся через draft_pending.
+                clear_kv_from(&mut draft_ctx, draft_pos)?;
+            }
 
-        let mut batch = LlamaBatch::new(PREFILL_BATCH_SIZE as usize, 1);
-        let n_tokens = tokens_list.len();
-        let last_pos = (n_tokens as i32).saturating_sub(1);
+            let mut candidates = if proposal.is_empty() {
+                Vec::new()
+            } else {
+                self.tokenize_continuation(&proposal)?
+            };
+            candidates.truncate(budget);
 
-        // Prefill по чанкам (см. generate()).
-        let mut pos = 0i32;
-        for chunk in tokens_list.chunks(PREFILL_BATCH_SIZE as usize) {
+            // 2. Проверка: next + кандидаты одним batch, логиты на каждой позиции.
             batch.clear();
-            for (j, &token) in chunk.iter().enumerate() {
-                let p = pos + j as i32;
-                let is_last = p == last_pos;
+            for (i, &token) in std::iter::once(&next).chain(candidates.iter()).enumerate() {
                 batch
-                    .add(token, p, &[0], is_last)
+                    .add(token, (target_pos + i) as i32, &[0], true)
                     .map_err(|e| LocalProviderError::InferenceFailed(e.to_string()))?;
             }
             ctx.decode(&mut batch)
                 .map_err(|e| LocalProviderError::InferenceFailed(e.to_string()))?;
-            pos += chunk.len() as i32;
-        }
-
-        let mut sampler = LlamaSampler::chain_simple([
-            LlamaSampler::dist(1234),
-            LlamaSampler::greedy(),
-        ]);
-
-        let mut n_cur = n_tokens as i32;
-
-        for _ in 0..max_tokens {
-            if cancel_requested.load(Ordering::Relaxed) {
-                break;
+            target_pos += 1;
+
+            // 3. Принимаем совпавший префикс; первый несовпавший — токен целевой модели.
+            let mut accepted = 0;
+            next = sampler.sample(&ctx, 0);
+            for &candidate in &candidates {
+                if next != candidate
+                    || stats.generated >= max_tokens
+                    || cancel_requested.load(Ordering::Relaxed)
+                    || self.model.is_eog_token(candidate)
+                {
+                    break;
+                }
+                let piece = self.piece(candidate);
+                on_token(&piece);
+                stats.generated += 1;
+                draft_pending.push_str(&piece);
+                accepted += 1;
+                next = sampler.sample(&ctx, accepted as i32);
             }
+            stats.drafted += candidates.len();
+            stats.accepted += accepted;
+            target_pos += accepted;
+            clear_kv_from(&mut ctx, target_pos)?;
+        }
 
-            let token = sampler.sample(&ctx, batch.n_tokens() - 1);
-            sampler.accept(token);
+        Ok(stats)
+    }
+}
 
-            if self.model.is_eog_token(token) {
-                break;
-            }
+/// Метрики speculative decoding одного запроса.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpeculativeStats {
+    /// Токенов ответа всего.
+    pub generated: usize,
+    /// Токенов, предложенных черновой моделью.
+    pub drafted: usize,
+    /// Из них принято целевой моделью.
+    pub accepted: usize,
+}
 
-            let piece = self
-                .model
-                .token_to_str(token, llama_cpp_2::model::Special::Tokenize)
-                .unwrap_or_else(|_| String::new());
-            on_token(&piece);
+impl SpeculativeStats {
+    /// Доля принятых токенов черновика (0.0 — черновик не использовался).
+    pub fn acceptance_rate(&self) -> f64 {
+        if self.drafted == 0 {
+            0.0
+        } else {
+            self.accepted as f64 / self.drafted as f64
+        }
+    }
+}
 
-            batch.clear();
+/// Декодирует `tokens` с позиции `pos` чанками по `n_batch`; логиты — только у последнего токена.
+fn decode_at(
+    ctx: &mut LlamaContext<'_>,
+    batch: &mut LlamaBatch,
+    tokens: &[LlamaToken],
+    pos: usize,
+    n_batch: usize,
+) -> Result<(), LocalProviderError> {
+    let last = tokens.len().saturating_sub(1);
+    for (c, chunk) in tokens.chunks(n_batch.max(1)).enumerate() {
+        batch.clear();
+        for (j, &token) in chunk.iter().enumerate() {
+            let i = c * n_batch.max(1) + j;
             batch
-                .add(token, n_cur, &[0], true)
-                .map_err(|e| LocalProviderError::InferenceFailed(e.to_string()))?;
-
-            n_cur += 1;
-
-            ctx.decode(&mut batch)
+                .add(token, (pos + i) as i32, &[0], i == last)
                 .map_err(|e| LocalProviderError::InferenceFailed(e.to_string()))?;
         }
-
-        Ok(())
+        ctx.decode(batch)
+            .map_err(|e| LocalProviderError::InferenceFailed(e.to_string()))?;
     }
+    Ok(())
+}
+
+/// Удаляет из KV-кэша позиции начиная с `pos` (отвергнутые токены черновика).
+fn clear_kv_from(ctx: &mut LlamaContext<'_>, pos: usize) -> Result<(), LocalProviderError> {
+    ctx.clear_kv_cache_seq(Some(0), Some(pos as u32), None)
+        .map(|_| ())
+        .map_err(|e| LocalProviderError::InferenceFailed(e.to_string()))
 }
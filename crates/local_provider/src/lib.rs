@@ -4,14 +4,19 @@
 //! По умолчанию — GigaChat3-10B-A1.8B для десктопа (~10 ГБ).
 
 mod config;
+mod embedding;
 mod error;
 pub mod hardware_detect;
 mod inference;
 mod model_manager;
+mod pool;
 mod provider;
+mod scheduler;
 mod tokenizer;
 
-pub use config::{LocalConfig, ModelVariant};
+pub use config::{DraftConfig, LocalConfig, ModelVariant, DEFAULT_DRAFT_TOKENS};
+pub use embedding::LocalEmbeddingProvider;
 pub use error::LocalProviderError;
-pub use model_manager::{DownloadProgress, ModelManager};
+pub use model_manager::{backfill_catalog_ids, DownloadProgress, ModelManager};
+pub use pool::{LoadedModelInfo, ModelPool, PoolConfig, DEFAULT_IDLE_TIMEOUT_SECS};
 pub use provider::LocalProvider;
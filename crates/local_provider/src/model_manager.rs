@@ -1,5 +1,6 @@
 //! Загрузка, проверка, hot-swap моделей GigaChat3 из HuggingFace.
 
+use model_catalog::{ModelFile, ModelMetadata, VerificationState};
 use std::path::{Path, PathBuf};
 use tokio::io::AsyncWriteExt;
 
@@ -7,6 +8,7 @@ use crate::config::{
     hf_api_tree_url, hf_resolve_url, LocalConfig, ModelVariant, HuggingFaceModelConfig,
 };
 use crate::error::LocalProviderError;
+use crate::hardware_detect::free_disk_bytes;
 
 /// Прогресс загрузки для UI.
 #[derive(Debug, Clone, serde::Serialize)]
@@ -31,6 +33,31 @@ struct HfTreeItem {
     item_type: String,
     path: String,
     size: Option<u64>,
+    /// Для LFS-файлов oid — SHA256 содержимого.
+    lfs: Option<HfLfs>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct HfLfs {
+    oid: String,
+}
+
+/// Файл модели для скачивания.
+#[derive(Debug, Clone)]
+struct HfFile {
+    path: String,
+    size: u64,
+    sha256: Option<String>,
+}
+
+impl From<HfTreeItem> for HfFile {
+    fn from(item: HfTreeItem) -> Self {
+        Self {
+            path: item.path,
+            size: item.size.unwrap_or(0),
+            sha256: item.lfs.map(|l| l.oid),
+        }
+    }
 }
 
 /// Управление локальными GGUF-моделями GigaChat3.
@@ -80,10 +107,28 @@ impl ModelManager {
 
         let files = self.list_hf_files(&hf).await?;
         let file_count = files.len();
-        let mut bytes_total: u64 = files.iter().map(|(_, s)| s).sum();
+        let mut bytes_total: u64 = files.iter().map(|f| f.size).sum();
         let mut bytes_done: u64 = 0;
 
-        for (idx, (path, size)) in files.iter().enumerate() {
+        // Докачиваем только недостающее; не влезает на диск — не начинаем.
+        let required: u64 = files
+            .iter()
+            .filter(|f| {
+                let out_path = target_dir.join(Path::new(&f.path).file_name().unwrap_or_default());
+                out_path.metadata().ok().map(|m| m.len()) != Some(f.size)
+            })
+            .map(|f| f.size)
+            .sum();
+        if let Some(available) = free_disk_bytes(&target_dir) {
+            if available < required {
+                return Err(LocalProviderError::InsufficientDisk {
+                    required,
+                    available,
+                });
+            }
+        }
+
+        for (idx, HfFile { path, size, .. }) in files.iter().enumerate() {
             let url = hf_resolve_url(hf.repo_id, path);
             let out_path = target_dir.join(
                 Path::new(path)
@@ -125,7 +170,10 @@ impl ModelManager {
             let total = response.content_length().unwrap_or(*size);
             bytes_total += total;
 
-            let mut file = tokio::fs::File::create(&out_path)
+            // Пишем во временный .part и переименовываем после загрузки: оборванная загрузка
+            // не выглядит установленной моделью, а очистка моделей находит и удаляет её.
+            let part_path = partial_path(&out_path);
+            let mut file = tokio::fs::File::create(&part_path)
                 .await
                 .map_err(|e| LocalProviderError::DownloadFailed(e.to_string()))?;
 
@@ -148,15 +196,17 @@ impl ModelManager {
             }
 
             file.flush().await?;
+            drop(file);
+            tokio::fs::rename(&part_path, &out_path).await?;
         }
 
-        self.prepare_for_inference(&target_dir)?;
+        self.prepare_for_inference(&target_dir, &files)?;
         self.find_gguf_path()
             .ok_or_else(|| LocalProviderError::DownloadFailed("No GGUF after download".into()))
     }
 
     /// Список файлов модели на HuggingFace (async, не блокирует runtime).
-    async fn list_hf_files(&self, hf: &HuggingFaceModelConfig) -> Result<Vec<(String, u64)>, LocalProviderError> {
+    async fn list_hf_files(&self, hf: &HuggingFaceModelConfig) -> Result<Vec<HfFile>, LocalProviderError> {
         let client = reqwest::Client::builder()
             .timeout(std::time::Duration::from_secs(60))
             .build()
@@ -175,12 +225,11 @@ impl ModelManager {
                     .map_err(|e| LocalProviderError::DownloadFailed(e.to_string()))?;
 
                 for item in body {
-                    if item.item_type == "file" && item.path.ends_with(".gguf") {
-                        let path = item.path;
-                        if path == hf.file_pattern || path.contains(hf.quant) {
-                            let size = item.size.unwrap_or(0);
-                            return Ok(vec![(path, size)]);
-                        }
+                    if item.item_type == "file"
+                        && item.path.ends_with(".gguf")
+                        && (item.path == hf.file_pattern || item.path.contains(hf.quant))
+                    {
+                        return Ok(vec![HfFile::from(item)]);
                     }
                 }
                 Err(LocalProviderError::DownloadFailed(format!(
@@ -200,12 +249,12 @@ impl ModelManager {
                     .await
                     .map_err(|e| LocalProviderError::DownloadFailed(e.to_string()))?;
 
-                let mut files: Vec<(String, u64)> = body
+                let mut files: Vec<HfFile> = body
                     .into_iter()
                     .filter(|i| i.item_type == "file" && i.path.ends_with(".gguf"))
-                    .map(|i| (i.path, i.size.unwrap_or(0)))
+                    .map(HfFile::from)
                     .collect();
-                files.sort_by(|a, b| a.0.cmp(&b.0));
+                files.sort_by(|a, b| a.path.cmp(&b.path));
                 if files.is_empty() {
                     return Err(LocalProviderError::DownloadFailed(
                         "No GGUF files in 702B quant".into(),
@@ -229,28 +278,70 @@ impl ModelManager {
         }
     }
 
-    /// Подготовить модель к inference: записать metadata.json.
-    pub fn prepare_for_inference(&self, dir: &Path) -> Result<(), LocalProviderError> {
+    /// Подготовить модель к inference: записать metadata.json для каталога моделей
+    /// (id, версия, файлы с ожидаемым SHA256 от HuggingFace).
+    fn prepare_for_inference(&self, dir: &Path, files: &[HfFile]) -> Result<(), LocalProviderError> {
         let name = match self.config.model_variant {
             ModelVariant::GigaChat => "GigaChat3-10B-A1.8B",
             ModelVariant::DeepSeekCoder => "DeepSeek-Coder-6.7B-Instruct",
             ModelVariant::SmolLM2 => "SmolLM2-1.7B-Instruct",
             ModelVariant::Full => "GigaChat3-702B-A36B-preview",
         };
-        let metadata = serde_json::json!({
-            "name": name,
-            "source": "huggingface",
-            "license": "MIT",
-            "offline": true
+        let hf = self.config.model_variant.hf_config();
+        let metadata = ModelMetadata {
+            id: Some(self.config.model_variant.model_id().to_string()),
+            name: name.to_string(),
+            source: Some("huggingface".to_string()),
+            repo_id: Some(hf.repo_id.to_string()),
+            version: Some(hf.quant.to_string()),
+            license: Some("MIT".to_string()),
+            offline: true,
+            files: files
+                .iter()
+                .map(|f| ModelFile {
+                    name: Path::new(&f.path)
+                        .file_name()
+                        .map(|n| n.to_string_lossy().to_string())
+                        .unwrap_or_else(|| f.path.clone()),
+                    size_bytes: f.size,
+                    sha256: f.sha256.clone(),
+                })
+                .collect(),
+            verification: VerificationState::Unverified,
+        };
+        metadata
+            .write(dir)
+            .map_err(|e| LocalProviderError::IntegrityCheckFailed(e.to_string()))
+    }
+
+    /// Дописывает model_id в metadata.json старой установки (без id каталог называл модель
+    /// именем директории, а пул и журнал использования — model_id). true — файл изменён.
+    pub fn backfill_catalog_id(&self) -> Result<bool, LocalProviderError> {
+        let dir = self.config.model_dir();
+        if self.find_gguf_path().is_none() {
+            return Ok(false);
+        }
+        let to_err = |e: model_catalog::ModelManagerError| {
+            LocalProviderError::IntegrityCheckFailed(e.to_string())
+        };
+        let mut metadata = ModelMetadata::read(&dir).map_err(to_err)?.unwrap_or_else(|| {
+            ModelMetadata {
+                name: self.config.model_variant.display_name().to_string(),
+                ..ModelMetadata::default()
+            }
         });
-        let path = dir.join("metadata.json");
-        std::fs::write(path, serde_json::to_string_pretty(&metadata).unwrap_or_default())
-            .map_err(|e| LocalProviderError::Io(e))?;
-        Ok(())
+        if metadata.id.is_some() {
+            return Ok(false);
+        }
+        metadata.id = Some(self.config.model_variant.model_id().to_string());
+        metadata.write(&dir).map_err(to_err)?;
+        Ok(true)
     }
 
-    /// Проверка целостности (опционально по SHA256).
-    pub fn verify_integrity(&self, _path: &Path) -> Result<bool, LocalProviderError> {
+    /// Быстрая проверка перед загрузкой (заголовок GGUF, размеры; SHA256 — в каталоге моделей).
+    pub fn verify_integrity(&self, path: &Path) -> Result<bool, LocalProviderError> {
+        let dir = path.parent().unwrap_or(path);
+        model_catalog::quick_check(dir).map_err(LocalProviderError::IntegrityCheckFailed)?;
         Ok(true)
     }
 
@@ -274,6 +365,20 @@ impl ModelManager {
         candidates.into_iter().next()
     }
 
+    /// Суммарный размер GGUF-файлов модели (для split — всех частей).
+    pub fn installed_size_bytes(&self) -> u64 {
+        let dir = self.config.model_dir();
+        std::fs::read_dir(&dir)
+            .map(|rd| {
+                rd.filter_map(|e| e.ok())
+                    .filter(|e| e.path().extension().map(|x| x == "gguf").unwrap_or(false))
+                    .filter_map(|e| e.metadata().ok())
+                    .map(|m| m.len())
+                    .sum()
+            })
+            .unwrap_or(0)
+    }
+
     /// Список моделей.
     pub fn list_models(&self) -> Result<Vec<ModelInfo>, LocalProviderError> {
         let dir = self.config.model_dir();
@@ -298,3 +403,28 @@ impl ModelManager {
             .ok_or_else(|| LocalProviderError::ModelNotFound(name.to_string()))
     }
 }
+
+/// Путь временного файла загрузки: `<name>.part`.
+fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    path.with_file_name(name)
+}
+
+/// Дописывает model_id в metadata.json всех старых установок в models_dir.
+pub fn backfill_catalog_ids(models_dir: &Path) {
+    for variant in ModelVariant::ALL {
+        let config = LocalConfig {
+            models_dir: models_dir.to_path_buf(),
+            model_variant: variant,
+            ..LocalConfig::default_config()
+        };
+        match ModelManager::new(config).backfill_catalog_id() {
+            Ok(true) => tracing::info!(model = %variant.model_id(), "catalog id backfilled"),
+            Ok(false) => {}
+            Err(e) => {
+                tracing::warn!(model = %variant.model_id(), error = %e, "catalog id not backfilled")
+            }
+        }
+    }
+}
@@ -0,0 +1,477 @@
+//! Пул загруженных моделей: учёт RAM, LRU-выгрузка, idle-таймаут.
+//!
+//! Общий для всех LocalProvider — иначе каждая модель держит движок в памяти до конца сессии.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex as StdMutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use crate::error::LocalProviderError;
+use crate::hardware_detect::ram_bytes;
+use crate::inference::InferenceEngine;
+
+/// Запас RAM сверх размера весов: KV-кэш и буферы llama.cpp.
+pub(crate) const LOAD_OVERHEAD_BYTES: u64 = 512 * 1024 * 1024;
+/// Idle-таймаут по умолчанию (15 мин).
+pub const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 900;
+/// Период проверки простаивающих моделей.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Настройки пула.
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// Через сколько простоя выгружать модель. None — не выгружать по таймауту.
+    pub idle_timeout: Option<Duration>,
+    /// При нехватке RAM выгружать давно не использованные модели; иначе — сразу ошибка.
+    pub evict_on_pressure: bool,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Some(Duration::from_secs(DEFAULT_IDLE_TIMEOUT_SECS)),
+            evict_on_pressure: true,
+        }
+    }
+}
+
+struct PoolEntry {
+    engine: Arc<InferenceEngine>,
+    size_bytes: u64,
+    last_used: Instant,
+}
+
+impl PoolEntry {
+    /// Движок занят, пока кто-то кроме пула держит Arc (идёт генерация).
+    fn in_use(&self) -> bool {
+        Arc::strong_count(&self.engine) > 1
+    }
+}
+
+/// Загруженные модели и RAM, зарезервированная под идущие загрузки.
+#[derive(Default)]
+struct PoolState {
+    entries: HashMap<String, PoolEntry>,
+    reserved_bytes: u64,
+}
+
+/// Модель-кандидат на выгрузку.
+struct EvictionCandidate {
+    model_id: String,
+    last_used: Instant,
+    size_bytes: u64,
+    in_use: bool,
+}
+
+/// Какие модели выгрузить (LRU, занятые не трогаются), чтобы при `available` байтах свободной RAM
+/// хватило `required`. Без `evict` — сразу ошибка при нехватке.
+fn plan_eviction(
+    mut candidates: Vec<EvictionCandidate>,
+    mut available: u64,
+    required: u64,
+    evict: bool,
+) -> Result<Vec<String>, LocalProviderError> {
+    if available >= required {
+        return Ok(Vec::new());
+    }
+    if !evict {
+        return Err(LocalProviderError::InsufficientMemory { required, available });
+    }
+    candidates.retain(|c| !c.in_use);
+    candidates.sort_by_key(|c| c.last_used);
+    let mut evicted = Vec::new();
+    for c in candidates {
+        if available >= required {
+            break;
+        }
+        available += c.size_bytes;
+        evicted.push(c.model_id);
+    }
+    if available >= required {
+        Ok(evicted)
+    } else {
+        Err(LocalProviderError::InsufficientMemory { required, available })
+    }
+}
+
+/// Модели, простаивающие дольше `timeout` на момент `now` (занятые не выгружаются).
+fn idle_models<'a>(
+    entries: impl Iterator<Item = (&'a str, Instant, bool)>,
+    timeout: Duration,
+    now: Instant,
+) -> Vec<String> {
+    entries
+        .filter(|(_, last_used, in_use)| !in_use && now.saturating_duration_since(*last_used) > timeout)
+        .map(|(id, _, _)| id.to_string())
+        .collect()
+}
+
+/// Загруженная модель для UI.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LoadedModelInfo {
+    pub model_id: String,
+    pub size_bytes: u64,
+    pub idle_secs: u64,
+    pub in_use: bool,
+}
+
+/// Загрузки по model_id. Блокировка короткая и без await — снимается и из Drop.
+type LoadingGates = StdMutex<HashMap<String, Arc<Mutex<()>>>>;
+
+/// Участие в загрузке модели: при любом выходе из acquire (успех, ошибка, отмена future)
+/// последний участник снимает запись о загрузке.
+struct LoadingGuard<'a> {
+    loading: &'a LoadingGates,
+    model_id: &'a str,
+    gate: Arc<Mutex<()>>,
+}
+
+impl Drop for LoadingGuard<'_> {
+    fn drop(&mut self) {
+        let mut loading = self.loading.lock().unwrap_or_else(|e| e.into_inner());
+        // Arc держат карта и участники; пока ждут другие, запись снимет последний из них.
+        let last = loading
+            .get(self.model_id)
+            .is_some_and(|g| Arc::ptr_eq(g, &self.gate) && Arc::strong_count(g) == 2);
+        if last {
+            loading.remove(self.model_id);
+        }
+    }
+}
+
+/// Пул движков: model_id → загруженный InferenceEngine.
+pub struct ModelPool {
+    state: Mutex<PoolState>,
+    /// Загрузка по model_id: второй запрос той же модели ждёт первый, остальные модели не блокируются.
+    loading: LoadingGates,
+    config: RwLock<PoolConfig>,
+    watcher_started: AtomicBool,
+}
+
+impl ModelPool {
+    pub fn new(config: PoolConfig) -> Self {
+        Self {
+            state: Mutex::new(PoolState::default()),
+            loading: StdMutex::new(HashMap::new()),
+            config: RwLock::new(config),
+            watcher_started: AtomicBool::new(false),
+        }
+    }
+
+    pub fn config(&self) -> PoolConfig {
+        self.config
+            .read()
+            .map(|c| c.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn set_idle_timeout(&self, timeout: Option<Duration>) {
+        if let Ok(mut c) = self.config.write() {
+            c.idle_timeout = timeout;
+        }
+    }
+
+    /// Уже загруженный движок (без загрузки). Обновляет время использования.
+    pub async fn get(&self, model_id: &str) -> Option<Arc<InferenceEngine>> {
+        let mut state = self.state.lock().await;
+        state.entries.get_mut(model_id).map(|e| {
+            e.last_used = Instant::now();
+            Arc::clone(&e.engine)
+        })
+    }
+
+    /// Загружена или загружается ли модель (без обновления времени использования).
+    pub async fn contains(&self, model_id: &str) -> bool {
+        self.state.lock().await.entries.contains_key(model_id)
+            || self
+                .loading
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(model_id)
+                .is_some_and(|gate| gate.try_lock().is_err())
+    }
+
+    /// Возвращает движок модели, загружая его через `load` при необходимости.
+    /// Перед загрузкой проверяет свободную RAM и при нехватке выгружает LRU-модели.
+    /// Пул не блокируется на время загрузки: RAM модели резервируется до её окончания.
+    pub async fn acquire<F>(
+        self: &Arc<Self>,
+        model_id: &str,
+        size_bytes: u64,
+        load: F,
+    ) -> Result<Arc<InferenceEngine>, LocalProviderError>
+    where
+        F: FnOnce() -> Result<InferenceEngine, LocalProviderError> + Send + 'static,
+    {
+        if let Some(engine) = self.get(model_id).await {
+            return Ok(engine);
+        }
+        let guard = {
+            let mut loading = self.loading.lock().unwrap_or_else(|e| e.into_inner());
+            LoadingGuard {
+                loading: &self.loading,
+                model_id,
+                gate: Arc::clone(loading.entry(model_id.to_string()).or_default()),
+            }
+        };
+        let _loading = guard.gate.lock().await;
+        // Пока ждали, модель мог загрузить предыдущий запрос.
+        if let Some(engine) = self.get(model_id).await {
+            return Ok(engine);
+        }
+
+        {
+            let mut state = self.state.lock().await;
+            self.make_room(&mut state, size_bytes)?;
+            state.reserved_bytes += size_bytes;
+        }
+        let loaded = tokio::task::spawn_blocking(load)
+            .await
+            .map_err(|e| LocalProviderError::ModelLoadFailed(e.to_string()))
+            .and_then(|r| r);
+
+        let mut state = self.state.lock().await;
+        state.reserved_bytes = state.reserved_bytes.saturating_sub(size_bytes);
+        let engine = Arc::new(loaded?);
+        state.entries.insert(
+            model_id.to_string(),
+            PoolEntry {
+                engine: Arc::clone(&engine),
+                size_bytes,
+                last_used: Instant::now(),
+            },
+        );
+        drop(state);
+
+        tracing::info!(model = %model_id, size_bytes, "local model loaded");
+        self.ensure_idle_watcher();
+        Ok(engine)
+    }
+
+    /// Освобождает RAM под модель размера `size_bytes`, выгружая простаивающие модели (LRU).
+    /// RAM идущих загрузок считается занятой.
+    fn make_room(&self, state: &mut PoolState, size_bytes: u64) -> Result<(), LocalProviderError> {
+        let required = size_bytes + LOAD_OVERHEAD_BYTES;
+        let available = ram_bytes()?.saturating_sub(state.reserved_bytes);
+        let candidates = state
+            .entries
+            .iter()
+            .map(|(id, e)| EvictionCandidate {
+                model_id: id.clone(),
+                last_used: e.last_used,
+                size_bytes: e.size_bytes,
+                in_use: e.in_use(),
+            })
+            .collect();
+        for id in plan_eviction(candidates, available, required, self.config().evict_on_pressure)? {
+            state.entries.remove(&id);
+            tracing::info!(model = %id, "local model evicted (LRU, low memory)");
+        }
+        Ok(())
+    }
+
+    /// Выгружает модель. Ok(false) — модель не была загружена.
+    pub async fn unload(&self, model_id: &str) -> Result<bool, LocalProviderError> {
+        let mut state = self.state.lock().await;
+        match state.entries.get(model_id) {
+            None => Ok(false),
+            Some(e) if e.in_use() => Err(LocalProviderError::ModelBusy(model_id.to_string())),
+            Some(_) => {
+                state.entries.remove(model_id);
+                tracing::info!(model = %model_id, "local model unloaded");
+                Ok(true)
+            }
+        }
+    }
+
+    /// Выгружает модели, простаивающие дольше idle_timeout. Возвращает их id.
+    pub async fn evict_idle(&self) -> Vec<String> {
+        let Some(timeout) = self.config().idle_timeout else {
+            return Vec::new();
+        };
+        let mut state = self.state.lock().await;
+        let idle = idle_models(
+            state
+                .entries
+                .iter()
+                .map(|(id, e)| (id.as_str(), e.last_used, e.in_use())),
+            timeout,
+            Instant::now(),
+        );
+        for id in &idle {
+            state.entries.remove(id);
+            tracing::info!(model = %id, "local model unloaded (idle)");
+        }
+        idle
+    }
+
+    /// Список загруженных моделей.
+    pub async fn list_loaded(&self) -> Vec<LoadedModelInfo> {
+        let state = self.state.lock().await;
+        let mut out: Vec<LoadedModelInfo> = state
+            .entries
+            .iter()
+            .map(|(id, e)| LoadedModelInfo {
+                model_id: id.clone(),
+                size_bytes: e.size_bytes,
+                idle_secs: e.last_used.elapsed().as_secs(),
+                in_use: e.in_use(),
+            })
+            .collect();
+        out.sort_by(|a, b| a.model_id.cmp(&b.model_id));
+        out
+    }
+
+    /// Фоновая задача idle-выгрузки; стартует при первой загрузке (нужен tokio runtime).
+    fn ensure_idle_watcher(self: &Arc<Self>) {
+        if self.watcher_started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let weak = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(IDLE_CHECK_INTERVAL);
+            loop {
+                tick.tick().await;
+                let Some(pool) = weak.upgrade() else {
+                    break;
+                };
+                pool.evict_idle().await;
+            }
+        });
+    }
+}
+
+impl Default for ModelPool {
+    fn default() -> Self {
+        Self::new(PoolConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GB: u64 = 1024 * 1024 * 1024;
+
+    /// «Сейчас» с запасом вперёд: вычитание возраста не уходит за начало монотонных часов.
+    fn test_now() -> Instant {
+        Instant::now() + Duration::from_secs(3600)
+    }
+
+    fn ago(now: Instant, secs: u64) -> Instant {
+        now.checked_sub(Duration::from_secs(secs))
+            .expect("test_now leaves room for the age")
+    }
+
+    fn candidate(id: &str, age_secs: u64, size_bytes: u64, in_use: bool, now: Instant) -> EvictionCandidate {
+        EvictionCandidate {
+            model_id: id.to_string(),
+            last_used: ago(now, age_secs),
+            size_bytes,
+            in_use,
+        }
+    }
+
+    #[test]
+    fn no_eviction_when_ram_is_enough() {
+        let now = test_now();
+        let plan = plan_eviction(vec![candidate("a", 10, 4 * GB, false, now)], 8 * GB, 5 * GB, true).unwrap();
+        assert!(plan.is_empty());
+    }
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let now = test_now();
+        let candidates = vec![
+            candidate("recent", 5, 4 * GB, false, now),
+            candidate("oldest", 300, 2 * GB, false, now),
+            candidate("older", 100, 2 * GB, false, now),
+        ];
+        let plan = plan_eviction(candidates, GB, 5 * GB, true).unwrap();
+        assert_eq!(plan, vec!["oldest", "older"]);
+    }
+
+    #[test]
+    fn busy_models_are_not_evicted() {
+        let now = test_now();
+        let candidates = vec![
+            candidate("busy", 500, 8 * GB, true, now),
+            candidate("idle", 10, 2 * GB, false, now),
+        ];
+        let err = plan_eviction(candidates, GB, 5 * GB, true).unwrap_err();
+        assert!(matches!(
+            err,
+            LocalProviderError::InsufficientMemory { required, available } if required == 5 * GB && available == 3 * GB
+        ));
+    }
+
+    #[test]
+    fn no_eviction_when_disabled() {
+        let now = test_now();
+        let err = plan_eviction(vec![candidate("a", 100, 8 * GB, false, now)], GB, 5 * GB, false).unwrap_err();
+        assert!(matches!(err, LocalProviderError::InsufficientMemory { .. }));
+    }
+
+    #[test]
+    fn idle_models_respect_timeout_and_use() {
+        let now = test_now();
+        let entries = [
+            ("stale", ago(now, 120), false),
+            ("fresh", ago(now, 10), false),
+            ("busy", ago(now, 600), true),
+        ];
+        let idle = idle_models(entries.iter().copied(), Duration::from_secs(60), now);
+        assert_eq!(idle, vec!["stale"]);
+    }
+
+    fn gates(pool: &ModelPool) -> usize {
+        pool.loading.lock().unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn failed_load_releases_gate() {
+        let pool = Arc::new(ModelPool::default());
+        let Err(err) = pool
+            .acquire("m", 1, || Err(LocalProviderError::ModelLoadFailed("boom".to_string())))
+            .await
+        else {
+            panic!("load must fail");
+        };
+        assert!(matches!(err, LocalProviderError::ModelLoadFailed(_)));
+        assert_eq!(gates(&pool), 0);
+        assert!(!pool.contains("m").await);
+        assert_eq!(pool.state.lock().await.reserved_bytes, 0);
+    }
+
+    #[tokio::test]
+    async fn rejected_load_releases_gate() {
+        let pool = Arc::new(ModelPool::default());
+        // Больше любой RAM: make_room отказывает до загрузки.
+        let Err(err) = pool.acquire("m", u64::MAX / 2, || panic!("must not load")).await else {
+            panic!("make_room must refuse");
+        };
+        assert!(matches!(err, LocalProviderError::InsufficientMemory { .. }));
+        assert_eq!(gates(&pool), 0);
+    }
+
+    #[test]
+    fn gate_kept_while_others_wait() {
+        let pool = ModelPool::default();
+        let first = LoadingGuard {
+            loading: &pool.loading,
+            model_id: "m",
+            gate: Arc::clone(pool.loading.lock().unwrap().entry("m".to_string()).or_default()),
+        };
+        let second = LoadingGuard {
+            loading: &pool.loading,
+            model_id: "m",
+            gate: Arc::clone(pool.loading.lock().unwrap().entry("m".to_string()).or_default()),
+        };
+        drop(first);
+        assert_eq!(gates(&pool), 1);
+        drop(second);
+        assert_eq!(gates(&pool), 0);
+    }
+}
@@ -1,6 +1,6 @@
 //! LocalProvider — impl AiProvider для offline GigaChat3 (GGUF, llama.cpp).
 //!
-//! Streaming token-by-token; отмена через cancel(request_id).
+//! Streaming token-by-token; очередь запросов с позицией; отмена через cancel(request_id).
 
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -13,30 +13,46 @@ use ai_providers::{
     ProviderCapabilities, ProviderError, ProviderType,
 };
 use async_trait::async_trait;
+use backend_core::append_log;
 
 use crate::config::LocalConfig;
 use crate::error::LocalProviderError;
-use crate::inference::InferenceEngine;
+use crate::inference::{InferenceEngine, SpeculativeStats};
 use crate::model_manager::{DownloadProgress, ModelManager};
+use crate::pool::ModelPool;
+use crate::scheduler::RequestScheduler;
 
 const CHUNK_CHANNEL_CAP: usize = 64;
 
 pub struct LocalProvider {
     config: LocalConfig,
     model_manager: ModelManager,
-    engine: RwLock<Option<Arc<InferenceEngine>>>,
+    /// Общий пул загруженных движков (RAM, LRU, idle-выгрузка).
+    pool: Arc<ModelPool>,
     /// request_id → флаг отмены (проверяется в inference loop). Arc для передачи в cancel() без блокировки.
     active_requests: Arc<RwLock<HashMap<String, Arc<AtomicBool>>>>,
+    /// Очередь запросов к движку (max_parallel одновременных генераций).
+    scheduler: Arc<RequestScheduler>,
+    /// Предупреждение о недоступном черновике уже записано (пишется один раз).
+    draft_warned: AtomicBool,
 }
 
 impl LocalProvider {
     pub fn new(config: LocalConfig) -> Self {
+        Self::with_pool(config, Arc::new(ModelPool::default()))
+    }
+
+    /// Провайдер, делящий пул моделей с другими LocalProvider.
+    pub fn with_pool(config: LocalConfig, pool: Arc<ModelPool>) -> Self {
         let model_manager = ModelManager::new(config.clone());
+        let scheduler = Arc::new(RequestScheduler::new(config.max_parallel));
         Self {
             config,
             model_manager,
-            engine: RwLock::new(None),
+            pool,
             active_requests: Arc::new(RwLock::new(HashMap::new())),
+            scheduler,
+            draft_warned: AtomicBool::new(false),
         }
     }
 
@@ -47,33 +63,75 @@ impl LocalProvider {
     }
 
     async fn ensure_engine(&self) -> Result<Arc<InferenceEngine>, ProviderError> {
-        {
-            let guard = self.engine.read().await;
-            if let Some(ref e) = *guard {
-                return Ok(Arc::clone(e));
-            }
-        }
+        self.load_engine(&self.config).await
+    }
 
-        let mut guard = self.engine.write().await;
-        if let Some(ref e) = *guard {
-            return Ok(Arc::clone(e));
+    /// Движок модели `config.model_variant` из пула (загружает при необходимости).
+    /// Файловые операции (журнал, поиск и проверка весов) — в spawn_blocking.
+    async fn load_engine(&self, config: &LocalConfig) -> Result<Arc<InferenceEngine>, ProviderError> {
+        let model_id = config.model_variant.model_id();
+        // Журнал использования для учёта места и очистки неиспользуемых моделей.
+        let models_dir = config.models_dir.clone();
+        let recorded =
+            tokio::task::spawn_blocking(move || model_catalog::record_usage(&models_dir, model_id))
+                .await;
+        if let Ok(Err(e)) = recorded {
+            tracing::debug!(model = %model_id, error = %e, "usage not recorded");
+        }
+        if let Some(engine) = self.pool.get(model_id).await {
+            return Ok(engine);
         }
 
-        let path = self
-            .model_manager
-            .find_gguf_path()
-            .ok_or_else(|| ProviderError::Unavailable("Model not found".to_string()))?;
+        let model_manager = ModelManager::new(config.clone());
+        let (path, size_bytes) = tokio::task::spawn_blocking(move || {
+            let path = model_manager
+                .find_gguf_path()
+                .ok_or_else(|| ProviderError::Unavailable("Model not found".to_string()))?;
+            model_manager
+                .verify_integrity(&path)
+                .map_err(|e| ProviderError::Unavailable(e.to_string()))?;
+            Ok::<_, ProviderError>((path, model_manager.installed_size_bytes()))
+        })
+        .await
+        .map_err(|e| ProviderError::Unavailable(e.to_string()))??;
 
-        self.model_manager
-            .verify_integrity(&path)
-            .map_err(|e| ProviderError::Unavailable(e.to_string()))?;
+        let config = config.clone();
+        self.pool
+            .acquire(model_id, size_bytes, move || InferenceEngine::load(&path, &config))
+            .await
+            .map_err(|e| ProviderError::Unavailable(e.to_string()))
+    }
 
-        let engine = InferenceEngine::load(&path, self.config.n_threads)
-            .map_err(|e| ProviderError::Unavailable(e.to_string()))?;
+    /// Движок черновой модели для speculative decoding. None — черновик не настроен или
+    /// недоступен (тогда генерация идёт без него).
+    async fn ensure_draft_engine(&self) -> Option<(Arc<InferenceEngine>, usize)> {
+        let draft = self.config.draft?;
+        let config = self.config.draft_config()?;
+        match self.load_engine(&config).await {
+            Ok(engine) => Some((engine, draft.n_draft)),
+            Err(e) if self.draft_warned.swap(true, Ordering::Relaxed) => {
+                tracing::debug!(
+                    draft = %draft.variant.model_id(),
+                    error = %e,
+                    "draft model unavailable"
+                );
+                None
+            }
+            Err(e) => {
+                tracing::warn!(
+                    model = %self.config.model_variant.model_id(),
+                    draft = %draft.variant.model_id(),
+                    error = %e,
+                    "draft model unavailable, speculative decoding disabled"
+                );
+                None
+            }
+        }
+    }
 
-        let engine = Arc::new(engine);
-        *guard = Some(Arc::clone(&engine));
-        Ok(engine)
+    /// Пул моделей, которым пользуется провайдер.
+    pub fn pool(&self) -> &Arc<ModelPool> {
+        &self.pool
     }
 
     pub async fn ensure_model<F>(&self, on_progress: F) -> Result<(), LocalProviderError>
@@ -94,6 +152,25 @@ impl LocalProvider {
     }
 }
 
+/// Пишет метрики speculative decoding в runtime.log.
+fn log_speculative_stats(model_id: &str, stats: &SpeculativeStats) {
+    if stats.drafted == 0 {
+        return;
+    }
+    append_log(
+        None,
+        "runtime.log",
+        &format!(
+            "speculative model={} generated={} drafted={} accepted={} acceptance_rate={:.2}",
+            model_id,
+            stats.generated,
+            stats.drafted,
+            stats.accepted,
+            stats.acceptance_rate()
+        ),
+    );
+}
+
 #[async_trait]
 impl AiProvider for LocalProvider {
     fn id(&self) -> &str {
@@ -117,7 +194,7 @@ impl AiProvider for LocalProvider {
                 AiMode::Generate,
                 AiMode::Agent,
             ]),
-            max_context_tokens: Some(crate::config::DEFAULT_CONTEXT_SIZE),
+            max_context_tokens: Some(self.config.context_size),
         }
     }
 
@@ -127,11 +204,23 @@ impl AiProvider for LocalProvider {
         options: GenerateOptions,
     ) -> Result<AiChunkStream, ProviderError> {
         let engine = self.ensure_engine().await?;
+        let draft = self.ensure_draft_engine().await;
 
         let max_tokens = options
             .max_tokens
-            .unwrap_or(crate::config::DEFAULT_MAX_TOKENS)
-            .min(crate::config::DEFAULT_MAX_TOKENS);
+            .unwrap_or(self.config.max_tokens)
+            .min(self.config.max_tokens);
+
+        let tokens = engine.tokenize_prompt(&request.prompt).map_err(|e| match e {
+            LocalProviderError::PromptTooLong {
+                prompt_tokens,
+                context_size,
+            } => ProviderError::PromptTooLong {
+                prompt_tokens,
+                context_tokens: context_size,
+            },
+            other => ProviderError::Generation(other.to_string()),
+        })?;
 
         let cancel_flag = Arc::new(AtomicBool::new(false));
         {
@@ -139,44 +228,96 @@ impl AiProvider for LocalProvider {
             guard.insert(request.id.clone(), Arc::clone(&cancel_flag));
         }
 
-        let (tx, mut rx) = mpsc::channel::<AiChunk>(CHUNK_CHANNEL_CAP);
-        let prompt = request.prompt.clone();
-        let engine_clone = Arc::clone(&engine);
-        let cancel_clone = Arc::clone(&cancel_flag);
         let request_id = request.id.clone();
-
-        tokio::task::spawn_blocking(move || {
-            let result = engine_clone.generate_stream(
-                &prompt,
-                max_tokens,
-                cancel_clone.as_ref(),
-                |piece| {
-                    let _ = tx.blocking_send(AiChunk::Token {
-                        value: piece.to_string(),
-                    });
-                },
-            );
-            if let Err(e) = result {
-                let _ = tx.blocking_send(AiChunk::Error {
-                    error: e.to_string(),
-                });
-            } else {
-                let _ = tx.blocking_send(AiChunk::End);
-            }
-        });
-
-        let request_id_guard = request_id.clone();
+        let prompt = request.prompt;
+        let model_id = self.config.model_variant.model_id();
+        let scheduler = Arc::clone(&self.scheduler);
         let active_guard = Arc::clone(&self.active_requests);
         let stream = async_stream::stream! {
             yield AiChunk::Start;
-            while let Some(chunk) = rx.recv().await {
-                yield chunk.clone();
-                if matches!(chunk, AiChunk::End | AiChunk::Error { .. }) {
-                    break;
+
+            // Ждём свободный слот; пока ждём — сообщаем позицию в очереди.
+            let permits = scheduler.permits();
+            let permit = match Arc::clone(&permits).try_acquire_owned() {
+                Ok(p) => Some(p),
+                Err(_) => {
+                    let mut changes = scheduler.subscribe();
+                    let queue_guard = scheduler.enqueue(&request_id);
+                    let acquire = permits.acquire_owned();
+                    tokio::pin!(acquire);
+                    let mut last_position = None;
+                    let permit = loop {
+                        if cancel_flag.load(Ordering::Relaxed) {
+                            break None;
+                        }
+                        let position = scheduler.position(&request_id);
+                        if position.is_some() && position != last_position {
+                            last_position = position;
+                            yield AiChunk::Queued {
+                                position: position.unwrap_or(1),
+                            };
+                        }
+                        tokio::select! {
+                            p = &mut acquire => break p.ok(),
+                            _ = changes.changed() => {}
+                        }
+                    };
+                    drop(queue_guard);
+                    permit
                 }
+            };
+
+            match permit {
+                Some(permit) if !cancel_flag.load(Ordering::Relaxed) => {
+                    let (tx, mut rx) = mpsc::channel::<AiChunk>(CHUNK_CHANNEL_CAP);
+                    let cancel_clone = Arc::clone(&cancel_flag);
+                    tokio::task::spawn_blocking(move || {
+                        let _permit = permit;
+                        let send = |piece: &str| {
+                            let _ = tx.blocking_send(AiChunk::Token {
+                                value: piece.to_string(),
+                            });
+                        };
+                        let result = match draft {
+                            Some((draft_engine, n_draft)) => engine
+                                .generate_speculative(
+                                    &draft_engine,
+                                    &prompt,
+                                    &tokens,
+                                    max_tokens,
+                                    n_draft,
+                                    cancel_clone.as_ref(),
+                                    send,
+                                )
+                                .map(|stats| log_speculative_stats(model_id, &stats)),
+                            None => engine.generate_stream(
+                                &tokens,
+                                max_tokens,
+                                cancel_clone.as_ref(),
+                                send,
+                            ),
+                        };
+                        if let Err(e) = result {
+                            let _ = tx.blocking_send(AiChunk::Error {
+                                error: e.to_string(),
+                            });
+                        } else {
+                            let _ = tx.blocking_send(AiChunk::End);
+                        }
+                    });
+                    while let Some(chunk) = rx.recv().await {
+                        yield chunk.clone();
+                        if matches!(chunk, AiChunk::End | AiChunk::Error { .. }) {
+                            break;
+                        }
+                    }
+                }
+                // Отменён, пока ждал в очереди.
+                _ => yield AiChunk::End,
             }
+
             let mut g = active_guard.write().await;
-            g.remove(&request_id_guard);
+            g.remove(&request_id);
         };
 
         Ok(Box::pin(stream))
@@ -184,6 +325,7 @@ impl AiProvider for LocalProvider {
 
     fn cancel(&self, request_id: &str) {
         let active = Arc::clone(&self.active_requests);
+        let scheduler = Arc::clone(&self.scheduler);
         let rid = request_id.to_string();
         tokio::spawn(async move {
             let mut guard = active.write().await;
@@ -191,6 +333,8 @@ impl AiProvider for LocalProvider {
                 f.store(true, Ordering::Relaxed);
             }
             guard.remove(&rid);
+            // Ожидающий в очереди запрос просыпается и завершается, не занимая слот.
+            scheduler.remove(&rid);
         });
     }
 
@@ -0,0 +1,90 @@
+//! Очередь запросов к движку: ограничение параллелизма, позиция в очереди, отмена ожидающих.
+//!
+//! Без очереди два одновременных запроса (чат + агент) поднимают по полному llama-контексту
+//! и делят между собой все потоки CPU.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{watch, Semaphore};
+
+/// Планировщик запросов одного движка.
+pub struct RequestScheduler {
+    /// Слоты генерации (max_parallel). Semaphore в tokio честный (FIFO).
+    permits: Arc<Semaphore>,
+    /// request_id ожидающих запросов в порядке постановки.
+    waiting: Mutex<VecDeque<String>>,
+    /// Счётчик изменений очереди — ожидающие пересчитывают позицию.
+    changed: watch::Sender<u64>,
+}
+
+impl RequestScheduler {
+    pub fn new(max_parallel: usize) -> Self {
+        let (changed, _) = watch::channel(0);
+        Self {
+            permits: Arc::new(Semaphore::new(max_parallel.max(1))),
+            waiting: Mutex::new(VecDeque::new()),
+            changed,
+        }
+    }
+
+    pub fn permits(&self) -> Arc<Semaphore> {
+        Arc::clone(&self.permits)
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.changed.subscribe()
+    }
+
+    /// Ставит запрос в очередь. Guard убирает его из очереди при drop (стрим закрыт, запрос стартовал).
+    pub fn enqueue(self: &Arc<Self>, request_id: &str) -> QueueGuard {
+        if let Ok(mut w) = self.waiting.lock() {
+            w.push_back(request_id.to_string());
+        }
+        self.notify();
+        QueueGuard {
+            scheduler: Arc::clone(self),
+            request_id: request_id.to_string(),
+        }
+    }
+
+    /// Позиция в очереди (1 — следующий на выполнение). None — запроса нет в очереди.
+    pub fn position(&self, request_id: &str) -> Option<usize> {
+        self.waiting
+            .lock()
+            .ok()?
+            .iter()
+            .position(|id| id == request_id)
+            .map(|p| p + 1)
+    }
+
+    /// Убирает запрос из очереди. true — запрос ожидал.
+    pub fn remove(&self, request_id: &str) -> bool {
+        let removed = match self.waiting.lock() {
+            Ok(mut w) => match w.iter().position(|id| id == request_id) {
+                Some(idx) => w.remove(idx).is_some(),
+                None => false,
+            },
+            Err(_) => false,
+        };
+        if removed {
+            self.notify();
+        }
+        removed
+    }
+
+    fn notify(&self) {
+        self.changed.send_modify(|v| *v = v.wrapping_add(1));
+    }
+}
+
+/// Место запроса в очереди; освобождается при drop.
+pub struct QueueGuard {
+    scheduler: Arc<RequestScheduler>,
+    request_id: String,
+}
+
+impl Drop for QueueGuard {
+    fn drop(&mut self) {
+        self.scheduler.remove(&self.request_id);
+    }
+}
@@ -120,11 +120,16 @@ impl McpClient {
                 .get("inputSchema")
                 .cloned()
                 .unwrap_or_else(|| serde_json::json!({}));
+            let read_only = item
+                .pointer("/annotations/readOnlyHint")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
             out.push(McpToolDescriptor {
                 server: server_name.to_string(),
                 name,
                 description,
                 input_schema,
+                read_only,
             });
         }
         Ok(out)
@@ -31,6 +31,8 @@ pub struct McpToolDescriptor {
     pub name: String,
     pub description: Option<String>,
     pub input_schema: serde_json::Value,
+    /// Сервер пометил инструмент как только читающий (annotations.readOnlyHint).
+    pub read_only: bool,
 }
 
 impl McpToolDescriptor {
@@ -0,0 +1,330 @@
+//! Учёт места на диске по директориям моделей и очистка: недокачанные и лишние файлы,
+//! давно не используемые модели. Очистка поддерживает dry-run.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::manager::{unix_now, ModelManagerError, ModelMetadata, METADATA_FILE};
+use crate::usage::{read_usage, record_first_seen};
+
+/// Файлы моложе этого не считаются брошенными: возможно, идёт загрузка.
+const PARTIAL_GRACE: Duration = Duration::from_secs(3600);
+/// Суффиксы временных файлов загрузки.
+const PARTIAL_SUFFIXES: &[&str] = &[".part", ".partial", ".tmp", ".download", ".incomplete"];
+
+/// Роль файла в директории модели.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    /// Веса модели.
+    Model,
+    /// metadata.json.
+    Metadata,
+    /// Недокачанный файл (временный суффикс или размер меньше ожидаемого).
+    Partial,
+    /// Не относится к текущей установке (старая квантизация, посторонний файл).
+    Orphaned,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileUsage {
+    pub name: String,
+    pub size_bytes: u64,
+    pub kind: FileKind,
+    /// Изменён недавно — возможно, ещё пишется; очистка его не трогает.
+    pub recent: bool,
+}
+
+/// Занятое место в директории модели.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelDiskUsage {
+    /// model_id; None — в директории нет модели (только мусор).
+    pub model_id: Option<String>,
+    pub path: PathBuf,
+    pub total_bytes: u64,
+    /// Из них partial + orphaned.
+    pub reclaimable_bytes: u64,
+    /// Последнее использование из журнала (unix-секунды).
+    pub last_used: Option<u64>,
+    pub files: Vec<FileUsage>,
+}
+
+/// Что удалять при очистке.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CleanupOptions {
+    /// Только показать, что будет удалено.
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub remove_partial: bool,
+    #[serde(default)]
+    pub remove_orphaned: bool,
+    /// Удалять модели целиком, если не использовались столько дней. Модель без записи в
+    /// журнале не удаляется: очистка отмечает её, и срок отсчитывается с этого момента.
+    #[serde(default)]
+    pub unused_for_days: Option<u64>,
+    /// model_id, которые нельзя трогать (загружены в память).
+    #[serde(default)]
+    pub protected: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CleanupItem {
+    pub path: PathBuf,
+    pub size_bytes: u64,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CleanupReport {
+    pub dry_run: bool,
+    pub items: Vec<CleanupItem>,
+    /// Освобождено (при dry_run — будет освобождено).
+    pub freed_bytes: u64,
+}
+
+/// Учёт места по всем поддиректориям models_dir, крупные первыми.
+pub fn disk_usage(models_dir: &Path) -> Vec<ModelDiskUsage> {
+    let usage = read_usage(models_dir);
+    let Ok(rd) = std::fs::read_dir(models_dir) else {
+        return Vec::new();
+    };
+    let mut out: Vec<ModelDiskUsage> = rd
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir())
+        .filter_map(|e| match scan_dir(&e.path()) {
+            Ok(mut u) => {
+                u.last_used = u.model_id.as_ref().and_then(|id| usage.get(id).copied());
+                Some(u)
+            }
+            Err(err) => {
+                tracing::warn!(dir = %e.path().display(), error = %err, "disk usage skipped");
+                None
+            }
+        })
+        .collect();
+    out.sort_by_key(|u| std::cmp::Reverse(u.total_bytes));
+    out
+}
+
+/// Удаляет (или при dry_run перечисляет) недокачанные, лишние файлы и неиспользуемые модели.
+pub fn cleanup(models_dir: &Path, options: &CleanupOptions) -> Result<CleanupReport, ModelManagerError> {
+    let protected: HashSet<&str> = options.protected.iter().map(String::as_str).collect();
+    let unused_cutoff = options
+        .unused_for_days
+        .map(|days| unix_now().saturating_sub(days * 24 * 3600));
+    let mut items = Vec::new();
+    let mut unseen = Vec::new();
+
+    for dir in disk_usage(models_dir) {
+        if dir.model_id.as_deref().is_some_and(|id| protected.contains(id)) {
+            continue;
+        }
+
+        // Время изменения файлов не говорит об использовании: чтение весов его не обновляет.
+        if dir.last_used.is_none() {
+            unseen.extend(dir.model_id.clone());
+        }
+        let unused = dir.model_id.is_some()
+            && matches!((unused_cutoff, dir.last_used), (Some(cutoff), Some(t)) if t < cutoff);
+        let has_recent = dir.files.iter().any(|f| f.recent);
+        if unused && !has_recent {
+            items.push(CleanupItem {
+                path: dir.path.clone(),
+                size_bytes: dir.total_bytes,
+                reason: format!(
+                    "model {} unused for {}+ days",
+                    dir.model_id.as_deref().unwrap_or_default(),
+                    options.unused_for_days.unwrap_or_default()
+                ),
+            });
+            continue;
+        }
+
+        for file in dir.files.iter().filter(|f| !f.recent) {
+            let reason = match file.kind {
+                FileKind::Partial if options.remove_partial => "partial download",
+                FileKind::Orphaned if options.remove_orphaned => "orphaned file",
+                _ => continue,
+            };
+            items.push(CleanupItem {
+                path: dir.path.join(&file.name),
+                size_bytes: file.size_bytes,
+                reason: reason.to_string(),
+            });
+        }
+    }
+
+    if !options.dry_run && !unseen.is_empty() {
+        record_first_seen(models_dir, &unseen)?;
+    }
+
+    let mut freed_bytes = 0;
+    for item in &items {
+        if !options.dry_run {
+            let removed = if item.path.is_dir() {
+                std::fs::remove_dir_all(&item.path)
+            } else {
+                std::fs::remove_file(&item.path)
+            };
+            if let Err(e) = removed {
+                tracing::warn!(path = %item.path.display(), error = %e, "cleanup failed");
+                continue;
+            }
+            tracing::info!(path = %item.path.display(), bytes = item.size_bytes, reason = %item.reason, "model cleanup");
+        }
+        freed_bytes += item.size_bytes;
+    }
+
+    Ok(CleanupReport {
+        dry_run: options.dry_run,
+        items,
+        freed_bytes,
+    })
+}
+
+/// Классифицирует файлы директории модели по metadata.json.
+fn scan_dir(dir: &Path) -> Result<ModelDiskUsage, ModelManagerError> {
+    let metadata = ModelMetadata::read(dir).ok().flatten();
+    let expected = metadata.as_ref().map(|m| &m.files).filter(|f| !f.is_empty());
+    let now = SystemTime::now();
+
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)?.filter_map(|e| e.ok()) {
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        if !meta.is_file() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        let size_bytes = meta.len();
+        let is_gguf = name.ends_with(".gguf");
+        let kind = if name == METADATA_FILE {
+            FileKind::Metadata
+        } else if PARTIAL_SUFFIXES.iter().any(|s| name.ends_with(s)) {
+            FileKind::Partial
+        } else if !is_gguf {
+            FileKind::Orphaned
+        } else {
+            match expected.map(|files| files.iter().find(|f| f.name == name)) {
+                // В metadata.json файлы перечислены, этого среди них нет — старая квантизация.
+                Some(None) => FileKind::Orphaned,
+                Some(Some(f)) if size_bytes < f.size_bytes => FileKind::Partial,
+                // Без списка файлов (старая установка, модель положена вручную) — считаем весами.
+                _ => FileKind::Model,
+            }
+        };
+        let recent = meta
+            .modified()
+            .ok()
+            .and_then(|m| now.duration_since(m).ok())
+            .is_some_and(|age| age < PARTIAL_GRACE);
+        files.push(FileUsage {
+            name,
+            size_bytes,
+            kind,
+            recent,
+        });
+    }
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let has_model = files.iter().any(|f| f.kind == FileKind::Model);
+    let model_id = has_model.then(|| {
+        metadata
+            .as_ref()
+            .and_then(|m| m.id.clone())
+            .unwrap_or_else(|| {
+                dir.file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default()
+            })
+    });
+    Ok(ModelDiskUsage {
+        model_id,
+        path: dir.to_path_buf(),
+        total_bytes: files.iter().map(|f| f.size_bytes).sum(),
+        reclaimable_bytes: files
+            .iter()
+            .filter(|f| matches!(f.kind, FileKind::Partial | FileKind::Orphaned))
+            .map(|f| f.size_bytes)
+            .sum(),
+        last_used: None,
+        files,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usage::USAGE_FILE;
+
+    struct TempModels(PathBuf);
+
+    impl TempModels {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!(
+                "kengaide-disk-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&root);
+            std::fs::create_dir_all(&root).unwrap();
+            Self(root)
+        }
+
+        /// Модель с весами, изменёнными давно (не попадает под PARTIAL_GRACE).
+        fn add_model(&self, id: &str) {
+            let dir = self.0.join(id);
+            std::fs::create_dir_all(&dir).unwrap();
+            let file = std::fs::File::create(dir.join("model.gguf")).unwrap();
+            file.set_modified(SystemTime::now() - Duration::from_secs(400 * 24 * 3600))
+                .unwrap();
+        }
+
+        fn set_usage(&self, usage: &[(&str, u64)]) {
+            let map: std::collections::HashMap<&str, u64> = usage.iter().copied().collect();
+            std::fs::write(self.0.join(USAGE_FILE), serde_json::to_string(&map).unwrap()).unwrap();
+        }
+    }
+
+    impl Drop for TempModels {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn unused_for(days: u64, dry_run: bool) -> CleanupOptions {
+        CleanupOptions {
+            dry_run,
+            unused_for_days: Some(days),
+            ..CleanupOptions::default()
+        }
+    }
+
+    #[test]
+    fn unused_models_come_from_journal_only() {
+        let models = TempModels::new("journal");
+        models.add_model("stale");
+        models.add_model("daily");
+        models.add_model("unseen");
+        let now = unix_now();
+        models.set_usage(&[("stale", now - 60 * 24 * 3600), ("daily", now - 3600)]);
+
+        let report = cleanup(&models.0, &unused_for(30, true)).unwrap();
+        let paths: Vec<&Path> = report.items.iter().map(|i| i.path.as_path()).collect();
+        // Старые файлы «unseen» не делают её неиспользуемой.
+        assert_eq!(paths, [models.0.join("stale").as_path()]);
+        assert!(!read_usage(&models.0).contains_key("unseen"));
+
+        let report = cleanup(&models.0, &unused_for(30, false)).unwrap();
+        assert_eq!(report.items.len(), 1);
+        assert!(!models.0.join("stale").exists());
+        assert!(models.0.join("daily").exists());
+        assert!(models.0.join("unseen").exists());
+        // Очистка отметила модель без записи: срок пошёл с этого момента.
+        assert!(read_usage(&models.0).get("unseen").is_some_and(|t| *t >= now));
+    }
+}
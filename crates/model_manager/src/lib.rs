@@ -1,7 +1,18 @@
-//! Model Manager — загрузка, хранение, версии моделей.
+//! Model Manager — каталог установленных моделей: metadata.json, размеры, версии, проверка,
+//! учёт места на диске и очистка.
 //!
 //! Работает только через AI Runtime. Не общается с UI напрямую.
 
+mod disk;
 mod manager;
+mod usage;
 
-pub use manager::{ModelInfo, ModelManager, ModelManagerError};
+pub use disk::{
+    cleanup, disk_usage, CleanupItem, CleanupOptions, CleanupReport, FileKind, FileUsage,
+    ModelDiskUsage,
+};
+pub use usage::{read_usage, record_usage, USAGE_FILE};
+pub use manager::{
+    quick_check, ModelFile, ModelInfo, ModelManager, ModelManagerError, ModelMetadata,
+    VerificationState, METADATA_FILE,
+};
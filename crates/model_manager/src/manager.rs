@@ -1,29 +1,108 @@
-//! Управление моделями: список, загрузка, проверка ресурсов.
+//! Каталог установленных моделей: сканирование models_dir, metadata.json, размеры, проверка.
+//!
+//! Скачивание — в провайдерах (local_provider); каталог видит результат на диске.
 
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 
+/// Файл с описанием модели в её директории.
+pub const METADATA_FILE: &str = "metadata.json";
+/// Магические байты заголовка GGUF.
+const GGUF_MAGIC: &[u8; 4] = b"GGUF";
+
 #[derive(Error, Debug)]
 pub enum ModelManagerError {
     #[error("Model not found: {0}")]
     NotFound(String),
-    #[error("Download failed: {0}")]
-    DownloadFailed(String),
-    #[error("Insufficient resources: {0}")]
-    InsufficientResources(String),
+    #[error("Invalid metadata: {0}")]
+    InvalidMetadata(String),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Файл весов модели.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModelFile {
+    pub name: String,
+    pub size_bytes: u64,
+    /// Ожидаемый SHA256 (из источника или первой проверки).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+/// Результат проверки файлов модели.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum VerificationState {
+    #[default]
+    Unverified,
+    Verified { verified_at: u64 },
+    Failed { reason: String },
+}
+
+/// Содержимое metadata.json. Все поля кроме name необязательны (старые установки).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModelMetadata {
+    /// model_id провайдера ("deepseek-coder"); пусто — имя директории.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo_id: Option<String>,
+    /// Версия/квантизация ("Q4_K_M").
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    #[serde(default)]
+    pub offline: bool,
+    #[serde(default)]
+    pub files: Vec<ModelFile>,
+    #[serde(default)]
+    pub verification: VerificationState,
 }
 
+impl ModelMetadata {
+    pub fn read(dir: &Path) -> Result<Option<Self>, ModelManagerError> {
+        let path = dir.join(METADATA_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let raw = std::fs::read_to_string(&path)?;
+        serde_json::from_str(&raw)
+            .map(Some)
+            .map_err(|e| ModelManagerError::InvalidMetadata(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn write(&self, dir: &Path) -> Result<(), ModelManagerError> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| ModelManagerError::InvalidMetadata(e.to_string()))?;
+        std::fs::write(dir.join(METADATA_FILE), json)?;
+        Ok(())
+    }
+}
+
+/// Установленная модель в каталоге.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelInfo {
     pub id: String,
     pub name: String,
+    /// Директория модели.
+    pub path: PathBuf,
+    /// Суммарный размер GGUF-файлов.
     pub size_bytes: u64,
-    pub required_ram_gb: u32,
-    pub supports_gpu: bool,
+    pub version: Option<String>,
+    pub files: Vec<ModelFile>,
+    pub verification: VerificationState,
 }
 
-/// Model Manager. Заглушка: реальная логика — загрузка, кэш, проверка RAM/GPU.
+/// Каталог моделей в models_dir (по поддиректории на модель).
 pub struct ModelManager {
     models_dir: PathBuf,
 }
@@ -33,24 +112,70 @@ impl ModelManager {
         Self { models_dir }
     }
 
-    /// Список доступных (загруженных) моделей.
-    pub fn list_available(&self) -> Vec<ModelInfo> {
-        // Заглушка: пустой список.
-        let _ = &self.models_dir;
-        Vec::new()
+    /// Установленные модели (директории с GGUF-файлами), по id.
+    pub fn list_installed(&self) -> Vec<ModelInfo> {
+        let Ok(rd) = std::fs::read_dir(&self.models_dir) else {
+            return Vec::new();
+        };
+        let mut out: Vec<ModelInfo> = rd
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().is_dir())
+            .filter_map(|e| match scan_model_dir(&e.path()) {
+                Ok(info) => info,
+                Err(err) => {
+                    tracing::warn!(dir = %e.path().display(), error = %err, "model dir skipped");
+                    None
+                }
+            })
+            .collect();
+        out.sort_by(|a, b| a.id.cmp(&b.id));
+        out
     }
 
-    /// Проверка, загружена ли модель.
-    pub fn is_loaded(&self, model_id: &str) -> bool {
-        let _ = model_id;
-        false
+    /// Модель по id (model_id из metadata.json или имя директории).
+    pub fn get(&self, model_id: &str) -> Option<ModelInfo> {
+        self.list_installed().into_iter().find(|m| m.id == model_id)
     }
 
-    /// Запуск загрузки модели. Заглушка.
-    pub async fn download(&self, _model_id: &str) -> Result<(), ModelManagerError> {
-        Err(ModelManagerError::DownloadFailed(
-            "Download not implemented".into(),
-        ))
+    pub fn is_installed(&self, model_id: &str) -> bool {
+        self.get(model_id).is_some()
+    }
+
+    /// Полная проверка: заголовок GGUF, размеры, SHA256. Без ожидаемой суммы — она
+    /// вычисляется и запоминается. Результат сохраняется в metadata.json.
+    pub fn verify(&self, model_id: &str) -> Result<VerificationState, ModelManagerError> {
+        let info = self
+            .get(model_id)
+            .ok_or_else(|| ModelManagerError::NotFound(model_id.to_string()))?;
+        let mut metadata = ModelMetadata::read(&info.path)?.unwrap_or_else(|| ModelMetadata {
+            id: Some(info.id.clone()),
+            name: info.name.clone(),
+            ..ModelMetadata::default()
+        });
+        if metadata.files.is_empty() {
+            metadata.files = info.files.clone();
+        }
+
+        let state = match verify_files(&info.path, &mut metadata.files, true) {
+            Ok(()) => VerificationState::Verified {
+                verified_at: unix_now(),
+            },
+            Err(reason) => VerificationState::Failed { reason },
+        };
+        metadata.verification = state.clone();
+        metadata.write(&info.path)?;
+        tracing::info!(model = %model_id, state = ?state, "model verified");
+        Ok(state)
+    }
+
+    /// Удаляет директорию модели. Возвращает освобождённые байты.
+    pub fn delete(&self, model_id: &str) -> Result<u64, ModelManagerError> {
+        let info = self
+            .get(model_id)
+            .ok_or_else(|| ModelManagerError::NotFound(model_id.to_string()))?;
+        std::fs::remove_dir_all(&info.path)?;
+        tracing::info!(model = %model_id, bytes = info.size_bytes, "model deleted");
+        Ok(info.size_bytes)
     }
 
     /// Путь к директории моделей.
@@ -58,3 +183,129 @@ impl ModelManager {
         &self.models_dir
     }
 }
+
+/// Быстрая проверка перед загрузкой: заголовок GGUF и размеры из metadata.json, без SHA256.
+/// Модель с проваленной полной проверкой не проходит до повторной проверки.
+pub fn quick_check(dir: &Path) -> Result<(), String> {
+    let metadata = ModelMetadata::read(dir).map_err(|e| e.to_string())?;
+    let mut files = match metadata {
+        Some(ref m) => {
+            if let VerificationState::Failed { ref reason } = m.verification {
+                return Err(reason.clone());
+            }
+            m.files.clone()
+        }
+        None => Vec::new(),
+    };
+    if files.is_empty() {
+        files = gguf_files(dir).map_err(|e| e.to_string())?;
+    }
+    verify_files(dir, &mut files, false)
+}
+
+/// Описание модели по её директории. None — в директории нет GGUF.
+fn scan_model_dir(dir: &Path) -> Result<Option<ModelInfo>, ModelManagerError> {
+    let on_disk = gguf_files(dir)?;
+    if on_disk.is_empty() {
+        return Ok(None);
+    }
+    let dir_name = dir
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let metadata = ModelMetadata::read(dir)?.unwrap_or_default();
+    let files = if metadata.files.is_empty() {
+        on_disk.clone()
+    } else {
+        metadata.files.clone()
+    };
+    Ok(Some(ModelInfo {
+        id: metadata.id.clone().unwrap_or_else(|| dir_name.clone()),
+        name: if metadata.name.is_empty() {
+            dir_name
+        } else {
+            metadata.name.clone()
+        },
+        path: dir.to_path_buf(),
+        size_bytes: on_disk.iter().map(|f| f.size_bytes).sum(),
+        version: metadata.version,
+        files,
+        verification: metadata.verification,
+    }))
+}
+
+/// GGUF-файлы директории (без контрольных сумм), по имени.
+fn gguf_files(dir: &Path) -> Result<Vec<ModelFile>, ModelManagerError> {
+    let mut files: Vec<ModelFile> = std::fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().map(|x| x == "gguf").unwrap_or(false))
+        .filter_map(|e| {
+            let size_bytes = e.metadata().ok()?.len();
+            Some(ModelFile {
+                name: e.file_name().to_string_lossy().to_string(),
+                size_bytes,
+                sha256: None,
+            })
+        })
+        .collect();
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(files)
+}
+
+/// Проверяет файлы; при `full` сверяет (или заполняет) SHA256. Err — причина для пользователя.
+fn verify_files(dir: &Path, files: &mut [ModelFile], full: bool) -> Result<(), String> {
+    if files.is_empty() {
+        return Err("no GGUF files".to_string());
+    }
+    for file in files.iter_mut() {
+        let path = dir.join(&file.name);
+        let len = std::fs::metadata(&path)
+            .map_err(|_| format!("{}: missing", file.name))?
+            .len();
+        if len != file.size_bytes {
+            return Err(format!(
+                "{}: size {} != expected {}",
+                file.name, len, file.size_bytes
+            ));
+        }
+        let mut magic = [0u8; 4];
+        std::fs::File::open(&path)
+            .and_then(|mut f| f.read_exact(&mut magic))
+            .map_err(|e| format!("{}: {}", file.name, e))?;
+        if &magic != GGUF_MAGIC {
+            return Err(format!("{}: not a GGUF file", file.name));
+        }
+        if full {
+            let actual = sha256_file(&path).map_err(|e| format!("{}: {}", file.name, e))?;
+            match file.sha256 {
+                Some(ref expected) if !expected.eq_ignore_ascii_case(&actual) => {
+                    return Err(format!("{}: SHA256 mismatch", file.name));
+                }
+                Some(_) => {}
+                None => file.sha256 = Some(actual),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1024 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,57 @@
+//! Журнал использования моделей: model_id → время последней загрузки (models_dir/usage.json).
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::manager::{unix_now, ModelManagerError};
+
+/// Файл журнала в models_dir.
+pub const USAGE_FILE: &str = "usage.json";
+
+/// Запись журнала — чтение, изменение и запись целиком; параллельные загрузки моделей
+/// не должны терять отметки друг друга.
+static USAGE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Время последнего использования моделей (unix-секунды).
+pub fn read_usage(models_dir: &Path) -> HashMap<String, u64> {
+    std::fs::read_to_string(models_dir.join(USAGE_FILE))
+        .ok()
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .unwrap_or_default()
+}
+
+/// Отмечает использование модели сейчас.
+pub fn record_usage(models_dir: &Path, model_id: &str) -> Result<(), ModelManagerError> {
+    update_usage(models_dir, |usage| {
+        usage.insert(model_id.to_string(), unix_now());
+    })
+}
+
+/// Отмечает сейчас модели, которых нет в журнале (установлены до него или вручную): срок
+/// неиспользования отсчитывается с первой встречи, а не с времени изменения файлов.
+pub fn record_first_seen(models_dir: &Path, model_ids: &[String]) -> Result<(), ModelManagerError> {
+    update_usage(models_dir, |usage| {
+        let now = unix_now();
+        for id in model_ids {
+            usage.entry(id.clone()).or_insert(now);
+        }
+    })
+}
+
+fn update_usage(
+    models_dir: &Path,
+    change: impl FnOnce(&mut HashMap<String, u64>),
+) -> Result<(), ModelManagerError> {
+    let _guard = USAGE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut usage = read_usage(models_dir);
+    change(&mut usage);
+    let json = serde_json::to_string_pretty(&usage)
+        .map_err(|e| ModelManagerError::InvalidMetadata(e.to_string()))?;
+    std::fs::create_dir_all(models_dir)?;
+    // Через временный файл: читатель не увидит журнал записанным наполовину.
+    let tmp = models_dir.join(format!("{}.tmp", USAGE_FILE));
+    std::fs::write(&tmp, json)?;
+    std::fs::rename(&tmp, models_dir.join(USAGE_FILE))?;
+    Ok(())
+}
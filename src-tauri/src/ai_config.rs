@@ -1,6 +1,8 @@
 //! AI Config — провайдеры и активный провайдер (~/.kengaide/ai_config.json).
 
+use ai_runtime::{ApprovalPolicy, PlanningPolicy};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 const CONFIG_FILE: &str = "ai_config.json";
@@ -9,6 +11,49 @@ const CONFIG_FILE: &str = "ai_config.json";
 pub struct AiConfig {
     pub providers: Vec<ProviderEntry>,
     pub active_provider_id: Option<String>,
+    /// Через сколько секунд простоя выгружать локальную модель из памяти (0 — не выгружать).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_idle_timeout_secs: Option<u64>,
+    /// Параметры inference локальных моделей: model_id → настройки.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub local_models: HashMap<String, LocalModelSettings>,
+    /// Путь к GGUF-модели эмбеддингов для семантического индекса проекта; пусто — индекс выключен.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding_model_path: Option<String>,
+    /// Режим подтверждения изменений агента.
+    #[serde(default)]
+    pub agent_approval: ApprovalPolicy,
+    /// Фаза планирования агента.
+    #[serde(default)]
+    pub agent_planning: PlanningPolicy,
+    /// Проверять правки агента сборкой/тестами проекта (команды — verification.json).
+    #[serde(default)]
+    pub agent_verification: bool,
+}
+
+/// Параметры inference локальной модели. Пустое поле — значение по умолчанию.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LocalModelSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_size: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n_threads: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n_batch: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub use_mmap: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub use_mlock: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_parallel: Option<usize>,
+    /// model_id черновой модели для speculative decoding (например, "smollm2").
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub draft_model: Option<String>,
+    /// Токенов черновика за шаг.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub draft_tokens: Option<usize>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1,16 +1,24 @@
 //! Tauri commands — IPC между UI и Backend.
 
 use ai_providers::{ApiProvider, AiChunk, AiProvider, GenerateOptions};
-use ai_runtime::{run_agent_loop, AgentProgress, AiResponse, ChunkEmitter};
+use ai_runtime::{
+    run_agent_loop, AgentApproval, AgentProgress, AgentSessionOptions, AiResponse, ApprovalDecision,
+    AgentPlanning, ApprovalPolicy, ChangeSelection, ChangeSet, ChunkEmitter, PendingApproval,
+    PendingPlan, PlanDecision, PlanningPolicy, StagedChange, load_verification_config,
+};
 use backend_core::command_router::AiRequest;
+use backend_core::fs::{ProjectWalker, WalkEntry};
 use backend_core::{
-    create_project_from_template, ensure_audit_dir, ensure_logs_dir, list_sessions,
-    read_session_events, rollback_patch, TEMPLATES,
+    append_audit_event, create_project_from_template, ensure_audit_dir, ensure_logs_dir,
+    list_sessions, list_snapshots, list_transcripts, load_transcript, read_session_events,
+    restore_snapshot, rollback_patch, selection_lines, AuditEvent, ReferenceResolver,
+    RestoreReport, SessionSnapshot, SnapshotManifest, TranscriptSummary, TEMPLATES,
 };
+use context_manager::SymbolIndex;
 
-use crate::ai_config::{load_config, save_config, ProviderEntry};
+use crate::ai_config::{load_config, save_config, LocalModelSettings, ProviderEntry};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tauri::{Emitter, State};
 
@@ -30,6 +38,8 @@ pub struct AiModelSelectedPayload {
     pub request_id: String,
     pub role: String,
     pub model_id: String,
+    /// Что не поместилось в окно модели.
+    pub context_omitted: Vec<context_manager::OmittedItem>,
 }
 
 /// Payload события agent_progress: request_id + прогресс (tool_call, tool_result, done).
@@ -72,8 +82,8 @@ pub struct ProjectTreeNode {
     pub children: Option<Vec<ProjectTreeNode>>,
 }
 
-/// Директории, которые не показываем в дереве (тяжёлые/служебные).
-const SKIP_DIRS: &[&str] = &[".git", "node_modules", "target", "__pycache__", ".next", "dist", "build", ".venv", "venv"];
+/// Скрытые директории, которые показываем в дереве.
+const TREE_VISIBLE_HIDDEN: &[&str] = &[".cursor", ".vscode", ".kengaide"];
 
 /// Статус Git для Status Bar: ветка и количество изменений.
 #[derive(Debug, Clone, Serialize)]
@@ -235,6 +245,47 @@ pub async fn rollback_patches(
     Ok(ok_count)
 }
 
+/// Снимки сессий агента открытого проекта (новые первыми).
+#[tauri::command]
+pub async fn list_session_snapshots(state: State<'_, AppState>) -> Result<Vec<SnapshotManifest>, String> {
+    let root = state
+        .project
+        .read()
+        .await
+        .current()
+        .map(|p| p.root.clone())
+        .ok_or("Нет открытого проекта")?;
+    Ok(list_snapshots(&root))
+}
+
+/// Откатывает все изменения сессии агента по снимку. Файлы, изменённые после сессии,
+/// возвращаются в conflicts и не трогаются без force.
+#[tauri::command]
+pub async fn restore_session_snapshot(
+    state: State<'_, AppState>,
+    session_id: String,
+    force: bool,
+) -> Result<RestoreReport, String> {
+    let root = state
+        .project
+        .read()
+        .await
+        .current()
+        .map(|p| p.root.clone())
+        .ok_or("Нет открытого проекта")?;
+    let report = restore_snapshot(&root, &session_id, force).map_err(|e| e.to_string())?;
+    append_audit_event(
+        Some(&root),
+        &session_id,
+        &AuditEvent::SnapshotRestored {
+            files: report.restored.clone(),
+            conflicts: report.conflicts.clone(),
+        },
+    );
+    refresh_indexes(&state, &root, report.restored.iter().map(PathBuf::from).collect()).await;
+    Ok(report)
+}
+
 /// Список session_id в аудите (E6).
 #[tauri::command]
 pub async fn list_audit_sessions(state: State<'_, AppState>) -> Result<Vec<String>, String> {
@@ -305,79 +356,56 @@ pub async fn get_project_tree(state: State<'_, AppState>) -> Result<Option<Vec<P
             None => return Ok(None),
         }
     };
-    let tree = tokio::task::spawn_blocking(move || {
-        build_tree(&root, &root, 0, &mut 0)
-    })
+    let tree = tokio::task::spawn_blocking(move || build_tree(&root))
     .await
     .map_err(|e| e.to_string())?;
     Ok(Some(tree))
 }
 
-fn build_tree(
-    root: &std::path::Path,
-    dir: &std::path::Path,
-    depth: usize,
-    count: &mut usize,
-) -> Vec<ProjectTreeNode> {
-    if depth >= MAX_TREE_DEPTH || *count >= MAX_TREE_NODES {
-        return Vec::new();
-    }
-    let read_dir = match std::fs::read_dir(dir) {
-        Ok(rd) => rd,
-        Err(_) => return Vec::new(),
-    };
-    let mut entries: Vec<_> = read_dir
-        .filter_map(|e| e.ok())
-        .map(|e| {
-            let path = e.path();
-            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("").to_string();
-            let is_dir = e.file_type().map(|ft| ft.is_dir()).unwrap_or(false);
-            (name, path, is_dir)
-        })
+/// Дерево для UI: обход с учётом .gitignore/.kengaide/ignore, не больше MAX_TREE_NODES узлов.
+fn build_tree(root: &std::path::Path) -> Vec<ProjectTreeNode> {
+    let entries: Vec<WalkEntry> = ProjectWalker::new(root)
+        .allow_hidden(TREE_VISIBLE_HIDDEN)
+        .max_depth(MAX_TREE_DEPTH)
+        .walk()
+        .take(MAX_TREE_NODES)
         .collect();
-    entries.sort_by(|a, b| {
-        let a_dir = a.2;
-        let b_dir = b.2;
-        match (a_dir, b_dir) {
-            (true, false) => std::cmp::Ordering::Less,
-            (false, true) => std::cmp::Ordering::Greater,
-            _ => a.0.to_lowercase().cmp(&b.0.to_lowercase()),
-        }
-    });
+    nest_tree(&entries, &mut 0, 1)
+}
+
+/// Собирает узлы уровня `depth` из плоского обхода в глубину; директории первыми.
+fn nest_tree(entries: &[WalkEntry], next: &mut usize, depth: usize) -> Vec<ProjectTreeNode> {
     let mut out = Vec::new();
-    for (name, path, is_dir) in entries {
-        if *count >= MAX_TREE_NODES {
-            break;
-        }
-        if name.starts_with('.') && name != ".cursor" && name != ".vscode" && name != ".kengaide" {
-            continue;
-        }
-        let path_str = path
-            .strip_prefix(root)
-            .map(|p| p.to_string_lossy().into_owned())
-            .unwrap_or_else(|_| path.to_string_lossy().into_owned());
-        if is_dir {
-            if SKIP_DIRS.contains(&name.as_str()) {
-                continue;
-            }
-            *count += 1;
-            let children = build_tree(root, &path, depth + 1, count);
+    while let Some(entry) = entries.get(*next).filter(|e| e.depth == depth) {
+        *next += 1;
+        let name = entry
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let path = entry.path.to_string_lossy().into_owned();
+        if entry.is_dir {
+            let children = nest_tree(entries, next, depth + 1);
             out.push(ProjectTreeNode {
                 name,
-                path: path_str,
+                path,
                 kind: "dir".to_string(),
                 children: if children.is_empty() { None } else { Some(children) },
             });
         } else {
-            *count += 1;
             out.push(ProjectTreeNode {
                 name,
-                path: path_str,
+                path,
                 kind: "file".to_string(),
                 children: None,
             });
         }
     }
+    out.sort_by(|a, b| match (a.kind == "dir", b.kind == "dir") {
+        (true, false) => std::cmp::Ordering::Less,
+        (false, true) => std::cmp::Ordering::Greater,
+        _ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+    });
     out
 }
 
@@ -445,14 +473,128 @@ pub async fn write_project_file(
         .await
         .map_err(|e| e.to_string())?
         .map_err(|e| format!("Ошибка записи: {}", e))?;
+    refresh_indexes(&state, &root, vec![rel]).await;
     Ok(())
 }
 
+/// Переиндексирует изменённые на диске файлы проекта (символы и семантический индекс) в фоне.
+/// Пути — относительно корня; удалённые файлы убираются из индексов.
+async fn refresh_indexes(state: &AppState, root: &Path, paths: Vec<PathBuf>) {
+    if paths.is_empty() {
+        return;
+    }
+    if let Some(index) = state
+        .ai_controller
+        .symbol_index()
+        .await
+        .filter(|i| i.root() == root)
+    {
+        let paths = paths.clone();
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = index.update_files(&paths) {
+                tracing::warn!(error = %e, "symbol index update failed");
+            }
+        });
+    }
+    if let Some(index) = state
+        .ai_controller
+        .semantic_index()
+        .await
+        .filter(|i| i.root() == root)
+    {
+        tokio::spawn(async move {
+            if let Err(e) = index.update_files(&paths).await {
+                tracing::warn!(error = %e, "semantic index update failed");
+            }
+        });
+    }
+}
+
+/// Подключает индекс символов открытого проекта и обновляет его в фоне.
+async fn attach_symbol_index(state: &AppState, root: PathBuf) {
+    let index = Arc::new(SymbolIndex::open(&root));
+    state
+        .ai_controller
+        .set_symbol_index(Some(Arc::clone(&index)))
+        .await;
+    tokio::task::spawn_blocking(move || match index.update() {
+        Ok(stats) => tracing::info!(?stats, "symbol index updated"),
+        Err(e) => tracing::warn!(error = %e, "symbol index update failed"),
+    });
+}
+
+/// Подключает семантический индекс открытого проекта (если задана модель эмбеддингов)
+/// и обновляет его в фоне: первый раз — полностью, дальше — только изменённые файлы.
+async fn attach_semantic_index(state: &AppState, root: PathBuf) {
+    #[cfg(feature = "local")]
+    {
+        let Some(model_path) = load_config().embedding_model_path.filter(|p| !p.is_empty()) else {
+            state.ai_controller.set_semantic_index(None).await;
+            return;
+        };
+        let embedder = Arc::new(local_provider::LocalEmbeddingProvider::new(PathBuf::from(
+            model_path,
+        )));
+        let index = Arc::new(context_manager::SemanticIndex::open(&root, embedder));
+        state
+            .ai_controller
+            .set_semantic_index(Some(Arc::clone(&index)))
+            .await;
+        tokio::spawn(async move {
+            match index.update().await {
+                Ok(stats) => tracing::info!(?stats, "semantic index updated"),
+                Err(e) => tracing::warn!(error = %e, "semantic index update failed"),
+            }
+        });
+        return;
+    }
+    #[cfg(not(feature = "local"))]
+    {
+        let _ = root;
+        state.ai_controller.set_semantic_index(None).await;
+    }
+}
+
+/// Подхватывает непринятые изменения агента, сохранённые в .kengaide/staged/ открытого проекта.
+/// Слои другого проекта убираются: их сессии сохраняют их на диск сами.
+async fn restore_change_sets(state: &AppState, root: PathBuf) {
+    // Снимок сессии — чтобы принятое после перезапуска можно было откатить.
+    let restored = tokio::task::spawn_blocking(move || {
+        let sets = ChangeSet::load_all(&root);
+        for set in &sets {
+            let Some(session_id) = set.session_id() else {
+                continue;
+            };
+            match SessionSnapshot::new(&root, session_id) {
+                Ok(snapshot) => set.attach_snapshot(Arc::new(snapshot)),
+                Err(e) => tracing::warn!(error = %e, "session snapshot unavailable"),
+            }
+        }
+        sets
+    })
+    .await
+    .unwrap_or_default();
+    let mut change_sets = state.change_sets.write().await;
+    change_sets.clear();
+    for set in restored {
+        if let Some(session_id) = set.session_id() {
+            change_sets.insert(session_id.to_string(), Arc::new(set));
+        }
+    }
+}
+
 #[tauri::command]
 pub async fn open_project(state: State<'_, AppState>, payload: OpenProjectPayload) -> Result<(), String> {
     let path = PathBuf::from(&payload.path);
-    let mut project = state.project.write().await;
-    project.open(path).map_err(|e| e.to_string())?;
+    state
+        .project
+        .write()
+        .await
+        .open(path.clone())
+        .map_err(|e| e.to_string())?;
+    attach_symbol_index(&state, path.clone()).await;
+    attach_semantic_index(&state, path.clone()).await;
+    restore_change_sets(&state, path).await;
     Ok(())
 }
 
@@ -475,8 +617,15 @@ pub async fn open_project_dialog(state: State<'_, AppState>) -> Result<Option<St
         None => return Ok(None),
     };
     let path_buf = PathBuf::from(&path);
-    let mut project = state.project.write().await;
-    project.open(path_buf).map_err(|e| e.to_string())?;
+    state
+        .project
+        .write()
+        .await
+        .open(path_buf.clone())
+        .map_err(|e| e.to_string())?;
+    attach_symbol_index(&state, path_buf.clone()).await;
+    attach_semantic_index(&state, path_buf.clone()).await;
+    restore_change_sets(&state, path_buf).await;
     Ok(Some(path))
 }
 
@@ -520,8 +669,15 @@ pub async fn create_project(
     .map_err(|e| e.to_string())?;
 
     let path_str = project_path.to_string_lossy().into_owned();
-    let mut project = state.project.write().await;
-    project.open(project_path).map_err(|e| e.to_string())?;
+    state
+        .project
+        .write()
+        .await
+        .open(project_path.clone())
+        .map_err(|e| e.to_string())?;
+    attach_symbol_index(&state, project_path.clone()).await;
+    attach_semantic_index(&state, project_path.clone()).await;
+    restore_change_sets(&state, project_path).await;
     Ok(path_str)
 }
 
@@ -650,16 +806,57 @@ pub struct AiRequestPayload {
     pub selection: Option<String>,
 }
 
+impl AiRequestPayload {
+    /// Резолвер ссылок запроса: индекс символов проекта, текущий файл и выделенные строки.
+    fn resolver<'a>(&self, root: &'a Path, symbols: Option<&'a SymbolIndex>) -> ReferenceResolver<'a> {
+        let mut resolver = ReferenceResolver::new(root);
+        if let Some(index) = symbols {
+            resolver = resolver.with_symbols(index);
+        }
+        if let Some(path) = &self.current_file_path {
+            let lines = self
+                .current_file_content
+                .as_deref()
+                .zip(self.selection.as_deref())
+                .and_then(|(content, selection)| selection_lines(content, selection));
+            resolver = resolver.with_current_file(Path::new(path), lines);
+        }
+        resolver
+    }
+}
+
+/// Индекс символов, если он построен для этого проекта.
+async fn project_symbol_index(state: &AppState, project_root: Option<&Path>) -> Option<Arc<SymbolIndex>> {
+    state
+        .ai_controller
+        .symbol_index()
+        .await
+        .filter(|index| project_root == Some(index.root()))
+}
+
+/// Валидирует запрос; ссылки @file:/@folder:/@symbol:/@git:... разрешаются относительно открытого проекта.
+async fn validate_request(
+    state: &AppState,
+    payload: &AiRequestPayload,
+    project_root: Option<&Path>,
+) -> Result<(), String> {
+    let symbols = project_symbol_index(state, project_root).await;
+    let resolver = project_root.map(|root| payload.resolver(root, symbols.as_deref()));
+    state
+        .router
+        .validate(&payload.request, resolver.as_ref())
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn ai_request(state: State<'_, AppState>, payload: AiRequestPayload) -> Result<AiResponse, String> {
-    state.router.validate(&payload.request).map_err(|e| e.to_string())?;
-
     let project_root = state
         .project
         .read()
         .await
         .current()
         .map(|p| p.root.clone());
+    validate_request(&state, &payload, project_root.as_deref()).await?;
 
     let current_file = payload
         .current_file_path
@@ -687,14 +884,13 @@ pub async fn ai_request_stream(
     state: State<'_, AppState>,
     payload: AiRequestPayload,
 ) -> Result<String, String> {
-    state.router.validate(&payload.request).map_err(|e| e.to_string())?;
-
     let project_root = state
         .project
         .read()
         .await
         .current()
         .map(|p| p.root.clone());
+    validate_request(&state, &payload, project_root.as_deref()).await?;
 
     let current_file = payload
         .current_file_path
@@ -738,6 +934,7 @@ pub async fn ai_request_stream(
             request_id: result.request_id.clone(),
             role: result.role.as_str().to_string(),
             model_id: result.model_id,
+            context_omitted: result.context_omitted,
         },
     );
 
@@ -799,23 +996,66 @@ pub async fn ai_agent_request(
     state: State<'_, AppState>,
     payload: AiRequestPayload,
 ) -> Result<String, String> {
-    state.router.validate(&payload.request).map_err(|e| e.to_string())?;
-
-    let (message, project_root) = match &payload.request {
-        AiRequest::Agent { message } => {
-            let root = state
-                .project
-                .read()
-                .await
-                .current()
-                .map(|p| p.root.clone());
-            (message.clone(), root)
-        }
+    let message = match &payload.request {
+        AiRequest::Agent { message, .. } => message.clone(),
         _ => return Err("ai_agent_request requires request.type: agent".to_string()),
     };
+    let project_root = state
+        .project
+        .read()
+        .await
+        .current()
+        .map(|p| p.root.clone())
+        .ok_or("Open a project first (Agent needs project root)")?;
+    validate_request(&state, &payload, Some(&project_root)).await?;
+    let message = with_referenced_context(&state, &project_root, message, &payload).await;
+    spawn_agent(app, &state, project_root, message, None).await
+}
 
-    let project_root = project_root.ok_or("Open a project first (Agent needs project root)")?;
+/// Продолжает сохранённую сессию агента новым сообщением ("теперь добавь тесты").
+/// Возвращает request_id; прогресс — по событию "agent_progress" с тем же session_id.
+#[tauri::command]
+pub async fn continue_agent_session(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    session_id: String,
+    message: String,
+) -> Result<String, String> {
+    if message.trim().is_empty() {
+        return Err("Message is empty".to_string());
+    }
+    let project_root = state
+        .project
+        .read()
+        .await
+        .current()
+        .map(|p| p.root.clone())
+        .ok_or("Open a project first (Agent needs project root)")?;
+    load_transcript(&project_root, &session_id).map_err(|e| e.to_string())?;
+    spawn_agent(app, &state, project_root, message, Some(session_id)).await
+}
 
+/// Сессии агента, которые можно продолжить (новые первыми).
+#[tauri::command]
+pub async fn list_agent_sessions(state: State<'_, AppState>) -> Result<Vec<TranscriptSummary>, String> {
+    let root = state
+        .project
+        .read()
+        .await
+        .current()
+        .map(|p| p.root.clone())
+        .ok_or("Нет открытого проекта")?;
+    Ok(list_transcripts(&root))
+}
+
+/// Запускает цикл агента в фоне: новая сессия или продолжение (resume = session_id).
+async fn spawn_agent(
+    app: tauri::AppHandle,
+    state: &AppState,
+    project_root: PathBuf,
+    message: String,
+    resume: Option<String>,
+) -> Result<String, String> {
     let (providers, preferred_id): (Vec<std::sync::Arc<dyn AiProvider>>, Option<String>) = {
         let guard = state.ai_runtime.read().await;
         (
@@ -838,6 +1078,57 @@ pub async fn ai_agent_request(
         );
     });
 
+    let config = load_config();
+    let policy = config.agent_approval;
+    let approval = policy.enabled.then(|| AgentApproval {
+        gate: Arc::clone(&state.approvals),
+        policy,
+    });
+    let planning_policy = config.agent_planning;
+    let planning = planning_policy.enabled.then(|| AgentPlanning {
+        gate: Arc::clone(&state.approvals),
+        policy: planning_policy,
+    });
+    let verification = config
+        .agent_verification
+        .then(|| load_verification_config(Some(project_root.as_path())));
+    // Агент пишет в слой; на диск — после принятия пользователем (accept_staged_changes).
+    // Продолжение сессии работает поверх её непринятых правок: транскрипт ссылается на них.
+    let changes = {
+        let mut change_sets = state.change_sets.write().await;
+        let pending = resume.as_deref().and_then(|session_id| {
+            let key = change_sets
+                .iter()
+                .find(|(_, set)| set.session_id() == Some(session_id))
+                .map(|(key, _)| key.clone());
+            match key {
+                Some(key) => change_sets.remove(&key),
+                None => ChangeSet::load(&project_root, session_id).map(Arc::new),
+            }
+        });
+        let changes = pending.unwrap_or_else(|| Arc::new(ChangeSet::new(project_root.clone())));
+        change_sets.insert(request_id.clone(), Arc::clone(&changes));
+        changes
+    };
+    let options = AgentSessionOptions {
+        approval,
+        changes: Some(Arc::clone(&changes)),
+        resume,
+        planning,
+        verification,
+        semantic_index: state
+            .ai_controller
+            .semantic_index()
+            .await
+            .filter(|i| i.root() == project_root),
+        symbol_index: state
+            .ai_controller
+            .symbol_index()
+            .await
+            .filter(|i| i.root() == project_root),
+    };
+
+    let change_sets = Arc::clone(&state.change_sets);
     let project_root_clone = project_root.clone();
     let preferred_for_spawn = preferred_id.clone();
     tokio::spawn(async move {
@@ -846,11 +1137,22 @@ pub async fn ai_agent_request(
             &project_root_clone,
             &message,
             emitter,
-            50,
             preferred_for_spawn.as_deref(),
+            options,
         )
         .await
         {
+            if !changes.is_empty() {
+                let _ = app.emit(
+                    "agent_progress",
+                    &AgentProgressPayload {
+                        request_id: request_id_for_spawn.clone(),
+                        progress: AgentProgress::ChangesStaged {
+                            changes: changes.changes(),
+                        },
+                    },
+                );
+            }
             let _ = app.emit(
                 "agent_progress",
                 &AgentProgressPayload {
@@ -861,11 +1163,201 @@ pub async fn ai_agent_request(
                 },
             );
         }
+        if changes.is_empty() {
+            change_sets.write().await.remove(&request_id_for_spawn);
+        }
     });
 
     Ok(request_id)
 }
 
+/// Слой правок сессии агента по request_id.
+async fn change_set(state: &AppState, request_id: &str) -> Result<Arc<ChangeSet>, String> {
+    state
+        .change_sets
+        .read()
+        .await
+        .get(request_id)
+        .cloned()
+        .ok_or_else(|| format!("No staged changes for request {}", request_id))
+}
+
+/// Слой незаписанных изменений для UI.
+#[derive(Debug, Serialize)]
+pub struct StagedChangeSet {
+    pub request_id: String,
+    pub changes: Vec<StagedChange>,
+}
+
+/// Все слои с непринятыми изменениями — в том числе восстановленные после перезапуска
+/// (их request_id — session_id сессии).
+#[tauri::command]
+pub async fn list_staged_change_sets(state: State<'_, AppState>) -> Result<Vec<StagedChangeSet>, String> {
+    Ok(state
+        .change_sets
+        .read()
+        .await
+        .iter()
+        .map(|(request_id, set)| StagedChangeSet {
+            request_id: request_id.clone(),
+            changes: set.changes(),
+        })
+        .filter(|s| !s.changes.is_empty())
+        .collect())
+}
+
+/// Незаписанные изменения сессии агента: diff по файлам и hunk'и.
+#[tauri::command]
+pub async fn get_staged_changes(
+    state: State<'_, AppState>,
+    request_id: String,
+) -> Result<Vec<StagedChange>, String> {
+    Ok(change_set(&state, &request_id).await?.changes())
+}
+
+/// Записывает принятые изменения на диск: все (selection = None), по файлам или по hunk'ам.
+/// Возвращает записанные пути; непринятое остаётся в слое.
+#[tauri::command]
+pub async fn accept_staged_changes(
+    state: State<'_, AppState>,
+    request_id: String,
+    selection: Option<Vec<ChangeSelection>>,
+) -> Result<Vec<String>, String> {
+    let changes = change_set(&state, &request_id).await?;
+    let written = match selection {
+        Some(selection) => changes.accept(&selection),
+        None => changes.accept_all(),
+    }
+    .map_err(|e| e.to_string())?;
+    if changes.is_empty() {
+        state.change_sets.write().await.remove(&request_id);
+    }
+    refresh_indexes(&state, changes.root(), written.iter().map(PathBuf::from).collect()).await;
+    Ok(written)
+}
+
+/// Отбрасывает изменения файла или всей сессии (path = None).
+#[tauri::command]
+pub async fn discard_staged_changes(
+    state: State<'_, AppState>,
+    request_id: String,
+    path: Option<String>,
+) -> Result<(), String> {
+    let changes = change_set(&state, &request_id).await?;
+    changes.discard(path.as_deref());
+    if changes.is_empty() {
+        state.change_sets.write().await.remove(&request_id);
+    }
+    Ok(())
+}
+
+/// Решение пользователя по вызову, ожидающему подтверждения (событие ApprovalRequired).
+#[tauri::command]
+pub fn resolve_agent_approval(
+    state: State<'_, AppState>,
+    approval_id: String,
+    decision: ApprovalDecision,
+) -> Result<(), String> {
+    if state.approvals.resolve(&approval_id, decision) {
+        Ok(())
+    } else {
+        Err(format!("No pending approval: {}", approval_id))
+    }
+}
+
+/// Вызовы агентов, ждущие подтверждения (UI, открытый после события ApprovalRequired).
+#[tauri::command]
+pub fn list_pending_approvals(state: State<'_, AppState>) -> Vec<PendingApproval> {
+    state.approvals.pending()
+}
+
+/// Планы агента, ждущие проверки (например, после перезагрузки окна).
+#[tauri::command]
+pub fn list_pending_plans(state: State<'_, AppState>) -> Vec<PendingPlan> {
+    state.approvals.pending_plans()
+}
+
+/// Политика подтверждения изменений агента.
+#[tauri::command]
+pub fn get_agent_approval_policy() -> ApprovalPolicy {
+    load_config().agent_approval
+}
+
+/// Сохраняет политику; действует для следующих запусков агента.
+#[tauri::command]
+pub fn set_agent_approval_policy(policy: ApprovalPolicy) -> Result<(), String> {
+    let mut config = load_config();
+    config.agent_approval = policy;
+    save_config(&config)
+}
+
+/// Решение пользователя по плану агента (событие Plan с review).
+#[tauri::command]
+pub fn resolve_agent_plan(
+    state: State<'_, AppState>,
+    plan_id: String,
+    decision: PlanDecision,
+) -> Result<(), String> {
+    if state.approvals.resolve_plan(&plan_id, decision) {
+        Ok(())
+    } else {
+        Err(format!("No plan awaiting review: {}", plan_id))
+    }
+}
+
+/// Настройки фазы планирования агента.
+#[tauri::command]
+pub fn get_agent_planning_policy() -> PlanningPolicy {
+    load_config().agent_planning
+}
+
+/// Сохраняет настройки планирования; действуют для следующих запусков агента.
+#[tauri::command]
+pub fn set_agent_planning_policy(policy: PlanningPolicy) -> Result<(), String> {
+    let mut config = load_config();
+    config.agent_planning = policy;
+    save_config(&config)
+}
+
+/// Включена ли проверка правок агента сборкой/тестами.
+#[tauri::command]
+pub fn get_agent_verification() -> bool {
+    load_config().agent_verification
+}
+
+/// Включает или выключает проверку правок агента; действует для следующих запусков.
+#[tauri::command]
+pub fn set_agent_verification(enabled: bool) -> Result<(), String> {
+    let mut config = load_config();
+    config.agent_verification = enabled;
+    save_config(&config)
+}
+
+/// Дописывает к задаче агента содержимое явных ссылок, упакованное в лимит контекста.
+async fn with_referenced_context(
+    state: &AppState,
+    project_root: &Path,
+    message: String,
+    payload: &AiRequestPayload,
+) -> String {
+    let references = payload.request.references().unwrap_or_default();
+    if references.is_empty() {
+        return message;
+    }
+    let symbols = project_symbol_index(state, Some(project_root)).await;
+    let resolver = payload.resolver(project_root, symbols.as_deref());
+    let limits = state.ai_runtime.read().await.context_limits().clone();
+    let context = context_manager::ContextBuilder::new(limits)
+        .references(&resolver, &references)
+        .build();
+    let files: Vec<String> = context
+        .extra_files
+        .iter()
+        .map(|f| format!("File: {}\n```\n{}\n```", f.path.display(), f.content))
+        .collect();
+    format!("{}\n\nReferenced context:\n{}", message, files.join("\n\n"))
+}
+
 /// Информация о системе для первого запуска.
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct SystemInfo {
@@ -893,6 +1385,24 @@ pub async fn get_system_info() -> Result<SystemInfo, String> {
     })
 }
 
+/// Preflight железа: RAM, диск в директории моделей, CPU-расширения и ранжированные модели.
+#[cfg(feature = "local")]
+#[tauri::command]
+pub async fn hardware_preflight() -> Result<local_provider::hardware_detect::HardwareReport, String> {
+    let models_dir = local_provider::LocalConfig::default_models_dir();
+    tokio::task::spawn_blocking(move || local_provider::hardware_detect::preflight(&models_dir))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// Без локальных моделей рекомендовать нечего.
+#[cfg(not(feature = "local"))]
+#[tauri::command]
+pub async fn hardware_preflight() -> Result<(), String> {
+    Err("Локальные модели недоступны в этой сборке".to_string())
+}
+
 #[tauri::command]
 pub async fn local_model_status(state: State<'_, AppState>) -> Result<LocalModelStatus, String> {
     #[cfg(feature = "local")]
@@ -939,6 +1449,209 @@ pub async fn local_model_info(state: State<'_, AppState>) -> Result<LocalModelIn
     })
 }
 
+/// Локальная модель, загруженная в память.
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadedLocalModel {
+    pub model_id: String,
+    pub size_bytes: u64,
+    pub idle_secs: u64,
+    pub in_use: bool,
+}
+
+/// Список локальных моделей, загруженных в память.
+#[tauri::command]
+pub async fn list_loaded_models(state: State<'_, AppState>) -> Result<Vec<LoadedLocalModel>, String> {
+    #[cfg(feature = "local")]
+    {
+        let loaded = state.model_pool.list_loaded().await;
+        return Ok(loaded
+            .into_iter()
+            .map(|m| LoadedLocalModel {
+                model_id: m.model_id,
+                size_bytes: m.size_bytes,
+                idle_secs: m.idle_secs,
+                in_use: m.in_use,
+            })
+            .collect());
+    }
+    #[cfg(not(feature = "local"))]
+    {
+        let _ = &state;
+        Ok(Vec::new())
+    }
+}
+
+/// Выгружает локальную модель из памяти. false — модель не была загружена.
+#[tauri::command]
+pub async fn unload_local_model(state: State<'_, AppState>, model_id: String) -> Result<bool, String> {
+    #[cfg(feature = "local")]
+    {
+        return state
+            .model_pool
+            .unload(&model_id)
+            .await
+            .map_err(|e| e.to_string());
+    }
+    #[cfg(not(feature = "local"))]
+    {
+        let _ = (&state, model_id);
+        Ok(false)
+    }
+}
+
+/// Установленные модели из каталога (размер, версия, состояние проверки).
+#[tauri::command]
+pub async fn list_installed_models(state: State<'_, AppState>) -> Result<Vec<model_manager::ModelInfo>, String> {
+    let catalog = state.ai_runtime.read().await.model_catalog();
+    tokio::task::spawn_blocking(move || catalog.list_installed())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Удаляет модель с диска (сначала выгружает из памяти). Возвращает освобождённые байты.
+/// Модель, которая осталась в пуле (например, ещё загружается), не удаляется.
+#[tauri::command]
+pub async fn delete_installed_model(state: State<'_, AppState>, model_id: String) -> Result<u64, String> {
+    #[cfg(feature = "local")]
+    {
+        state
+            .model_pool
+            .unload(&model_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        if state.model_pool.contains(&model_id).await {
+            return Err(format!("Модель {} используется, удаление отменено", model_id));
+        }
+    }
+    let catalog = state.ai_runtime.read().await.model_catalog();
+    tokio::task::spawn_blocking(move || catalog.delete(&model_id))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// Повторная полная проверка файлов модели (SHA256); результат сохраняется в metadata.json.
+#[tauri::command]
+pub async fn verify_installed_model(
+    state: State<'_, AppState>,
+    model_id: String,
+) -> Result<model_manager::VerificationState, String> {
+    let catalog = state.ai_runtime.read().await.model_catalog();
+    tokio::task::spawn_blocking(move || catalog.verify(&model_id))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// Место на диске по директориям моделей: веса, недокачанные и лишние файлы, последнее использование.
+#[tauri::command]
+pub async fn models_disk_usage(state: State<'_, AppState>) -> Result<Vec<model_manager::ModelDiskUsage>, String> {
+    let catalog = state.ai_runtime.read().await.model_catalog();
+    tokio::task::spawn_blocking(move || model_manager::disk_usage(catalog.models_dir()))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Очистка моделей. С dry_run только возвращает список того, что будет удалено.
+/// Загруженные в память модели не трогаются.
+#[tauri::command]
+pub async fn cleanup_models(
+    state: State<'_, AppState>,
+    options: model_manager::CleanupOptions,
+) -> Result<model_manager::CleanupReport, String> {
+    #[cfg(feature = "local")]
+    let mut options = options;
+    #[cfg(feature = "local")]
+    options.protected.extend(
+        state
+            .model_pool
+            .list_loaded()
+            .await
+            .into_iter()
+            .map(|m| m.model_id),
+    );
+    let catalog = state.ai_runtime.read().await.model_catalog();
+    tokio::task::spawn_blocking(move || model_manager::cleanup(catalog.models_dir(), &options))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// Задаёт таймаут простоя, после которого локальная модель выгружается (0 — не выгружать).
+#[tauri::command]
+pub async fn set_model_idle_timeout(state: State<'_, AppState>, timeout_secs: u64) -> Result<(), String> {
+    #[cfg(feature = "local")]
+    state
+        .model_pool
+        .set_idle_timeout((timeout_secs > 0).then(|| std::time::Duration::from_secs(timeout_secs)));
+    #[cfg(not(feature = "local"))]
+    let _ = &state;
+    let mut config = load_config();
+    config.local_idle_timeout_secs = Some(timeout_secs);
+    save_config(&config)
+}
+
+/// Параметры inference локальной модели (context_size, max_tokens, n_threads, n_batch, mmap/mlock).
+#[tauri::command]
+pub fn get_local_model_settings(model_id: String) -> Result<LocalModelSettings, String> {
+    Ok(load_config()
+        .local_models
+        .get(&model_id)
+        .cloned()
+        .unwrap_or_default())
+}
+
+/// Сохраняет параметры inference локальной модели. Применяются после перезапуска IDE.
+#[tauri::command]
+pub fn set_local_model_settings(model_id: String, settings: LocalModelSettings) -> Result<(), String> {
+    #[cfg(feature = "local")]
+    {
+        let mut config = local_provider::LocalConfig::default_config();
+        if let Some(variant) = local_provider::ModelVariant::from_model_id(&model_id) {
+            config.model_variant = variant;
+        }
+        if let Some(ref draft) = settings.draft_model {
+            if local_provider::ModelVariant::from_model_id(draft).is_none() {
+                return Err(format!("Неизвестная черновая модель: {}", draft));
+            }
+        }
+        crate::state::apply_local_settings(&mut config, &settings);
+        config.validate().map_err(|e| e.to_string())?;
+    }
+    let mut config = load_config();
+    config.local_models.insert(model_id, settings);
+    save_config(&config)
+}
+
+/// Путь к GGUF-модели эмбеддингов для семантического индекса.
+#[tauri::command]
+pub fn get_embedding_model_path() -> Option<String> {
+    load_config().embedding_model_path
+}
+
+/// Сохраняет модель эмбеддингов (None — выключить индекс) и переподключает индекс открытого проекта.
+#[tauri::command]
+pub async fn set_embedding_model_path(
+    state: State<'_, AppState>,
+    path: Option<String>,
+) -> Result<(), String> {
+    let path = path.filter(|p| !p.trim().is_empty());
+    if let Some(ref p) = path {
+        if !std::path::Path::new(p).is_file() {
+            return Err(format!("Файл модели не найден: {}", p));
+        }
+    }
+    let mut config = load_config();
+    config.embedding_model_path = path;
+    save_config(&config)?;
+    let root = state.project.read().await.current().map(|p| p.root.clone());
+    match root {
+        Some(root) => attach_semantic_index(&state, root).await,
+        None => state.ai_controller.set_semantic_index(None).await,
+    }
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn start_model_download(
     app: tauri::AppHandle,
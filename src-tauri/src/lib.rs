@@ -4,8 +4,28 @@ mod ai_config;
 mod commands;
 mod state;
 
+/// События `tracing` из крейтов пишутся в ~/.kengaide/logs/runtime.log — рядом с append_log.
+fn init_logging() {
+    let Ok(dir) = backend_core::ensure_logs_dir(None) else {
+        return;
+    };
+    let Ok(file) = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join("runtime.log"))
+    else {
+        return;
+    };
+    let _ = tracing_subscriber::fmt()
+        .with_writer(std::sync::Mutex::new(file))
+        .with_ansi(false)
+        .with_max_level(tracing::Level::INFO)
+        .try_init();
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    init_logging();
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
         .manage(state::AppState::default())
@@ -15,6 +35,7 @@ pub fn run() {
             commands::create_project,
             commands::get_project_templates,
             commands::get_system_info,
+            commands::hardware_preflight,
             commands::list_ai_providers,
             commands::get_active_provider,
             commands::set_active_provider,
@@ -23,6 +44,8 @@ pub fn run() {
             commands::open_mcp_config_folder,
             commands::open_logs_folder,
             commands::rollback_patches,
+            commands::list_session_snapshots,
+            commands::restore_session_snapshot,
             commands::list_audit_sessions,
             commands::get_audit_events,
             commands::open_audit_folder,
@@ -33,11 +56,39 @@ pub fn run() {
             commands::ai_request,
             commands::ai_request_stream,
             commands::ai_agent_request,
+            commands::continue_agent_session,
+            commands::list_agent_sessions,
             commands::ai_cancel,
+            commands::resolve_agent_approval,
+            commands::list_pending_approvals,
+            commands::list_pending_plans,
+            commands::list_staged_change_sets,
+            commands::get_staged_changes,
+            commands::accept_staged_changes,
+            commands::discard_staged_changes,
+            commands::get_agent_approval_policy,
+            commands::set_agent_approval_policy,
+            commands::resolve_agent_plan,
+            commands::get_agent_planning_policy,
+            commands::set_agent_planning_policy,
+            commands::get_agent_verification,
+            commands::set_agent_verification,
             commands::local_model_status,
             commands::local_model_info,
             commands::start_model_download,
             commands::start_model_download_provider,
+            commands::list_loaded_models,
+            commands::unload_local_model,
+            commands::list_installed_models,
+            commands::delete_installed_model,
+            commands::verify_installed_model,
+            commands::models_disk_usage,
+            commands::cleanup_models,
+            commands::set_model_idle_timeout,
+            commands::get_local_model_settings,
+            commands::set_local_model_settings,
+            commands::get_embedding_model_path,
+            commands::set_embedding_model_path,
             commands::git_status,
             commands::get_app_version,
         ])
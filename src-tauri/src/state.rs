@@ -1,17 +1,23 @@
 //! Состояние приложения: Backend, AI Runtime, AiController, провайдеры.
 
 use ai_providers::ApiProvider;
-use ai_runtime::{ensure_model_roles_config, AiController, AiRuntime};
+use ai_runtime::{ensure_model_roles_config, AiController, AiRuntime, ApprovalGate, ChangeSet};
 use backend_core::{CommandRouter, FsService, ProjectService};
 use gigachat_provider::GigaChatProvider;
 #[cfg(feature = "local")]
-use local_provider::{LocalConfig, LocalProvider, ModelVariant};
+use local_provider::{
+    DraftConfig, LocalConfig, LocalProvider, ModelPool, ModelVariant, PoolConfig,
+    DEFAULT_DRAFT_TOKENS, DEFAULT_IDLE_TIMEOUT_SECS,
+};
 use model_manager::ModelManager;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
 use crate::ai_config::load_config;
+#[cfg(feature = "local")]
+use crate::ai_config::LocalModelSettings;
 
 pub struct AppState {
     pub fs: Arc<FsService>,
@@ -19,8 +25,102 @@ pub struct AppState {
     pub router: Arc<CommandRouter>,
     pub ai_runtime: Arc<RwLock<AiRuntime>>,
     pub ai_controller: Arc<AiController>,
+    /// Ожидающие подтверждения вызовы агента.
+    pub approvals: Arc<ApprovalGate>,
+    /// Незаписанные изменения сессий агента: request_id (у восстановленных — session_id) → слой правок.
+    pub change_sets: Arc<RwLock<HashMap<String, Arc<ChangeSet>>>>,
     #[cfg(feature = "local")]
     pub local_providers: Vec<Arc<LocalProvider>>,
+    /// Общий пул загруженных локальных моделей.
+    #[cfg(feature = "local")]
+    pub model_pool: Arc<ModelPool>,
+}
+
+/// LocalConfig модели с пользовательскими настройками из ai_config.json.
+/// Некорректные настройки игнорируются (с предупреждением в лог).
+#[cfg(feature = "local")]
+pub(crate) fn local_config(
+    variant: ModelVariant,
+    settings: &std::collections::HashMap<String, LocalModelSettings>,
+) -> LocalConfig {
+    let mut config = LocalConfig::default_config();
+    config.model_variant = variant;
+    let Some(s) = settings.get(variant.model_id()) else {
+        return config;
+    };
+    let mut custom = config.clone();
+    apply_local_settings(&mut custom, s);
+    if let Some(draft) = custom.draft.as_mut() {
+        *draft =
+            DraftConfig::from_config(&draft_load_config(draft.variant, settings), draft.n_draft);
+    }
+    match custom.validate() {
+        Ok(()) => custom,
+        Err(e) => {
+            tracing::warn!(model = %variant.model_id(), error = %e, "local model settings ignored");
+            config
+        }
+    }
+}
+
+/// Параметры загрузки черновой модели — из её собственных настроек, как у её провайдера.
+/// Черновик самой черновой модели не учитывается.
+#[cfg(feature = "local")]
+fn draft_load_config(
+    variant: ModelVariant,
+    settings: &HashMap<String, LocalModelSettings>,
+) -> LocalConfig {
+    let mut config = LocalConfig::default_config();
+    config.model_variant = variant;
+    let Some(s) = settings.get(variant.model_id()) else {
+        return config;
+    };
+    let mut custom = config.clone();
+    apply_local_settings(&mut custom, s);
+    custom.draft = None;
+    if custom.validate().is_ok() {
+        custom
+    } else {
+        config
+    }
+}
+
+/// Переносит заданные поля настроек в LocalConfig.
+#[cfg(feature = "local")]
+pub(crate) fn apply_local_settings(config: &mut LocalConfig, s: &LocalModelSettings) {
+    if let Some(v) = s.context_size {
+        config.context_size = v;
+    }
+    if let Some(v) = s.max_tokens {
+        config.max_tokens = v;
+    }
+    if s.n_threads.is_some() {
+        config.n_threads = s.n_threads;
+    }
+    if let Some(v) = s.n_batch {
+        config.n_batch = v;
+    }
+    if let Some(v) = s.use_mmap {
+        config.use_mmap = v;
+    }
+    if let Some(v) = s.use_mlock {
+        config.use_mlock = v;
+    }
+    if let Some(v) = s.max_parallel {
+        config.max_parallel = v;
+    }
+    if let Some(ref id) = s.draft_model {
+        config.draft = match ModelVariant::from_model_id(id) {
+            Some(variant) => Some(DraftConfig::new(
+                variant,
+                s.draft_tokens.unwrap_or(DEFAULT_DRAFT_TOKENS),
+            )),
+            None => {
+                tracing::warn!(draft = %id, "unknown draft model ignored");
+                None
+            }
+        };
+    }
 }
 
 impl Default for AppState {
@@ -31,28 +131,40 @@ impl Default for AppState {
             .join("kengaide")
             .join("models");
         std::fs::create_dir_all(&models_dir).ok();
+        // Старые установки без id в metadata.json: каталог должен звать их так же, как пул.
+        #[cfg(feature = "local")]
+        local_provider::backfill_catalog_ids(&models_dir);
 
         let model_manager = Arc::new(ModelManager::new(models_dir));
         let fs_read: Arc<dyn Fn(&Path) -> Result<String, std::io::Error> + Send + Sync> =
             Arc::new(|p| std::fs::read_to_string(p));
 
         let mut ai_runtime = AiRuntime::new(model_manager, fs_read);
+        let ai_config = load_config();
+
+        #[cfg(feature = "local")]
+        let model_pool = {
+            let idle_secs = ai_config
+                .local_idle_timeout_secs
+                .unwrap_or(DEFAULT_IDLE_TIMEOUT_SECS);
+            Arc::new(ModelPool::new(PoolConfig {
+                idle_timeout: (idle_secs > 0).then(|| std::time::Duration::from_secs(idle_secs)),
+                ..PoolConfig::default()
+            }))
+        };
 
         #[cfg(feature = "local")]
         let local_providers = {
-            let mut gigachat_config = LocalConfig::default_config();
-            gigachat_config.model_variant = ModelVariant::GigaChat;
-            let gigachat = Arc::new(LocalProvider::new(gigachat_config));
+            let gigachat_config = local_config(ModelVariant::GigaChat, &ai_config.local_models);
+            let gigachat = Arc::new(LocalProvider::with_pool(gigachat_config, model_pool.clone()));
             ai_runtime.add_provider(gigachat.clone());
 
-            let mut deepseek_config = LocalConfig::default_config();
-            deepseek_config.model_variant = ModelVariant::DeepSeekCoder;
-            let deepseek = Arc::new(LocalProvider::new(deepseek_config));
+            let deepseek_config = local_config(ModelVariant::DeepSeekCoder, &ai_config.local_models);
+            let deepseek = Arc::new(LocalProvider::with_pool(deepseek_config, model_pool.clone()));
             ai_runtime.add_provider(deepseek.clone());
 
-            let mut smollm2_config = LocalConfig::default_config();
-            smollm2_config.model_variant = ModelVariant::SmolLM2;
-            let smollm2 = Arc::new(LocalProvider::new(smollm2_config));
+            let smollm2_config = local_config(ModelVariant::SmolLM2, &ai_config.local_models);
+            let smollm2 = Arc::new(LocalProvider::with_pool(smollm2_config, model_pool.clone()));
             ai_runtime.add_provider(smollm2.clone());
 
             vec![gigachat, deepseek, smollm2]
@@ -67,7 +179,6 @@ impl Default for AppState {
             }
         }
 
-        let ai_config = load_config();
         for entry in &ai_config.providers {
             let provider: Option<Arc<ApiProvider>> = match entry.provider_type.as_str() {
                 "openai" => Some(Arc::new(ApiProvider::openai_with_id(
@@ -105,8 +216,12 @@ impl Default for AppState {
             router: Arc::new(CommandRouter::new()),
             ai_runtime,
             ai_controller,
+            approvals: Arc::new(ApprovalGate::new()),
+            change_sets: Arc::new(RwLock::new(HashMap::new())),
             #[cfg(feature = "local")]
             local_providers,
+            #[cfg(feature = "local")]
+            model_pool,
         }
     }
 }
@@ -19,17 +19,35 @@ import {
 } from "./constants";
 import type {
   AiChunkPayload,
+  AiModelSelectedPayload,
   AiRequestPayload,
   AiRequestType,
   AgentProgressPayload,
+  AgentSessionSummary,
+  ApprovalDecision,
+  ApprovalPolicy,
+  ChangeSelection,
+  CheckOutcome,
   CommandItem,
   DownloadProgress,
   InvokeFn,
   LocalModelStatus,
+  OmittedItem,
+  PendingApproval,
+  PendingPlan,
+  PlanDecision,
+  PlanningPolicy,
+  PlanStep,
   ProjectTreeNode,
+  RestoreReport,
+  StagedChange,
+  StagedChangeSet,
   ThemeId,
 } from "./types";
 
+/** События под-агента, которые обрабатываются как события главного агента. */
+const SUB_AGENT_HANDLED = ["approval_required", "approval_resolved", "patch_apply_success", "patch_applied", "tool_result"];
+
 function App() {
   const [code, setCode] = useState("// KengaIDE\nfn main() {\n    println!(\"Hello\");\n}\n");
   const [aiInput, setAiInput] = useState("");
@@ -44,6 +62,8 @@ function App() {
   const [tauriReady, setTauriReady] = useState(false);
   const [inTauri, setInTauri] = useState(false);
   const [streamingRequestId, setStreamingRequestId] = useState<string | null>(null);
+  const [queuePosition, setQueuePosition] = useState<number | null>(null);
+  const [contextOmitted, setContextOmitted] = useState<OmittedItem[]>([]);
   const [agentRequestId, setAgentRequestId] = useState<string | null>(null);
   const [modelSelection, setModelSelection] = useState<{ role: string; model_id: string } | null>(null);
   const [toolTimeline, setToolTimeline] = useState<
@@ -56,12 +76,30 @@ function App() {
       message?: string;
       before?: string;
       after?: string;
+      decision?: string;
+      before_tokens?: number;
+      after_tokens?: number;
+      passed?: boolean;
+      checks?: CheckOutcome[];
+      sub_agent?: string;
     }>
   >([]);
   const [toolTimelineExpanded, setToolTimelineExpanded] = useState(true);
   const [appliedPatches, setAppliedPatches] = useState<Array<{ path: string; before: string; after: string }>>([]);
   const [lastAgentMessage, setLastAgentMessage] = useState("");
   const [lastSessionId, setLastSessionId] = useState<string | null>(null);
+  const [agentSessions, setAgentSessions] = useState<AgentSessionSummary[]>([]);
+  const [approvalPolicy, setApprovalPolicy] = useState<ApprovalPolicy | null>(null);
+  const [pendingApproval, setPendingApproval] = useState<PendingApproval | null>(null);
+  const [approvalEdit, setApprovalEdit] = useState<string | null>(null);
+  const [planningPolicy, setPlanningPolicy] = useState<PlanningPolicy | null>(null);
+  const [verificationEnabled, setVerificationEnabled] = useState<boolean | null>(null);
+  const [agentPlan, setAgentPlan] = useState<{ plan_id: string; steps: PlanStep[]; review: boolean } | null>(null);
+  /** Редактируемый план: шаг на строку. */
+  const [planEdit, setPlanEdit] = useState<string | null>(null);
+  const [stagedChanges, setStagedChanges] = useState<{ requestId: string; changes: StagedChange[] } | null>(null);
+  /** path → выбранные hunk'и (по умолчанию все). */
+  const [stagedSelection, setStagedSelection] = useState<Record<string, number[]>>({});
   const [splitActive, setSplitActive] = useState(false);
   const [splitFilePath, setSplitFilePath] = useState<string | null>(null);
   const [splitCode, setSplitCode] = useState("");
@@ -213,6 +251,195 @@ function App() {
       .catch(() => {});
   }, [inTauri]);
 
+  useEffect(() => {
+    if (!inTauri || !invokeRef.current) return;
+    invokeRef.current("get_agent_approval_policy")
+      .then((v) => setApprovalPolicy(v as ApprovalPolicy))
+      .catch(() => {});
+    // Запросы, пришедшие до открытия окна, иначе агент ждал бы до таймаута.
+    invokeRef.current("list_pending_approvals")
+      .then((v) => {
+        const [first] = v as PendingApproval[];
+        if (first) setPendingApproval(first);
+      })
+      .catch(() => {});
+    invokeRef.current("list_pending_plans")
+      .then((v) => {
+        const [first] = v as PendingPlan[];
+        if (first) setAgentPlan({ plan_id: first.plan_id, steps: first.steps, review: true });
+      })
+      .catch(() => {});
+  }, [inTauri]);
+
+  const toggleApprovalMode = async () => {
+    const inv = invokeRef.current;
+    if (!inv || !approvalPolicy) return;
+    const next = { ...approvalPolicy, enabled: !approvalPolicy.enabled };
+    try {
+      await inv("set_agent_approval_policy", { policy: next });
+      setApprovalPolicy(next);
+    } catch (e) {
+      setAiResponse((prev) => prev + `\nОшибка сохранения режима подтверждения: ${String(e)}\n`);
+    }
+  };
+
+  useEffect(() => {
+    if (!inTauri || !invokeRef.current) return;
+    invokeRef.current("get_agent_planning_policy")
+      .then((v) => setPlanningPolicy(v as PlanningPolicy))
+      .catch(() => {});
+  }, [inTauri]);
+
+  const togglePlanningMode = async () => {
+    const inv = invokeRef.current;
+    if (!inv || !planningPolicy) return;
+    const enabled = !planningPolicy.enabled;
+    const next = { ...planningPolicy, enabled, review: enabled };
+    try {
+      await inv("set_agent_planning_policy", { policy: next });
+      setPlanningPolicy(next);
+    } catch (e) {
+      setAiResponse((prev) => prev + `\nОшибка сохранения режима планирования: ${String(e)}\n`);
+    }
+  };
+
+  useEffect(() => {
+    if (!inTauri || !invokeRef.current) return;
+    invokeRef.current("get_agent_verification")
+      .then((v) => setVerificationEnabled(v as boolean))
+      .catch(() => {});
+  }, [inTauri]);
+
+  const toggleVerification = async () => {
+    const inv = invokeRef.current;
+    if (!inv || verificationEnabled === null) return;
+    const next = !verificationEnabled;
+    try {
+      await inv("set_agent_verification", { enabled: next });
+      setVerificationEnabled(next);
+    } catch (e) {
+      setAiResponse((prev) => prev + `\nОшибка сохранения режима проверки: ${String(e)}\n`);
+    }
+  };
+
+  const resolvePlan = async (decision: PlanDecision) => {
+    const inv = invokeRef.current;
+    if (!inv || !agentPlan) return;
+    try {
+      await inv("resolve_agent_plan", { planId: agentPlan.plan_id, decision });
+    } catch (e) {
+      setAiResponse((prev) => prev + `\nОшибка решения по плану: ${String(e)}\n`);
+    }
+    setAgentPlan((prev) => (prev ? { ...prev, review: false } : prev));
+    setPlanEdit(null);
+  };
+
+  const resolveApproval = async (decision: ApprovalDecision) => {
+    const inv = invokeRef.current;
+    if (!inv || !pendingApproval) return;
+    try {
+      await inv("resolve_agent_approval", { approvalId: pendingApproval.approval_id, decision });
+    } catch (e) {
+      setAiResponse((prev) => prev + `\nОшибка подтверждения: ${String(e)}\n`);
+    }
+    setPendingApproval(null);
+    setApprovalEdit(null);
+  };
+
+  const showStagedChanges = (requestId: string, changes: StagedChange[]) => {
+    setStagedChanges(changes.length > 0 ? { requestId, changes } : null);
+    setStagedSelection(Object.fromEntries(changes.map((c) => [c.path, c.hunks.map((_, i) => i)])));
+  };
+
+  const toggleStagedHunk = (path: string, hunk: number) => {
+    setStagedSelection((prev) => {
+      const current = prev[path] ?? [];
+      const next = current.includes(hunk) ? current.filter((h) => h !== hunk) : [...current, hunk].sort((a, b) => a - b);
+      return { ...prev, [path]: next };
+    });
+  };
+
+  const afterStagedWrite = async (requestId: string) => {
+    const inv = invokeRef.current;
+    if (!inv) return;
+    try {
+      showStagedChanges(requestId, (await inv("get_staged_changes", { requestId })) as StagedChange[]);
+    } catch {
+      setStagedChanges(null);
+    }
+    refreshProjectTree();
+    if (currentFilePath) {
+      inv("read_project_file", { relativePath: currentFilePath })
+        .then((c) => setCode(c as string))
+        .catch(() => {});
+    }
+  };
+
+  const acceptStagedChanges = async (all: boolean) => {
+    const inv = invokeRef.current;
+    if (!inv || !stagedChanges) return;
+    const selection: ChangeSelection[] | null = all
+      ? null
+      : stagedChanges.changes
+          .map((c) => {
+            const hunks = stagedSelection[c.path] ?? [];
+            return hunks.length === c.hunks.length ? { path: c.path } : { path: c.path, hunks };
+          })
+          .filter((sel) => !sel.hunks || sel.hunks.length > 0);
+    try {
+      const written = (await inv("accept_staged_changes", {
+        requestId: stagedChanges.requestId,
+        selection,
+      })) as string[];
+      setAiResponse((prev) => prev + `\nЗаписано файлов: ${written.length}.\n`);
+    } catch (e) {
+      setAiResponse((prev) => prev + `\nОшибка записи изменений: ${String(e)}\n`);
+    }
+    await afterStagedWrite(stagedChanges.requestId);
+  };
+
+  const discardStagedChanges = async (path?: string) => {
+    const inv = invokeRef.current;
+    if (!inv || !stagedChanges) return;
+    try {
+      await inv("discard_staged_changes", { requestId: stagedChanges.requestId, path: path ?? null });
+    } catch (e) {
+      setAiResponse((prev) => prev + `\nОшибка: ${String(e)}\n`);
+    }
+    await afterStagedWrite(stagedChanges.requestId);
+  };
+
+  const undoAgentSession = async (sessionId: string) => {
+    const inv = invokeRef.current;
+    if (!inv) return;
+    try {
+      let report = (await inv("restore_session_snapshot", { sessionId, force: false })) as RestoreReport;
+      if (
+        report.conflicts.length > 0 &&
+        window.confirm(`Файлы изменены после сессии агента:\n${report.conflicts.join("\n")}\n\nПерезаписать их исходными версиями?`)
+      ) {
+        const forced = (await inv("restore_session_snapshot", { sessionId, force: true })) as RestoreReport;
+        report = { restored: [...report.restored, ...forced.restored], conflicts: forced.conflicts };
+      }
+      setAiResponse(
+        (prev) =>
+          prev +
+          `\nОткат сессии: восстановлено ${report.restored.length} файлов` +
+          (report.conflicts.length > 0 ? `, пропущено (изменены позже): ${report.conflicts.join(", ")}` : "") +
+          ".\n"
+      );
+      setAppliedPatches([]);
+      refreshProjectTree();
+      if (currentFilePath) {
+        inv("read_project_file", { relativePath: currentFilePath })
+          .then((c) => setCode(c as string))
+          .catch(() => {});
+      }
+    } catch (e) {
+      setAiResponse((prev) => prev + `\nОшибка отката сессии: ${String(e)}\n`);
+    }
+  };
+
   const toggleTreePath = (path: string) => {
     setExpandedPaths((prev) => {
       const next = new Set(prev);
@@ -436,8 +663,9 @@ function App() {
     if (!inTauri) return;
     let unlisten: (() => void) | undefined;
     import("@tauri-apps/api/event").then(({ listen }) => {
-      listen<{ request_id: string; role: string; model_id: string }>("ai_model_selected", (ev) => {
+      listen<AiModelSelectedPayload>("ai_model_selected", (ev) => {
         setModelSelection({ role: ev.payload.role, model_id: ev.payload.model_id });
+        setContextOmitted(ev.payload.context_omitted ?? []);
       }).then((fn) => {
         unlisten = fn;
       });
@@ -457,14 +685,21 @@ function App() {
         switch (type) {
           case "start":
             setAiResponse("");
+            setQueuePosition(null);
+            break;
+          case "queued":
+            setQueuePosition((ev.payload as { position: number }).position);
             break;
           case "token":
+            setQueuePosition(null);
             setAiResponse((prev) => prev + (ev.payload as { value: string }).value);
             break;
           case "end":
+            setQueuePosition(null);
             setStreamingRequestId(null);
             break;
           case "error":
+            setQueuePosition(null);
             setAiResponse((prev) => prev + "\n[Ошибка: " + (ev.payload as { error: string }).error + "]");
             setStreamingRequestId(null);
             break;
@@ -489,27 +724,39 @@ function App() {
     let unlisten: (() => void) | undefined;
     import("@tauri-apps/api/event").then(({ listen }) => {
       listen<AgentProgressPayload>("agent_progress", (ev) => {
-        const { request_id, kind } = ev.payload;
+        const { request_id } = ev.payload;
         if (request_id !== agentRequestId) return;
+        // События под-агента (delegate) разворачиваются; в ленте — с отметкой под-агента.
+        const sub = ev.payload.kind === "sub_agent" ? ev.payload : null;
+        const payload = sub ? { ...sub.progress, request_id } : ev.payload;
+        const kind = payload.kind;
         setToolTimeline((prev) => {
-          const entry = { ...ev.payload } as {
+          const entry = { ...payload, sub_agent: sub?.session_id } as {
             kind: string;
             name?: string;
             path?: string;
             success?: boolean;
             output?: string;
             message?: string;
+            decision?: string;
+            before_tokens?: number;
+            after_tokens?: number;
+            passed?: boolean;
+            checks?: CheckOutcome[];
+            sub_agent?: string;
           };
           return [...prev, entry];
         });
+        // Конец сессии, план и сводка изменений — только от главного агента.
+        if (sub && !SUB_AGENT_HANDLED.includes(kind)) return;
         switch (kind) {
           case "session_started":
-            setLastSessionId((ev.payload as { session_id: string }).session_id);
+            setLastSessionId((payload as { session_id: string }).session_id);
             break;
           case "model_selected":
             setModelSelection({
-              role: (ev.payload as { role: string }).role,
-              model_id: (ev.payload as { model_id: string }).model_id,
+              role: (payload as { role: string }).role,
+              model_id: (payload as { model_id: string }).model_id,
             });
             break;
           case "thinking":
@@ -518,14 +765,14 @@ function App() {
           case "patch_apply_started":
             break;
           case "tool_result": {
-            const pl = ev.payload as { name?: string; success?: boolean };
+            const pl = payload as { name?: string; success?: boolean };
             if (pl.name === "create_project" && pl.success) {
               refreshProjectTree();
             }
             break;
           }
           case "patch_apply_success": {
-            const patchedPath = (ev.payload as { path: string }).path;
+            const patchedPath = (payload as { path: string }).path;
             if (invokeRef.current && patchedPath === currentFilePath) {
               invokeRef
                 .current("read_project_file", { relativePath: patchedPath })
@@ -536,14 +783,52 @@ function App() {
           }
           case "patch_apply_error":
             break;
+          case "approval_required": {
+            const p = payload as {
+              approval_id: string;
+              name: string;
+              path?: string;
+              diff: string;
+              arguments: Record<string, unknown>;
+            };
+            setPendingApproval({
+              approval_id: p.approval_id,
+              name: p.name,
+              path: p.path,
+              diff: p.diff,
+              arguments: p.arguments,
+            });
+            setApprovalEdit(null);
+            break;
+          }
+          case "approval_resolved":
+            setPendingApproval(null);
+            break;
+          case "plan": {
+            const p = payload as { plan_id: string; steps: PlanStep[]; review: boolean };
+            setAgentPlan({ plan_id: p.plan_id, steps: p.steps, review: p.review });
+            setPlanEdit(null);
+            break;
+          }
+          case "plan_updated": {
+            const steps = (payload as { steps: PlanStep[] }).steps;
+            setAgentPlan((prev) => (prev ? { ...prev, steps } : prev));
+            break;
+          }
+          case "changes_staged":
+            showStagedChanges(request_id, (payload as { changes: StagedChange[] }).changes);
+            break;
           case "patch_applied": {
-            const p = ev.payload as { path: string; before: string; after: string };
+            const p = payload as { path: string; before: string; after: string };
             setAppliedPatches((prev) => [...prev, { path: p.path, before: p.before, after: p.after }]);
             break;
           }
           case "done":
-            setAiResponse((prev) => prev + "\n\n" + (ev.payload as { message: string }).message);
+            setAiResponse((prev) => prev + "\n\n" + (payload as { message: string }).message);
             setAgentRequestId(null);
+            setPendingApproval(null);
+            setAgentPlan((prev) => (prev ? { ...prev, review: false } : prev));
+            setPlanEdit(null);
             break;
         }
       }).then((fn) => {
@@ -771,6 +1056,53 @@ function App() {
     }
   };
 
+  const loadAgentSessions = () => {
+    if (!invokeRef.current || !projectPath) {
+      setAgentSessions([]);
+      return;
+    }
+    invokeRef.current("list_agent_sessions")
+      .then((v) => setAgentSessions(v as AgentSessionSummary[]))
+      .catch(() => setAgentSessions([]));
+  };
+
+  useEffect(() => {
+    if (inTauri && !agentRequestId) loadAgentSessions();
+  }, [inTauri, projectPath, agentRequestId]);
+
+  // Непринятые изменения прошлых запусков сохраняются в проекте — показываем их после открытия.
+  useEffect(() => {
+    if (!inTauri || !invokeRef.current || !projectPath) return;
+    invokeRef.current("list_staged_change_sets")
+      .then((v) => {
+        const [first] = v as StagedChangeSet[];
+        if (first) showStagedChanges(first.request_id, first.changes);
+      })
+      .catch(() => {});
+  }, [inTauri, projectPath]);
+
+  /** Продолжает сохранённую сессию агента сообщением из поля ввода. */
+  const handleContinueSession = async (sessionId: string) => {
+    const inv = invokeRef.current;
+    if (!inv || streamingRequestId || agentRequestId) return;
+    const msg = aiInput.trim();
+    if (!msg) {
+      setAiResponse("Введите сообщение для продолжения сессии.\n");
+      return;
+    }
+    try {
+      setAiResponse((prev) => prev + `\n\n> ${msg}\nАгент продолжает сессию…\n`);
+      setToolTimeline([]);
+      setAgentPlan(null);
+      setAppliedPatches([]);
+      setLastAgentMessage(msg);
+      const requestId = (await inv("continue_agent_session", { sessionId, message: msg })) as string;
+      setAgentRequestId(requestId);
+    } catch (e) {
+      setAiResponse((prev) => prev + `Ошибка: ${String(e)}\n`);
+    }
+  };
+
   const handleAgentRequest = async (messageOverride?: string) => {
     const inv = invokeRef.current;
     if (!inv) {
@@ -796,6 +1128,7 @@ function App() {
     try {
       setAiResponse("Агент запущен…\n");
       setToolTimeline([]);
+      setAgentPlan(null);
       setAppliedPatches([]);
       setLastAgentMessage(msg);
       setLastSessionId(null);
@@ -1441,6 +1774,26 @@ function App() {
         >
           <h3 className="kenga-ai-panel-title">AI</h3>
           <div style={{ flex: 1, minHeight: 0, display: "flex", flexDirection: "column", overflow: "hidden" }}>
+          {agentSessions.length > 0 && !agentRequestId && (
+            <select
+              className="kenga-agent-session-select"
+              value={lastSessionId ?? ""}
+              onChange={(e) => {
+                const id = e.target.value || null;
+                setLastSessionId(id);
+                setLastAgentMessage(agentSessions.find((s) => s.session_id === id)?.task ?? "");
+              }}
+              title="Сессия агента: продолжить (↪ Continue) или откатить (⏪ Undo session)"
+            >
+              <option value="">Новая сессия агента</option>
+              {agentSessions.map((s) => (
+                <option key={s.session_id} value={s.session_id}>
+                  {s.task.slice(0, 60)}
+                  {s.task.length > 60 ? "…" : ""} ({new Date(s.updated_at).toLocaleString()})
+                </option>
+              ))}
+            </select>
+          )}
           {(agentRequestId || streamingRequestId || lastAgentMessage || toolTimeline.length > 0) && (
             <div className="kenga-ai-toolbar">
               <button
@@ -1492,6 +1845,228 @@ function App() {
                   ⏪ Rollback
                 </button>
               )}
+              {lastSessionId && !agentRequestId && !streamingRequestId && (
+                <button
+                  type="button"
+                  className="kenga-ai-btn-secondary"
+                  onClick={() => handleContinueSession(lastSessionId)}
+                  title="Продолжить сессию сообщением из поля ввода"
+                >
+                  ↪ Continue
+                </button>
+              )}
+              {lastSessionId && !agentRequestId && (
+                <button
+                  type="button"
+                  className="kenga-ai-btn-danger"
+                  onClick={() => undoAgentSession(lastSessionId)}
+                  title="Вернуть все файлы, изменённые сессией агента, к исходному состоянию"
+                >
+                  ⏪ Undo session
+                </button>
+              )}
+              {approvalPolicy && (
+                <button
+                  type="button"
+                  className="kenga-ai-btn-secondary"
+                  onClick={toggleApprovalMode}
+                  title="Останавливать агента на изменяющих вызовах и ждать подтверждения"
+                >
+                  {approvalPolicy.enabled ? "🔐 Approval: on" : "🔓 Approval: off"}
+                </button>
+              )}
+              {planningPolicy && (
+                <button
+                  type="button"
+                  className="kenga-ai-btn-secondary"
+                  onClick={togglePlanningMode}
+                  title="Строить план перед выполнением и показывать его на проверку"
+                >
+                  {planningPolicy.enabled ? "🗺 Plan: on" : "🗺 Plan: off"}
+                </button>
+              )}
+              {verificationEnabled !== null && (
+                <button
+                  type="button"
+                  className="kenga-ai-btn-secondary"
+                  onClick={toggleVerification}
+                  title="После правок запускать cargo check / pytest / npm test и отдавать ошибки агенту"
+                >
+                  {verificationEnabled ? "🧪 Verify: on" : "🧪 Verify: off"}
+                </button>
+              )}
+            </div>
+          )}
+          {stagedChanges && !agentRequestId && (
+            <div className="kenga-approval">
+              <div className="kenga-approval-title">
+                Изменения агента не записаны ({stagedChanges.changes.length} файлов)
+              </div>
+              {stagedChanges.changes.map((c) => (
+                <details key={c.path} className="kenga-staged-file">
+                  <summary>
+                    {c.created ? "＋ " : "± "}
+                    {c.path}{" "}
+                    <span className="kenga-status-muted">
+                      ({(stagedSelection[c.path] ?? []).length}/{c.hunks.length})
+                    </span>
+                    <button type="button" className="kenga-ai-btn-danger" onClick={() => discardStagedChanges(c.path)} title="Отбросить файл">
+                      ✕
+                    </button>
+                  </summary>
+                  {c.hunks.map((h, i) => (
+                    <label key={i} className="kenga-staged-hunk">
+                      <input
+                        type="checkbox"
+                        checked={(stagedSelection[c.path] ?? []).includes(i)}
+                        onChange={() => toggleStagedHunk(c.path, i)}
+                      />
+                      <pre className="kenga-approval-diff">{h.header + "\n" + h.text}</pre>
+                    </label>
+                  ))}
+                </details>
+              ))}
+              <div style={{ display: "flex", gap: 8, flexWrap: "wrap" }}>
+                <button type="button" className="kenga-ai-btn-run" onClick={() => acceptStagedChanges(true)}>
+                  ✓ Accept all
+                </button>
+                <button type="button" className="kenga-ai-btn-secondary" onClick={() => acceptStagedChanges(false)} title="Записать отмеченные hunk'и">
+                  ✓ Accept selected
+                </button>
+                <button type="button" className="kenga-ai-btn-danger" onClick={() => discardStagedChanges()}>
+                  ✕ Discard
+                </button>
+              </div>
+            </div>
+          )}
+          {agentPlan && (
+            <div className="kenga-approval">
+              <div className="kenga-approval-title">
+                {agentPlan.review ? "План агента — проверьте перед выполнением" : "План агента"}
+              </div>
+              {planEdit === null ? (
+                <ol className="kenga-plan-steps">
+                  {agentPlan.steps.map((s) => (
+                    <li key={s.number} className={`kenga-plan-step kenga-plan-step-${s.status}`}>
+                      {{ pending: "○", in_progress: "◐", done: "✓", failed: "⚠" }[s.status]} {s.description}
+                    </li>
+                  ))}
+                </ol>
+              ) : (
+                <textarea
+                  className="kenga-approval-edit"
+                  value={planEdit}
+                  onChange={(e) => setPlanEdit(e.target.value)}
+                  spellCheck={false}
+                />
+              )}
+              {agentPlan.review && (
+                <div style={{ display: "flex", gap: 8, flexWrap: "wrap" }}>
+                  {planEdit === null ? (
+                    <>
+                      <button type="button" className="kenga-ai-btn-run" onClick={() => resolvePlan({ decision: "approve" })}>
+                        ✓ Run plan
+                      </button>
+                      <button
+                        type="button"
+                        className="kenga-ai-btn-secondary"
+                        onClick={() => setPlanEdit(agentPlan.steps.map((s) => s.description).join("\n"))}
+                        title="Один шаг на строку"
+                      >
+                        ✎ Edit
+                      </button>
+                      <button type="button" className="kenga-ai-btn-danger" onClick={() => resolvePlan({ decision: "cancel" })}>
+                        ✕ Cancel
+                      </button>
+                    </>
+                  ) : (
+                    <>
+                      <button
+                        type="button"
+                        className="kenga-ai-btn-run"
+                        onClick={() =>
+                          resolvePlan({
+                            decision: "edit",
+                            steps: planEdit.split("\n").map((l) => l.trim()).filter((l) => l.length > 0),
+                          })
+                        }
+                      >
+                        ✓ Run edited plan
+                      </button>
+                      <button type="button" className="kenga-ai-btn-secondary" onClick={() => setPlanEdit(null)}>
+                        Cancel
+                      </button>
+                    </>
+                  )}
+                </div>
+              )}
+            </div>
+          )}
+          {pendingApproval && (
+            <div className="kenga-approval">
+              <div className="kenga-approval-title">
+                Подтвердите {pendingApproval.name}
+                {pendingApproval.path && <> — {pendingApproval.path}</>}
+              </div>
+              {approvalEdit === null ? (
+                <pre className="kenga-approval-diff">{pendingApproval.diff}</pre>
+              ) : (
+                <textarea
+                  className="kenga-approval-edit"
+                  value={approvalEdit}
+                  onChange={(e) => setApprovalEdit(e.target.value)}
+                  spellCheck={false}
+                />
+              )}
+              <div style={{ display: "flex", gap: 8, flexWrap: "wrap" }}>
+                {approvalEdit === null ? (
+                  <>
+                    <button type="button" className="kenga-ai-btn-run" onClick={() => resolveApproval({ decision: "approve" })}>
+                      ✓ Approve
+                    </button>
+                    <button type="button" className="kenga-ai-btn-secondary" onClick={() => resolveApproval({ decision: "approve_all" })} title="Подтвердить все изменения до конца сессии">
+                      ✓✓ Approve all
+                    </button>
+                    <button
+                      type="button"
+                      className="kenga-ai-btn-secondary"
+                      onClick={() => setApprovalEdit(JSON.stringify(pendingApproval.arguments, null, 2))}
+                    >
+                      ✎ Edit
+                    </button>
+                    <button
+                      type="button"
+                      className="kenga-ai-btn-danger"
+                      onClick={() => {
+                        const reason = window.prompt("Причина отказа (необязательно)") ?? undefined;
+                        resolveApproval({ decision: "reject", reason });
+                      }}
+                    >
+                      ✕ Reject
+                    </button>
+                  </>
+                ) : (
+                  <>
+                    <button
+                      type="button"
+                      className="kenga-ai-btn-run"
+                      onClick={() => {
+                        try {
+                          const args = JSON.parse(approvalEdit) as Record<string, unknown>;
+                          resolveApproval({ decision: "edit", arguments: args });
+                        } catch (e) {
+                          setAiResponse((prev) => prev + `\nНекорректный JSON аргументов: ${String(e)}\n`);
+                        }
+                      }}
+                    >
+                      ✓ Apply edited
+                    </button>
+                    <button type="button" className="kenga-ai-btn-secondary" onClick={() => setApprovalEdit(null)}>
+                      Cancel
+                    </button>
+                  </>
+                )}
+              </div>
             </div>
           )}
           {toolTimeline.length > 0 && (
@@ -1510,10 +2085,14 @@ function App() {
                       (t) =>
                         t.kind !== "patch_applied" &&
                         t.kind !== "model_selected" &&
-                        t.kind !== "session_started"
+                        t.kind !== "session_started" &&
+                        t.kind !== "approval_required" &&
+                        t.kind !== "plan" &&
+                        t.kind !== "plan_updated" &&
+                        t.kind !== "changes_staged"
                     )
                     .map((t, i) => (
-                    <div key={i} className="kenga-tool-entry">
+                    <div key={i} className={t.sub_agent ? "kenga-tool-entry kenga-tool-entry-sub" : "kenga-tool-entry"}>
                       {t.kind === "thinking" && (
                         <span className="kenga-tool-thinking" title="Модель генерирует ответ">
                           🧠 Thinking…
@@ -1572,6 +2151,45 @@ function App() {
                           </span>
                         </>
                       )}
+                      {t.kind === "approval_resolved" && (
+                        <>
+                          <span className="kenga-tool-call" style={{ flexShrink: 0 }}>🔐</span>
+                          <span className="kenga-status-muted">{t.decision}</span>
+                        </>
+                      )}
+                      {t.kind === "context_compacted" && (
+                        <>
+                          <span className="kenga-tool-call" style={{ flexShrink: 0 }}>🗜</span>
+                          <span className="kenga-status-muted">
+                            Context compacted: ~{t.before_tokens} → ~{t.after_tokens} tokens
+                          </span>
+                        </>
+                      )}
+                      {t.kind === "verification_started" && (
+                        <>
+                          <span className="kenga-tool-call" style={{ flexShrink: 0 }}>🧪</span>
+                          <span className="kenga-status-muted">Running checks…</span>
+                        </>
+                      )}
+                      {t.kind === "verification" && (
+                        <>
+                          <span className={t.passed ? "kenga-tool-result-ok" : "kenga-tool-result-err"} style={{ flexShrink: 0 }}>
+                            {t.passed ? "🧪" : "⚠"}
+                          </span>
+                          <span
+                            className={t.passed ? "kenga-tool-result-ok" : "kenga-tool-result-err"}
+                            title={t.checks?.map((c) => c.output).join("\n\n")}
+                          >
+                            {t.checks
+                              ?.map((c) =>
+                                c.status === "failed"
+                                  ? `${c.command}: ${Math.max(c.diagnostics.length, 1)} error(s)`
+                                  : `${c.command}: ${c.status}`
+                              )
+                              .join("; ")}
+                          </span>
+                        </>
+                      )}
                       {t.kind === "done" && (
                         <>
                           <span className="kenga-tool-result-ok" style={{ flexShrink: 0 }}>✅</span>
@@ -1670,7 +2288,17 @@ function App() {
           )}
           {streamingRequestId && (
             <div style={{ fontSize: 11, color: "var(--kenga-muted)", marginBottom: 8 }}>
-              Генерация…
+              {queuePosition !== null ? `В очереди: ${queuePosition}` : "Генерация…"}
+            </div>
+          )}
+          {contextOmitted.length > 0 && (
+            <div
+              style={{ fontSize: 11, color: "var(--kenga-muted)", marginBottom: 8 }}
+              title={contextOmitted
+                .map((o) => (o.kept_chars === 0 ? `${o.name}: отброшен` : `${o.name}: ${o.kept_chars} из ${o.original_chars} симв.`))
+                .join("\n")}
+            >
+              Контекст сокращён под окно модели: {contextOmitted.length}
             </div>
           )}
           <pre
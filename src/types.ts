@@ -11,6 +11,9 @@ export interface AiRequestPayload {
     selection?: string;
     instruction?: string;
     prompt?: string;
+    /** Явные ссылки на контекст: "@file:path", "@folder:path", "@symbol:name", "@git:diff", "@git:file[:path]",
+     *  "@git:log[:path]", "@git:blame[:path:start-end]" (без пути — текущий файл и выделение), "@audit:session_id". */
+    references?: string[];
   };
   current_file_path?: string;
   current_file_content?: string;
@@ -26,8 +29,71 @@ export interface DownloadProgress {
   file_count: number;
 }
 
+export interface ModelRecommendation {
+  model_id: string;
+  display_name: string;
+  size_bytes: number;
+  installed: boolean;
+  suitability: "recommended" | "possible" | "unsupported";
+  reasons: string[];
+}
+
+export interface HardwareReport {
+  total_ram_bytes: number;
+  available_ram_bytes: number;
+  free_disk_bytes: number | null;
+  cpu_cores: number;
+  cpu_features: { avx2: boolean; avx512f: boolean; fma: boolean; neon: boolean };
+  recommendations: ModelRecommendation[];
+}
+
+export type VerificationState =
+  | { status: "unverified" }
+  | { status: "verified"; verified_at: number }
+  | { status: "failed"; reason: string };
+
+export interface InstalledModel {
+  id: string;
+  name: string;
+  path: string;
+  size_bytes: number;
+  version: string | null;
+  files: { name: string; size_bytes: number; sha256?: string }[];
+  verification: VerificationState;
+}
+
+export interface ModelDiskUsage {
+  model_id: string | null;
+  path: string;
+  total_bytes: number;
+  reclaimable_bytes: number;
+  last_used: number | null;
+  files: { name: string; size_bytes: number; kind: "model" | "metadata" | "partial" | "orphaned"; recent: boolean }[];
+}
+
+export interface CleanupReport {
+  dry_run: boolean;
+  items: { path: string; size_bytes: number; reason: string }[];
+  freed_bytes: number;
+}
+
+/** Элемент контекста, урезанный под окно модели (kept_chars 0 — отброшен). */
+export interface OmittedItem {
+  name: string;
+  original_chars: number;
+  kept_chars: number;
+}
+
+export interface AiModelSelectedPayload {
+  request_id: string;
+  role: string;
+  model_id: string;
+  context_omitted: OmittedItem[];
+}
+
 export type AiChunkPayload =
   | { request_id: string; type: "start" }
+  | { request_id: string; type: "queued"; position: number }
   | { request_id: string; type: "token"; value: string }
   | { request_id: string; type: "end" }
   | { request_id: string; type: "error"; error: string };
@@ -55,6 +121,125 @@ export type AgentProgressPayload =
   | { request_id: string; kind: "patch_apply_success"; path: string }
   | { request_id: string; kind: "patch_apply_error"; path: string; message: string }
   | { request_id: string; kind: "patch_applied"; path: string; before: string; after: string }
+  | {
+      request_id: string;
+      kind: "approval_required";
+      approval_id: string;
+      name: string;
+      path?: string;
+      diff: string;
+      arguments: Record<string, unknown>;
+    }
+  | { request_id: string; kind: "approval_resolved"; approval_id: string; decision: string }
+  | { request_id: string; kind: "plan"; plan_id: string; steps: PlanStep[]; review: boolean }
+  | { request_id: string; kind: "plan_updated"; steps: PlanStep[] }
+  | { request_id: string; kind: "context_compacted"; before_tokens: number; after_tokens: number }
+  | { request_id: string; kind: "verification_started" }
+  | { request_id: string; kind: "verification"; passed: boolean; checks: CheckOutcome[] }
+  | {
+      request_id: string;
+      kind: "sub_agent";
+      session_id: string;
+      /** Событие под-агента (delegate) в том же формате, без request_id. */
+      progress: { kind: string; [key: string]: unknown };
+    }
+  | { request_id: string; kind: "changes_staged"; changes: StagedChange[] }
   | { request_id: string; kind: "done"; message: string };
 
+/** Незаписанное изменение файла сессии агента. */
+export interface StagedChange {
+  path: string;
+  created: boolean;
+  diff: string;
+  hunks: { header: string; text: string }[];
+}
+
+/** Что принять: файл целиком (без hunks) или hunk'и по индексам. */
+export interface ChangeSelection {
+  path: string;
+  hunks?: number[];
+}
+
+/** Результат команды проверки правок агента (cargo check, pytest, npm test). */
+export interface CheckOutcome {
+  kind: "rust" | "python" | "node";
+  dir: string;
+  command: string;
+  status: "passed" | "failed" | "unavailable";
+  diagnostics: { file?: string; line?: number; message: string }[];
+  output: string;
+}
+
+/** Сохранённая сессия агента, которую можно продолжить. */
+export interface AgentSessionSummary {
+  session_id: string;
+  created_at: string;
+  updated_at: string;
+  task: string;
+  turns: number;
+}
+
+/** Результат отката сессии агента по снимку. */
+export interface RestoreReport {
+  restored: string[];
+  conflicts: string[];
+}
+
+/** Решение по вызову агента, ожидающему подтверждения (resolve_agent_approval). */
+export type ApprovalDecision =
+  | { decision: "approve" }
+  | { decision: "approve_all" }
+  | { decision: "reject"; reason?: string }
+  | { decision: "edit"; arguments: Record<string, unknown> };
+
+/** Шаг плана агента. */
+export interface PlanStep {
+  number: number;
+  description: string;
+  status: "pending" | "in_progress" | "done" | "failed";
+}
+
+export type PlanDecision =
+  | { decision: "approve" }
+  | { decision: "edit"; steps: string[] }
+  | { decision: "cancel" };
+
+/** Фаза планирования агента (ai_config.json). */
+export interface PlanningPolicy {
+  enabled: boolean;
+  review: boolean;
+  use_planning_model: boolean;
+  /** Сколько ждать проверки плана, секунд; без решения задача отменяется. 0 — без ограничения. */
+  timeout_secs: number;
+}
+
+/** План агента, ждущий проверки (list_pending_plans). */
+export interface PendingPlan {
+  plan_id: string;
+  steps: PlanStep[];
+}
+
+/** Политика подтверждения изменений агента (ai_config.json). */
+export interface ApprovalPolicy {
+  enabled: boolean;
+  auto_approve: string[];
+  /** Сколько ждать решения, секунд; без решения вызов отклоняется. 0 — без ограничения. */
+  timeout_secs: number;
+}
+
+/** Вызов агента, ждущий подтверждения (list_pending_approvals). */
+export interface PendingApproval {
+  approval_id: string;
+  name: string;
+  path?: string;
+  diff: string;
+  arguments: Record<string, unknown>;
+}
+
+/** Слой непринятых изменений агента (list_staged_change_sets). */
+export interface StagedChangeSet {
+  request_id: string;
+  changes: StagedChange[];
+}
+
 export type ThemeId = "light" | "dark" | "high-contrast";